@@ -0,0 +1,667 @@
+//! Local HTTP control API for scripts
+//!
+//! An opt-in server bound to 127.0.0.1 that lets shell scripts drive the app
+//! through the same managers as the webview:
+//!
+//! | Method | Path                         | Body                | Response              |
+//! |--------|------------------------------|---------------------|-----------------------|
+//! | GET    | `/health`                    |                     | `HealthResponse`      |
+//! | GET    | `/sessions`                  |                     | `[SessionInfo]`       |
+//! | POST   | `/sessions`                  | `SessionConfig`     | `CreateSessionResult` |
+//! | POST   | `/sessions/{id}/prompt`      | `PromptRequest`     | `SendPromptResult`    |
+//! | POST   | `/sessions/{id}/interrupt`   |                     | 204                   |
+//!
+//! Every request needs `Authorization: Bearer <token>`, where the token is
+//! generated once and stored in `control_token` in the app data directory.
+//! Errors are `SessionError` JSON. Whether the server runs, and on which port,
+//! is kept in `control_server.json` and can be changed at runtime with
+//! `set_control_server`.
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{Path as UrlPath, Request, State as AxumState};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use tokio::sync::{mpsc, oneshot, RwLock};
+
+use super::files::report_if_storage_full;
+use super::session::{
+    forward_cli_messages, AppState, CreateSessionResult, SendPromptResult, SessionError,
+};
+use crate::services::settings_file;
+use crate::services::{
+    ProcessError, ProcessManager, PromptOptions, PromptRecord, SessionConfig, SessionInfo,
+    StreamMessage,
+};
+
+/// File name of the server settings in the app data directory
+pub const CONTROL_SETTINGS_FILE_NAME: &str = "control_server.json";
+
+/// File name of the bearer token in the app data directory
+pub const CONTROL_TOKEN_FILE_NAME: &str = "control_token";
+
+/// Port used when the settings do not name one
+pub const DEFAULT_CONTROL_PORT: u16 = 47821;
+
+fn default_port() -> u16 {
+    DEFAULT_CONTROL_PORT
+}
+
+/// Persisted control server settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ControlServerSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_port")]
+    pub port: u16,
+}
+
+impl Default for ControlServerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_CONTROL_PORT,
+        }
+    }
+}
+
+impl ControlServerSettings {
+    /// Load the settings from `app_data_dir`; missing or malformed files mean disabled
+    pub fn load(app_data_dir: &Path) -> Self {
+        settings_file::load(app_data_dir, CONTROL_SETTINGS_FILE_NAME)
+    }
+
+    /// Write the settings to `app_data_dir` atomically
+    pub async fn save(&self, app_data_dir: &Path) -> std::io::Result<()> {
+        settings_file::save(app_data_dir, CONTROL_SETTINGS_FILE_NAME, self).await
+    }
+}
+
+/// Read the bearer token from `app_data_dir`, generating it on first use
+///
+/// On Unix the token file is only readable by the current user.
+pub fn load_or_create_token(app_data_dir: &Path) -> std::io::Result<String> {
+    let path = app_data_dir.join(CONTROL_TOKEN_FILE_NAME);
+    match std::fs::read_to_string(&path) {
+        Ok(token) if !token.trim().is_empty() => return Ok(token.trim().to_string()),
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    let token = format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    );
+    std::fs::create_dir_all(app_data_dir)?;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    std::io::Write::write_all(&mut options.open(&path)?, token.as_bytes())?;
+    Ok(token)
+}
+
+/// Hands a prompt's output channel to whatever consumes it (the webview forwarder)
+pub type PromptForwarder =
+    Arc<dyn Fn(String, &PromptRecord, mpsc::Receiver<StreamMessage>) + Send + Sync>;
+
+/// Shared state of the request handlers
+#[derive(Clone)]
+struct ControlState {
+    manager: Arc<RwLock<ProcessManager>>,
+    token: Arc<str>,
+    forward: PromptForwarder,
+}
+
+/// A `SessionError` with an HTTP status
+struct ApiError(StatusCode, SessionError);
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self(
+            status,
+            SessionError {
+                message: message.into(),
+            },
+        )
+    }
+}
+
+impl From<ProcessError> for ApiError {
+    fn from(e: ProcessError) -> Self {
+        let status = match e {
+            ProcessError::SessionNotFound(_) => StatusCode::NOT_FOUND,
+            ProcessError::SessionBusy | ProcessError::SessionArchived(_) => StatusCode::CONFLICT,
+            ProcessError::PromptTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            ProcessError::SpawnFailed(_) | ProcessError::ArchiveFailed(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            _ => StatusCode::BAD_REQUEST,
+        };
+        Self(status, e.into())
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(e: JsonRejection) -> Self {
+        Self::new(e.status(), e.body_text())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(self.1)).into_response()
+    }
+}
+
+/// Response of `GET /health`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthResponse {
+    pub status: String,
+    pub version: String,
+    /// Number of live sessions
+    pub sessions: usize,
+}
+
+/// Body of `POST /sessions/{id}/prompt`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptRequest {
+    pub prompt: String,
+    #[serde(default)]
+    pub request_id: Option<String>,
+    #[serde(default)]
+    pub model_override: Option<String>,
+}
+
+/// Compare without returning early, so response times do not leak the token
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+async fn require_token(
+    AxumState(state): AxumState<ControlState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| constant_time_eq(token.trim().as_bytes(), state.token.as_bytes()));
+    if !authorized {
+        return ApiError::new(StatusCode::UNAUTHORIZED, "Missing or invalid bearer token")
+            .into_response();
+    }
+    next.run(request).await
+}
+
+async fn health(AxumState(state): AxumState<ControlState>) -> Json<HealthResponse> {
+    let sessions = state.manager.read().await.get_sessions(false).await.len();
+    Json(HealthResponse {
+        status: "ok".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        sessions,
+    })
+}
+
+async fn list_sessions(AxumState(state): AxumState<ControlState>) -> Json<Vec<SessionInfo>> {
+    Json(state.manager.read().await.get_sessions(false).await)
+}
+
+async fn create_session(
+    AxumState(state): AxumState<ControlState>,
+    config: Result<Json<SessionConfig>, JsonRejection>,
+) -> Result<Json<CreateSessionResult>, ApiError> {
+    let Json(config) = config?;
+    let manager = state.manager.read().await;
+    let session_id = manager.create_session(config).await?;
+    let sync_warning = manager
+        .get_session(&session_id)
+        .await
+        .and_then(|info| info.sync_warning);
+    Ok(Json(CreateSessionResult {
+        session_id,
+        sync_warning,
+    }))
+}
+
+async fn send_prompt(
+    AxumState(state): AxumState<ControlState>,
+    UrlPath(session_id): UrlPath<String>,
+    request: Result<Json<PromptRequest>, JsonRejection>,
+) -> Result<Json<SendPromptResult>, ApiError> {
+    let Json(request) = request?;
+    let (tx, rx) = mpsc::channel::<StreamMessage>(64);
+    let options = PromptOptions {
+        request_id: request.request_id,
+        model_override: request.model_override,
+        ..Default::default()
+    };
+    let record = state
+        .manager
+        .read()
+        .await
+        .send_prompt(&session_id, &request.prompt, options, tx)
+        .await?;
+    (state.forward)(session_id, &record, rx);
+
+    Ok(Json(SendPromptResult {
+        prompt_id: record.prompt_id,
+        model: record.model,
+    }))
+}
+
+async fn interrupt(
+    AxumState(state): AxumState<ControlState>,
+    UrlPath(session_id): UrlPath<String>,
+) -> Result<StatusCode, ApiError> {
+    state.manager.read().await.interrupt(&session_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn router(state: ControlState) -> Router {
+    Router::new()
+        .route("/health", get(health))
+        .route("/sessions", get(list_sessions).post(create_session))
+        .route("/sessions/{id}/prompt", post(send_prompt))
+        .route("/sessions/{id}/interrupt", post(interrupt))
+        .layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state)
+}
+
+/// A running server
+struct RunningServer {
+    addr: SocketAddr,
+    shutdown: oneshot::Sender<()>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+/// The control server, stopped until `start` is called
+#[derive(Default)]
+pub struct ControlServer {
+    running: Option<RunningServer>,
+}
+
+impl ControlServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Address the server listens on, if it is running
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.running.as_ref().map(|running| running.addr)
+    }
+
+    /// Listen on 127.0.0.1:`port` (0 picks a free port), replacing a running server
+    pub async fn start(
+        &mut self,
+        port: u16,
+        token: &str,
+        manager: Arc<RwLock<ProcessManager>>,
+        forward: PromptForwarder,
+    ) -> std::io::Result<SocketAddr> {
+        self.stop().await;
+
+        let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await?;
+        let addr = listener.local_addr()?;
+        let app = router(ControlState {
+            manager,
+            token: Arc::from(token),
+            forward,
+        });
+        let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+        let task = tauri::async_runtime::spawn(async move {
+            let serve = axum::serve(listener, app).with_graceful_shutdown(async {
+                let _ = shutdown_rx.await;
+            });
+            if let Err(e) = serve.await {
+                log::error!("Control server failed: {}", e);
+            }
+        });
+
+        log::info!("Control server listening on {}", addr);
+        self.running = Some(RunningServer {
+            addr,
+            shutdown,
+            task,
+        });
+        Ok(addr)
+    }
+
+    /// Stop the server and wait until the port is released
+    pub async fn stop(&mut self) {
+        if let Some(running) = self.running.take() {
+            let _ = running.shutdown.send(());
+            // In-flight requests finish first; a stuck client must not block the toggle
+            let abort = running.task.abort_handle();
+            if tokio::time::timeout(std::time::Duration::from_secs(2), running.task)
+                .await
+                .is_err()
+            {
+                abort.abort();
+            }
+            log::info!("Control server on {} stopped", running.addr);
+        }
+    }
+}
+
+/// State of the control server, as shown in the settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControlServerStatus {
+    pub enabled: bool,
+    pub port: u16,
+    /// Address the server is listening on; None when stopped
+    pub address: Option<String>,
+    /// File holding the bearer token
+    pub token_path: PathBuf,
+}
+
+/// Start or stop the server in `state` to match `settings`
+///
+/// Prompts sent over HTTP stream to the webview like any other prompt.
+pub async fn apply_control_settings(
+    app: &AppHandle,
+    state: &AppState,
+    app_data_dir: &Path,
+    settings: &ControlServerSettings,
+) -> Result<ControlServerStatus, String> {
+    let mut server = state.control_server.lock().await;
+    if settings.enabled {
+        let token = load_or_create_token(app_data_dir)
+            .map_err(|e| format!("Failed to load control token: {}", e))?;
+        let forward_app = app.clone();
+        let forward: PromptForwarder = Arc::new(move |session_id, record, rx| {
+            forward_cli_messages(forward_app.clone(), session_id, record, rx)
+        });
+        server
+            .start(
+                settings.port,
+                &token,
+                state.process_manager.clone(),
+                forward,
+            )
+            .await
+            .map_err(|e| format!("Failed to start control server: {}", e))?;
+    } else {
+        server.stop().await;
+    }
+
+    Ok(ControlServerStatus {
+        enabled: settings.enabled,
+        port: settings.port,
+        address: server.address().map(|addr| addr.to_string()),
+        token_path: app_data_dir.join(CONTROL_TOKEN_FILE_NAME),
+    })
+}
+
+fn app_data_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {}", e))
+}
+
+/// Get the control server settings and whether it is running
+#[tauri::command]
+pub async fn get_control_server_status(
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<ControlServerStatus, String> {
+    let dir = app_data_dir(&app)?;
+    let settings = ControlServerSettings::load(&dir);
+    let server = state.control_server.lock().await;
+    Ok(ControlServerStatus {
+        enabled: settings.enabled,
+        port: settings.port,
+        address: server.address().map(|addr| addr.to_string()),
+        token_path: dir.join(CONTROL_TOKEN_FILE_NAME),
+    })
+}
+
+/// Enable or disable the control server (and optionally change its port)
+///
+/// Takes effect immediately and is remembered across restarts.
+#[tauri::command]
+pub async fn set_control_server(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    enabled: bool,
+    port: Option<u16>,
+) -> Result<ControlServerStatus, String> {
+    let dir = app_data_dir(&app)?;
+    let mut settings = ControlServerSettings::load(&dir);
+    settings.enabled = enabled;
+    if let Some(port) = port {
+        settings.port = port;
+    }
+    let status = apply_control_settings(&app, &state, &dir, &settings).await?;
+    if let Err(e) = settings.save(&dir).await {
+        report_if_storage_full(&state, &dir.join(CONTROL_SETTINGS_FILE_NAME), &e).await;
+        return Err(format!("Failed to save control server settings: {}", e));
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    const TOKEN: &str = "test-token";
+
+    /// A manager whose CLI prints one result message per prompt
+    #[cfg(unix)]
+    fn fake_manager(dir: &Path) -> Arc<RwLock<ProcessManager>> {
+        use crate::services::test_support::write_fake_cli;
+
+        let cli = write_fake_cli(dir, "echo '{\"type\":\"result\",\"subtype\":\"success\"}'");
+        Arc::new(RwLock::new(ProcessManager::with_cli_path(cli)))
+    }
+
+    /// Drains prompt output like the webview forwarder would
+    fn draining_forwarder() -> PromptForwarder {
+        Arc::new(|_, _, mut rx| {
+            tokio::spawn(async move { while rx.recv().await.is_some() {} });
+        })
+    }
+
+    /// A client that ignores proxy settings from the environment
+    fn client() -> reqwest::Client {
+        reqwest::Client::builder().no_proxy().build().unwrap()
+    }
+
+    async fn start_server(manager: Arc<RwLock<ProcessManager>>) -> (ControlServer, String) {
+        let mut server = ControlServer::new();
+        let addr = server
+            .start(0, TOKEN, manager, draining_forwarder())
+            .await
+            .unwrap();
+        (server, format!("http://{}", addr))
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_api_requires_token() {
+        let dir = TempDir::new().unwrap();
+        let (mut server, base) = start_server(fake_manager(dir.path())).await;
+        let client = client();
+
+        for auth in [None, Some("Bearer wrong"), Some(TOKEN)] {
+            let mut request = client.get(format!("{}/health", base));
+            if let Some(auth) = auth {
+                request = request.header("Authorization", auth);
+            }
+            let response = request.send().await.unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+            let error: SessionError = response.json().await.unwrap();
+            assert!(error.message.contains("bearer token"));
+        }
+
+        let health: HealthResponse = client
+            .get(format!("{}/health", base))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(health.status, "ok");
+        assert_eq!(health.sessions, 0);
+        server.stop().await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_session_lifecycle_over_http() {
+        let dir = TempDir::new().unwrap();
+        let manager = fake_manager(dir.path());
+        let (mut server, base) = start_server(manager.clone()).await;
+        let client = client();
+
+        let created: CreateSessionResult = client
+            .post(format!("{}/sessions", base))
+            .bearer_auth(TOKEN)
+            .json(&json!({ "working_dir": dir.path(), "model": "sonnet" }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let sessions: Vec<SessionInfo> = client
+            .get(format!("{}/sessions", base))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id, created.session_id);
+
+        let sent: SendPromptResult = client
+            .post(format!("{}/sessions/{}/prompt", base, created.session_id))
+            .bearer_auth(TOKEN)
+            .json(&json!({ "prompt": "hello" }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(sent.model, "sonnet");
+        let history = manager
+            .read()
+            .await
+            .get_prompt_history(&created.session_id)
+            .await
+            .unwrap();
+        assert_eq!(history[0].prompt_id, sent.prompt_id);
+
+        let response = client
+            .post(format!(
+                "{}/sessions/{}/interrupt",
+                base, created.session_id
+            ))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+
+        // Errors keep the SessionError shape
+        let response = client
+            .post(format!("{}/sessions/missing/prompt", base))
+            .bearer_auth(TOKEN)
+            .json(&json!({ "prompt": "hello" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        let error: SessionError = response.json().await.unwrap();
+        assert!(error.message.contains("missing"));
+
+        let response = client
+            .post(format!("{}/sessions", base))
+            .bearer_auth(TOKEN)
+            .header("Content-Type", "application/json")
+            .body("{")
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_client_error());
+        let _: SessionError = response.json().await.unwrap();
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_server_restarts_without_app_restart() {
+        let manager = Arc::new(RwLock::new(ProcessManager::new()));
+        let (mut server, base) = start_server(manager.clone()).await;
+        server.stop().await;
+        assert_eq!(server.address(), None);
+        assert!(client()
+            .get(format!("{}/health", base))
+            .send()
+            .await
+            .is_err());
+
+        let addr = server
+            .start(0, TOKEN, manager, draining_forwarder())
+            .await
+            .unwrap();
+        let response = client()
+            .get(format!("http://{}/health", addr))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_settings_and_token_persist() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(
+            ControlServerSettings::load(dir.path()),
+            ControlServerSettings::default()
+        );
+        let settings = ControlServerSettings {
+            enabled: true,
+            port: 5000,
+        };
+        settings.save(dir.path()).await.unwrap();
+        assert_eq!(ControlServerSettings::load(dir.path()), settings);
+
+        let token = load_or_create_token(dir.path()).unwrap();
+        assert_eq!(token.len(), 64);
+        assert_eq!(load_or_create_token(dir.path()).unwrap(), token);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.path().join(CONTROL_TOKEN_FILE_NAME))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
@@ -3,11 +3,26 @@
 //! This module provides Tauri commands for file operations including
 //! atomic writes for the Edit Arbiter system.
 
+use ignore::overrides::OverrideBuilder;
+use ignore::{WalkBuilder, WalkState};
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
-use std::path::Path;
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::Ordering;
+use tauri::{AppHandle, Emitter, Manager, State};
 use thiserror::Error;
 use tokio::fs;
+use tokio::sync::broadcast;
+
+use super::session::AppState;
+use crate::services::file_versions::{self, FileVersion};
+use crate::services::ignore_rules::{IgnoreRules, ALWAYS_IGNORED_DIRS};
+use crate::services::memory::{is_memory_file, load_memory_chain, MemoryFile};
+use crate::services::operations::CancellationToken;
+use crate::services::paths::{decode_path, encode_path, path_from_output};
+use crate::services::settings_file;
+use crate::services::storage::{self, StorageCritical};
+use crate::services::{ProcessError, ProcessManager, WatchEvent, WatcherConfig, WatcherStats};
 
 /// Errors that can occur during file operations
 #[derive(Error, Debug, Serialize)]
@@ -20,6 +35,16 @@ pub enum FileError {
     IoError(String),
     #[error("File was modified externally")]
     ConflictDetected,
+    #[error("Not a CLAUDE.md memory file: {0}")]
+    NotMemoryFile(String),
+    #[error("Operation was cancelled")]
+    Cancelled,
+    #[error("Disk is full: {0}")]
+    StorageFull(String),
+    #[error("File already exists: {0}")]
+    AlreadyExists(String),
+    #[error("Session is read-only: {0}")]
+    ReadOnlySession(String),
 }
 
 impl From<std::io::Error> for FileError {
@@ -27,11 +52,18 @@ impl From<std::io::Error> for FileError {
         match e.kind() {
             std::io::ErrorKind::NotFound => FileError::NotFound(e.to_string()),
             std::io::ErrorKind::PermissionDenied => FileError::PermissionDenied(e.to_string()),
+            _ if storage::is_out_of_space(&e) => FileError::StorageFull(e.to_string()),
             _ => FileError::IoError(e.to_string()),
         }
     }
 }
 
+impl From<notify::Error> for FileError {
+    fn from(e: notify::Error) -> Self {
+        FileError::IoError(e.to_string())
+    }
+}
+
 /// Result of a file read operation
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FileReadResult {
@@ -39,11 +71,43 @@ pub struct FileReadResult {
     pub hash: String,
 }
 
+/// What an edit does to its file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EditKind {
+    /// Replace the content of an existing file
+    #[default]
+    Modify,
+    /// Write a file that must not exist yet
+    Create,
+    /// Remove a file
+    Delete,
+}
+
+/// One edit of an `apply_edits` batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileEdit {
+    pub path: String,
+    #[serde(default)]
+    pub kind: EditKind,
+    /// The content the edit was based on
+    #[serde(default)]
+    pub original_content: String,
+    /// Hash of the content the edit was based on; checked instead of `original_content`
+    #[serde(default)]
+    pub expected_hash: Option<String>,
+    /// Ignored for deletions
+    #[serde(default)]
+    pub proposed_content: String,
+}
+
 /// Result of applying an edit
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ApplyResult {
     Success,
+    /// The file was removed by a `Delete` edit
+    Deleted,
     Conflict {
         current_content: String,
         base_content: String,
@@ -56,158 +120,879 @@ pub enum ApplyResult {
 
 /// Compute SHA256 hash of content
 pub fn compute_hash(content: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(content.as_bytes());
-    hex::encode(hasher.finalize())
+    file_versions::content_hash(content)
 }
 
 /// Read a file and return its content with hash
 #[tauri::command]
 pub async fn read_file(path: &str) -> Result<FileReadResult, FileError> {
-    let content = fs::read_to_string(path).await?;
+    let content = fs::read_to_string(decode_path(path)).await?;
     let hash = compute_hash(&content);
     Ok(FileReadResult { content, hash })
 }
 
+/// Refuse a file change made on behalf of a read-only session
+///
+/// Changes without a session id (the app's own persistence) are not restricted.
+pub async fn ensure_writable(
+    manager: &ProcessManager,
+    session_id: Option<&str>,
+) -> Result<(), FileError> {
+    match session_id {
+        Some(id) if manager.is_read_only(id).await => {
+            Err(FileError::ReadOnlySession(id.to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
 /// Write a file atomically (write to temp, then rename)
+///
+/// The app's persistence (history, usage, settings) goes through this command.
+/// When the disk is full it fails with `StorageFull` and a single
+/// `storage-critical` event is emitted, so the frontend can stop persisting
+/// and keep its in-memory state.
 #[tauri::command]
-pub async fn write_file_atomic(path: &str, content: &str) -> Result<(), FileError> {
-    let path = Path::new(path);
+pub async fn write_file_atomic(
+    state: State<'_, AppState>,
+    path: &str,
+    content: &str,
+    session_id: Option<String>,
+) -> Result<(), FileError> {
+    let manager = state.process_manager.read().await;
+    ensure_writable(&manager, session_id.as_deref()).await?;
+    drop(manager);
 
-    // Create parent directories if they don't exist
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).await?;
+    let path = decode_path(path);
+    let result = storage::write_atomic(&path, content.as_bytes()).await;
+    if let Err(ref e) = result {
+        report_if_storage_full(&state, &path, e).await;
     }
+    result.map_err(FileError::from)
+}
 
-    // Write to a temporary file first
-    let temp_path = path.with_extension("tmp");
-    fs::write(&temp_path, content).await?;
-
-    // Rename to the target path (atomic on most filesystems)
-    fs::rename(&temp_path, path).await?;
+/// Write a file atomically (write to temp, then rename)
+pub async fn write_atomic(path: &str, content: &str) -> Result<(), FileError> {
+    Ok(storage::write_atomic(&decode_path(path), content.as_bytes()).await?)
+}
 
-    Ok(())
+/// Emit `storage-critical` if writing one of the app's own files failed for lack of space
+///
+/// Shared by `write_file_atomic` and the settings commands, so a full disk is
+/// reported once whichever file hit it.
+pub async fn report_if_storage_full(state: &AppState, path: &Path, e: &std::io::Error) {
+    if storage::is_out_of_space(e) {
+        state
+            .process_manager
+            .read()
+            .await
+            .report_storage_critical(StorageCritical::new(path, e));
+    }
 }
 
 /// Check if a file has been modified since we last read it
 #[tauri::command]
 pub async fn check_file_modified(path: &str, expected_hash: &str) -> Result<bool, FileError> {
-    let content = fs::read_to_string(path).await?;
+    let content = fs::read_to_string(decode_path(path)).await?;
     let current_hash = compute_hash(&content);
     Ok(current_hash != expected_hash)
 }
 
+/// An edit that passed its conflict check, with what it replaces
+struct CheckedEdit<'a> {
+    edit: &'a FileEdit,
+    /// Content before the edit; None if the file does not exist
+    previous: Option<String>,
+}
+
+impl CheckedEdit<'_> {
+    fn applied(self) -> AppliedEdit {
+        AppliedEdit {
+            path: self.edit.path.clone(),
+            before: self.previous,
+            after: (self.edit.kind != EditKind::Delete).then(|| self.edit.proposed_content.clone()),
+        }
+    }
+}
+
+/// What an applied edit replaced and wrote; None for a missing file
+struct AppliedEdit {
+    path: String,
+    before: Option<String>,
+    after: Option<String>,
+}
+
+/// Check `edit` against its file's current content
+///
+/// `Modify` and `Delete` conflict when the file is missing or its content
+/// (or hash, if given) differs from what the edit was based on; a `Modify`
+/// based on empty content with no hash is not checked. `Create` fails with
+/// `AlreadyExists` if the file is present.
+async fn check_edit(edit: &FileEdit) -> Result<Result<CheckedEdit<'_>, ApplyResult>, FileError> {
+    let current = match fs::read_to_string(decode_path(&edit.path)).await {
+        Ok(content) => Some(content),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    let Some(current) = current else {
+        if edit.kind == EditKind::Create {
+            return Ok(Ok(CheckedEdit {
+                edit,
+                previous: None,
+            }));
+        }
+        return Ok(Err(conflict(edit, String::new())));
+    };
+
+    let unchecked = edit.kind == EditKind::Modify
+        && edit.original_content.is_empty()
+        && edit.expected_hash.is_none();
+    let expected = match edit.expected_hash {
+        Some(ref hash) => compute_hash(&current) == *hash,
+        None => current == edit.original_content,
+    };
+    match edit.kind {
+        EditKind::Create => Err(FileError::AlreadyExists(edit.path.clone())),
+        _ if unchecked || expected => Ok(Ok(CheckedEdit {
+            edit,
+            previous: Some(current),
+        })),
+        _ => Ok(Err(conflict(edit, current))),
+    }
+}
+
+fn conflict(edit: &FileEdit, current_content: String) -> ApplyResult {
+    ApplyResult::Conflict {
+        current_content,
+        base_content: edit.original_content.clone(),
+        proposed_content: edit.proposed_content.clone(),
+    }
+}
+
+/// Write (or remove) the file of a checked edit
+async fn write_edit(edit: &FileEdit) -> Result<ApplyResult, FileError> {
+    match edit.kind {
+        EditKind::Delete => {
+            fs::remove_file(decode_path(&edit.path)).await?;
+            Ok(ApplyResult::Deleted)
+        }
+        EditKind::Modify | EditKind::Create => {
+            write_atomic(&edit.path, &edit.proposed_content).await?;
+            Ok(ApplyResult::Success)
+        }
+    }
+}
+
+/// Put a file back the way it was before a checked edit
+async fn undo_edit(checked: &CheckedEdit<'_>) -> Result<(), FileError> {
+    match checked.previous {
+        Some(ref previous) => write_atomic(&checked.edit.path, previous).await,
+        None => Ok(fs::remove_file(decode_path(&checked.edit.path)).await?),
+    }
+}
+
+/// Keep what `applied` replaced and wrote as versions of the session's files
+///
+/// Each write is attributed to the prompt that last touched the file.
+async fn keep_versions(
+    manager: &ProcessManager,
+    session_id: Option<&str>,
+    applied: &[AppliedEdit],
+) {
+    let Some(session_id) = session_id else {
+        return;
+    };
+    for edit in applied {
+        let prompt_id = manager.prompt_touching(session_id, &edit.path).await;
+        let kept = manager
+            .record_file_edit(
+                session_id,
+                &edit.path,
+                edit.before.as_deref(),
+                edit.after.as_deref(),
+                prompt_id.as_deref(),
+            )
+            .await;
+        if let Err(e) = kept {
+            log::warn!("Failed to keep versions of {}: {}", edit.path, e);
+        }
+    }
+}
+
 /// Apply an edit with conflict detection
+///
+/// `kind` defaults to `Modify`. `expected_hash`, if given, is checked instead
+/// of `original_content`. Refused if `session_id` names a read-only session;
+/// otherwise the replaced and written contents are kept for
+/// `get_file_versions`.
 #[tauri::command]
 pub async fn apply_edit(
+    state: State<'_, AppState>,
+    path: &str,
+    original_content: &str,
+    proposed_content: &str,
+    kind: Option<EditKind>,
+    expected_hash: Option<String>,
+    session_id: Option<String>,
+) -> Result<ApplyResult, FileError> {
+    let edit = FileEdit {
+        path: path.to_string(),
+        kind: kind.unwrap_or_default(),
+        original_content: original_content.to_string(),
+        expected_hash,
+        proposed_content: proposed_content.to_string(),
+    };
+    let manager = state.process_manager.read().await;
+    apply_session_edit(&manager, session_id.as_deref(), edit).await
+}
+
+/// [`apply_edit`] on behalf of `session_id`
+pub async fn apply_session_edit(
+    manager: &ProcessManager,
+    session_id: Option<&str>,
+    edit: FileEdit,
+) -> Result<ApplyResult, FileError> {
+    ensure_writable(manager, session_id).await?;
+    let (result, applied) = apply_one(&edit).await?;
+    keep_versions(manager, session_id, applied.as_slice()).await;
+    Ok(result)
+}
+
+/// [`apply_edit`] without the read-only check
+pub async fn apply_file_edit(
     path: &str,
     original_content: &str,
     proposed_content: &str,
+    kind: Option<EditKind>,
+    expected_hash: Option<String>,
+) -> Result<ApplyResult, FileError> {
+    let edit = FileEdit {
+        path: path.to_string(),
+        kind: kind.unwrap_or_default(),
+        original_content: original_content.to_string(),
+        expected_hash,
+        proposed_content: proposed_content.to_string(),
+    };
+    Ok(apply_one(&edit).await?.0)
+}
+
+/// Check and apply `edit`, returning what it replaced if it was applied
+async fn apply_one(edit: &FileEdit) -> Result<(ApplyResult, Option<AppliedEdit>), FileError> {
+    match check_edit(edit).await? {
+        Ok(checked) => {
+            let result = write_edit(edit).await?;
+            Ok((result, Some(checked.applied())))
+        }
+        Err(conflict) => Ok((conflict, None)),
+    }
+}
+
+/// Apply several edits, of any kinds, all or nothing
+///
+/// Every edit is checked before any file is touched; if one conflicts or
+/// fails its check, nothing is written and the results report which (the
+/// others get an `Error` saying they were not applied). If a write fails
+/// part way, the edits already applied are undone and the error is returned.
+/// Refused if `session_id` names a read-only session; otherwise versions are
+/// kept as for `apply_edit`.
+#[tauri::command]
+pub async fn apply_edits(
+    state: State<'_, AppState>,
+    edits: Vec<FileEdit>,
+    session_id: Option<String>,
+) -> Result<Vec<ApplyResult>, FileError> {
+    let manager = state.process_manager.read().await;
+    ensure_writable(&manager, session_id.as_deref()).await?;
+    let (results, applied) = apply_all(&edits).await?;
+    keep_versions(&manager, session_id.as_deref(), &applied).await;
+    Ok(results)
+}
+
+/// [`apply_edits`] without the read-only check
+pub async fn apply_file_edits(edits: Vec<FileEdit>) -> Result<Vec<ApplyResult>, FileError> {
+    Ok(apply_all(&edits).await?.0)
+}
+
+/// Check and apply `edits` all or nothing, returning what they replaced if applied
+async fn apply_all(edits: &[FileEdit]) -> Result<(Vec<ApplyResult>, Vec<AppliedEdit>), FileError> {
+    let mut checked = Vec::with_capacity(edits.len());
+    let mut failures = Vec::new();
+    for (index, edit) in edits.iter().enumerate() {
+        match check_edit(edit).await {
+            Ok(Ok(edit)) => checked.push(edit),
+            Ok(Err(conflict)) => failures.push((index, conflict)),
+            Err(e) => failures.push((
+                index,
+                ApplyResult::Error {
+                    message: e.to_string(),
+                },
+            )),
+        }
+    }
+    if !failures.is_empty() {
+        let mut results: Vec<ApplyResult> = (0..edits.len())
+            .map(|_| ApplyResult::Error {
+                message: "Not applied: another edit in the batch failed its check".to_string(),
+            })
+            .collect();
+        for (index, failure) in failures {
+            results[index] = failure;
+        }
+        return Ok((results, Vec::new()));
+    }
+
+    let mut results = Vec::with_capacity(checked.len());
+    for (index, edit) in checked.iter().enumerate() {
+        match write_edit(edit.edit).await {
+            Ok(result) => results.push(result),
+            Err(e) => {
+                for applied in checked[..index].iter().rev() {
+                    if let Err(undo) = undo_edit(applied).await {
+                        log::error!("Failed to undo edit of {}: {}", applied.edit.path, undo);
+                    }
+                }
+                return Err(e);
+            }
+        }
+    }
+    let applied = checked.into_iter().map(CheckedEdit::applied).collect();
+    Ok((results, applied))
+}
+
+/// The versions of a file kept for a session, oldest first
+///
+/// Lists the content each edit applied for the session replaced and wrote,
+/// once per distinct content, with the prompt that wrote it; the file's
+/// current content is flagged `current`.
+#[tauri::command]
+pub async fn get_file_versions(
+    state: State<'_, AppState>,
+    session_id: String,
+    path: String,
+) -> Result<Vec<FileVersion>, FileError> {
+    let manager = state.process_manager.read().await;
+    manager
+        .file_versions(&session_id, &path)
+        .await
+        .map_err(version_error)
+}
+
+/// Put a file back to a version listed by `get_file_versions`
+///
+/// Written like an edit: it conflicts if the file no longer has the content
+/// hashed by `expected_hash` (when given), and the replaced content is kept
+/// as a version.
+#[tauri::command]
+pub async fn restore_file_version(
+    state: State<'_, AppState>,
+    session_id: String,
+    path: String,
+    version_id: String,
+    expected_hash: Option<String>,
 ) -> Result<ApplyResult, FileError> {
-    // Read current file content
-    let current_content = match fs::read_to_string(path).await {
-        Ok(content) => content,
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-            // File doesn't exist - this is OK for new files
-            String::new()
+    let manager = state.process_manager.read().await;
+    restore_version(&manager, &session_id, &path, &version_id, expected_hash).await
+}
+
+fn version_error(e: ProcessError) -> FileError {
+    match e {
+        ProcessError::SessionNotFound(_) | ProcessError::FileVersionNotFound(_) => {
+            FileError::NotFound(e.to_string())
         }
+        e => FileError::IoError(e.to_string()),
+    }
+}
+
+/// [`restore_file_version`] with the process manager
+pub async fn restore_version(
+    manager: &ProcessManager,
+    session_id: &str,
+    path: &str,
+    version_id: &str,
+    expected_hash: Option<String>,
+) -> Result<ApplyResult, FileError> {
+    let content = manager
+        .file_version_content(session_id, path, version_id)
+        .await
+        .map_err(version_error)?;
+    let current = match fs::read_to_string(decode_path(path)).await {
+        Ok(current) => Some(current),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
         Err(e) => return Err(e.into()),
     };
+    let edit = FileEdit {
+        path: path.to_string(),
+        kind: match current {
+            Some(_) => EditKind::Modify,
+            None => EditKind::Create,
+        },
+        expected_hash: expected_hash.or_else(|| current.as_deref().map(compute_hash)),
+        original_content: current.unwrap_or_default(),
+        proposed_content: content,
+    };
+    apply_session_edit(manager, Some(session_id), edit).await
+}
+
+/// Depth of `list_dir_tree` when none is given
+const DEFAULT_TREE_DEPTH: u32 = 3;
+
+/// Hidden entries and build directories, which the fallback walkers never descend into
+fn is_skipped(name: &OsStr) -> bool {
+    name.as_encoded_bytes().starts_with(b".") || is_always_ignored(name)
+}
+
+/// Whether `name` is one of the build directories never listed
+fn is_always_ignored(name: &OsStr) -> bool {
+    ALWAYS_IGNORED_DIRS
+        .iter()
+        .any(|dir| name == OsStr::new(dir))
+}
+
+/// Run a command to completion, killing it if the operation is cancelled first
+async fn run_cancellable(
+    command: &mut tokio::process::Command,
+    token: &CancellationToken,
+) -> Result<std::process::Output, FileError> {
+    let child = command
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    tokio::select! {
+        output = child.wait_with_output() => Ok(output?),
+        // Dropping the wait future kills the child (kill_on_drop)
+        _ = token.cancelled() => Err(FileError::Cancelled),
+    }
+}
+
+/// All files under `dir`, skipping hidden entries, build directories and
+/// anything matched by the root's `.gitignore`
+///
+/// Checks `token` before each directory entry so a cancelled walk stops promptly.
+async fn walk_files(dir: &Path, token: &CancellationToken) -> Result<Vec<PathBuf>, FileError> {
+    let rules = IgnoreRules::load(dir);
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if token.is_cancelled() {
+                return Err(FileError::Cancelled);
+            }
+            let file_name = entry.file_name();
+            if is_skipped(&file_name) {
+                continue;
+            }
+            let path = entry.path();
+            let is_dir = entry.file_type().await?.is_dir();
+            if rules.is_ignored(&path, is_dir) {
+                continue;
+            }
+            if is_dir {
+                pending.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+
+    Ok(files)
+}
 
-    // Check for external modification
-    if !original_content.is_empty() && current_content != original_content {
-        return Ok(ApplyResult::Conflict {
-            current_content,
-            base_content: original_content.to_string(),
-            proposed_content: proposed_content.to_string(),
+/// Put a file listing in its final form, whichever lister produced it
+///
+/// Paths are made relative to the listed directory with `/` separators and
+/// no `./` prefix, filtered by `pattern` (a gitignore-style glob: without a
+/// `/` it matches file names at any depth), stripped of files inside
+/// always-ignored directories and sorted by path, so ripgrep and the built-in
+/// walker agree exactly. A path that is not valid Unicode is returned in the
+/// form `encode_path` gives it, so it can be passed back to `read_file`.
+fn normalize_listing(
+    pattern: &str,
+    paths: impl IntoIterator<Item = PathBuf>,
+) -> Result<Vec<String>, FileError> {
+    let mut glob = OverrideBuilder::new("");
+    let glob = glob
+        .add(pattern)
+        .and_then(|builder| builder.build())
+        .map_err(|e| FileError::IoError(format!("Invalid pattern {}: {}", pattern, e)))?;
+    let mut files: Vec<String> = paths
+        .into_iter()
+        .map(|path| {
+            path.components()
+                .filter_map(|component| match component {
+                    Component::Normal(name) => Some(name.to_os_string()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        })
+        .filter(|components| !components.iter().any(|name| is_always_ignored(name)))
+        .map(|components| components.iter().collect::<PathBuf>())
+        .filter(|path| glob.matched(path, false).is_whitelist())
+        .map(|path| match path.to_str() {
+            Some(relative) => relative.replace(std::path::MAIN_SEPARATOR, "/"),
+            None => encode_path(&path),
+        })
+        .collect();
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// Files under `dir`, as relative paths, via the `ignore` crate's parallel walker
+///
+/// Uses the same rules as `rg --files --no-require-git`: hidden entries are
+/// skipped and `.gitignore`, `.ignore` and git exclude files are honored at
+/// every level, in or out of a git repository.
+async fn walk_listing(dir: &str, token: &CancellationToken) -> Result<Vec<PathBuf>, FileError> {
+    let root = decode_path(dir);
+    if !root.is_dir() {
+        return Err(FileError::NotFound(dir.to_string()));
+    }
+    let mut walker = WalkBuilder::new(&root);
+    walker.require_git(false);
+
+    let token = token.clone();
+    tokio::task::spawn_blocking(move || {
+        let (tx, rx) = std::sync::mpsc::channel();
+        walker.build_parallel().run(|| {
+            let tx = tx.clone();
+            let token = token.clone();
+            let root = root.clone();
+            Box::new(move |entry| {
+                if token.is_cancelled() {
+                    return WalkState::Quit;
+                }
+                // Unreadable entries are skipped, as ripgrep does
+                let Ok(entry) = entry else {
+                    return WalkState::Continue;
+                };
+                if entry
+                    .file_type()
+                    .is_some_and(|file_type| file_type.is_file())
+                {
+                    if let Ok(relative) = entry.path().strip_prefix(&root) {
+                        let _ = tx.send(relative.to_path_buf());
+                    }
+                }
+                WalkState::Continue
+            })
         });
+        drop(tx);
+        if token.is_cancelled() {
+            return Err(FileError::Cancelled);
+        }
+        Ok(rx.into_iter().collect())
+    })
+    .await
+    .map_err(|e| FileError::IoError(e.to_string()))?
+}
+
+/// Files under `dir`, as relative paths, via `rg --files`
+///
+/// The pattern is applied afterwards: `--glob` would override the ignore
+/// rules, listing gitignored and hidden files.
+async fn ripgrep_listing(dir: &str, token: &CancellationToken) -> Result<Vec<PathBuf>, FileError> {
+    let mut command = tokio::process::Command::new("rg");
+    command
+        .args(["--files", "--no-require-git", "--null"])
+        .current_dir(decode_path(dir));
+
+    let output = run_cancellable(&mut command, token).await?;
+    // Exit status 1 means no files matched
+    if !output.status.success() && output.status.code() != Some(1) {
+        return Err(FileError::IoError(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
     }
+    Ok(output
+        .stdout
+        .split(|&byte| byte == 0)
+        .filter(|path| !path.is_empty())
+        .map(path_from_output)
+        .collect())
+}
+
+/// List files matching `pattern` under `dir`
+///
+/// The built-in walker is used unless `use_ripgrep` is set, in which case
+/// ripgrep is tried first and the walker is the fallback. Both give the same
+/// output.
+async fn find_files(
+    dir: &str,
+    pattern: &str,
+    use_ripgrep: bool,
+    token: &CancellationToken,
+) -> Result<Vec<String>, FileError> {
+    if use_ripgrep {
+        match ripgrep_listing(dir, token).await {
+            Ok(files) => return normalize_listing(pattern, files),
+            Err(FileError::Cancelled) => return Err(FileError::Cancelled),
+            Err(e) => log::debug!("ripgrep listing failed, using the built-in walker: {}", e),
+        }
+    }
+    normalize_listing(pattern, walk_listing(dir, token).await?)
+}
 
-    // Apply the edit atomically
-    write_file_atomic(path, proposed_content).await?;
+/// File name of the file listing settings in the app data directory
+pub const LISTING_SETTINGS_FILE_NAME: &str = "file_listing.json";
 
-    Ok(ApplyResult::Success)
+/// Persisted `list_files` settings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListingSettings {
+    /// Try ripgrep before the built-in walker
+    #[serde(default)]
+    pub use_ripgrep: bool,
 }
 
-/// List files matching a glob pattern
+impl ListingSettings {
+    /// Load the settings from `app_data_dir`; missing or malformed files mean the walker
+    pub fn load(app_data_dir: &Path) -> Self {
+        settings_file::load(app_data_dir, LISTING_SETTINGS_FILE_NAME)
+    }
+
+    /// Write the settings to `app_data_dir` atomically
+    pub async fn save(&self, app_data_dir: &Path) -> std::io::Result<()> {
+        settings_file::save(app_data_dir, LISTING_SETTINGS_FILE_NAME, self).await
+    }
+}
+
+/// List files matching a glob pattern, as paths relative to `dir` sorted by path
+///
+/// Pass an `operation_id` to be able to stop the listing with `cancel_operation`.
 #[tauri::command]
-pub async fn list_files(dir: &str, pattern: &str) -> Result<Vec<String>, FileError> {
-    use std::process::Command;
-
-    // Use ripgrep for fast file listing that respects .gitignore
-    let output = Command::new("rg")
-        .args(["--files", "--glob", pattern])
-        .current_dir(dir)
-        .output();
-
-    match output {
-        Ok(output) if output.status.success() => {
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            let files: Vec<String> = stdout
-                .lines()
-                .map(|s| s.to_string())
-                .collect();
-            Ok(files)
-        }
+pub async fn list_files(
+    state: State<'_, AppState>,
+    dir: &str,
+    pattern: &str,
+    operation_id: Option<String>,
+) -> Result<Vec<String>, FileError> {
+    let operation = state.operations.register(operation_id);
+    let use_ripgrep = state.ripgrep_listing.load(Ordering::Relaxed);
+    find_files(dir, pattern, use_ripgrep, operation.token()).await
+}
+
+/// Let `list_files` use ripgrep, when installed, instead of the built-in walker
+///
+/// Takes effect immediately and is remembered across restarts.
+#[tauri::command]
+pub async fn set_ripgrep_listing(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    enabled: bool,
+) -> Result<(), FileError> {
+    state.ripgrep_listing.store(enabled, Ordering::Relaxed);
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| FileError::IoError(format!("Failed to get app data dir: {}", e)))?;
+    let settings = ListingSettings {
+        use_ripgrep: enabled,
+    };
+    if let Err(e) = settings.save(&dir).await {
+        report_if_storage_full(&state, &dir.join(LISTING_SETTINGS_FILE_NAME), &e).await;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// A line matching a content search
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchMatch {
+    pub path: String,
+    pub line_number: u64,
+    pub line: String,
+}
+
+/// Parse ripgrep `--null --line-number --no-heading` output (`path\0line:text`)
+fn parse_rg_matches(stdout: &[u8]) -> Vec<SearchMatch> {
+    stdout
+        .split(|&byte| byte == b'\n')
+        .filter_map(|line| {
+            let nul = line.iter().position(|&byte| byte == 0)?;
+            let rest = String::from_utf8_lossy(&line[nul + 1..]);
+            let (line_number, text) = rest.split_once(':')?;
+            Some(SearchMatch {
+                path: encode_path(&path_from_output(&line[..nul])),
+                line_number: line_number.parse().ok()?,
+                line: text.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Search file contents under `dir` for a literal string
+async fn search_files(
+    dir: &str,
+    query: &str,
+    glob: Option<&str>,
+    token: &CancellationToken,
+) -> Result<Vec<SearchMatch>, FileError> {
+    let mut command = tokio::process::Command::new("rg");
+    command.args([
+        "--null",
+        "--line-number",
+        "--no-heading",
+        "--color",
+        "never",
+        "--fixed-strings",
+    ]);
+    if let Some(glob) = glob {
+        command.args(["--glob", glob]);
+    }
+    command.args(["--", query]).current_dir(decode_path(dir));
+
+    match run_cancellable(&mut command, token).await {
+        Ok(output) if output.status.success() => Ok(parse_rg_matches(&output.stdout)),
+        // Exit status 1 means no matches
+        Ok(output) if output.status.code() == Some(1) => Ok(Vec::new()),
+        Err(FileError::Cancelled) => Err(FileError::Cancelled),
         Ok(_) | Err(_) => {
-            // Fallback to basic directory listing if ripgrep fails
-            let mut files = Vec::new();
-            list_files_recursive(Path::new(dir), pattern, &mut files).await?;
-            Ok(files)
+            let pattern_ext = glob.map(|g| g.trim_start_matches("*."));
+            let mut matches = Vec::new();
+            for path in walk_files(&decode_path(dir), token).await? {
+                if token.is_cancelled() {
+                    return Err(FileError::Cancelled);
+                }
+                if let Some(ext) = pattern_ext {
+                    if ext != "*" && path.extension().is_none_or(|e| e != OsStr::new(ext)) {
+                        continue;
+                    }
+                }
+                // Unreadable and binary files are skipped, as ripgrep does
+                let Ok(content) = fs::read_to_string(&path).await else {
+                    continue;
+                };
+                for (i, line) in content.lines().enumerate() {
+                    if line.contains(query) {
+                        matches.push(SearchMatch {
+                            path: encode_path(&path),
+                            line_number: i as u64 + 1,
+                            line: line.to_string(),
+                        });
+                    }
+                }
+            }
+            Ok(matches)
         }
     }
 }
 
-/// Recursive file listing (fallback when ripgrep unavailable)
-async fn list_files_recursive(
+/// Search file contents for a literal string (ripgrep, with a fallback walker)
+///
+/// Pass an `operation_id` to be able to stop the search with `cancel_operation`.
+#[tauri::command]
+pub async fn search_content(
+    state: State<'_, AppState>,
+    dir: &str,
+    query: &str,
+    glob: Option<String>,
+    operation_id: Option<String>,
+) -> Result<Vec<SearchMatch>, FileError> {
+    let operation = state.operations.register(operation_id);
+    search_files(dir, query, glob.as_deref(), operation.token()).await
+}
+
+/// A node in a directory tree listing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TreeNode {
+    pub name: String,
+    pub path: String,
+    pub is_dir: bool,
+    /// Children of a directory, empty beyond the depth limit
+    pub children: Vec<TreeNode>,
+}
+
+/// Build the tree under `dir` down to `depth` levels, checking `token` per entry
+async fn build_tree(
     dir: &Path,
-    pattern: &str,
-    files: &mut Vec<String>,
-) -> Result<(), FileError> {
+    depth: u32,
+    token: &CancellationToken,
+) -> Result<Vec<TreeNode>, FileError> {
+    let mut nodes = Vec::new();
     let mut entries = fs::read_dir(dir).await?;
 
     while let Some(entry) = entries.next_entry().await? {
-        let path = entry.path();
-        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
-
-        // Skip hidden files and common ignored directories
-        if file_name.starts_with('.') || file_name == "node_modules" || file_name == "target" {
+        if token.is_cancelled() {
+            return Err(FileError::Cancelled);
+        }
+        let file_name = entry.file_name();
+        if is_skipped(&file_name) {
             continue;
         }
-
-        if path.is_dir() {
-            Box::pin(list_files_recursive(&path, pattern, files)).await?;
+        let path = entry.path();
+        let is_dir = entry.file_type().await?.is_dir();
+        let children = if is_dir && depth > 1 {
+            Box::pin(build_tree(&path, depth - 1, token)).await?
         } else {
-            // Simple glob matching (just extension for now)
-            let pattern_ext = pattern.trim_start_matches("*.");
-            if let Some(ext) = path.extension() {
-                if ext.to_string_lossy() == pattern_ext || pattern == "*" {
-                    files.push(path.to_string_lossy().to_string());
-                }
-            }
-        }
+            Vec::new()
+        };
+        nodes.push(TreeNode {
+            name: file_name.to_string_lossy().into_owned(),
+            path: encode_path(&path),
+            is_dir,
+            children,
+        });
     }
 
-    Ok(())
+    // Directories first, then by name
+    nodes.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+    Ok(nodes)
+}
+
+/// List the directory tree under `dir` (default depth 3)
+///
+/// Pass an `operation_id` to be able to stop the walk with `cancel_operation`.
+#[tauri::command]
+pub async fn list_dir_tree(
+    state: State<'_, AppState>,
+    dir: &str,
+    max_depth: Option<u32>,
+    operation_id: Option<String>,
+) -> Result<Vec<TreeNode>, FileError> {
+    let operation = state.operations.register(operation_id);
+    let depth = max_depth.unwrap_or(DEFAULT_TREE_DEPTH).max(1);
+    build_tree(&decode_path(dir), depth, operation.token()).await
+}
+
+/// Cancel a running file operation; unknown or finished ids are ignored
+///
+/// Returns whether an operation was cancelled.
+#[tauri::command]
+pub async fn cancel_operation(
+    state: State<'_, AppState>,
+    operation_id: String,
+) -> Result<bool, FileError> {
+    Ok(state.operations.cancel(&operation_id))
 }
 
 /// Check if a file exists
 #[tauri::command]
 pub async fn file_exists(path: &str) -> Result<bool, FileError> {
-    Ok(Path::new(path).exists())
+    Ok(decode_path(path).exists())
 }
 
 /// Ensure a directory exists, creating it if necessary
 #[tauri::command]
 pub async fn ensure_dir(path: &str) -> Result<(), FileError> {
-    fs::create_dir_all(path).await?;
+    fs::create_dir_all(decode_path(path)).await?;
     Ok(())
 }
 
 /// Delete a file
+///
+/// Refused if `session_id` names a read-only session.
 #[tauri::command]
-pub async fn delete_file(path: &str) -> Result<(), FileError> {
-    fs::remove_file(path).await?;
+pub async fn delete_file(
+    state: State<'_, AppState>,
+    path: &str,
+    session_id: Option<String>,
+) -> Result<(), FileError> {
+    ensure_writable(&*state.process_manager.read().await, session_id.as_deref()).await?;
+    fs::remove_file(decode_path(path)).await?;
     Ok(())
 }
 
@@ -221,7 +1006,7 @@ pub struct FileMetadata {
 
 #[tauri::command]
 pub async fn get_file_metadata(path: &str) -> Result<FileMetadata, FileError> {
-    let metadata = fs::metadata(path).await?;
+    let metadata = fs::metadata(decode_path(path)).await?;
     let modified = metadata
         .modified()?
         .duration_since(std::time::UNIX_EPOCH)
@@ -235,9 +1020,94 @@ pub async fn get_file_metadata(path: &str) -> Result<FileMetadata, FileError> {
     })
 }
 
+/// Forward file watcher events to the frontend as Tauri events
+pub fn forward_watch_events(app: AppHandle, mut events: broadcast::Receiver<WatchEvent>) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    for name in event.event_names() {
+                        if let Err(e) = app.emit(name, &event) {
+                            log::error!("Failed to emit {} event: {}", name, e);
+                        }
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("Dropped {} file watch events", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// Watch a file or directory; changes are emitted as `file-changed` events
+#[tauri::command]
+pub async fn watch_path(state: State<'_, AppState>, path: String) -> Result<(), FileError> {
+    state.file_watcher.lock().await.watch(&decode_path(&path))?;
+    Ok(())
+}
+
+/// Stop watching a path previously passed to `watch_path`
+#[tauri::command]
+pub async fn unwatch_path(state: State<'_, AppState>, path: String) -> Result<(), FileError> {
+    state.file_watcher.lock().await.unwatch(&decode_path(&path));
+    Ok(())
+}
+
+/// Counters of the file watcher (watched roots, events seen, delivered and debounced)
+#[tauri::command]
+pub async fn get_watcher_stats(state: State<'_, AppState>) -> Result<WatcherStats, FileError> {
+    Ok(state.file_watcher.lock().await.stats())
+}
+
+/// Set the file watcher's hashing size limit and debounce window
+#[tauri::command]
+pub async fn configure_watcher(
+    state: State<'_, AppState>,
+    config: WatcherConfig,
+) -> Result<(), FileError> {
+    state.file_watcher.lock().await.set_config(config);
+    Ok(())
+}
+
+/// Get the CLAUDE.md memory files that apply to a working directory
+///
+/// Returns the user file followed by one entry per directory from the
+/// repository root down to `working_dir`, whether or not each file exists.
+/// The files are added to the file watcher so that edits made by Claude
+/// itself emit `memory-changed` events.
+#[tauri::command]
+pub async fn get_claude_memory(
+    state: State<'_, AppState>,
+    working_dir: String,
+) -> Result<Vec<MemoryFile>, FileError> {
+    let home_dir = dirs::home_dir();
+    let files = load_memory_chain(&decode_path(&working_dir), home_dir.as_deref()).await;
+
+    let mut watcher = state.file_watcher.lock().await;
+    for file in &files {
+        if let Err(e) = watcher.watch(&decode_path(&file.path)) {
+            log::warn!("Failed to watch memory file {}: {}", file.path, e);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Write a CLAUDE.md memory file atomically
+#[tauri::command]
+pub async fn save_claude_memory(path: &str, content: &str) -> Result<(), FileError> {
+    if !is_memory_file(&decode_path(path)) {
+        return Err(FileError::NotMemoryFile(path.to_string()));
+    }
+    write_atomic(path, content).await
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::services::SessionConfig;
     use tempfile::TempDir;
 
     #[tokio::test]
@@ -257,12 +1127,20 @@ mod tests {
         assert!(matches!(result, Err(FileError::NotFound(_))));
     }
 
+    #[test]
+    fn test_out_of_space_maps_to_storage_full() {
+        let e = std::io::Error::from(std::io::ErrorKind::StorageFull);
+        assert!(matches!(FileError::from(e), FileError::StorageFull(_)));
+        let e = std::io::Error::from(std::io::ErrorKind::Other);
+        assert!(matches!(FileError::from(e), FileError::IoError(_)));
+    }
+
     #[tokio::test]
     async fn test_write_file_atomic_success() {
         let dir = TempDir::new().unwrap();
         let path = dir.path().join("new.txt");
 
-        write_file_atomic(path.to_str().unwrap(), "content")
+        write_atomic(path.to_str().unwrap(), "content")
             .await
             .unwrap();
         assert_eq!(std::fs::read_to_string(&path).unwrap(), "content");
@@ -274,9 +1152,7 @@ mod tests {
         let path = dir.path().join("existing.txt");
         std::fs::write(&path, "old").unwrap();
 
-        write_file_atomic(path.to_str().unwrap(), "new")
-            .await
-            .unwrap();
+        write_atomic(path.to_str().unwrap(), "new").await.unwrap();
         assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
     }
 
@@ -285,7 +1161,7 @@ mod tests {
         let dir = TempDir::new().unwrap();
         let path = dir.path().join("sub/dir/file.txt");
 
-        write_file_atomic(path.to_str().unwrap(), "content")
+        write_atomic(path.to_str().unwrap(), "content")
             .await
             .unwrap();
         assert!(path.exists());
@@ -325,7 +1201,7 @@ mod tests {
         let path = dir.path().join("test.txt");
         std::fs::write(&path, "original").unwrap();
 
-        let result = apply_edit(path.to_str().unwrap(), "original", "new")
+        let result = apply_file_edit(path.to_str().unwrap(), "original", "new", None, None)
             .await
             .unwrap();
         assert!(matches!(result, ApplyResult::Success));
@@ -338,22 +1214,251 @@ mod tests {
         let path = dir.path().join("test.txt");
         std::fs::write(&path, "modified externally").unwrap();
 
-        let result = apply_edit(path.to_str().unwrap(), "original", "proposed")
+        let result = apply_file_edit(path.to_str().unwrap(), "original", "proposed", None, None)
             .await
             .unwrap();
         assert!(matches!(result, ApplyResult::Conflict { .. }));
     }
 
+    fn edit(path: &Path, kind: EditKind, original: &str, proposed: &str) -> FileEdit {
+        FileEdit {
+            path: path.to_string_lossy().into_owned(),
+            kind,
+            original_content: original.to_string(),
+            expected_hash: None,
+            proposed_content: proposed.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_modify_of_missing_file_conflicts() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("gone.txt");
+
+        let result = apply_file_edit(path.to_str().unwrap(), "", "content", None, None)
+            .await
+            .unwrap();
+        let ApplyResult::Conflict {
+            current_content, ..
+        } = result
+        else {
+            panic!("expected a conflict, got {:?}", result);
+        };
+        assert_eq!(current_content, "");
+        assert!(!path.exists());
+    }
+
     #[tokio::test]
     async fn test_apply_edit_new_file() {
         let dir = TempDir::new().unwrap();
         let path = dir.path().join("new.txt");
 
-        let result = apply_edit(path.to_str().unwrap(), "", "new content")
+        let create = Some(EditKind::Create);
+        let result = apply_file_edit(path.to_str().unwrap(), "", "new content", create, None)
             .await
             .unwrap();
         assert!(matches!(result, ApplyResult::Success));
         assert_eq!(std::fs::read_to_string(&path).unwrap(), "new content");
+
+        // Creating it again fails, leaving the file alone
+        let result = apply_file_edit(path.to_str().unwrap(), "", "other", create, None).await;
+        assert!(matches!(result, Err(FileError::AlreadyExists(_))));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new content");
+    }
+
+    #[tokio::test]
+    async fn test_apply_edit_delete() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("old.txt");
+        let path_str = path.to_str().unwrap();
+        let delete = Some(EditKind::Delete);
+        std::fs::write(&path, "old").unwrap();
+
+        // The file was modified since the edit was proposed
+        let result = apply_file_edit(path_str, "older", "", delete, None)
+            .await
+            .unwrap();
+        assert!(matches!(result, ApplyResult::Conflict { .. }));
+        let stale_hash = Some(compute_hash("older"));
+        let result = apply_file_edit(path_str, "", "", delete, stale_hash)
+            .await
+            .unwrap();
+        assert!(matches!(result, ApplyResult::Conflict { .. }));
+        assert!(path.exists());
+
+        let hash = Some(compute_hash("old"));
+        let result = apply_file_edit(path_str, "", "", delete, hash)
+            .await
+            .unwrap();
+        assert!(matches!(result, ApplyResult::Deleted));
+        assert!(!path.exists());
+
+        // Deleting a file that is already gone is a conflict too
+        let result = apply_file_edit(path_str, "old", "", delete, None)
+            .await
+            .unwrap();
+        assert!(matches!(result, ApplyResult::Conflict { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_apply_edits_mixed_kinds() {
+        let dir = TempDir::new().unwrap();
+        let modified = dir.path().join("modified.txt");
+        let created = dir.path().join("sub/created.txt");
+        let deleted = dir.path().join("deleted.txt");
+        std::fs::write(&modified, "before").unwrap();
+        std::fs::write(&deleted, "doomed").unwrap();
+
+        let results = apply_file_edits(vec![
+            edit(&modified, EditKind::Modify, "before", "after"),
+            edit(&created, EditKind::Create, "", "fresh"),
+            edit(&deleted, EditKind::Delete, "doomed", ""),
+        ])
+        .await
+        .unwrap();
+        assert!(matches!(
+            results[..],
+            [
+                ApplyResult::Success,
+                ApplyResult::Success,
+                ApplyResult::Deleted
+            ]
+        ));
+        assert_eq!(std::fs::read_to_string(&modified).unwrap(), "after");
+        assert_eq!(std::fs::read_to_string(&created).unwrap(), "fresh");
+        assert!(!deleted.exists());
+    }
+
+    #[tokio::test]
+    async fn test_apply_edits_is_all_or_nothing() {
+        let dir = TempDir::new().unwrap();
+        let modified = dir.path().join("modified.txt");
+        let created = dir.path().join("created.txt");
+        let deleted = dir.path().join("deleted.txt");
+        std::fs::write(&modified, "before").unwrap();
+        std::fs::write(&deleted, "changed since the edit was proposed").unwrap();
+
+        let results = apply_file_edits(vec![
+            edit(&modified, EditKind::Modify, "before", "after"),
+            edit(&created, EditKind::Create, "", "fresh"),
+            edit(&deleted, EditKind::Delete, "doomed", ""),
+        ])
+        .await
+        .unwrap();
+        assert!(matches!(results[0], ApplyResult::Error { .. }));
+        assert!(matches!(results[1], ApplyResult::Error { .. }));
+        assert!(matches!(results[2], ApplyResult::Conflict { .. }));
+        assert_eq!(std::fs::read_to_string(&modified).unwrap(), "before");
+        assert!(!created.exists());
+        assert!(deleted.exists());
+
+        // A create over an existing file fails the batch the same way
+        let results = apply_file_edits(vec![
+            edit(&modified, EditKind::Modify, "before", "after"),
+            edit(&deleted, EditKind::Create, "", "fresh"),
+        ])
+        .await
+        .unwrap();
+        let ApplyResult::Error { ref message } = results[1] else {
+            panic!("expected an error, got {:?}", results[1]);
+        };
+        assert!(message.starts_with("File already exists"));
+        assert_eq!(std::fs::read_to_string(&modified).unwrap(), "before");
+    }
+
+    #[tokio::test]
+    async fn test_read_only_session_writes_are_refused() {
+        let dir = TempDir::new().unwrap();
+        let manager = ProcessManager::new();
+        let config = SessionConfig {
+            working_dir: dir.path().to_path_buf(),
+            model: "sonnet".to_string(),
+            ..Default::default()
+        };
+        let writable = manager.create_session(config.clone()).await.unwrap();
+        let read_only = manager
+            .create_session(SessionConfig {
+                read_only: true,
+                ..config
+            })
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            ensure_writable(&manager, Some(&read_only)).await,
+            Err(FileError::ReadOnlySession(ref id)) if *id == read_only
+        ));
+        assert!(ensure_writable(&manager, Some(&writable)).await.is_ok());
+        assert!(ensure_writable(&manager, Some("unknown")).await.is_ok());
+        assert!(ensure_writable(&manager, None).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_restoring_a_middle_version() {
+        let dir = TempDir::new().unwrap();
+        let mut manager = ProcessManager::new();
+        manager.set_data_dir(&dir.path().join("app-data"));
+        let config = SessionConfig {
+            working_dir: dir.path().to_path_buf(),
+            model: "sonnet".to_string(),
+            ..Default::default()
+        };
+        let session_id = manager.create_session(config).await.unwrap();
+        let path = dir.path().join("config.rs");
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "v1").unwrap();
+
+        let modify = |from: &str, to: &str| FileEdit {
+            path: path.to_string(),
+            kind: EditKind::Modify,
+            original_content: from.to_string(),
+            expected_hash: None,
+            proposed_content: to.to_string(),
+        };
+        for (from, to) in [("v1", "v2"), ("v2", "v3"), ("v3", "v4")] {
+            let result = apply_session_edit(&manager, Some(&session_id), modify(from, to))
+                .await
+                .unwrap();
+            assert!(matches!(result, ApplyResult::Success));
+        }
+        // Writing the same content again adds no version
+        apply_session_edit(&manager, Some(&session_id), modify("v4", "v4"))
+            .await
+            .unwrap();
+
+        let versions = manager.file_versions(&session_id, path).await.unwrap();
+        let ids: Vec<String> = versions.iter().map(|v| v.version_id.clone()).collect();
+        let expected: Vec<String> = ["v1", "v2", "v3", "v4"].map(compute_hash).to_vec();
+        assert_eq!(ids, expected);
+        assert!(versions[3].current);
+        assert!(versions[..3].iter().all(|v| !v.current));
+
+        let result = restore_version(&manager, &session_id, path, &ids[1], None)
+            .await
+            .unwrap();
+        assert!(matches!(result, ApplyResult::Success));
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "v2");
+        let versions = manager.file_versions(&session_id, path).await.unwrap();
+        assert_eq!(versions.len(), 4);
+        assert!(versions[1].current && !versions[3].current);
+
+        // A stale expected hash conflicts instead of overwriting
+        let result = restore_version(
+            &manager,
+            &session_id,
+            path,
+            &ids[0],
+            Some(compute_hash("v4")),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(result, ApplyResult::Conflict { .. }));
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "v2");
+
+        assert!(matches!(
+            restore_version(&manager, &session_id, path, &compute_hash("v9"), None).await,
+            Err(FileError::NotFound(_))
+        ));
     }
 
     #[tokio::test]
@@ -366,6 +1471,328 @@ mod tests {
         assert!(!file_exists("/nonexistent/file.txt").await.unwrap());
     }
 
+    /// Generate `dirs` directories of `files_per_dir` files each
+    fn generate_tree(root: &Path, dirs: usize, files_per_dir: usize) {
+        for d in 0..dirs {
+            let dir = root.join(format!("dir{}", d));
+            std::fs::create_dir_all(&dir).unwrap();
+            for f in 0..files_per_dir {
+                std::fs::write(dir.join(format!("file{}.txt", f)), "x").unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_walk_files_skips_ignored_dirs() {
+        let dir = TempDir::new().unwrap();
+        generate_tree(dir.path(), 2, 2);
+        std::fs::create_dir_all(dir.path().join("node_modules/pkg")).unwrap();
+        std::fs::write(dir.path().join("node_modules/pkg/index.js"), "").unwrap();
+        std::fs::write(dir.path().join(".hidden"), "").unwrap();
+
+        let files = walk_files(dir.path(), &CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(files.len(), 4);
+
+        // Gitignored paths are skipped too
+        std::fs::write(dir.path().join(".gitignore"), "dir1/\n").unwrap();
+        let files = walk_files(dir.path(), &CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|f| f.starts_with(dir.path().join("dir0"))));
+    }
+
+    /// A tree with nested `.gitignore`s, a negation, hidden and always-ignored entries
+    fn listing_fixture(root: &Path) {
+        for (path, content) in [
+            (".gitignore", "*.log\nbuild/\n"),
+            ("a.rs", ""),
+            ("debug.log", ""),
+            ("build/out.rs", ""),
+            ("docs/guide.md", ""),
+            ("src/.gitignore", "generated.rs\n!keep.log\n"),
+            ("src/main.rs", ""),
+            ("src/generated.rs", ""),
+            ("src/keep.log", ""),
+            ("src/nested/.gitignore", "secret/\n"),
+            ("src/nested/lib.rs", ""),
+            ("src/nested/secret/key.rs", ""),
+            (".hidden/config.rs", ""),
+            ("node_modules/pkg/index.rs", ""),
+        ] {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+    }
+
+    fn rg_installed() -> bool {
+        std::process::Command::new("rg")
+            .arg("--version")
+            .output()
+            .is_ok_and(|output| output.status.success())
+    }
+
+    #[tokio::test]
+    async fn test_walker_listing_honors_nested_gitignores() {
+        let dir = TempDir::new().unwrap();
+        listing_fixture(dir.path());
+        let root = dir.path().to_str().unwrap();
+        let token = CancellationToken::new();
+
+        assert_eq!(
+            find_files(root, "*", false, &token).await.unwrap(),
+            vec![
+                "a.rs",
+                "docs/guide.md",
+                "src/keep.log",
+                "src/main.rs",
+                "src/nested/lib.rs"
+            ]
+        );
+        assert_eq!(
+            find_files(root, "*.rs", false, &token).await.unwrap(),
+            vec!["a.rs", "src/main.rs", "src/nested/lib.rs"]
+        );
+        assert_eq!(
+            find_files(root, "src/**/*.rs", false, &token)
+                .await
+                .unwrap(),
+            vec!["src/main.rs", "src/nested/lib.rs"]
+        );
+        assert!(matches!(
+            find_files(&format!("{}/missing", root), "*", false, &token).await,
+            Err(FileError::NotFound(_))
+        ));
+    }
+
+    /// Read, edit and read back each of `listed` (relative to `root`), as the frontend does
+    async fn edit_listed_files(root: &str, listed: &[String]) {
+        for relative in listed {
+            let path = encode_path(&decode_path(root).join(decode_path(relative)));
+            let read = read_file(&path).await.unwrap();
+            assert_eq!(read.content, "before");
+            let result = apply_file_edit(&path, "before", "after", None, Some(read.hash))
+                .await
+                .unwrap();
+            assert!(matches!(result, ApplyResult::Success), "{}", relative);
+            assert_eq!(read_file(&path).await.unwrap().content, "after");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_localized_paths_survive_read_edit_and_listing() {
+        let dir = TempDir::new().unwrap();
+        let root_dir = dir.path().join("用户 Анна 🚀");
+        let names = [
+            "项目 文件/📁 notes.md",
+            // A separator on Windows, part of the file name elsewhere
+            r"Users\田中 太郎\🎉 party plan.txt",
+            "emoji 😀/with space.rs",
+        ];
+        for name in names {
+            let path = root_dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "before").unwrap();
+        }
+        let root = encode_path(&root_dir);
+        let token = CancellationToken::new();
+
+        let mut expected: Vec<String> = names
+            .iter()
+            .map(|name| {
+                if cfg!(windows) {
+                    name.replace('\\', "/")
+                } else {
+                    name.to_string()
+                }
+            })
+            .collect();
+        expected.sort();
+        let listed = find_files(&root, "*", false, &token).await.unwrap();
+        assert_eq!(listed, expected);
+        if rg_installed() {
+            assert_eq!(find_files(&root, "*", true, &token).await.unwrap(), listed);
+        }
+
+        edit_listed_files(&root, &listed).await;
+        // No temporary file is left behind under another name
+        assert_eq!(find_files(&root, "*", false, &token).await.unwrap(), listed);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_non_unicode_file_names_are_listed_round_trippably() {
+        use std::os::unix::ffi::OsStrExt;
+        let dir = TempDir::new().unwrap();
+        let name = OsStr::from_bytes(b"caf\xe9 \xe6\x96 notes.txt");
+        std::fs::write(dir.path().join(name), "before").unwrap();
+        let root = encode_path(dir.path());
+        let token = CancellationToken::new();
+
+        let listed = find_files(&root, "*.txt", false, &token).await.unwrap();
+        assert_eq!(listed, vec![encode_path(Path::new(name))]);
+        if rg_installed() {
+            assert_eq!(
+                find_files(&root, "*.txt", true, &token).await.unwrap(),
+                listed
+            );
+        }
+        edit_listed_files(&root, &listed).await;
+    }
+
+    #[tokio::test]
+    async fn test_ripgrep_and_walker_listings_match() {
+        if !rg_installed() {
+            eprintln!("ripgrep is not installed, skipping");
+            return;
+        }
+        let dir = TempDir::new().unwrap();
+        listing_fixture(dir.path());
+        let root = dir.path().to_str().unwrap();
+        let token = CancellationToken::new();
+
+        for pattern in ["*", "**/*", "*.rs", "*.log", "src/**/*.rs", "*.none"] {
+            let ripgrep = ripgrep_listing(root, &token).await.unwrap();
+            let walker = walk_listing(root, &token).await.unwrap();
+            assert_eq!(
+                normalize_listing(pattern, ripgrep).unwrap(),
+                normalize_listing(pattern, walker).unwrap(),
+                "pattern {}",
+                pattern
+            );
+            assert_eq!(
+                find_files(root, pattern, true, &token).await.unwrap(),
+                find_files(root, pattern, false, &token).await.unwrap()
+            );
+        }
+    }
+
+    /// Compare the walker with ripgrep on a large tree; run with `--ignored --nocapture`
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore]
+    async fn bench_walker_against_ripgrep() {
+        if !rg_installed() {
+            eprintln!("ripgrep is not installed, skipping");
+            return;
+        }
+        let dir = TempDir::new().unwrap();
+        generate_tree(dir.path(), 500, 200);
+        std::fs::write(dir.path().join(".gitignore"), "dir1*/\n").unwrap();
+        let root = dir.path().to_str().unwrap();
+        let token = CancellationToken::new();
+
+        let mut best = [std::time::Duration::MAX; 2];
+        for _ in 0..5 {
+            for (i, use_ripgrep) in [true, false].into_iter().enumerate() {
+                let started = std::time::Instant::now();
+                let files = find_files(root, "*", use_ripgrep, &token).await.unwrap();
+                assert_eq!(files.len(), 389 * 200);
+                best[i] = best[i].min(started.elapsed());
+            }
+        }
+        eprintln!("ripgrep {:?}, walker {:?}", best[0], best[1]);
+        assert!(best[1] < best[0] * 2);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_stops_large_walk() {
+        let dir = TempDir::new().unwrap();
+        generate_tree(dir.path(), 100, 100);
+
+        let registry = crate::services::OperationRegistry::new();
+        let operation = registry.register(Some("walk".to_string()));
+        let root = dir.path().to_path_buf();
+        let walk = tokio::spawn(async move {
+            let result = walk_files(&root, operation.token()).await;
+            drop(operation);
+            result
+        });
+
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        assert!(registry.cancel("walk"));
+
+        let started = std::time::Instant::now();
+        let result = tokio::time::timeout(std::time::Duration::from_secs(2), walk)
+            .await
+            .expect("Walk did not stop after cancellation")
+            .unwrap();
+        assert!(matches!(result, Err(FileError::Cancelled)));
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+        // The finished operation unregistered itself
+        assert!(registry.is_empty());
+        assert!(!registry.cancel("walk"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_cancel_kills_child_process() {
+        let token = CancellationToken::new();
+        let mut command = tokio::process::Command::new("sleep");
+        command.arg("10");
+
+        let canceller = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            canceller.cancel();
+        });
+
+        let started = std::time::Instant::now();
+        let result = run_cancellable(&mut command, &token).await;
+        assert!(matches!(result, Err(FileError::Cancelled)));
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_build_tree_respects_depth() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("a/b/c")).unwrap();
+        std::fs::write(dir.path().join("z.txt"), "").unwrap();
+
+        let tree = build_tree(dir.path(), 2, &CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0].name, "a");
+        assert_eq!(tree[0].children[0].name, "b");
+        assert!(tree[0].children[0].children.is_empty());
+        assert_eq!(tree[1].name, "z.txt");
+    }
+
+    #[tokio::test]
+    async fn test_search_files_finds_lines() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("a.rs"), "fn main() {}\n// TODO: fix\n").unwrap();
+        std::fs::write(dir.path().join("b.txt"), "TODO elsewhere\n").unwrap();
+
+        let matches = search_files(
+            dir.path().to_str().unwrap(),
+            "TODO",
+            Some("*.rs"),
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(matches.len(), 1);
+        assert!(matches[0].path.ends_with("a.rs"));
+        assert_eq!(matches[0].line_number, 2);
+    }
+
+    #[test]
+    fn test_parse_rg_matches() {
+        let matches = parse_rg_matches(b"src/a:b.rs\x0012:let x = 1;\nbad line\n");
+        assert_eq!(
+            matches,
+            vec![SearchMatch {
+                path: "src/a:b.rs".to_string(),
+                line_number: 12,
+                line: "let x = 1;".to_string(),
+            }]
+        );
+    }
+
     #[tokio::test]
     async fn test_compute_hash() {
         let hash1 = compute_hash("hello");
@@ -375,4 +1802,14 @@ mod tests {
         assert_eq!(hash1, hash2);
         assert_ne!(hash1, hash3);
     }
+
+    #[tokio::test]
+    async fn test_listing_settings_persist() {
+        let dir = TempDir::new().unwrap();
+        assert!(!ListingSettings::load(dir.path()).use_ripgrep);
+
+        let settings = ListingSettings { use_ripgrep: true };
+        settings.save(dir.path()).await.unwrap();
+        assert_eq!(ListingSettings::load(dir.path()), settings);
+    }
 }
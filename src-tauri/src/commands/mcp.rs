@@ -7,11 +7,18 @@
 //! - Fetching capabilities
 
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use std::process::{Child, Command, Stdio};
+use std::process::{Command, Stdio};
+use tauri::State;
 use thiserror::Error;
 use tokio::fs;
 
+use super::session::AppState;
+use crate::services::ManagedMcpServer;
+use crate::services::http_client::http_client;
+use crate::services::paths::{decode_path, encode_path};
+#[cfg(target_os = "windows")]
+use crate::services::win_process;
+
 /// Errors that can occur during MCP operations
 #[derive(Error, Debug, Serialize)]
 pub enum MCPError {
@@ -85,7 +92,7 @@ pub struct MCPPrompt {
 /// Read MCP configuration from a file
 #[tauri::command]
 pub async fn read_mcp_config(path: String) -> Result<String, MCPError> {
-    let content = fs::read_to_string(&path).await.map_err(|_| {
+    let content = fs::read_to_string(decode_path(&path)).await.map_err(|_| {
         MCPError::ConfigNotFound(path.clone())
     })?;
     Ok(content)
@@ -94,7 +101,7 @@ pub async fn read_mcp_config(path: String) -> Result<String, MCPError> {
 /// Write MCP configuration to a file
 #[tauri::command]
 pub async fn write_mcp_config(path: String, content: String) -> Result<(), MCPError> {
-    let path_buf = PathBuf::from(&path);
+    let path_buf = decode_path(&path);
 
     // Create parent directories if they don't exist
     if let Some(parent) = path_buf.parent() {
@@ -108,7 +115,7 @@ pub async fn write_mcp_config(path: String, content: String) -> Result<(), MCPEr
 /// Check if MCP config file exists
 #[tauri::command]
 pub async fn mcp_config_exists(path: String) -> Result<bool, MCPError> {
-    Ok(PathBuf::from(path).exists())
+    Ok(decode_path(&path).exists())
 }
 
 /// Get default MCP config paths
@@ -117,71 +124,119 @@ pub async fn get_mcp_config_paths(working_dir: String) -> Result<Vec<String>, MC
     let home_dir = dirs::home_dir()
         .ok_or_else(|| MCPError::IoError("Cannot determine home directory".to_string()))?;
 
-    let paths = vec![
+    let working_dir = decode_path(&working_dir);
+    let paths = [
         // User scope
-        home_dir
-            .join(".claude")
-            .join("claude_desktop_config.json")
-            .to_string_lossy()
-            .to_string(),
+        home_dir.join(".claude").join("claude_desktop_config.json"),
         // Project scope
-        PathBuf::from(&working_dir)
-            .join(".mcp.json")
-            .to_string_lossy()
-            .to_string(),
-        PathBuf::from(&working_dir)
-            .join(".claude")
-            .join("mcp.json")
-            .to_string_lossy()
-            .to_string(),
+        working_dir.join(".mcp.json"),
+        working_dir.join(".claude").join("mcp.json"),
     ];
 
-    Ok(paths)
+    Ok(paths.iter().map(|path| encode_path(path)).collect())
 }
 
-/// Start an MCP server (stdio transport)
-#[tauri::command]
-pub async fn start_mcp_server(
-    name: String,
-    command: String,
-    args: Vec<String>,
-    env: Option<std::collections::HashMap<String, String>>,
-) -> Result<u32, MCPError> {
-    let mut cmd = Command::new(&command);
-    cmd.args(&args);
+/// Spawn a stdio MCP server process and return its PID
+fn spawn_mcp_process(server: &ManagedMcpServer) -> Result<u32, MCPError> {
+    let mut cmd = Command::new(&server.command);
+    cmd.args(&server.args);
     cmd.stdin(Stdio::piped());
     cmd.stdout(Stdio::piped());
     cmd.stderr(Stdio::piped());
 
     // Set environment variables
-    if let Some(env_vars) = env {
+    if let Some(ref env_vars) = server.env {
         for (key, value) in env_vars {
             cmd.env(key, value);
         }
     }
 
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        cmd.creation_flags(win_process::SPAWN_FLAGS);
+    }
+
     let child = cmd.spawn().map_err(|e| {
-        MCPError::StartFailed(format!("Failed to spawn process for {}: {}", name, e))
+        MCPError::StartFailed(format!("Failed to spawn process for {}: {}", server.name, e))
     })?;
 
-    let pid = child.id();
+    // Lets `terminate_process` kill the server's own children too
+    #[cfg(target_os = "windows")]
+    win_process::track(child.id());
 
-    // TODO: Store the child process handle for management
-    // For now, we just return the PID
+    Ok(child.id())
+}
+
+/// Start an MCP server (stdio transport)
+///
+/// The server is recorded in the MCP registry so that sessions reporting it
+/// as failed can offer a restart.
+#[tauri::command]
+pub async fn start_mcp_server(
+    state: State<'_, AppState>,
+    name: String,
+    command: String,
+    args: Vec<String>,
+    env: Option<std::collections::HashMap<String, String>>,
+) -> Result<u32, MCPError> {
+    let mut server = ManagedMcpServer {
+        name,
+        command,
+        args,
+        env,
+        pid: 0,
+    };
+    server.pid = spawn_mcp_process(&server)?;
+    let pid = server.pid;
+
+    let manager = state.process_manager.read().await;
+    manager.mcp_registry().register(server).await;
+
+    Ok(pid)
+}
+
+/// Restart a GUI-managed MCP server by name (the target of a restart action)
+#[tauri::command]
+pub async fn restart_mcp_server(
+    state: State<'_, AppState>,
+    name: String,
+) -> Result<u32, MCPError> {
+    let manager = state.process_manager.read().await;
+    let registry = manager.mcp_registry();
+    let mut server = registry
+        .get(&name)
+        .await
+        .ok_or_else(|| MCPError::ServerNotFound(name.clone()))?;
+
+    // The old process may already have exited, which is why it is being restarted
+    if let Err(e) = terminate_process(server.pid) {
+        log::debug!("Stopping MCP server {} before restart: {}", name, e);
+    }
+
+    server.pid = spawn_mcp_process(&server)?;
+    let pid = server.pid;
+    registry.register(server).await;
 
     Ok(pid)
 }
 
 /// Stop an MCP server by PID
 #[tauri::command]
-pub async fn stop_mcp_server(pid: u32) -> Result<(), MCPError> {
+pub async fn stop_mcp_server(state: State<'_, AppState>, pid: u32) -> Result<(), MCPError> {
+    terminate_process(pid)?;
+
+    let manager = state.process_manager.read().await;
+    manager.mcp_registry().remove_pid(pid).await;
+
+    Ok(())
+}
+
+/// Send a termination signal to a process (on Windows, kill its process tree)
+fn terminate_process(pid: u32) -> Result<(), MCPError> {
     #[cfg(target_os = "windows")]
     {
-        use std::process::Command;
-        Command::new("taskkill")
-            .args(&["/PID", &pid.to_string(), "/F"])
-            .output()
-            .map_err(|e| MCPError::StopFailed(e.to_string()))?;
+        win_process::kill_tree(pid).map_err(|e| MCPError::StopFailed(e.to_string()))?;
     }
 
     #[cfg(not(target_os = "windows"))]
@@ -218,7 +273,8 @@ pub async fn is_process_running(pid: u32) -> Result<bool, MCPError> {
         use nix::unistd::Pid;
 
         let pid = Pid::from_raw(pid as i32);
-        match kill(pid, Signal::from_c_int(0)) {
+        // Signal 0 only checks that the process exists
+        match kill(pid, None::<Signal>) {
             Ok(_) => Ok(true),
             Err(_) => Ok(false),
         }
@@ -229,9 +285,7 @@ pub async fn is_process_running(pid: u32) -> Result<bool, MCPError> {
 #[tauri::command]
 pub async fn health_check_mcp_server(url: String) -> Result<bool, MCPError> {
     // Simple HTTP GET to check if server is responding
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(5))
-        .build()
+    let client = http_client(std::time::Duration::from_secs(5))
         .map_err(|e| MCPError::ProcessError(e.to_string()))?;
 
     let response = client
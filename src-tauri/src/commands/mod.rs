@@ -2,12 +2,16 @@
 //!
 //! This module contains all the command handlers that can be invoked from the frontend.
 
+pub mod control;
 pub mod files;
 pub mod mcp;
 pub mod session;
 pub mod system;
+pub mod workspace;
 
+pub use control::*;
 pub use files::*;
 pub use mcp::*;
 pub use session::*;
 pub use system::*;
+pub use workspace::*;
@@ -8,21 +8,60 @@
 //! - Multi-turn conversations use `--resume <claude_session_id>`
 //! - Messages are streamed via Tauri events
 
-use crate::services::{ProcessManager, SessionConfig, SessionInfo, StreamMessage};
+use super::control::ControlServer;
+use crate::services::cli_args::{CommandLine, ShellFlavor};
+use crate::services::tools;
+use crate::services::{
+    BridgePolicy, CLIMessagePayload, CliStatus, EnvironmentSnapshot, EventSink, FakeBackend,
+    FileWatcher, FrontendBridge, MissedMessages, ModelCatalog, OperationRegistry, ProcessManager,
+    ProjectLayout, PromptOptions, PromptRecord, RestoredLayout, SessionConfig, SessionEvent,
+    SessionEventBridge, SessionInfo, SessionsDiff, StreamMessage, SyncWarning, ToolMatcherError,
+    ToolPreset, TranscriptExport, UsageSummary, WorkspaceStore,
+};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
-use tauri::{AppHandle, Emitter, State};
-use tokio::sync::{mpsc, RwLock};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::{broadcast, mpsc, Mutex, RwLock};
 
 /// Application state containing the process manager
 pub struct AppState {
     pub process_manager: Arc<RwLock<ProcessManager>>,
+    pub file_watcher: Arc<Mutex<FileWatcher>>,
+    /// Cancellable long-running file operations
+    pub operations: OperationRegistry,
+    /// Multi-root workspace definitions
+    pub workspaces: Arc<Mutex<WorkspaceStore>>,
+    /// Local HTTP control API, when enabled
+    pub control_server: Arc<Mutex<ControlServer>>,
+    /// `list_files` tries ripgrep before the built-in walker (see `ListingSettings`)
+    pub ripgrep_listing: AtomicBool,
+    /// Event delivery to the webview (see `frontend_bridge`)
+    pub frontend: FrontendBridge,
 }
 
 impl AppState {
     pub fn new() -> Self {
+        let process_manager = match FakeBackend::from_env() {
+            Some(backend) => {
+                log::info!(
+                    "Using the fake Claude backend (fixtures: {})",
+                    backend.fixtures().join(", ")
+                );
+                ProcessManager::with_backend(Arc::new(backend))
+            }
+            None => ProcessManager::new(),
+        };
         Self {
-            process_manager: Arc::new(RwLock::new(ProcessManager::new())),
+            process_manager: Arc::new(RwLock::new(process_manager)),
+            file_watcher: Arc::new(Mutex::new(FileWatcher::new())),
+            operations: OperationRegistry::new(),
+            workspaces: Arc::new(Mutex::new(WorkspaceStore::in_memory())),
+            control_server: Arc::new(Mutex::new(ControlServer::new())),
+            ripgrep_listing: AtomicBool::new(false),
+            frontend: FrontendBridge::new(),
         }
     }
 }
@@ -47,33 +86,203 @@ impl From<crate::services::ProcessError> for SessionError {
     }
 }
 
+impl From<crate::services::WorkspaceError> for SessionError {
+    fn from(e: crate::services::WorkspaceError) -> Self {
+        Self {
+            message: e.to_string(),
+        }
+    }
+}
+
 /// Result of creating a session
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateSessionResult {
     pub session_id: String,
+    /// Set when the working directory is inside a cloud-synced folder
+    #[serde(default)]
+    pub sync_warning: Option<SyncWarning>,
 }
 
-/// Payload for cli-message events sent to frontend
-#[derive(Debug, Clone, Serialize)]
-pub struct CLIMessagePayload {
-    #[serde(rename = "sessionId")]
-    pub session_id: String,
-    pub message: StreamMessage,
+/// Result of sending a prompt
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SendPromptResult {
+    pub prompt_id: String,
+    pub model: String,
+}
+
+/// Outcome of dispatching a broadcast prompt to one session
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BroadcastTargetResult {
+    pub prompt_id: Option<String>,
+    pub model: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Result of broadcasting a prompt
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BroadcastPromptResult {
+    /// Identifies the `broadcast-completed` event for this broadcast
+    pub broadcast_id: String,
+    /// Per session id
+    pub results: HashMap<String, BroadcastTargetResult>,
+}
+
+/// Sends events through the app handle
+pub struct AppEventSink(pub AppHandle);
+
+impl EventSink for AppEventSink {
+    fn emit(&self, event: &str, payload: serde_json::Value) -> Result<(), String> {
+        self.0.emit(event, payload).map_err(|e| e.to_string())
+    }
+
+    fn emit_to(&self, window: &str, event: &str, payload: serde_json::Value) -> Result<(), String> {
+        self.0
+            .emit_to(window, event, payload)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// The bridge delivering session events through `app`
+pub(crate) fn event_bridge(app: AppHandle) -> SessionEventBridge {
+    let state = app.state::<AppState>();
+    let frontend = state.frontend.clone();
+    let process_manager = state.process_manager.clone();
+    SessionEventBridge::new(Arc::new(AppEventSink(app)), frontend, process_manager)
+}
+
+/// Forward process manager events (e.g. "prompt-completed") to the frontend
+pub fn forward_session_events(app: AppHandle, events: broadcast::Receiver<SessionEvent>) {
+    tauri::async_runtime::spawn(event_bridge(app).forward_session_events(events));
 }
 
 /// Create a new Claude CLI session (logical, no process spawned yet)
 ///
 /// Returns the app session ID. The actual Claude process is spawned
-/// when `send_prompt` is called.
+/// when `send_prompt` is called. With `workspace_id`, the workspace's first
+/// root becomes the working directory and its other roots are added with
+/// `--add-dir`.
 #[tauri::command]
 pub async fn spawn_session(
     state: State<'_, AppState>,
     config: SessionConfig,
+    workspace_id: Option<String>,
 ) -> Result<CreateSessionResult, SessionError> {
+    let config = match workspace_id {
+        Some(id) => {
+            let workspace = state.workspaces.lock().await.get(&id)?;
+            workspace.session_config(config)?
+        }
+        None => config,
+    };
     let manager = state.process_manager.read().await;
     let session_id = manager.create_session(config).await?;
+    let sync_warning = manager
+        .get_session(&session_id)
+        .await
+        .and_then(|info| info.sync_warning);
+
+    Ok(CreateSessionResult {
+        session_id,
+        sync_warning,
+    })
+}
+
+/// Replace a session's configuration (takes effect on the next prompt)
+#[tauri::command]
+pub async fn update_session_config(
+    state: State<'_, AppState>,
+    session_id: String,
+    config: SessionConfig,
+) -> Result<SessionInfo, SessionError> {
+    let manager = state.process_manager.read().await;
+    Ok(manager.update_session_config(&session_id, config).await?)
+}
+
+/// List the models sessions can be configured with, plus the default model
+#[tauri::command]
+pub async fn list_available_models(
+    state: State<'_, AppState>,
+) -> Result<ModelCatalog, SessionError> {
+    let manager = state.process_manager.read().await;
+    Ok(manager.model_catalog().clone())
+}
+
+/// List the allowed-tools presets (built-in and user-defined) usable as `preset:<name>`
+#[tauri::command]
+pub async fn list_tool_presets(
+    state: State<'_, AppState>,
+) -> Result<Vec<ToolPreset>, SessionError> {
+    let manager = state.process_manager.read().await;
+    Ok(manager.tool_presets().all().to_vec())
+}
+
+/// Result of `validate_tool_matchers`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolMatcherValidation {
+    /// The entries as sessions would store them; empty if any entry is invalid
+    pub normalized: Vec<String>,
+    pub errors: Vec<ToolMatcherError>,
+}
+
+/// Validate `allowed_tools` entries (e.g. as the user types them), with suggested fixes
+#[tauri::command]
+pub async fn validate_tool_matchers(
+    entries: Vec<String>,
+) -> Result<ToolMatcherValidation, SessionError> {
+    Ok(match tools::validate_tool_matchers(&entries) {
+        Ok(normalized) => ToolMatcherValidation {
+            normalized,
+            errors: Vec::new(),
+        },
+        Err(errors) => ToolMatcherValidation {
+            normalized: Vec::new(),
+            errors,
+        },
+    })
+}
+
+/// Forward a prompt's CLI messages to the frontend as "cli-message" events
+///
+/// See [`SessionEventBridge::forward_prompt`].
+pub(crate) fn forward_cli_messages(
+    app: AppHandle,
+    session_id: String,
+    record: &PromptRecord,
+    rx: mpsc::Receiver<StreamMessage>,
+) {
+    event_bridge(app).spawn_prompt(session_id, record, rx);
+}
 
-    Ok(CreateSessionResult { session_id })
+/// Called by the frontend once it is mounted and listening for events
+///
+/// If messages were held while the webview was unreachable, a
+/// `frontend-recovered` event lists the sessions to replay; they are also
+/// returned.
+#[tauri::command]
+pub async fn frontend_ready(app: AppHandle) -> Result<Vec<MissedMessages>, SessionError> {
+    event_bridge(app)
+        .frontend_ready()
+        .ok_or_else(|| SessionError {
+            message: "Frontend events are still failing".to_string(),
+        })
+}
+
+/// Get what happens to running prompts while the frontend is unreachable
+#[tauri::command]
+pub async fn get_event_bridge_policy(
+    state: State<'_, AppState>,
+) -> Result<BridgePolicy, SessionError> {
+    Ok(state.frontend.policy())
+}
+
+/// Set what happens to running prompts while the frontend is unreachable
+#[tauri::command]
+pub async fn set_event_bridge_policy(
+    state: State<'_, AppState>,
+    policy: BridgePolicy,
+) -> Result<(), SessionError> {
+    state.frontend.set_policy(policy);
+    Ok(())
 }
 
 /// Send a prompt to a session - spawns a NEW Claude CLI process
@@ -82,38 +291,222 @@ pub async fn spawn_session(
 /// 1. Creates: `claude -p "<prompt>" --output-format stream-json [--resume <id>]`
 /// 2. Streams JSON messages via "cli-message" Tauri events
 /// 3. Process terminates when Claude is done responding
+///
+/// `request_id` is an optional client-generated id; resending the same id
+/// within a few seconds returns the already-running prompt's ID.
+/// `model_override` runs this prompt only on a different model.
 #[tauri::command]
 pub async fn send_prompt(
     app: AppHandle,
     state: State<'_, AppState>,
     session_id: String,
     prompt: String,
-) -> Result<(), SessionError> {
+    request_id: Option<String>,
+    model_override: Option<String>,
+) -> Result<SendPromptResult, SessionError> {
     let manager = state.process_manager.read().await;
 
     // Create channel for receiving messages from the process
-    let (tx, mut rx) = mpsc::channel::<StreamMessage>(64);
+    let (tx, rx) = mpsc::channel::<StreamMessage>(64);
 
     // Spawn the prompt (this creates the Claude CLI process)
-    manager.send_prompt(&session_id, &prompt, tx).await?;
-
-    // Spawn a task to forward messages to the frontend via Tauri events
-    let session_id_clone = session_id.clone();
-    tokio::spawn(async move {
-        while let Some(msg) = rx.recv().await {
-            let payload = CLIMessagePayload {
-                session_id: session_id_clone.clone(),
-                message: msg,
-            };
-
-            if let Err(e) = app.emit("cli-message", &payload) {
-                log::error!("Failed to emit cli-message event: {}", e);
-                break;
+    let options = PromptOptions {
+        request_id,
+        model_override,
+        ..Default::default()
+    };
+    let record = manager.send_prompt(&session_id, &prompt, options, tx).await?;
+
+    // Forward messages to the frontend via Tauri events
+    forward_cli_messages(app, session_id, &record, rx);
+
+    Ok(SendPromptResult {
+        prompt_id: record.prompt_id,
+        model: record.model,
+    })
+}
+
+/// Preview what a prompt would do, without executing anything
+///
+/// The CLI runs in plan mode on a fork of the session's conversation, so the
+/// session's prompt count and resume id are unchanged. Messages stream as
+/// "cli-message" events with `dry_run: true`; the "prompt-completed" event
+/// carries the proposed file operations in the prompt's `plan`.
+#[tauri::command]
+pub async fn send_prompt_dry_run(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    session_id: String,
+    prompt: String,
+) -> Result<SendPromptResult, SessionError> {
+    let manager = state.process_manager.read().await;
+
+    let (tx, rx) = mpsc::channel::<StreamMessage>(64);
+    let record = manager
+        .send_prompt_dry_run(&session_id, &prompt, tx)
+        .await?;
+    forward_cli_messages(app, session_id, &record, rx);
+
+    Ok(SendPromptResult {
+        prompt_id: record.prompt_id,
+        model: record.model,
+    })
+}
+
+/// Send one prompt to several sessions at once
+///
+/// Sessions that are missing or busy get an error entry; the others run the
+/// prompt concurrently and stream as usual. A `broadcast-completed` event
+/// fires once all of them have finished.
+#[tauri::command]
+pub async fn broadcast_prompt(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    session_ids: Vec<String>,
+    prompt: String,
+) -> Result<BroadcastPromptResult, SessionError> {
+    let manager = state.process_manager.read().await;
+
+    let mut receivers = HashMap::new();
+    let mut targets = Vec::new();
+    for session_id in session_ids {
+        let (tx, rx) = mpsc::channel::<StreamMessage>(64);
+        receivers.insert(session_id.clone(), rx);
+        targets.push((session_id, tx));
+    }
+
+    let dispatch = manager.broadcast_prompt(&prompt, targets).await;
+    let mut results = HashMap::new();
+    for (session_id, result) in dispatch.results {
+        let target = match result {
+            Ok(record) => {
+                if let Some(rx) = receivers.remove(&session_id) {
+                    forward_cli_messages(app.clone(), session_id.clone(), &record, rx);
+                }
+                BroadcastTargetResult {
+                    prompt_id: Some(record.prompt_id),
+                    model: Some(record.model),
+                    error: None,
+                }
             }
-        }
-    });
+            Err(e) => BroadcastTargetResult {
+                prompt_id: None,
+                model: None,
+                error: Some(e.to_string()),
+            },
+        };
+        results.insert(session_id, target);
+    }
 
-    Ok(())
+    Ok(BroadcastPromptResult {
+        broadcast_id: dispatch.broadcast_id,
+        results,
+    })
+}
+
+/// Run a whitelisted CLI meta command (compact, clear, cost, memory) in a session
+///
+/// `command` may be given with or without the leading slash, e.g. "/compact"
+/// or "compact focus on the parser". Output streams like a normal prompt.
+#[tauri::command]
+pub async fn send_meta_command(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    session_id: String,
+    command: String,
+) -> Result<SendPromptResult, SessionError> {
+    let manager = state.process_manager.read().await;
+
+    let (tx, rx) = mpsc::channel::<StreamMessage>(64);
+    let record = manager.send_meta_command(&session_id, &command, tx).await?;
+    forward_cli_messages(app, session_id, &record, rx);
+
+    Ok(SendPromptResult {
+        prompt_id: record.prompt_id,
+        model: record.model,
+    })
+}
+
+/// Messages forwarded for a session after `since_sequence` (all when omitted)
+///
+/// Used by a reloaded webview to rebuild its conversation view. With `emit`,
+/// the messages are also sent as `cli-message` events flagged `replayed`.
+#[tauri::command]
+pub async fn replay_session_events(
+    app: AppHandle,
+    session_id: String,
+    since_sequence: Option<u64>,
+    emit: Option<bool>,
+) -> Result<Vec<CLIMessagePayload>, SessionError> {
+    Ok(event_bridge(app)
+        .replay(
+            &session_id,
+            since_sequence.unwrap_or(0),
+            emit.unwrap_or(false),
+        )
+        .await?)
+}
+
+/// Prompt counts, cost and spawn latency percentiles per day across all sessions
+#[tauri::command]
+pub async fn get_usage_summary(state: State<'_, AppState>) -> Result<UsageSummary, SessionError> {
+    let manager = state.process_manager.read().await;
+    Ok(manager.usage_summary().await)
+}
+
+/// Get the prompt history of a session, oldest first
+#[tauri::command]
+pub async fn get_prompt_history(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> Result<Vec<PromptRecord>, SessionError> {
+    let manager = state.process_manager.read().await;
+    Ok(manager.get_prompt_history(&session_id).await?)
+}
+
+/// Get the environment snapshot of one prompt (CLI version, models, git state, MCP servers)
+///
+/// Returns None for prompts recorded before snapshots existed.
+#[tauri::command]
+pub async fn get_prompt_snapshot(
+    state: State<'_, AppState>,
+    session_id: String,
+    prompt_id: String,
+) -> Result<Option<EnvironmentSnapshot>, SessionError> {
+    let manager = state.process_manager.read().await;
+    Ok(manager.prompt_snapshot(&session_id, &prompt_id).await?)
+}
+
+/// Export a session's transcript, headed by the snapshot of its latest prompt
+#[tauri::command]
+pub async fn export_transcript(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> Result<TranscriptExport, SessionError> {
+    let manager = state.process_manager.read().await;
+    Ok(manager.export_transcript(&session_id).await?)
+}
+
+/// Check that the Claude CLI runs, refreshing the version recorded in prompt snapshots
+#[tauri::command]
+pub async fn check_claude_cli(state: State<'_, AppState>) -> Result<CliStatus, SessionError> {
+    let manager = state.process_manager.read().await;
+    Ok(manager.check_cli().await)
+}
+
+/// Get the CLI command line a prompt would run for a session, for reproducing it in a terminal
+///
+/// Nothing is spawned. Quoting follows the platform's shell; secrets are masked.
+#[tauri::command]
+pub async fn get_session_command_line(
+    state: State<'_, AppState>,
+    session_id: String,
+    prompt: Option<String>,
+) -> Result<CommandLine, SessionError> {
+    let manager = state.process_manager.read().await;
+    Ok(manager
+        .command_line(&session_id, prompt.as_deref(), ShellFlavor::current())
+        .await?)
 }
 
 /// Send interrupt signal to a session (kills the active Claude process)
@@ -138,11 +531,118 @@ pub async fn terminate_session(
     Ok(())
 }
 
-/// Get all active sessions
+/// Merge a session into another that shares its Claude conversation
+///
+/// `merge_id`'s history and transcript move into `keep_id`, and `merge_id`
+/// is terminated. Refused while either session is thinking.
 #[tauri::command]
-pub async fn get_sessions(state: State<'_, AppState>) -> Result<Vec<SessionInfo>, SessionError> {
+pub async fn merge_sessions(
+    state: State<'_, AppState>,
+    keep_id: String,
+    merge_id: String,
+) -> Result<SessionInfo, SessionError> {
     let manager = state.process_manager.read().await;
-    Ok(manager.get_sessions().await)
+    Ok(manager.merge_sessions(&keep_id, &merge_id).await?)
+}
+
+/// Get the full content of a tool result that was truncated in a cli-message event
+///
+/// `blob_id` comes from the truncated result's `blob_id` field.
+#[tauri::command]
+pub async fn get_tool_result_blob(
+    state: State<'_, AppState>,
+    session_id: String,
+    blob_id: String,
+) -> Result<serde_json::Value, SessionError> {
+    let manager = state.process_manager.read().await;
+    Ok(manager.tool_result_blob(&session_id, &blob_id).await?)
+}
+
+/// Save the live sessions in `working_dir` as the project's layout
+///
+/// `session_order` lists session ids in tab order; others follow in creation order.
+#[tauri::command]
+pub async fn save_project_layout(
+    state: State<'_, AppState>,
+    working_dir: String,
+    session_order: Option<Vec<String>>,
+) -> Result<ProjectLayout, SessionError> {
+    let manager = state.process_manager.read().await;
+    Ok(manager
+        .save_project_layout(Path::new(&working_dir), &session_order.unwrap_or_default())
+        .await?)
+}
+
+/// Recreate the sessions saved for `working_dir`, returning their new ids in saved order
+///
+/// Sessions whose Claude conversation is gone from `~/.claude` are skipped with a warning.
+#[tauri::command]
+pub async fn restore_project_layout(
+    state: State<'_, AppState>,
+    working_dir: String,
+) -> Result<RestoredLayout, SessionError> {
+    let claude_dir = dirs::home_dir().map(|home| home.join(".claude"));
+    let manager = state.process_manager.read().await;
+    Ok(manager
+        .restore_project_layout(Path::new(&working_dir), claude_dir.as_deref())
+        .await?)
+}
+
+/// Save every project's layout when all sessions are terminated (including on exit)
+#[tauri::command]
+pub async fn set_layout_autosave(
+    state: State<'_, AppState>,
+    enabled: bool,
+) -> Result<(), SessionError> {
+    state
+        .process_manager
+        .write()
+        .await
+        .set_autosave_layouts(enabled);
+    Ok(())
+}
+
+/// Get all active sessions, plus archived ones if `include_archived` is set
+#[tauri::command]
+pub async fn get_sessions(
+    state: State<'_, AppState>,
+    include_archived: Option<bool>,
+) -> Result<Vec<SessionInfo>, SessionError> {
+    let manager = state.process_manager.read().await;
+    Ok(manager.get_sessions(include_archived.unwrap_or(false)).await)
+}
+
+/// Get the session list changes after `version`, to resync after missed `sessions-changed` events
+///
+/// The result has `full` set, and lists every live and archived session, when
+/// `version` is too old (or from a previous run) to diff from.
+#[tauri::command]
+pub async fn get_sessions_since(
+    state: State<'_, AppState>,
+    version: u64,
+) -> Result<SessionsDiff, SessionError> {
+    let manager = state.process_manager.read().await;
+    Ok(manager.sessions_since(version).await)
+}
+
+/// Archive a session (kills any active process, keeps metadata for later)
+#[tauri::command]
+pub async fn archive_session(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> Result<SessionInfo, SessionError> {
+    let manager = state.process_manager.read().await;
+    Ok(manager.archive_session(&session_id).await?)
+}
+
+/// Restore an archived session so it can be resumed
+#[tauri::command]
+pub async fn unarchive_session(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> Result<SessionInfo, SessionError> {
+    let manager = state.process_manager.read().await;
+    Ok(manager.unarchive_session(&session_id).await?)
 }
 
 /// Get information about a specific session
@@ -165,6 +665,19 @@ pub async fn is_session_alive(
     Ok(manager.is_alive(&session_id).await)
 }
 
+/// Seconds since the session's running prompt last showed progress
+///
+/// Counts stdout messages and progress lines on stderr; None when no prompt
+/// is running.
+#[tauri::command]
+pub async fn get_seconds_since_last_output(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> Result<Option<u64>, SessionError> {
+    let manager = state.process_manager.read().await;
+    Ok(manager.seconds_since_last_output(&session_id).await?)
+}
+
 /// Get the number of active sessions
 #[tauri::command]
 pub async fn get_session_count(state: State<'_, AppState>) -> Result<usize, SessionError> {
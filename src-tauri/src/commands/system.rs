@@ -1,7 +1,26 @@
 //! System commands for paths, directories, and git operations
 
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use tauri::Manager;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::mpsc;
+
+use super::files::report_if_storage_full;
+use super::session::AppState;
+use crate::services::app_log::{self, LogFilter, LogLine, LogSettings, LOG_SETTINGS_FILE_NAME};
+use crate::services::cloud_sync::{self, SyncWarning};
+use crate::services::connectivity::{self, ConnectivityStatus};
+use crate::services::git_info::{self, FileGitInfo};
+use crate::services::git_ops::{git_output, GitEvent, GitOperation};
+use crate::services::http_client::{
+    self, ProxyConfig, ProxySettings, ProxyTestResult, PROXY_SETTINGS_FILE_NAME,
+};
+use crate::services::money::{self, CostFormat, Usd, COST_FORMAT_FILE_NAME};
+use crate::services::operations::OperationGuard;
+use crate::services::paths::{decode_path, encode_path};
+use crate::services::storage::{self, StorageHealth, StorageThresholds};
 
 /// Get the app data directory path
 #[tauri::command]
@@ -14,25 +33,241 @@ pub async fn get_app_data_dir(app_handle: tauri::AppHandle) -> Result<String, St
     // Ensure directory exists
     std::fs::create_dir_all(&path).map_err(|e| format!("Failed to create app data dir: {}", e))?;
 
-    Ok(path.to_string_lossy().to_string())
+    Ok(encode_path(&path))
 }
 
 /// Get the user's home directory
 #[tauri::command]
 pub async fn get_home_dir() -> Result<String, String> {
     dirs::home_dir()
-        .map(|p| p.to_string_lossy().to_string())
+        .map(|p| encode_path(&p))
         .ok_or_else(|| "Failed to get home directory".to_string())
 }
 
+/// Check whether the Anthropic API is reachable
+///
+/// Probes the endpoint the CLI uses (`ANTHROPIC_BASE_URL` or the default),
+/// going through the configured proxy (proxy settings or environment).
+#[tauri::command]
+pub async fn check_connectivity() -> Result<ConnectivityStatus, String> {
+    Ok(connectivity::probe(&connectivity::api_endpoint()).await)
+}
+
+/// Check whether a directory is inside a cloud-synced folder (Dropbox, OneDrive, iCloud)
+///
+/// Sessions there still work, but sync clients can lock files mid-edit and
+/// cause spurious conflicts. Returns None for unsynced directories.
+#[tauri::command]
+pub async fn check_directory_sync_status(path: String) -> Result<Option<SyncWarning>, String> {
+    tokio::task::spawn_blocking(move || cloud_sync::check_directory(&decode_path(&path)))
+        .await
+        .map_err(|e| format!("Failed to check sync status: {}", e))
+}
+
+/// Timeout for `test_proxy_connection`
+const PROXY_TEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+fn app_data_dir(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {}", e))
+}
+
+/// Resolve `settings` off the async runtime (the keyring lookup may block)
+async fn resolve_proxy(
+    settings: ProxySettings,
+    password: Option<String>,
+) -> Result<ProxyConfig, String> {
+    tokio::task::spawn_blocking(move || match password {
+        Some(password) => {
+            ProxyConfig::resolve(&settings, |name| std::env::var(name).ok(), Some(password))
+        }
+        None => ProxyConfig::from_settings(&settings),
+    })
+    .await
+    .map_err(|e| format!("Failed to resolve proxy settings: {}", e))
+}
+
+/// Get the saved proxy settings (the password stays in the keyring)
+#[tauri::command]
+pub async fn get_proxy_settings(app_handle: AppHandle) -> Result<ProxySettings, String> {
+    Ok(ProxySettings::load(&app_data_dir(&app_handle)?))
+}
+
+/// Save the proxy settings and apply them to all backend HTTP clients
+///
+/// `password`, if given, is stored in the OS keyring under `settings.username`.
+#[tauri::command]
+pub async fn set_proxy_settings(
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+    settings: ProxySettings,
+    password: Option<String>,
+) -> Result<ProxySettings, String> {
+    if let (Some(username), Some(password)) = (settings.username.clone(), password) {
+        tokio::task::spawn_blocking(move || {
+            http_client::store_proxy_password(&username, &password)
+        })
+        .await
+        .map_err(|e| format!("Failed to store proxy password: {}", e))??;
+    }
+    let dir = app_data_dir(&app_handle)?;
+    if let Err(e) = settings.save(&dir).await {
+        report_if_storage_full(&state, &dir.join(PROXY_SETTINGS_FILE_NAME), &e).await;
+        return Err(format!("Failed to save proxy settings: {}", e));
+    }
+    http_client::set_proxy_config(resolve_proxy(settings.clone(), None).await?);
+    Ok(settings)
+}
+
+/// Get the cost display settings
+#[tauri::command]
+pub async fn get_cost_format() -> Result<CostFormat, String> {
+    Ok(money::cost_format())
+}
+
+/// Save the cost display settings and use them from now on
+#[tauri::command]
+pub async fn set_cost_format(
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+    format: CostFormat,
+) -> Result<CostFormat, String> {
+    let dir = app_data_dir(&app_handle)?;
+    if let Err(e) = format.save(&dir).await {
+        report_if_storage_full(&state, &dir.join(COST_FORMAT_FILE_NAME), &e).await;
+        return Err(format!("Failed to save cost format: {}", e));
+    }
+    money::set_cost_format(format);
+    Ok(format)
+}
+
+/// The last `tail_lines` lines of the app log (200 when omitted), oldest first
+///
+/// `level_filter` (e.g. "warn") keeps only lines at that level or more severe.
+#[tauri::command]
+pub async fn get_app_logs(
+    tail_lines: Option<usize>,
+    level_filter: Option<String>,
+) -> Result<Vec<LogLine>, String> {
+    let max_level = match level_filter {
+        Some(level) => level
+            .parse()
+            .map_err(|_| format!("Invalid log level: {}", level))?,
+        None => log::LevelFilter::Trace,
+    };
+    let count = tail_lines.unwrap_or(200);
+    tokio::task::spawn_blocking(move || app_log::recent_lines(count, max_level))
+        .await
+        .map_err(|e| format!("Failed to read logs: {}", e))?
+        .map_err(|e| format!("Failed to read logs: {}", e))
+}
+
+/// Get the saved log settings (default level and per-module levels)
+#[tauri::command]
+pub async fn get_log_settings(app_handle: AppHandle) -> Result<LogSettings, String> {
+    Ok(LogSettings::load(&app_data_dir(&app_handle)?))
+}
+
+/// Save the log settings and filter log lines by them from now on
+#[tauri::command]
+pub async fn set_log_settings(
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+    settings: LogSettings,
+) -> Result<LogSettings, String> {
+    LogFilter::from_settings(&settings)?;
+    let dir = app_data_dir(&app_handle)?;
+    if let Err(e) = settings.save(&dir).await {
+        report_if_storage_full(&state, &dir.join(LOG_SETTINGS_FILE_NAME), &e).await;
+        return Err(format!("Failed to save log settings: {}", e));
+    }
+    app_log::apply_settings(&settings)?;
+    Ok(settings)
+}
+
+/// Change the default log level at runtime, keeping the per-module levels
+#[tauri::command]
+pub async fn set_log_level(
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+    level: String,
+) -> Result<LogSettings, String> {
+    let dir = app_data_dir(&app_handle)?;
+    let settings = LogSettings {
+        level,
+        ..LogSettings::load(&dir)
+    };
+    set_log_settings(app_handle, state, settings).await
+}
+
+/// Format a cost in micro-dollars for display (e.g. "$0.30", "<$0.01")
+#[tauri::command]
+pub async fn format_cost(micros: u64) -> Result<String, String> {
+    Ok(Usd::from_micros(micros).to_string())
+}
+
+/// Send a test request through a proxy and report how it failed, if it did
+///
+/// Tests `settings` (and `password`) when given, else the active configuration,
+/// against `endpoint` (default: the API endpoint). The result distinguishes a
+/// refused connection, 407 Proxy Authentication Required, and TLS errors.
+#[tauri::command]
+pub async fn test_proxy_connection(
+    settings: Option<ProxySettings>,
+    password: Option<String>,
+    endpoint: Option<String>,
+) -> Result<ProxyTestResult, String> {
+    let config = match settings {
+        Some(settings) => resolve_proxy(settings, password).await?,
+        None => http_client::proxy_config(),
+    };
+    let endpoint = endpoint.unwrap_or_else(connectivity::api_endpoint);
+    Ok(http_client::test_connection(&config, &endpoint, PROXY_TEST_TIMEOUT).await)
+}
+
+/// Report free space on the app data volume (and `working_dir`'s) and app data writability
+///
+/// `thresholds` overrides the free-space levels that produce warnings. A
+/// healthy result re-arms the `storage-critical` event and resumes persisting
+/// state that was kept in memory while the disk was full.
+#[tauri::command]
+pub async fn check_storage_health(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+    working_dir: Option<String>,
+    thresholds: Option<StorageThresholds>,
+) -> Result<StorageHealth, String> {
+    let app_data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
+    let thresholds = thresholds.unwrap_or_default();
+
+    let health = tokio::task::spawn_blocking(move || {
+        storage::check_storage_health(
+            &app_data_dir,
+            working_dir.as_deref().map(Path::new),
+            &thresholds,
+        )
+    })
+    .await
+    .map_err(|e| format!("Storage check failed: {}", e))?;
+
+    if health.is_healthy() {
+        let manager = state.process_manager.read().await;
+        if manager.storage_alarm().is_raised() {
+            manager.storage_recovered().await;
+        }
+    }
+    Ok(health)
+}
+
 /// Get the current git branch name
 #[tauri::command]
 pub async fn git_current_branch(dir: String) -> Result<String, String> {
-    let output = Command::new("git")
-        .args(["rev-parse", "--abbrev-ref", "HEAD"])
-        .current_dir(&dir)
-        .output()
-        .map_err(|e| format!("Failed to run git: {}", e))?;
+    let output = git_output(&decode_path(&dir), &["rev-parse", "--abbrev-ref", "HEAD"]).await?;
 
     if output.status.success() {
         Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
@@ -44,11 +279,7 @@ pub async fn git_current_branch(dir: String) -> Result<String, String> {
 /// Get uncommitted changes (git diff)
 #[tauri::command]
 pub async fn git_diff(dir: String) -> Result<String, String> {
-    let output = Command::new("git")
-        .args(["diff"])
-        .current_dir(&dir)
-        .output()
-        .map_err(|e| format!("Failed to run git: {}", e))?;
+    let output = git_output(&decode_path(&dir), &["diff"]).await?;
 
     Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
@@ -56,11 +287,7 @@ pub async fn git_diff(dir: String) -> Result<String, String> {
 /// Get git status (short format)
 #[tauri::command]
 pub async fn git_status(dir: String) -> Result<String, String> {
-    let output = Command::new("git")
-        .args(["status", "--short"])
-        .current_dir(&dir)
-        .output()
-        .map_err(|e| format!("Failed to run git: {}", e))?;
+    let output = git_output(&decode_path(&dir), &["status", "--short"]).await?;
 
     Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
@@ -68,15 +295,97 @@ pub async fn git_status(dir: String) -> Result<String, String> {
 /// Get staged changes (git diff --cached)
 #[tauri::command]
 pub async fn git_staged(dir: String) -> Result<String, String> {
-    let output = Command::new("git")
-        .args(["diff", "--cached"])
-        .current_dir(&dir)
-        .output()
-        .map_err(|e| format!("Failed to run git: {}", e))?;
+    let output = git_output(&decode_path(&dir), &["diff", "--cached"]).await?;
 
     Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
+/// Get the git status and last commit of many paths with a fixed number of git runs
+///
+/// Relative paths are resolved against `dir`. Paths outside the repository
+/// are flagged `outside_repo` instead of failing the batch.
+#[tauri::command]
+pub async fn git_file_info_batch(
+    dir: String,
+    paths: Vec<String>,
+) -> Result<Vec<FileGitInfo>, String> {
+    git_info::file_info_batch(&decode_path(&dir), &paths).await
+}
+
+/// Run a git operation in the background, forwarding its events to the frontend
+fn spawn_git_operation(app: AppHandle, operation: GitOperation, guard: OperationGuard) {
+    let (tx, mut rx) = mpsc::channel::<GitEvent>(64);
+    tauri::async_runtime::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            if let Err(e) = app.emit(event.event_name(), &event) {
+                log::error!("Failed to emit {} event: {}", event.event_name(), e);
+            }
+        }
+    });
+    tauri::async_runtime::spawn(async move {
+        // The guard keeps the operation cancellable until git has finished
+        operation.run(guard.token(), tx).await;
+    });
+}
+
+/// Clone a repository in the background; returns the operation id
+///
+/// Progress is emitted as `git-progress` events and the result as a
+/// `git-completed` event. Local paths and `file://` URLs are rejected unless
+/// `allow_local` is set.
+#[tauri::command]
+pub async fn git_clone(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    url: String,
+    dest: String,
+    depth: Option<u32>,
+    allow_local: Option<bool>,
+) -> Result<String, String> {
+    let guard = state.operations.register(None);
+    let operation = GitOperation::clone(
+        guard.id(),
+        &url,
+        &decode_path(&dest),
+        depth,
+        allow_local.unwrap_or(false),
+    )
+    .map_err(|e| e.to_string())?;
+    let operation_id = guard.id().to_string();
+    spawn_git_operation(app, operation, guard);
+    Ok(operation_id)
+}
+
+/// Fetch from the remotes of the repository at `dir` in the background
+///
+/// Returns the operation id; events are the same as for `git_clone`.
+#[tauri::command]
+pub async fn git_fetch(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    dir: String,
+) -> Result<String, String> {
+    if !decode_path(&dir).is_dir() {
+        return Err(format!("Not a directory: {}", dir));
+    }
+    let guard = state.operations.register(None);
+    let operation = GitOperation::fetch(guard.id(), &decode_path(&dir));
+    let operation_id = guard.id().to_string();
+    spawn_git_operation(app, operation, guard);
+    Ok(operation_id)
+}
+
+/// Cancel a running clone or fetch; a partial clone directory is removed
+///
+/// Returns whether an operation was cancelled.
+#[tauri::command]
+pub async fn cancel_git_operation(
+    state: State<'_, AppState>,
+    operation_id: String,
+) -> Result<bool, String> {
+    Ok(state.operations.cancel(&operation_id))
+}
+
 /// Open a file in VS Code
 #[tauri::command]
 pub async fn open_in_vscode(path: String, line: Option<u32>) -> Result<(), String> {
@@ -95,43 +404,280 @@ pub async fn open_in_vscode(path: String, line: Option<u32>) -> Result<(), Strin
 }
 
 /// Open a diff view in VS Code
+///
+/// Both sides are written to unique files in the shared scratch directory,
+/// named after `path` so the editor tabs are recognizable.
 #[tauri::command]
 pub async fn open_diff_in_vscode(
-    _path: String,
+    state: State<'_, AppState>,
+    path: String,
     original: String,
     modified: String,
 ) -> Result<(), String> {
-    use std::io::Write;
+    let scratch = state.process_manager.read().await.scratch().clone();
+    let path = &decode_path(&path);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "diff".to_string());
+    // Keep the extension so the editor picks the right syntax highlighting
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_string())
+        .unwrap_or_default();
 
-    // Create temp files for the diff
-    let temp_dir = std::env::temp_dir();
-    let orig_path = temp_dir.join("original_diff.txt");
-    let mod_path = temp_dir.join("modified_diff.txt");
-
-    let mut orig_file = std::fs::File::create(&orig_path)
-        .map_err(|e| format!("Failed to create temp file: {}", e))?;
-    orig_file
-        .write_all(original.as_bytes())
-        .map_err(|e| format!("Failed to write temp file: {}", e))?;
-
-    let mut mod_file = std::fs::File::create(&mod_path)
-        .map_err(|e| format!("Failed to create temp file: {}", e))?;
-    mod_file
-        .write_all(modified.as_bytes())
-        .map_err(|e| format!("Failed to write temp file: {}", e))?;
+    let mut sides = Vec::new();
+    for (label, content) in [("original", &original), ("modified", &modified)] {
+        let side_path = scratch
+            .allocate_shared(&format!("{}-{}", label, stem), &extension)
+            .await
+            .map_err(|e| format!("Failed to create temp file: {}", e))?;
+        tokio::fs::write(&side_path, content)
+            .await
+            .map_err(|e| format!("Failed to write temp file: {}", e))?;
+        sides.push(side_path);
+    }
 
     Command::new("code")
-        .args([
-            "--diff",
-            orig_path.to_str().unwrap(),
-            mod_path.to_str().unwrap(),
-        ])
+        .arg("--diff")
+        .args(&sides)
         .spawn()
         .map_err(|e| format!("Failed to open VS Code diff: {}", e))?;
 
     Ok(())
 }
 
+/// Starter CLAUDE.md written by `prepare_project_dir` (`{name}` is the directory name)
+const STARTER_CLAUDE_MD: &str = "# {name}
+
+## Overview
+
+Describe what this project does and how it is organized.
+
+## Commands
+
+- Build:
+- Test:
+
+## Conventions
+
+List coding conventions Claude should follow in this project.
+";
+
+/// Starter `.claude/settings.json` written by `prepare_project_dir`
+const STARTER_CLAUDE_SETTINGS: &str = r#"{
+  "permissions": {
+    "allow": [],
+    "deny": []
+  }
+}
+"#;
+
+/// Bootstrap steps to run in `prepare_project_dir` (all off by default)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PrepareProjectOptions {
+    #[serde(default)]
+    pub git_init: bool,
+    #[serde(default)]
+    pub create_claude_md: bool,
+    #[serde(default)]
+    pub create_claude_dir: bool,
+    /// Bootstrap even when the directory is inside another repository
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// What a project directory already contains
+#[derive(Debug, Clone, Serialize)]
+pub struct ProjectDirStatus {
+    pub path: String,
+    pub is_empty: bool,
+    /// Whether the directory is the root of a git repository
+    pub is_git_repo: bool,
+    /// Root of the enclosing repository when the directory is a subdirectory of one
+    pub parent_repo: Option<String>,
+    pub has_claude_md: bool,
+    pub has_claude_dir: bool,
+    pub has_mcp_config: bool,
+}
+
+/// Outcome of one bootstrap step
+#[derive(Debug, Clone, Serialize)]
+pub struct BootstrapStep {
+    pub name: String,
+    pub success: bool,
+    /// The step had nothing to do (e.g. the file already existed)
+    pub skipped: bool,
+    pub message: Option<String>,
+}
+
+/// Result of `prepare_project_dir`: the directory state after any bootstrap steps
+#[derive(Debug, Clone, Serialize)]
+pub struct PrepareProjectResult {
+    pub status: ProjectDirStatus,
+    pub steps: Vec<BootstrapStep>,
+}
+
+impl BootstrapStep {
+    fn done(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            success: true,
+            skipped: false,
+            message: None,
+        }
+    }
+
+    fn skipped(name: &str, message: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            success: true,
+            skipped: true,
+            message: Some(message.to_string()),
+        }
+    }
+
+    fn failed(name: &str, message: String) -> Self {
+        Self {
+            name: name.to_string(),
+            success: false,
+            skipped: false,
+            message: Some(message),
+        }
+    }
+}
+
+/// Top level of the git repository containing `dir`, if any
+fn git_toplevel(dir: &Path) -> Option<PathBuf> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--show-toplevel"])
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let toplevel = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    Some(std::fs::canonicalize(&toplevel).unwrap_or(toplevel))
+}
+
+fn project_dir_status(dir: &Path) -> Result<ProjectDirStatus, String> {
+    let is_empty = std::fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read directory: {}", e))?
+        .next()
+        .is_none();
+
+    let canonical = std::fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    let toplevel = git_toplevel(dir);
+    let is_git_repo = toplevel.as_deref() == Some(canonical.as_path());
+    let parent_repo = toplevel.filter(|_| !is_git_repo).map(|p| encode_path(&p));
+
+    Ok(ProjectDirStatus {
+        path: encode_path(dir),
+        is_empty,
+        is_git_repo,
+        parent_repo,
+        has_claude_md: dir.join("CLAUDE.md").exists(),
+        has_claude_dir: dir.join(".claude").is_dir(),
+        has_mcp_config: dir.join(".mcp.json").exists() || dir.join(".claude/mcp.json").exists(),
+    })
+}
+
+fn git_init_step(dir: &Path, status: &ProjectDirStatus) -> BootstrapStep {
+    const STEP: &str = "git_init";
+    if status.is_git_repo {
+        return BootstrapStep::skipped(STEP, "Already a git repository");
+    }
+    match Command::new("git").arg("init").current_dir(dir).output() {
+        Ok(output) if output.status.success() => BootstrapStep::done(STEP),
+        Ok(output) => BootstrapStep::failed(
+            STEP,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ),
+        Err(e) => BootstrapStep::failed(STEP, format!("Failed to run git: {}", e)),
+    }
+}
+
+/// Write `content` to `path` unless it already exists
+fn create_file_step(name: &str, path: &Path, content: &str) -> BootstrapStep {
+    if path.exists() {
+        return BootstrapStep::skipped(name, "Already exists");
+    }
+    let written = match path.parent() {
+        Some(parent) => std::fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+    .and_then(|_| std::fs::write(path, content));
+    match written {
+        Ok(()) => BootstrapStep::done(name),
+        Err(e) => BootstrapStep::failed(name, format!("Failed to write {}: {}", path.display(), e)),
+    }
+}
+
+/// Report what a project directory contains and optionally bootstrap it
+///
+/// Bootstrap steps (`git init`, a starter `CLAUDE.md`, `.claude/settings.json`)
+/// run independently and are each reported. Bootstrapping a subdirectory of an
+/// existing repository is refused unless `force` is set.
+#[tauri::command]
+pub async fn prepare_project_dir(
+    path: String,
+    options: Option<PrepareProjectOptions>,
+) -> Result<PrepareProjectResult, String> {
+    let options = options.unwrap_or_default();
+    let dir = decode_path(&path);
+    if !dir.is_dir() {
+        return Err(format!("Not a directory: {}", path));
+    }
+
+    let status = project_dir_status(&dir)?;
+    let bootstrapping = options.git_init || options.create_claude_md || options.create_claude_dir;
+    if !bootstrapping {
+        return Ok(PrepareProjectResult {
+            status,
+            steps: Vec::new(),
+        });
+    }
+
+    if let Some(ref parent_repo) = status.parent_repo {
+        if !options.force {
+            return Err(format!(
+                "{} is inside the git repository at {}; use force to bootstrap anyway",
+                path, parent_repo
+            ));
+        }
+    }
+
+    let mut steps = Vec::new();
+    if options.git_init {
+        steps.push(git_init_step(&dir, &status));
+    }
+    if options.create_claude_md {
+        let name = dir
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Project".to_string());
+        let content = STARTER_CLAUDE_MD.replace("{name}", &name);
+        steps.push(create_file_step(
+            "claude_md",
+            &dir.join("CLAUDE.md"),
+            &content,
+        ));
+    }
+    if options.create_claude_dir {
+        steps.push(create_file_step(
+            "claude_settings",
+            &dir.join(".claude").join("settings.json"),
+            STARTER_CLAUDE_SETTINGS,
+        ));
+    }
+
+    Ok(PrepareProjectResult {
+        status: project_dir_status(&dir)?,
+        steps,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -151,4 +697,106 @@ mod tests {
         // Should fail in temp dir which is not a git repo
         assert!(result.is_err());
     }
+
+    fn bootstrap_all() -> Option<PrepareProjectOptions> {
+        Some(PrepareProjectOptions {
+            git_init: true,
+            create_claude_md: true,
+            create_claude_dir: true,
+            force: false,
+        })
+    }
+
+    fn dir_string(dir: &Path) -> String {
+        dir.to_string_lossy().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_prepare_project_dir_detects_empty_dir() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let result = prepare_project_dir(dir_string(dir.path()), None)
+            .await
+            .unwrap();
+
+        assert!(result.steps.is_empty());
+        assert!(result.status.is_empty);
+        assert!(!result.status.is_git_repo);
+        assert!(!result.status.has_claude_md);
+        assert!(!result.status.has_mcp_config);
+    }
+
+    #[tokio::test]
+    async fn test_prepare_project_dir_detects_existing_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("CLAUDE.md"), "# Notes").unwrap();
+        std::fs::write(dir.path().join(".mcp.json"), "{}").unwrap();
+
+        let status = prepare_project_dir(dir_string(dir.path()), None)
+            .await
+            .unwrap()
+            .status;
+        assert!(!status.is_empty);
+        assert!(status.has_claude_md);
+        assert!(status.has_mcp_config);
+    }
+
+    #[tokio::test]
+    async fn test_prepare_project_dir_bootstraps() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let result = prepare_project_dir(dir_string(dir.path()), bootstrap_all())
+            .await
+            .unwrap();
+
+        let names: Vec<&str> = result.steps.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["git_init", "claude_md", "claude_settings"]);
+        assert!(result.steps.iter().all(|s| s.success && !s.skipped));
+        assert!(result.status.is_git_repo);
+        assert!(result.status.has_claude_md);
+        assert!(result.status.has_claude_dir);
+        assert!(dir.path().join(".claude/settings.json").exists());
+
+        // Running again leaves existing files alone
+        std::fs::write(dir.path().join("CLAUDE.md"), "# Mine").unwrap();
+        let again = prepare_project_dir(dir_string(dir.path()), bootstrap_all())
+            .await
+            .unwrap();
+        assert!(again.steps.iter().all(|s| s.success && s.skipped));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("CLAUDE.md")).unwrap(),
+            "# Mine"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prepare_project_dir_refuses_nested_repo() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let status = Command::new("git")
+            .arg("init")
+            .current_dir(dir.path())
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success());
+        let nested = dir.path().join("packages").join("app");
+        std::fs::create_dir_all(&nested).unwrap();
+
+        let detected = prepare_project_dir(dir_string(&nested), None)
+            .await
+            .unwrap()
+            .status;
+        assert!(!detected.is_git_repo);
+        assert!(detected.parent_repo.is_some());
+
+        let refused = prepare_project_dir(dir_string(&nested), bootstrap_all()).await;
+        assert!(refused.unwrap_err().contains("inside the git repository"));
+        assert!(!nested.join("CLAUDE.md").exists());
+
+        let mut forced = bootstrap_all().unwrap();
+        forced.force = true;
+        let result = prepare_project_dir(dir_string(&nested), Some(forced))
+            .await
+            .unwrap();
+        assert!(result.steps.iter().all(|s| s.success));
+        assert!(result.status.is_git_repo);
+    }
 }
@@ -0,0 +1,187 @@
+//! Multi-root workspace commands
+//!
+//! Workspaces group several project roots; sessions spawned for a workspace
+//! see all of them (see `spawn_session`). Git queries on a workspace run on
+//! every root and return the results side by side.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::session::AppState;
+use super::system::{git_diff, git_status};
+use crate::services::paths::encode_path;
+use crate::services::Workspace;
+
+/// Git output for one root of a workspace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RootGitResult {
+    #[serde(with = "crate::services::paths::os_path")]
+    pub root: PathBuf,
+    /// The root does not exist; git was not run
+    pub missing: bool,
+    pub output: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Which git query to run on each root
+#[derive(Debug, Clone, Copy)]
+enum GitQuery {
+    Status,
+    Diff,
+}
+
+/// Run `query` on every root of `workspace`, primary root first
+async fn git_per_root(workspace: &Workspace, query: GitQuery) -> Vec<RootGitResult> {
+    let mut results = Vec::with_capacity(workspace.roots.len());
+    for root in &workspace.roots {
+        let mut result = RootGitResult {
+            root: root.path.clone(),
+            missing: root.missing,
+            output: None,
+            error: None,
+        };
+        if !root.missing {
+            let dir = encode_path(&root.path);
+            let output = match query {
+                GitQuery::Status => git_status(dir).await,
+                GitQuery::Diff => git_diff(dir).await,
+            };
+            match output {
+                Ok(output) => result.output = Some(output),
+                Err(e) => result.error = Some(e),
+            }
+        }
+        results.push(result);
+    }
+    results
+}
+
+/// Create and persist a workspace from a name and its roots (primary first)
+#[tauri::command]
+pub async fn create_workspace(
+    state: State<'_, AppState>,
+    name: String,
+    roots: Vec<PathBuf>,
+) -> Result<Workspace, String> {
+    state
+        .workspaces
+        .lock()
+        .await
+        .create(&name, roots)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// List all workspaces, with roots that no longer exist flagged as missing
+#[tauri::command]
+pub async fn get_workspaces(state: State<'_, AppState>) -> Result<Vec<Workspace>, String> {
+    Ok(state.workspaces.lock().await.list())
+}
+
+/// Delete a workspace definition (its roots are left alone)
+#[tauri::command]
+pub async fn delete_workspace(state: State<'_, AppState>, id: String) -> Result<(), String> {
+    state
+        .workspaces
+        .lock()
+        .await
+        .delete(&id)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Get `git status --short` for every root of a workspace
+#[tauri::command]
+pub async fn git_workspace_status(
+    state: State<'_, AppState>,
+    workspace_id: String,
+) -> Result<Vec<RootGitResult>, String> {
+    let workspace = state
+        .workspaces
+        .lock()
+        .await
+        .get(&workspace_id)
+        .map_err(|e| e.to_string())?;
+    Ok(git_per_root(&workspace, GitQuery::Status).await)
+}
+
+/// Get `git diff` for every root of a workspace
+#[tauri::command]
+pub async fn git_workspace_diff(
+    state: State<'_, AppState>,
+    workspace_id: String,
+) -> Result<Vec<RootGitResult>, String> {
+    let workspace = state
+        .workspaces
+        .lock()
+        .await
+        .get(&workspace_id)
+        .map_err(|e| e.to_string())?;
+    Ok(git_per_root(&workspace, GitQuery::Diff).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::process::Command;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    /// A repo with one committed file
+    fn init_repo(dir: &Path) {
+        std::fs::create_dir_all(dir).unwrap();
+        git(dir, &["init", "-q"]);
+        std::fs::write(dir.join("README.md"), "one\n").unwrap();
+        git(dir, &["add", "."]);
+        git(
+            dir,
+            &[
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=test@example.com",
+                "commit",
+                "-qm",
+                "init",
+            ],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_git_results_per_root() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let [api, web, gone] = ["api", "web", "gone"].map(|name| dir.path().join(name));
+        init_repo(&api);
+        init_repo(&web);
+        std::fs::write(web.join("README.md"), "two\n").unwrap();
+        std::fs::write(web.join("new.txt"), "").unwrap();
+
+        let workspace = Workspace::new("ws", vec![api.clone(), web.clone(), gone.clone()]).unwrap();
+
+        let status = git_per_root(&workspace, GitQuery::Status).await;
+        let roots: Vec<&PathBuf> = status.iter().map(|r| &r.root).collect();
+        assert_eq!(roots, vec![&api, &web, &gone]);
+        assert_eq!(status[0].output.as_deref(), Some(""));
+        let web_status = status[1].output.as_deref().unwrap();
+        assert!(web_status.contains(" M README.md"));
+        assert!(web_status.contains("?? new.txt"));
+        assert!(status[2].missing);
+        assert!(status[2].output.is_none());
+
+        let diff = git_per_root(&workspace, GitQuery::Diff).await;
+        assert_eq!(diff[0].output.as_deref(), Some(""));
+        assert!(diff[1].output.as_deref().unwrap().contains("+two"));
+    }
+}
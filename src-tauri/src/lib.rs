@@ -7,7 +7,15 @@
 pub mod commands;
 pub mod services;
 
+use std::sync::atomic::Ordering;
+
+use commands::control::{apply_control_settings, ControlServerSettings};
+use commands::files::ListingSettings;
 use commands::session::AppState;
+use services::app_log::{self, LogSettings};
+use services::http_client::{self, ProxyConfig, ProxySettings};
+use services::money::{self, CostFormat};
+use services::{ModelCatalog, ToolPresets, WorkspaceStore};
 use tauri::{
     menu::{Menu, MenuItem},
     tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
@@ -38,8 +46,8 @@ fn build_tray_menu(app: &tauri::AppHandle) -> tauri::Result<Menu<tauri::Wry>> {
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    // Initialize logger
-    env_logger::init();
+    // Log to stderr now, and to files once the app data dir is known
+    app_log::init();
 
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
@@ -50,6 +58,81 @@ pub fn run() {
         .plugin(tauri_plugin_global_shortcut::Builder::new().build())
         .manage(AppState::new())
         .setup(|app| {
+            // Load the model catalog and tool presets (including user overrides in
+            // models.json and tool_presets.json), archived sessions and workspaces, then start forwarding process manager events
+            let state = app.state::<AppState>();
+            let app_data_dir = app.path().app_data_dir().ok();
+            let events = tauri::async_runtime::block_on(async {
+                let mut manager = state.process_manager.write().await;
+                if let Some(ref dir) = app_data_dir {
+                    manager.set_model_catalog(ModelCatalog::load(dir));
+                    manager.set_tool_presets(ToolPresets::load(dir));
+                    manager.set_data_dir(dir);
+                    *state.workspaces.lock().await = WorkspaceStore::load(dir);
+                }
+                let events = manager.subscribe();
+                // Sessions of prompts that were running when the app crashed,
+                // reported in a crash-recovery event once forwarding starts
+                manager.recover_from_crash().await;
+                // Scratch dirs of sessions that did not survive the last run
+                manager.sweep_scratch().await;
+                events
+            });
+            commands::session::forward_session_events(app.handle().clone(), events);
+
+            // Cache the CLI version for prompt snapshots without delaying startup
+            let process_manager = state.process_manager.clone();
+            tauri::async_runtime::spawn(async move {
+                let status = process_manager.read().await.check_cli().await;
+                if let Some(error) = status.error {
+                    log::warn!("Claude CLI check failed: {}", error);
+                }
+            });
+
+            // Forward file watcher events (file-changed, memory-changed)
+            let watch_events = tauri::async_runtime::block_on(async {
+                state.file_watcher.lock().await.subscribe()
+            });
+            commands::files::forward_watch_events(app.handle().clone(), watch_events);
+
+            // Keep a log file users can send, filtered by the saved log settings
+            if let Some(ref dir) = app_data_dir {
+                if let Err(e) = app_log::log_to_dir(&app_log::log_dir(dir)) {
+                    log::error!("Failed to open log file: {}", e);
+                }
+                if let Err(e) = app_log::apply_settings(&LogSettings::load(dir)) {
+                    log::warn!("Ignoring invalid log settings: {}", e);
+                }
+            }
+
+            // Route backend HTTP clients through the saved proxy settings
+            if let Some(ref dir) = app_data_dir {
+                let proxy = ProxyConfig::from_settings(&ProxySettings::load(dir));
+                http_client::set_proxy_config(proxy);
+            }
+
+            // Display costs with the saved decimal separator
+            if let Some(ref dir) = app_data_dir {
+                money::set_cost_format(CostFormat::load(dir));
+            }
+
+            // List files with ripgrep if that was left enabled
+            if let Some(ref dir) = app_data_dir {
+                let settings = ListingSettings::load(dir);
+                state.ripgrep_listing.store(settings.use_ripgrep, Ordering::Relaxed);
+            }
+
+            // Start the local control API if it was left enabled
+            if let Some(ref dir) = app_data_dir {
+                let settings = ControlServerSettings::load(dir);
+                if settings.enabled {
+                    let start = apply_control_settings(app.handle(), &state, dir, &settings);
+                    if let Err(e) = tauri::async_runtime::block_on(start) {
+                        log::error!("{}", e);
+                    }
+                }
+            }
+
             // Build and register system tray
             let menu = build_tray_menu(app.handle())?;
             let _tray = TrayIconBuilder::new()
@@ -106,11 +189,37 @@ pub fn run() {
             // Session commands
             commands::session::spawn_session,
             commands::session::send_prompt,
+            commands::session::send_prompt_dry_run,
+            commands::session::send_meta_command,
+            commands::session::broadcast_prompt,
+            commands::session::update_session_config,
+            commands::session::list_available_models,
+            commands::session::list_tool_presets,
+            commands::session::validate_tool_matchers,
+            commands::session::get_prompt_history,
+            commands::session::get_prompt_snapshot,
+            commands::session::export_transcript,
+            commands::session::check_claude_cli,
+            commands::session::get_session_command_line,
+            commands::session::get_usage_summary,
+            commands::session::replay_session_events,
+            commands::session::frontend_ready,
+            commands::session::get_event_bridge_policy,
+            commands::session::set_event_bridge_policy,
             commands::session::send_interrupt,
             commands::session::terminate_session,
+            commands::session::merge_sessions,
+            commands::session::get_tool_result_blob,
+            commands::session::save_project_layout,
+            commands::session::restore_project_layout,
+            commands::session::set_layout_autosave,
             commands::session::get_sessions,
+            commands::session::get_sessions_since,
             commands::session::get_session,
+            commands::session::archive_session,
+            commands::session::unarchive_session,
             commands::session::is_session_alive,
+            commands::session::get_seconds_since_last_output,
             commands::session::get_session_count,
             commands::session::terminate_all_sessions,
             // File commands
@@ -118,20 +227,60 @@ pub fn run() {
             commands::files::write_file_atomic,
             commands::files::check_file_modified,
             commands::files::apply_edit,
+            commands::files::apply_edits,
+            commands::files::get_file_versions,
+            commands::files::restore_file_version,
             commands::files::list_files,
+            commands::files::set_ripgrep_listing,
+            commands::files::search_content,
+            commands::files::list_dir_tree,
+            commands::files::cancel_operation,
             commands::files::file_exists,
             commands::files::ensure_dir,
             commands::files::delete_file,
             commands::files::get_file_metadata,
+            commands::files::watch_path,
+            commands::files::unwatch_path,
+            commands::files::get_watcher_stats,
+            commands::files::configure_watcher,
+            commands::files::get_claude_memory,
+            commands::files::save_claude_memory,
             // System commands
             commands::system::get_app_data_dir,
             commands::system::get_home_dir,
+            commands::system::check_connectivity,
+            commands::system::get_proxy_settings,
+            commands::system::set_proxy_settings,
+            commands::system::test_proxy_connection,
+            commands::system::get_cost_format,
+            commands::system::set_cost_format,
+            commands::system::get_app_logs,
+            commands::system::get_log_settings,
+            commands::system::set_log_settings,
+            commands::system::set_log_level,
+            commands::system::format_cost,
+            commands::system::check_storage_health,
+            commands::system::prepare_project_dir,
+            commands::system::check_directory_sync_status,
             commands::system::git_current_branch,
             commands::system::git_diff,
             commands::system::git_status,
             commands::system::git_staged,
+            commands::system::git_file_info_batch,
+            commands::system::git_clone,
+            commands::system::git_fetch,
+            commands::system::cancel_git_operation,
             commands::system::open_in_vscode,
             commands::system::open_diff_in_vscode,
+            // Control API commands
+            commands::control::get_control_server_status,
+            commands::control::set_control_server,
+            // Workspace commands
+            commands::workspace::create_workspace,
+            commands::workspace::get_workspaces,
+            commands::workspace::delete_workspace,
+            commands::workspace::git_workspace_status,
+            commands::workspace::git_workspace_diff,
             // MCP commands
             commands::mcp::read_mcp_config,
             commands::mcp::write_mcp_config,
@@ -139,10 +288,20 @@ pub fn run() {
             commands::mcp::get_mcp_config_paths,
             commands::mcp::start_mcp_server,
             commands::mcp::stop_mcp_server,
+            commands::mcp::restart_mcp_server,
             commands::mcp::is_process_running,
             commands::mcp::health_check_mcp_server,
             commands::mcp::fetch_mcp_capabilities,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Kill running CLI processes on shutdown
+            if let tauri::RunEvent::Exit = event {
+                let state = app.state::<AppState>();
+                tauri::async_runtime::block_on(async {
+                    state.process_manager.read().await.terminate_all().await;
+                });
+            }
+        });
 }
@@ -0,0 +1,661 @@
+//! Application log: stderr plus rotating files in the app data dir
+//!
+//! End users never see the GUI process's stderr, so every line is also
+//! written to `logs/app.log` (rotated by size, see [`RotatingFile`]) where
+//! `get_app_logs` can read it back. Lines have a fixed shape,
+//! `<timestamp> <LEVEL> <target>: <message>`, with continuation lines
+//! indented, so they can be filtered by level and target.
+//!
+//! Which lines are logged is decided by a [`LogFilter`] built from
+//! [`LogSettings`]: a default level plus per-module directives like
+//! `services::parser=debug`. It can be replaced at runtime. Secrets are
+//! masked before a line reaches stderr or the file.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+
+use super::redact::redact_secrets;
+use super::settings_file;
+
+/// File name of the log settings in the app data directory
+pub const LOG_SETTINGS_FILE_NAME: &str = "logging.json";
+
+/// Directory of the log files in the app data directory
+const LOG_DIR_NAME: &str = "logs";
+
+/// The file being written; rotated ones get a `.1`, `.2`, ... suffix
+const LOG_FILE_NAME: &str = "app.log";
+
+/// Size at which the log file is rotated
+const MAX_LOG_FILE_BYTES: u64 = 5 * 1024 * 1024;
+
+/// Log files kept, including the one being written
+const KEPT_LOG_FILES: usize = 5;
+
+/// Level used when neither the settings nor `RUST_LOG` name one
+const DEFAULT_LEVEL: &str = "info";
+
+/// Log settings as stored in `logging.json`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogSettings {
+    /// Default level: off, error, warn, info, debug or trace
+    #[serde(default = "default_level")]
+    pub level: String,
+    /// Per-module levels such as `services::parser=debug`; this crate's
+    /// module paths may omit the crate name
+    #[serde(default)]
+    pub modules: Vec<String>,
+}
+
+fn default_level() -> String {
+    DEFAULT_LEVEL.to_string()
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self {
+            level: default_level(),
+            modules: Vec::new(),
+        }
+    }
+}
+
+impl LogSettings {
+    /// Settings from a `RUST_LOG` style spec, e.g. `warn,services::parser=debug`
+    pub fn from_spec(spec: &str) -> Self {
+        let mut settings = Self::default();
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            if directive.contains('=') || directive.parse::<LevelFilter>().is_err() {
+                settings.modules.push(directive.to_string());
+            } else {
+                settings.level = directive.to_string();
+            }
+        }
+        settings
+    }
+
+    /// Load the settings from `app_data_dir`; missing or malformed files mean the defaults
+    pub fn load(app_data_dir: &Path) -> Self {
+        settings_file::load(app_data_dir, LOG_SETTINGS_FILE_NAME)
+    }
+
+    /// Write the settings to `app_data_dir` atomically
+    pub async fn save(&self, app_data_dir: &Path) -> std::io::Result<()> {
+        settings_file::save(app_data_dir, LOG_SETTINGS_FILE_NAME, self).await
+    }
+}
+
+/// This crate's name, as it starts the targets of its log lines
+fn crate_name() -> &'static str {
+    module_path!().split("::").next().unwrap_or_default()
+}
+
+/// Which lines are logged: a default level and per-module overrides
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFilter {
+    default: LevelFilter,
+    /// Module paths with their level, longest path first
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self {
+            default: LevelFilter::Info,
+            modules: Vec::new(),
+        }
+    }
+}
+
+impl LogFilter {
+    pub fn from_settings(settings: &LogSettings) -> Result<Self, String> {
+        let parse_level = |level: &str| {
+            level
+                .trim()
+                .parse::<LevelFilter>()
+                .map_err(|_| format!("Invalid log level: {}", level))
+        };
+        let mut modules = Vec::new();
+        for directive in &settings.modules {
+            let (module, level) = directive
+                .split_once('=')
+                .ok_or_else(|| format!("Expected module=level, got: {}", directive))?;
+            let module = module.trim();
+            if module.is_empty() {
+                return Err(format!("Missing module in: {}", directive));
+            }
+            modules.push((module.to_string(), parse_level(level)?));
+        }
+        modules.sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+        Ok(Self {
+            default: parse_level(&settings.level)?,
+            modules,
+        })
+    }
+
+    /// The level lines of `target` are logged at
+    fn level_for(&self, target: &str) -> LevelFilter {
+        let within = |path: &str, module: &str| {
+            path.strip_prefix(module)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+        };
+        let local = target
+            .strip_prefix(crate_name())
+            .and_then(|rest| rest.strip_prefix("::"));
+        self.modules
+            .iter()
+            .find(|(module, _)| {
+                within(target, module) || local.is_some_and(|local| within(local, module))
+            })
+            .map_or(self.default, |&(_, level)| level)
+    }
+
+    pub fn enabled(&self, level: Level, target: &str) -> bool {
+        level <= self.level_for(target)
+    }
+
+    /// The most verbose level any target is logged at, for `log::set_max_level`
+    pub fn max_level(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|&(_, level)| level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+/// The log file, rotated once it reaches its size limit
+///
+/// `app.log` is written; on rotation it becomes `app.log.1`, the previous
+/// `app.log.1` becomes `app.log.2`, and so on up to the kept count.
+#[derive(Debug)]
+pub struct RotatingFile {
+    dir: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: Option<File>,
+    written: u64,
+}
+
+impl RotatingFile {
+    pub fn open(dir: &Path) -> std::io::Result<Self> {
+        Self::with_limits(dir, MAX_LOG_FILE_BYTES, KEPT_LOG_FILES)
+    }
+
+    fn with_limits(dir: &Path, max_bytes: u64, keep: usize) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let mut rotating = Self {
+            dir: dir.to_path_buf(),
+            max_bytes,
+            keep: keep.max(1),
+            file: None,
+            written: 0,
+        };
+        rotating.reopen()?;
+        Ok(rotating)
+    }
+
+    /// The `index`th newest log file in `dir`
+    fn path_in(dir: &Path, index: usize) -> PathBuf {
+        match index {
+            0 => dir.join(LOG_FILE_NAME),
+            _ => dir.join(format!("{}.{}", LOG_FILE_NAME, index)),
+        }
+    }
+
+    fn reopen(&mut self) -> std::io::Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(Self::path_in(&self.dir, 0))?;
+        self.written = file.metadata()?.len();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file = None;
+        let oldest = Self::path_in(&self.dir, self.keep - 1);
+        if let Err(e) = std::fs::remove_file(&oldest) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(e);
+            }
+        }
+        for index in (0..self.keep - 1).rev() {
+            let from = Self::path_in(&self.dir, index);
+            if from.exists() {
+                std::fs::rename(&from, Self::path_in(&self.dir, index + 1))?;
+            }
+        }
+        self.reopen()
+    }
+
+    /// Append `line`, rotating first if it would exceed the size limit
+    pub fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.written > 0 && self.written + len > self.max_bytes {
+            self.rotate()?;
+        }
+        if self.file.is_none() {
+            self.reopen()?;
+        }
+        let file = self.file.as_mut().expect("log file was just opened");
+        writeln!(file, "{}", line)?;
+        self.written += len;
+        Ok(())
+    }
+}
+
+/// `since_epoch` as an RFC 3339 UTC timestamp with milliseconds
+fn utc_timestamp(since_epoch: Duration) -> String {
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// A record as one log line, secrets masked and continuation lines indented
+fn format_line(record: &Record<'_>, since_epoch: Duration) -> String {
+    let message = record.args().to_string();
+    let message = redact_secrets(&message);
+    format!(
+        "{} {:<5} {}: {}",
+        utc_timestamp(since_epoch),
+        record.level(),
+        record.target(),
+        message.trim_end().replace('\n', "\n    ")
+    )
+}
+
+/// A parsed entry of the log file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LogLine {
+    pub timestamp: String,
+    pub level: String,
+    pub target: String,
+    /// The message, including its continuation lines
+    pub message: String,
+}
+
+impl LogLine {
+    fn parse(line: &str) -> Option<Self> {
+        let (timestamp, rest) = line.split_once(' ')?;
+        let rest = rest.trim_start();
+        let (level, rest) = rest.split_once(' ')?;
+        level.parse::<Level>().ok()?;
+        let (target, message) = rest.trim_start().split_once(": ")?;
+        Some(Self {
+            timestamp: timestamp.to_string(),
+            level: level.to_string(),
+            target: target.to_string(),
+            message: message.to_string(),
+        })
+    }
+
+    fn level(&self) -> Level {
+        self.level.parse().unwrap_or(Level::Error)
+    }
+}
+
+/// The entries of one log file, in order
+fn parse_log(content: &str) -> Vec<LogLine> {
+    let mut entries: Vec<LogLine> = Vec::new();
+    for line in content.lines() {
+        match (line.strip_prefix("    "), entries.last_mut()) {
+            (Some(continuation), Some(last)) => {
+                last.message.push('\n');
+                last.message.push_str(continuation);
+            }
+            _ => entries.extend(LogLine::parse(line)),
+        }
+    }
+    entries
+}
+
+/// The last `count` entries logged in `dir` at `max_level` or more severe, oldest first
+pub fn tail(dir: &Path, count: usize, max_level: LevelFilter) -> std::io::Result<Vec<LogLine>> {
+    let mut tail: Vec<LogLine> = Vec::new();
+    for index in 0..KEPT_LOG_FILES {
+        if tail.len() >= count {
+            break;
+        }
+        let content = match std::fs::read(RotatingFile::path_in(dir, index)) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => break,
+            Err(e) => return Err(e),
+        };
+        let mut entries: Vec<LogLine> = parse_log(&content)
+            .into_iter()
+            .filter(|entry| entry.level() <= max_level)
+            .collect();
+        let skip = entries.len().saturating_sub(count - tail.len());
+        entries.drain(..skip);
+        entries.append(&mut tail);
+        tail = entries;
+    }
+    Ok(tail)
+}
+
+/// Logger writing to stderr and, once attached, to a [`RotatingFile`]
+#[derive(Debug)]
+pub struct AppLogger {
+    filter: RwLock<LogFilter>,
+    file: Mutex<Option<RotatingFile>>,
+    to_stderr: bool,
+}
+
+impl AppLogger {
+    fn new(filter: LogFilter, to_stderr: bool) -> Self {
+        Self {
+            filter: RwLock::new(filter),
+            file: Mutex::new(None),
+            to_stderr,
+        }
+    }
+
+    fn set_filter(&self, filter: LogFilter) {
+        *self.filter.write().unwrap_or_else(|e| e.into_inner()) = filter;
+    }
+
+    fn attach_file(&self, file: RotatingFile) {
+        *self.file.lock().unwrap_or_else(|e| e.into_inner()) = Some(file);
+    }
+
+    /// Directory of the attached log file
+    fn log_dir(&self) -> Option<PathBuf> {
+        let file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.as_ref().map(|file| file.dir.clone())
+    }
+}
+
+impl Log for AppLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.filter
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .enabled(metadata.level(), metadata.target())
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let line = format_line(record, since_epoch);
+        if self.to_stderr {
+            eprintln!("{}", line);
+        }
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(file) = file.as_mut() {
+            if let Err(e) = file.write_line(&line) {
+                // Logging about the log file would recurse
+                if self.to_stderr {
+                    eprintln!("Failed to write log file: {}", e);
+                }
+            }
+        }
+    }
+
+    fn flush(&self) {
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(file) = file.as_mut().and_then(|f| f.file.as_mut()) {
+            let _ = file.flush();
+        }
+    }
+}
+
+fn logger() -> &'static AppLogger {
+    static LOGGER: OnceLock<AppLogger> = OnceLock::new();
+    LOGGER.get_or_init(|| AppLogger::new(LogFilter::default(), true))
+}
+
+/// Install the app logger, filtered by `RUST_LOG` if set (stderr only until
+/// [`log_to_dir`])
+pub fn init() {
+    let settings = std::env::var("RUST_LOG")
+        .map(|spec| LogSettings::from_spec(&spec))
+        .unwrap_or_default();
+    let filter = LogFilter::from_settings(&settings).unwrap_or_else(|e| {
+        eprintln!("Ignoring RUST_LOG: {}", e);
+        LogFilter::default()
+    });
+    if log::set_logger(logger()).is_ok() {
+        set_filter(filter);
+    }
+}
+
+fn set_filter(filter: LogFilter) {
+    log::set_max_level(filter.max_level());
+    logger().set_filter(filter);
+}
+
+/// Where the log files of the app live
+pub fn log_dir(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join(LOG_DIR_NAME)
+}
+
+/// Also write log lines to rotating files in `dir`
+pub fn log_to_dir(dir: &Path) -> std::io::Result<()> {
+    logger().attach_file(RotatingFile::open(dir)?);
+    Ok(())
+}
+
+/// Filter log lines by `settings` from now on
+///
+/// `RUST_LOG`, when set, takes precedence over saved settings.
+pub fn apply_settings(settings: &LogSettings) -> Result<(), String> {
+    let filter = LogFilter::from_settings(settings)?;
+    if std::env::var_os("RUST_LOG").is_some() {
+        log::info!("RUST_LOG is set, ignoring the saved log settings");
+        return Ok(());
+    }
+    set_filter(filter);
+    Ok(())
+}
+
+/// The last `count` lines logged to file at `max_level` or more severe
+pub fn recent_lines(count: usize, max_level: LevelFilter) -> std::io::Result<Vec<LogLine>> {
+    match logger().log_dir() {
+        Some(dir) => {
+            logger().flush();
+            tail(&dir, count, max_level)
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn record_at<'a>(level: Level, target: &'a str, args: std::fmt::Arguments<'a>) -> Record<'a> {
+        Record::builder()
+            .level(level)
+            .target(target)
+            .args(args)
+            .build()
+    }
+
+    fn settings(level: &str, modules: &[&str]) -> LogSettings {
+        LogSettings {
+            level: level.to_string(),
+            modules: modules.iter().map(|m| m.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_files_rotate_and_oldest_is_dropped() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut file = RotatingFile::with_limits(temp_dir.path(), 100, 3).unwrap();
+        for i in 0..20 {
+            file.write_line(&format!("line {:02} {}", i, "x".repeat(20)))
+                .unwrap();
+        }
+
+        let path = |index| RotatingFile::path_in(temp_dir.path(), index);
+        for index in 0..3 {
+            let len = std::fs::metadata(path(index)).unwrap().len();
+            assert!(len > 0 && len <= 100, "file {} has {} bytes", index, len);
+        }
+        assert!(!path(3).exists());
+        let newest = std::fs::read_to_string(path(0)).unwrap();
+        assert!(newest.ends_with(&format!("line 19 {}\n", "x".repeat(20))));
+        let older = std::fs::read_to_string(path(1)).unwrap();
+        assert!(older.lines().last().unwrap() < newest.lines().next().unwrap());
+
+        // Reopening appends to the current file instead of truncating it
+        let mut reopened = RotatingFile::with_limits(temp_dir.path(), 100, 3).unwrap();
+        assert_eq!(reopened.written, newest.len() as u64);
+        reopened.write_line("after restart").unwrap();
+        assert!(path(0).exists());
+    }
+
+    #[test]
+    fn test_tail_spans_rotated_files_and_filters_by_level() {
+        let temp_dir = TempDir::new().unwrap();
+        let logger = AppLogger::new(
+            LogFilter::from_settings(&settings("debug", &[])).unwrap(),
+            false,
+        );
+        logger.attach_file(RotatingFile::with_limits(temp_dir.path(), 400, 4).unwrap());
+        let target = "claude_gui_companion_lib::services::process";
+        for i in 0..12 {
+            let level = if i % 3 == 0 {
+                Level::Warn
+            } else {
+                Level::Debug
+            };
+            logger.log(&record_at(level, target, format_args!("event {}", i)));
+        }
+        logger.log(&record_at(
+            Level::Error,
+            target,
+            format_args!("spawn failed:\nline two\n"),
+        ));
+        logger.flush();
+        assert!(RotatingFile::path_in(temp_dir.path(), 1).exists());
+
+        let last = tail(temp_dir.path(), 4, LevelFilter::Trace).unwrap();
+        let messages: Vec<&str> = last.iter().map(|l| l.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec!["event 9", "event 10", "event 11", "spawn failed:\nline two"]
+        );
+        assert_eq!(last[0].level, "WARN");
+        assert_eq!(last[0].target, target);
+        assert!(last[0].timestamp.ends_with('Z'));
+
+        let warnings = tail(temp_dir.path(), 100, LevelFilter::Warn).unwrap();
+        let messages: Vec<&str> = warnings.iter().map(|l| l.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "event 0",
+                "event 3",
+                "event 6",
+                "event 9",
+                "spawn failed:\nline two"
+            ]
+        );
+
+        assert!(
+            tail(&temp_dir.path().join("missing"), 10, LevelFilter::Trace)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_filter_changes_apply_at_runtime() {
+        let logger = AppLogger::new(LogFilter::default(), false);
+        let parser = format!("{}::services::parser", crate_name());
+        let process = format!("{}::services::process", crate_name());
+        let enabled = |level, target: &str| {
+            logger.enabled(&Metadata::builder().level(level).target(target).build())
+        };
+        assert!(enabled(Level::Info, &parser));
+        assert!(!enabled(Level::Debug, &parser));
+
+        let filter =
+            LogFilter::from_settings(&settings("warn", &["services::parser=debug"])).unwrap();
+        assert_eq!(filter.max_level(), LevelFilter::Debug);
+        logger.set_filter(filter);
+        assert!(enabled(Level::Debug, &parser));
+        assert!(!enabled(Level::Trace, &parser));
+        assert!(!enabled(Level::Info, &process));
+        assert!(enabled(Level::Warn, &process));
+        // Only whole path segments match
+        assert!(!enabled(
+            Level::Debug,
+            &format!("{}::services::parser_extra", crate_name())
+        ));
+
+        // Longer paths win, and foreign crates are matched by full path
+        let filter = LogFilter::from_settings(&settings(
+            "info",
+            &["services=off", "services::parser=trace", "reqwest=debug"],
+        ))
+        .unwrap();
+        logger.set_filter(filter);
+        assert!(!enabled(Level::Error, &process));
+        assert!(enabled(Level::Trace, &parser));
+        assert!(enabled(Level::Debug, "reqwest::connect"));
+        assert!(!enabled(Level::Debug, "hyper::client"));
+
+        assert!(LogFilter::from_settings(&settings("loud", &[])).is_err());
+        assert!(LogFilter::from_settings(&settings("info", &["services::parser"])).is_err());
+    }
+
+    #[test]
+    fn test_spec_and_line_format() {
+        assert_eq!(
+            LogSettings::from_spec("warn, services::parser=debug"),
+            settings("warn", &["services::parser=debug"])
+        );
+        assert_eq!(LogSettings::from_spec(""), LogSettings::default());
+
+        assert_eq!(
+            utc_timestamp(Duration::from_millis(1_709_251_199_042)),
+            "2024-02-29T23:59:59.042Z"
+        );
+        let line = format_line(
+            &record_at(
+                Level::Info,
+                "app",
+                format_args!("key sk-ant-REDACTED"),
+            ),
+            Duration::ZERO,
+        );
+        assert_eq!(
+            line,
+            "1970-01-01T00:00:00.000Z INFO  app: key sk-[REDACTED]"
+        );
+        assert_eq!(LogLine::parse(&line).unwrap().message, "key sk-[REDACTED]");
+    }
+}
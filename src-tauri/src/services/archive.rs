@@ -0,0 +1,144 @@
+//! Persisted index of archived sessions
+//!
+//! Archived sessions are removed from the live session list but keep their
+//! metadata, config, and prompt history (including the Claude session ID
+//! needed for `--resume`) in `archived_sessions.json` in the app data
+//! directory, so they can be restored after a restart.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::process::{PromptRecord, SessionConfig, SessionInfo};
+
+/// File name of the archive index in the app data directory
+pub const ARCHIVE_FILE_NAME: &str = "archived_sessions.json";
+
+/// A session that has been moved out of the live list
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedSession {
+    pub info: SessionInfo,
+    pub config: SessionConfig,
+    #[serde(default)]
+    pub history: Vec<PromptRecord>,
+    pub archived_at: u64,
+}
+
+/// In-memory archive index, optionally backed by a file
+#[derive(Debug, Default)]
+pub struct ArchiveStore {
+    path: Option<PathBuf>,
+    sessions: HashMap<String, ArchivedSession>,
+    /// Set after the disk filled up; the index is then kept in memory only
+    persistence_suspended: bool,
+}
+
+impl ArchiveStore {
+    /// Create a store that is not persisted to disk
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Load the archive index from `app_data_dir`
+    ///
+    /// A missing file yields an empty index. A malformed file is logged and
+    /// treated as empty so startup is never blocked by it.
+    pub fn load(app_data_dir: &Path) -> Self {
+        let path = app_data_dir.join(ARCHIVE_FILE_NAME);
+        let sessions = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                log::warn!("Ignoring invalid {}: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("Failed to read {}: {}", path.display(), e);
+                }
+                HashMap::new()
+            }
+        };
+
+        Self {
+            path: Some(path),
+            sessions,
+            persistence_suspended: false,
+        }
+    }
+
+    /// Write the index to disk (write to temp, then rename)
+    ///
+    /// Does nothing while persistence is suspended.
+    pub async fn save(&self) -> std::io::Result<()> {
+        let Some(ref path) = self.path else {
+            return Ok(());
+        };
+        if self.persistence_suspended {
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let content = serde_json::to_string_pretty(&self.sessions)?;
+        let temp_path = path.with_extension("tmp");
+        tokio::fs::write(&temp_path, content).await?;
+        tokio::fs::rename(&temp_path, path).await
+    }
+
+    /// Stop writing the index to disk, keeping changes in memory
+    pub fn suspend_persistence(&mut self) {
+        self.persistence_suspended = true;
+    }
+
+    /// Write to disk again, saving the changes made while suspended
+    pub async fn resume_persistence(&mut self) -> std::io::Result<()> {
+        if !self.persistence_suspended {
+            return Ok(());
+        }
+        self.persistence_suspended = false;
+        let result = self.save().await;
+        if result.is_err() {
+            self.persistence_suspended = true;
+        }
+        result
+    }
+
+    pub fn is_persistence_suspended(&self) -> bool {
+        self.persistence_suspended
+    }
+
+    /// Path of the index file, if persisted
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn contains(&self, session_id: &str) -> bool {
+        self.sessions.contains_key(session_id)
+    }
+
+    pub fn get(&self, session_id: &str) -> Option<&ArchivedSession> {
+        self.sessions.get(session_id)
+    }
+
+    pub fn insert(&mut self, archived: ArchivedSession) {
+        self.sessions.insert(archived.info.id.clone(), archived);
+    }
+
+    pub fn remove(&mut self, session_id: &str) -> Option<ArchivedSession> {
+        self.sessions.remove(session_id)
+    }
+
+    /// Prompt history entries of all archived sessions
+    pub fn histories(&self) -> impl Iterator<Item = &PromptRecord> {
+        self.sessions.values().flat_map(|a| a.history.iter())
+    }
+
+    /// Info for all archived sessions, most recently archived first
+    pub fn infos(&self) -> Vec<SessionInfo> {
+        let mut archived: Vec<&ArchivedSession> = self.sessions.values().collect();
+        archived.sort_by_key(|a| std::cmp::Reverse(a.archived_at));
+        archived.into_iter().map(|a| a.info.clone()).collect()
+    }
+}
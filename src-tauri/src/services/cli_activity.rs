@@ -0,0 +1,245 @@
+//! Progress lines the CLI prints to stderr, as session activity
+//!
+//! Some CLI versions print human-readable progress to stderr even in
+//! stream-json mode: the tool being run, "Compacting…", retry waits. stdout
+//! can be silent for minutes meanwhile, so these lines are the only sign the
+//! CLI is still working. [`classify`] turns the ones it recognizes into a
+//! [`SessionActivity`]; everything else is ordinary stderr.
+//!
+//! The formats are a table of regexes ([`FORMATS`]). When the CLI's output
+//! changes, add an entry (and a fixture line to the tests) rather than
+//! loosening an existing pattern, and bump [`FORMATS_VERSION`].
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::Serialize;
+
+/// Version of the [`FORMATS`] table; bumped whenever an entry is added or changed
+pub const FORMATS_VERSION: u32 = 1;
+
+/// What the CLI is busy with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityKind {
+    /// A tool call is executing
+    ToolRunning,
+    /// The conversation is being compacted
+    Compacting,
+    /// Waiting on something outside the CLI (a retry, a rate limit)
+    Waiting,
+}
+
+/// A recognized progress line
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SessionActivity {
+    pub kind: ActivityKind,
+    /// The tool call or what is being waited for, when the line says
+    pub detail: Option<String>,
+}
+
+/// Known progress line formats, tried in order against a normalized line
+///
+/// A `detail` capture group, if present and matched, becomes the activity's
+/// detail.
+const FORMATS: &[(ActivityKind, &str)] = &[
+    // "Running Bash(npm test)…", "Running tool: Read"
+    (
+        ActivityKind::ToolRunning,
+        r"^Running (?:tool:\s*)?(?P<detail>[A-Za-z][\w-]*(?:\(.*\))?)$",
+    ),
+    // A bare tool call redrawn by the spinner: "Bash(npm test)", "mcp__github__search"
+    (
+        ActivityKind::ToolRunning,
+        r"^(?P<detail>(?:Bash|Read|Write|Edit|MultiEdit|NotebookEdit|Glob|Grep|LS|WebFetch|WebSearch|Task|TodoWrite|mcp__[\w-]+)(?:\(.*\))?)$",
+    ),
+    // "Compacting conversation…", "Auto-compacting…"
+    (
+        ActivityKind::Compacting,
+        r"^(?i:auto-)?(?i:compacting)(?: conversation)?$",
+    ),
+    // "Waiting for permission…", "Waiting for MCP servers"
+    (ActivityKind::Waiting, r"^(?P<detail>Waiting for .+)$"),
+    // "Retrying in 5s (attempt 2/10)…", "API rate limited, retrying in 30 seconds"
+    (
+        ActivityKind::Waiting,
+        r"^(?P<detail>(?:API )?(?i:rate limited)?,? ?(?i:retrying) in \d+(?:\.\d+)?\s*(?:s|sec|seconds?)\b.*)$",
+    ),
+];
+
+fn formats() -> &'static [(ActivityKind, Regex)] {
+    static COMPILED: OnceLock<Vec<(ActivityKind, Regex)>> = OnceLock::new();
+    COMPILED.get_or_init(|| {
+        FORMATS
+            .iter()
+            .map(|&(kind, re)| (kind, Regex::new(re).expect("invalid activity format")))
+            .collect()
+    })
+}
+
+/// Terminal escapes, spinner glyphs, the elapsed-time suffix and the
+/// trailing ellipsis around a progress line
+fn decorations() -> &'static [Regex] {
+    static DECORATIONS: OnceLock<Vec<Regex>> = OnceLock::new();
+    DECORATIONS.get_or_init(|| {
+        [
+            // ANSI CSI sequences (colors, cursor movement, line clearing)
+            r"\x1b\[[0-9;?]*[A-Za-z]",
+            // Leading spinner glyphs and bullets
+            r"^[^\p{L}\p{N}\[(]+",
+            // "(12s · esc to interrupt)", "(esc to interrupt)"
+            r"\s*\((?:\d+s\b[^()]*|[^()]*esc to interrupt[^()]*)\)\s*$",
+            // "…" or "..."
+            r"\s*(?:…|\.\.\.)\s*$",
+        ]
+        .iter()
+        .map(|re| Regex::new(re).expect("invalid decoration pattern"))
+        .collect()
+    })
+}
+
+/// The text of a progress line without its decorations
+fn normalize(line: &str) -> String {
+    let mut text = line.to_string();
+    for decoration in decorations() {
+        text = decoration.replace_all(&text, "").into_owned();
+    }
+    text.trim().to_string()
+}
+
+/// The activity a stderr line reports, if it is a known progress line
+pub fn classify(line: &str) -> Option<SessionActivity> {
+    classify_with_format(line).map(|(_, activity)| activity)
+}
+
+/// [`classify`], with the index of the matching [`FORMATS`] entry
+fn classify_with_format(line: &str) -> Option<(usize, SessionActivity)> {
+    let text = normalize(line);
+    if text.is_empty() {
+        return None;
+    }
+    formats()
+        .iter()
+        .enumerate()
+        .find_map(|(index, (kind, regex))| {
+            let captures = regex.captures(&text)?;
+            let detail = captures
+                .name("detail")
+                .map(|detail| detail.as_str().trim().to_string())
+                .filter(|detail| !detail.is_empty());
+            Some((
+                index,
+                SessionActivity {
+                    kind: *kind,
+                    detail,
+                },
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Progress lines as the CLI printed them, with the expected activity
+    const FIXTURES: &[(&str, ActivityKind, Option<&str>)] = &[
+        (
+            "Running Bash(npm test)…",
+            ActivityKind::ToolRunning,
+            Some("Bash(npm test)"),
+        ),
+        (
+            "Running tool: Read",
+            ActivityKind::ToolRunning,
+            Some("Read"),
+        ),
+        (
+            "\x1b[2K\x1b[1G⠹ Running Grep(\"fn main\") (12s · esc to interrupt)",
+            ActivityKind::ToolRunning,
+            Some("Grep(\"fn main\")"),
+        ),
+        (
+            "⏺ Bash(cargo build --release)",
+            ActivityKind::ToolRunning,
+            Some("Bash(cargo build --release)"),
+        ),
+        (
+            "✻ mcp__github__search_issues…",
+            ActivityKind::ToolRunning,
+            Some("mcp__github__search_issues"),
+        ),
+        ("Compacting conversation…", ActivityKind::Compacting, None),
+        (
+            "\x1b[33m✢ Auto-compacting... (esc to interrupt)\x1b[0m",
+            ActivityKind::Compacting,
+            None,
+        ),
+        (
+            "Waiting for permission…",
+            ActivityKind::Waiting,
+            Some("Waiting for permission"),
+        ),
+        (
+            "Retrying in 5s (attempt 2/10)…",
+            ActivityKind::Waiting,
+            Some("Retrying in 5s (attempt 2/10)"),
+        ),
+        (
+            "API rate limited, retrying in 30 seconds",
+            ActivityKind::Waiting,
+            Some("API rate limited, retrying in 30 seconds"),
+        ),
+    ];
+
+    /// stderr lines that are not progress and must stay raw
+    const NOT_PROGRESS: &[&str] = &[
+        "Error: getaddrinfo ENOTFOUND api.anthropic.com",
+        "[DEBUG] Running hooks for PreToolUse",
+        "(node:1234) ExperimentalWarning: Fetch API is an experimental feature",
+        "Running out of disk space",
+        "Reading configuration from ~/.claude/settings.json",
+        "Compacting failed: conversation too short",
+        "    at Object.<anonymous> (/usr/lib/node_modules/cli.js:12:7)",
+        "",
+        "⠋",
+    ];
+
+    #[test]
+    fn test_fixtures_are_classified() {
+        for &(line, kind, detail) in FIXTURES {
+            assert_eq!(
+                classify(line),
+                Some(SessionActivity {
+                    kind,
+                    detail: detail.map(str::to_string),
+                }),
+                "line: {:?}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_other_stderr_is_not_classified() {
+        for line in NOT_PROGRESS {
+            assert_eq!(classify(line), None, "line: {:?}", line);
+        }
+    }
+
+    #[test]
+    fn test_every_format_has_a_fixture() {
+        let matched: Vec<usize> = FIXTURES
+            .iter()
+            .filter_map(|(line, _, _)| classify_with_format(line))
+            .map(|(index, _)| index)
+            .collect();
+        for (index, (_, pattern)) in FORMATS.iter().enumerate() {
+            assert!(
+                matched.contains(&index),
+                "format {} has no fixture: {}",
+                index,
+                pattern
+            );
+        }
+    }
+}
@@ -0,0 +1,388 @@
+//! Claude CLI argument construction
+//!
+//! `-p` is a boolean flag (print mode); the prompt itself is a positional
+//! argument. Some flags (`--allowedTools`, `--add-dir`) take a variable number of values,
+//! and a prompt starting with `-` could be read as a flag, so the prompt is
+//! always placed last, after a `--` separator. Nothing in the prompt can then
+//! add, remove or change a flag. Arguments are OS strings, so directories
+//! reach the CLI exactly as they are named on disk.
+//!
+//! [`CommandLine`] renders the same arguments as a copyable shell command, so
+//! a session's CLI run can be reproduced in a terminal.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use super::process::SessionConfig;
+use super::redact::redact_secrets;
+
+/// Separator after which the CLI treats everything as positional
+pub const END_OF_OPTIONS: &str = "--";
+
+/// Permission mode read-only sessions are spawned in: the CLI can read and
+/// plan, but not edit files or run commands
+pub const READ_ONLY_PERMISSION_MODE: &str = "plan";
+
+/// Arguments for one `claude -p` run
+///
+/// `prompt` is None when the prompt is fed on stdin. `model` is the resolved
+/// model for this prompt (which may be a per-prompt override of the config's).
+pub fn build_claude_args(
+    config: &SessionConfig,
+    model: &str,
+    prompt: Option<&str>,
+    resume_id: Option<&str>,
+) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec!["-p".into(), "--output-format".into(), "stream-json".into()];
+
+    // Continue the previous conversation
+    if let Some(claude_id) = resume_id {
+        args.push("--resume".into());
+        args.push(claude_id.into());
+    }
+
+    args.push("--model".into());
+    args.push(model.into());
+
+    // A read-only session pre-approves nothing, so no allowed tool can act
+    if config.read_only {
+        args.push("--permission-mode".into());
+        args.push(READ_ONLY_PERMISSION_MODE.into());
+    } else if !config.allowed_tools.is_empty() {
+        args.push("--allowedTools".into());
+        args.push(config.allowed_tools.join(",").into());
+    }
+
+    for dir in &config.add_dirs {
+        args.push("--add-dir".into());
+        args.push(dir.into());
+    }
+
+    if let Some(prompt) = prompt {
+        args.push(END_OF_OPTIONS.into());
+        args.push(prompt.into());
+    }
+    args
+}
+
+/// Shell whose quoting rules a command line follows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShellFlavor {
+    /// sh/bash/zsh: single quotes, with `'` written as `'\''`
+    Posix,
+    /// Windows: double quotes following the `CommandLineToArgvW` rules
+    Windows,
+}
+
+impl ShellFlavor {
+    /// The flavor of the platform the app runs on
+    pub fn current() -> Self {
+        if cfg!(windows) {
+            Self::Windows
+        } else {
+            Self::Posix
+        }
+    }
+}
+
+/// Characters that never need quoting in either flavor
+fn is_plain(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-_./:=,+@".contains(c)
+}
+
+/// Quote one argument so the shell passes it through unchanged
+pub fn shell_quote(arg: &str, flavor: ShellFlavor) -> String {
+    if !arg.is_empty() && arg.chars().all(is_plain) {
+        return arg.to_string();
+    }
+    match flavor {
+        ShellFlavor::Posix => format!("'{}'", arg.replace('\'', r"'\''")),
+        ShellFlavor::Windows => {
+            // Backslashes are literal unless they precede a quote, where they
+            // (and the quote) must be escaped
+            let mut quoted = String::from("\"");
+            let mut backslashes = 0;
+            for c in arg.chars() {
+                match c {
+                    '\\' => backslashes += 1,
+                    '"' => {
+                        quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                        backslashes = 0;
+                    }
+                    _ => {
+                        quoted.push_str(&"\\".repeat(backslashes));
+                        backslashes = 0;
+                    }
+                }
+                if c != '\\' {
+                    quoted.push(c);
+                }
+            }
+            quoted.push_str(&"\\".repeat(backslashes * 2));
+            quoted.push('"');
+            quoted
+        }
+    }
+}
+
+/// The CLI invocation of a session, for copying into a terminal
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandLine {
+    /// Program followed by its arguments
+    pub argv: Vec<String>,
+    /// `argv` quoted for `shell` and joined with spaces
+    pub command: String,
+    pub shell: ShellFlavor,
+    /// Directory the CLI runs in
+    pub working_dir: PathBuf,
+    /// The prompt is too large for argv and is fed on stdin instead
+    pub prompt_on_stdin: bool,
+}
+
+impl CommandLine {
+    /// Build from a program and the arguments of [`build_claude_args`]
+    ///
+    /// Secrets in the arguments (typically in the prompt) are masked, so the
+    /// result is safe to show and copy. The CLI's environment is inherited
+    /// from the app and is not part of the command line; non-Unicode
+    /// arguments are shown lossily.
+    pub fn new(
+        program: &str,
+        args: &[OsString],
+        working_dir: &Path,
+        prompt_on_stdin: bool,
+        shell: ShellFlavor,
+    ) -> Self {
+        let argv: Vec<String> = std::iter::once(program)
+            .map(Into::into)
+            .chain(args.iter().map(|arg| arg.to_string_lossy()))
+            .map(|arg| redact_secrets(&arg).into_owned())
+            .collect();
+        let command = argv
+            .iter()
+            .map(|arg| shell_quote(arg, shell))
+            .collect::<Vec<_>>()
+            .join(" ");
+        Self {
+            argv,
+            command,
+            shell,
+            working_dir: working_dir.to_path_buf(),
+            prompt_on_stdin,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> SessionConfig {
+        SessionConfig {
+            allowed_tools: vec!["Read".to_string(), "Bash(git:*)".to_string()],
+            add_dirs: vec![PathBuf::from("/repos/lib")],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_args_layout() {
+        let args = build_claude_args(&config(), "sonnet", Some("hello"), Some("abc"));
+        assert_eq!(
+            args,
+            vec![
+                "-p",
+                "--output-format",
+                "stream-json",
+                "--resume",
+                "abc",
+                "--model",
+                "sonnet",
+                "--allowedTools",
+                "Read,Bash(git:*)",
+                "--add-dir",
+                "/repos/lib",
+                "--",
+                "hello",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prompt_cannot_change_flags() {
+        let baseline = build_claude_args(&config(), "sonnet", Some("hello"), Some("abc"));
+        let flags = &baseline[..baseline.len() - 1];
+
+        let hostile = [
+            "--help me with this",
+            "--dangerously-skip-permissions",
+            "-p",
+            "--",
+            "--model opus\n--allowedTools Bash",
+            "line one\nline two\r\n--resume other",
+            "it's \"quoted\" and 'single' `tick`",
+            "",
+        ];
+        for prompt in hostile {
+            let args = build_claude_args(&config(), "sonnet", Some(prompt), Some("abc"));
+            assert_eq!(args.len(), baseline.len(), "prompt: {:?}", prompt);
+            assert_eq!(&args[..args.len() - 1], flags, "prompt: {:?}", prompt);
+            assert_eq!(args.last().and_then(|arg| arg.to_str()), Some(prompt));
+        }
+    }
+
+    #[test]
+    fn test_stdin_prompt_has_no_positional() {
+        let args = build_claude_args(&SessionConfig::default(), "opus", None, None);
+        assert_eq!(
+            args,
+            vec!["-p", "--output-format", "stream-json", "--model", "opus"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_unicode_add_dir_is_passed_unchanged() {
+        use std::os::unix::ffi::OsStrExt;
+        let dir = PathBuf::from(std::ffi::OsStr::from_bytes(b"/repos/\xff lib"));
+        let config = SessionConfig {
+            add_dirs: vec![dir.clone()],
+            ..Default::default()
+        };
+        let args = build_claude_args(&config, "sonnet", None, None);
+        assert_eq!(args[args.len() - 2], "--add-dir");
+        assert_eq!(args.last(), Some(&dir.into_os_string()));
+    }
+
+    #[test]
+    fn test_read_only_forces_plan_mode() {
+        let config = SessionConfig {
+            read_only: true,
+            ..config()
+        };
+        let args = build_claude_args(&config, "sonnet", Some("hello"), None);
+        assert_eq!(
+            args,
+            vec![
+                "-p",
+                "--output-format",
+                "stream-json",
+                "--model",
+                "sonnet",
+                "--permission-mode",
+                "plan",
+                "--add-dir",
+                "/repos/lib",
+                "--",
+                "hello",
+            ]
+        );
+    }
+
+    /// Split a POSIX command line the way sh does (quotes and backslashes only)
+    fn posix_split(command: &str) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut current = String::new();
+        let mut in_arg = false;
+        let mut chars = command.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' => {
+                    in_arg = true;
+                    for c in chars.by_ref() {
+                        if c == '\'' {
+                            break;
+                        }
+                        current.push(c);
+                    }
+                }
+                '\\' => {
+                    in_arg = true;
+                    current.extend(chars.next());
+                }
+                ' ' => {
+                    if in_arg {
+                        args.push(std::mem::take(&mut current));
+                        in_arg = false;
+                    }
+                }
+                c => {
+                    in_arg = true;
+                    current.push(c);
+                }
+            }
+        }
+        if in_arg {
+            args.push(current);
+        }
+        args
+    }
+
+    const NASTY_PROMPTS: &[&str] = &[
+        "it's \"quoted\" and 'single' `tick`",
+        "$(rm -rf ~); echo $HOME && exit | cat > /dev/null",
+        "line one\nline two\n--resume other",
+        "trailing backslash \\",
+        "C:\\path with spaces\\\" and \\\\\"",
+        "'",
+        "",
+        "ünïcødé ✓ %PATH% !bang!",
+    ];
+
+    #[test]
+    fn test_posix_quoting_round_trips() {
+        assert_eq!(
+            shell_quote("stream-json", ShellFlavor::Posix),
+            "stream-json"
+        );
+        assert_eq!(shell_quote("", ShellFlavor::Posix), "''");
+        assert_eq!(shell_quote("it's", ShellFlavor::Posix), r"'it'\''s'");
+
+        for prompt in NASTY_PROMPTS {
+            let args = build_claude_args(&config(), "sonnet", Some(prompt), Some("abc"));
+            let line = CommandLine::new(
+                "/usr/bin/claude",
+                &args,
+                Path::new("/w"),
+                false,
+                ShellFlavor::Posix,
+            );
+            assert_eq!(
+                posix_split(&line.command),
+                line.argv,
+                "prompt: {:?}",
+                prompt
+            );
+        }
+    }
+
+    #[test]
+    fn test_windows_quoting() {
+        let quote = |arg| shell_quote(arg, ShellFlavor::Windows);
+        assert_eq!(quote("Read,Bash"), "Read,Bash");
+        assert_eq!(quote(""), "\"\"");
+        assert_eq!(quote("a b"), "\"a b\"");
+        assert_eq!(quote("say \"hi\""), r#""say \"hi\"""#);
+        assert_eq!(quote(r"C:\dir\"), r#""C:\dir\\""#);
+        assert_eq!(quote(r#"a\"b"#), r#""a\\\"b""#);
+        assert_eq!(quote(r"a\b c"), r#""a\b c""#);
+    }
+
+    #[test]
+    fn test_command_line_matches_args_and_masks_secrets() {
+        let prompt = "use key sk-ant-REDACTED";
+        let args = build_claude_args(&config(), "sonnet", Some(prompt), None);
+        let line = CommandLine::new("claude", &args, Path::new("/w"), false, ShellFlavor::Posix);
+
+        assert_eq!(line.argv[0], "claude");
+        for (shown, arg) in line.argv[1..args.len()].iter().zip(&args) {
+            assert_eq!(arg, shown.as_str());
+        }
+        assert!(!line.command.contains("abcdefghijklmnop"));
+        assert_eq!(line.argv.last().unwrap(), "use key sk-[REDACTED]");
+    }
+}
@@ -0,0 +1,356 @@
+//! Detection of cloud-synced working directories
+//!
+//! Sync clients (Dropbox, OneDrive, iCloud Drive) lock and rewrite files
+//! while the CLI edits them, which shows up as failed writes and spurious
+//! conflicts. Sessions in such folders are still created, with a warning.
+//!
+//! Detection is a pure function of a [`FsView`] and the environment, so each
+//! provider's pattern can be tested against a synthesized filesystem:
+//!
+//! - Dropbox: the roots listed in `~/.dropbox/info.json`, or a `.dropbox` /
+//!   `.dropbox.cache` marker in the directory or any parent but the home
+//!   directory
+//! - OneDrive: the `OneDrive`, `OneDriveConsumer` and `OneDriveCommercial`
+//!   environment variables (Windows), and `~/OneDrive*`
+//! - iCloud Drive: `~/Library/Mobile Documents` (macOS) and `~/iCloudDrive`
+//!   (Windows)
+//! - any of them under `~/Library/CloudStorage` (macOS File Provider roots)
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A file sync service
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncProvider {
+    Dropbox,
+    OneDrive,
+    ICloud,
+}
+
+impl SyncProvider {
+    /// Provider of a `~/Library/CloudStorage` entry such as `OneDrive-Personal`
+    fn from_cloud_storage_name(name: &str) -> Option<Self> {
+        if name.starts_with("Dropbox") {
+            Some(SyncProvider::Dropbox)
+        } else if name.starts_with("OneDrive") {
+            Some(SyncProvider::OneDrive)
+        } else if name.starts_with("iCloud") {
+            Some(SyncProvider::ICloud)
+        } else {
+            None
+        }
+    }
+}
+
+/// A working directory found inside a synced folder
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncWarning {
+    pub provider: SyncProvider,
+    /// The synced folder the directory is in
+    pub sync_root: PathBuf,
+}
+
+/// The filesystem queries detection needs
+pub trait FsView {
+    fn read_to_string(&self, path: &Path) -> Option<String>;
+    fn exists(&self, path: &Path) -> bool;
+    /// Names of the entries of a directory; empty if it cannot be read
+    fn list_dir(&self, path: &Path) -> Vec<String>;
+}
+
+/// The real filesystem
+pub struct RealFs;
+
+impl FsView for RealFs {
+    fn read_to_string(&self, path: &Path) -> Option<String> {
+        std::fs::read_to_string(path).ok()
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn list_dir(&self, path: &Path) -> Vec<String> {
+        std::fs::read_dir(path)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Files whose presence in a directory marks it as part of a Dropbox
+const DROPBOX_MARKERS: &[&str] = &[".dropbox", ".dropbox.cache"];
+
+/// Environment variables the OneDrive client sets on Windows
+const ONEDRIVE_ENV_VARS: &[&str] = &["OneDrive", "OneDriveConsumer", "OneDriveCommercial"];
+
+/// Roots listed in Dropbox's `info.json` (`{"personal": {"path": ...}, ...}`)
+fn dropbox_roots(info_json: &str) -> Vec<PathBuf> {
+    let Ok(Value::Object(accounts)) = serde_json::from_str(info_json) else {
+        return Vec::new();
+    };
+    accounts
+        .values()
+        .filter_map(|account| account.get("path").and_then(Value::as_str))
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Known sync roots for a user, most specific source first
+fn sync_roots(
+    home: Option<&Path>,
+    env: &dyn Fn(&str) -> Option<String>,
+    fs: &dyn FsView,
+) -> Vec<(SyncProvider, PathBuf)> {
+    let mut roots = Vec::new();
+    for var in ONEDRIVE_ENV_VARS {
+        if let Some(path) = env(var).filter(|path| !path.is_empty()) {
+            roots.push((SyncProvider::OneDrive, PathBuf::from(path)));
+        }
+    }
+    let Some(home) = home else {
+        return roots;
+    };
+
+    if let Some(info) = fs.read_to_string(&home.join(".dropbox").join("info.json")) {
+        roots.extend(
+            dropbox_roots(&info)
+                .into_iter()
+                .map(|root| (SyncProvider::Dropbox, root)),
+        );
+    }
+
+    let cloud_storage = home.join("Library").join("CloudStorage");
+    for name in fs.list_dir(&cloud_storage) {
+        if let Some(provider) = SyncProvider::from_cloud_storage_name(&name) {
+            roots.push((provider, cloud_storage.join(name)));
+        }
+    }
+
+    for name in fs.list_dir(home) {
+        if name.starts_with("OneDrive") {
+            roots.push((SyncProvider::OneDrive, home.join(name)));
+        }
+    }
+    for root in [
+        home.join("Library").join("Mobile Documents"),
+        home.join("iCloudDrive"),
+    ] {
+        if fs.exists(&root) {
+            roots.push((SyncProvider::ICloud, root));
+        }
+    }
+    roots
+}
+
+/// The synced folder `dir` is in, if any
+///
+/// `dir` should be absolute and normalized; roots are compared by path
+/// components, not by resolving links.
+pub fn detect_sync(
+    dir: &Path,
+    home: Option<&Path>,
+    env: &dyn Fn(&str) -> Option<String>,
+    fs: &dyn FsView,
+) -> Option<SyncWarning> {
+    let known = sync_roots(home, env, fs)
+        .into_iter()
+        .find(|(_, root)| dir.starts_with(root));
+    if let Some((provider, sync_root)) = known {
+        return Some(SyncWarning {
+            provider,
+            sync_root,
+        });
+    }
+
+    // A Dropbox that is not the configured one (another account, a moved
+    // folder); `~/.dropbox` is the client's settings, not a marker
+    dir.ancestors()
+        .filter(|ancestor| Some(*ancestor) != home)
+        .find_map(|ancestor| {
+            DROPBOX_MARKERS
+                .iter()
+                .any(|marker| fs.exists(&ancestor.join(marker)))
+                .then(|| SyncWarning {
+                    provider: SyncProvider::Dropbox,
+                    sync_root: ancestor.to_path_buf(),
+                })
+        })
+}
+
+/// [`detect_sync`] against the real filesystem, environment and home directory
+pub fn check_directory(dir: &Path) -> Option<SyncWarning> {
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let home = dirs::home_dir();
+    let home = home.map(|home| home.canonicalize().unwrap_or(home));
+    detect_sync(
+        &dir,
+        home.as_deref(),
+        &|name| std::env::var(name).ok(),
+        &RealFs,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// A filesystem of the given files; their parent directories exist too
+    #[derive(Default)]
+    struct FakeFs {
+        files: HashMap<PathBuf, String>,
+    }
+
+    impl FakeFs {
+        fn with(paths: &[(&str, &str)]) -> Self {
+            Self {
+                files: paths
+                    .iter()
+                    .map(|(path, content)| (PathBuf::from(path), content.to_string()))
+                    .collect(),
+            }
+        }
+    }
+
+    impl FsView for FakeFs {
+        fn read_to_string(&self, path: &Path) -> Option<String> {
+            self.files.get(path).cloned()
+        }
+
+        fn exists(&self, path: &Path) -> bool {
+            self.files.keys().any(|file| file.starts_with(path))
+        }
+
+        fn list_dir(&self, path: &Path) -> Vec<String> {
+            let mut names: Vec<String> = self
+                .files
+                .keys()
+                .filter_map(|file| file.strip_prefix(path).ok())
+                .filter_map(|rest| rest.components().next())
+                .map(|name| name.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names.dedup();
+            names
+        }
+    }
+
+    fn no_env(_: &str) -> Option<String> {
+        None
+    }
+
+    fn detect(dir: &str, fs: &FakeFs) -> Option<SyncWarning> {
+        detect_sync(Path::new(dir), Some(Path::new("/home/u")), &no_env, fs)
+    }
+
+    fn warning(provider: SyncProvider, root: &str) -> Option<SyncWarning> {
+        Some(SyncWarning {
+            provider,
+            sync_root: PathBuf::from(root),
+        })
+    }
+
+    #[test]
+    fn test_dropbox_from_info_json() {
+        let fs = FakeFs::with(&[(
+            "/home/u/.dropbox/info.json",
+            r#"{"personal": {"path": "/home/u/Dropbox"}, "business": {"path": "/data/Dropbox (Acme)"}}"#,
+        )]);
+        assert_eq!(
+            detect("/home/u/Dropbox/code/app", &fs),
+            warning(SyncProvider::Dropbox, "/home/u/Dropbox")
+        );
+        assert_eq!(
+            detect("/data/Dropbox (Acme)/repo", &fs),
+            warning(SyncProvider::Dropbox, "/data/Dropbox (Acme)")
+        );
+        // A sibling whose name merely starts the same is not inside
+        assert_eq!(detect("/home/u/Dropbox2/repo", &fs), None);
+        assert_eq!(detect("/home/u/code", &fs), None);
+    }
+
+    #[test]
+    fn test_dropbox_from_marker_up_the_tree() {
+        let fs = FakeFs::with(&[("/mnt/old-dropbox/.dropbox.cache/x", "")]);
+        assert_eq!(
+            detect("/mnt/old-dropbox/a/b", &fs),
+            warning(SyncProvider::Dropbox, "/mnt/old-dropbox")
+        );
+        let fs = FakeFs::with(&[("/mnt/box/.dropbox", "")]);
+        assert_eq!(
+            detect("/mnt/box", &fs),
+            warning(SyncProvider::Dropbox, "/mnt/box")
+        );
+    }
+
+    #[test]
+    fn test_onedrive_from_env_and_home() {
+        let fs = FakeFs::default();
+        let env = |name: &str| {
+            (name == "OneDriveCommercial").then(|| "C:\\Users\\u\\OneDrive - Acme".to_string())
+        };
+        let dir = Path::new("C:\\Users\\u\\OneDrive - Acme").join("repo");
+        assert_eq!(
+            detect_sync(&dir, None, &env, &fs),
+            warning(SyncProvider::OneDrive, "C:\\Users\\u\\OneDrive - Acme")
+        );
+
+        let fs = FakeFs::with(&[("/home/u/OneDrive/notes.txt", "")]);
+        assert_eq!(
+            detect("/home/u/OneDrive/repo", &fs),
+            warning(SyncProvider::OneDrive, "/home/u/OneDrive")
+        );
+    }
+
+    #[test]
+    fn test_icloud_known_paths() {
+        let fs = FakeFs::with(&[("/home/u/Library/Mobile Documents/com~apple~CloudDocs/a", "")]);
+        assert_eq!(
+            detect(
+                "/home/u/Library/Mobile Documents/com~apple~CloudDocs/repo",
+                &fs
+            ),
+            warning(SyncProvider::ICloud, "/home/u/Library/Mobile Documents")
+        );
+        let fs = FakeFs::with(&[("/home/u/iCloudDrive/a", "")]);
+        assert_eq!(
+            detect("/home/u/iCloudDrive/repo", &fs),
+            warning(SyncProvider::ICloud, "/home/u/iCloudDrive")
+        );
+    }
+
+    #[test]
+    fn test_macos_cloud_storage_roots() {
+        let fs = FakeFs::with(&[
+            ("/home/u/Library/CloudStorage/OneDrive-Personal/a", ""),
+            ("/home/u/Library/CloudStorage/Dropbox/a", ""),
+            ("/home/u/Library/CloudStorage/SomethingElse/a", ""),
+        ]);
+        assert_eq!(
+            detect("/home/u/Library/CloudStorage/OneDrive-Personal/repo", &fs),
+            warning(
+                SyncProvider::OneDrive,
+                "/home/u/Library/CloudStorage/OneDrive-Personal"
+            )
+        );
+        assert_eq!(
+            detect("/home/u/Library/CloudStorage/Dropbox/repo", &fs),
+            warning(
+                SyncProvider::Dropbox,
+                "/home/u/Library/CloudStorage/Dropbox"
+            )
+        );
+        assert_eq!(
+            detect("/home/u/Library/CloudStorage/SomethingElse/repo", &fs),
+            None
+        );
+    }
+}
@@ -0,0 +1,259 @@
+//! Connectivity probe for the Anthropic API
+//!
+//! Used by the `check_connectivity` command and automatically by the process
+//! manager when a prompt fails with what looks like a network error, so the UI
+//! can say "you appear to be offline" instead of showing a raw stream error.
+
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::http_client::{self, proxy_config};
+
+/// Endpoint probed when `ANTHROPIC_BASE_URL` is not set
+pub const DEFAULT_API_ENDPOINT: &str = "https://api.anthropic.com";
+
+/// Timeout for resolving the endpoint's host name
+const DNS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Timeout for the HEAD request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Result of a connectivity probe
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectivityStatus {
+    /// Whether the endpoint answered with any HTTP response
+    pub online: bool,
+    /// Round-trip time of the HEAD request, if it completed
+    pub latency_ms: Option<u64>,
+    pub proxy_detected: bool,
+    pub endpoint: String,
+    /// What failed (DNS, connect, timeout), if not online
+    pub error: Option<String>,
+}
+
+/// Rough classification of a failed prompt, from its error text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorClass {
+    Network,
+    Auth,
+    RateLimited,
+    Other,
+}
+
+/// Substrings (lowercase) that identify each error class
+const NETWORK_PATTERNS: &[&str] = &[
+    "enotfound",
+    "econnrefused",
+    "econnreset",
+    "etimedout",
+    "eai_again",
+    "getaddrinfo",
+    "network error",
+    "connection error",
+    "fetch failed",
+    "socket hang up",
+    "unable to connect",
+];
+const AUTH_PATTERNS: &[&str] = &[
+    "invalid api key",
+    "authentication_error",
+    "401",
+    "please run /login",
+];
+const RATE_LIMIT_PATTERNS: &[&str] = &["rate_limit", "rate limit", "429", "overloaded"];
+
+/// Classify error output from the CLI (stderr and error messages)
+pub fn classify_error(text: &str) -> ErrorClass {
+    let text = text.to_lowercase();
+    let matches = |patterns: &[&str]| patterns.iter().any(|p| text.contains(p));
+
+    if matches(NETWORK_PATTERNS) {
+        ErrorClass::Network
+    } else if matches(AUTH_PATTERNS) {
+        ErrorClass::Auth
+    } else if matches(RATE_LIMIT_PATTERNS) {
+        ErrorClass::RateLimited
+    } else {
+        ErrorClass::Other
+    }
+}
+
+/// The API endpoint the CLI talks to (`ANTHROPIC_BASE_URL` or the default)
+pub fn api_endpoint() -> String {
+    std::env::var("ANTHROPIC_BASE_URL")
+        .ok()
+        .filter(|url| !url.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_API_ENDPOINT.to_string())
+}
+
+/// Whether a proxy is configured (in the proxy settings or the environment)
+pub fn proxy_detected() -> bool {
+    proxy_config().is_configured()
+}
+
+/// Split a URL into (host, port) for DNS resolution
+fn host_and_port(endpoint: &str) -> Option<(String, u16)> {
+    let (scheme, rest) = endpoint.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority.rsplit('@').next()?;
+    let default_port = if scheme.eq_ignore_ascii_case("http") {
+        80
+    } else {
+        443
+    };
+
+    // Bracketed IPv6 literal, optionally with a port
+    if let Some(rest) = authority.strip_prefix('[') {
+        let (host, after) = rest.split_once(']')?;
+        let port = after
+            .strip_prefix(':')
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(default_port);
+        return Some((host.to_string(), port));
+    }
+
+    match authority.split_once(':') {
+        Some((host, port)) => Some((host.to_string(), port.parse().ok()?)),
+        None => Some((authority.to_string(), default_port)),
+    }
+}
+
+/// Probe `endpoint`: resolve its host, then send a HEAD request
+pub async fn probe(endpoint: &str) -> ConnectivityStatus {
+    let config = proxy_config();
+    let proxy_detected = config.is_configured();
+    let offline = |error: String| ConnectivityStatus {
+        online: false,
+        latency_ms: None,
+        proxy_detected,
+        endpoint: endpoint.to_string(),
+        error: Some(error),
+    };
+
+    let Some((host, port)) = host_and_port(endpoint) else {
+        return offline(format!("Invalid endpoint URL: {}", endpoint));
+    };
+
+    // Through a proxy the proxy resolves the host, so a local DNS failure is not conclusive
+    let proxied = reqwest::Url::parse(endpoint)
+        .ok()
+        .is_some_and(|url| config.proxy_for(&url).is_some());
+    if !proxied {
+        match tokio::time::timeout(DNS_TIMEOUT, tokio::net::lookup_host((host.as_str(), port)))
+            .await
+        {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => return offline(format!("DNS resolution failed: {}", e)),
+            Err(_) => return offline("DNS resolution timed out".to_string()),
+        }
+    }
+
+    let client = match http_client::client_with(&config, REQUEST_TIMEOUT) {
+        Ok(client) => client,
+        Err(e) => return offline(format!("Failed to build HTTP client: {}", e)),
+    };
+
+    let started = Instant::now();
+    match client.head(endpoint).send().await {
+        // Any HTTP response (even 401/404) means the API is reachable
+        Ok(_) => ConnectivityStatus {
+            online: true,
+            latency_ms: Some(started.elapsed().as_millis() as u64),
+            proxy_detected,
+            endpoint: endpoint.to_string(),
+            error: None,
+        },
+        Err(e) if e.is_timeout() => offline("Request timed out".to_string()),
+        Err(e) if e.is_connect() => offline(format!("Connection failed: {}", e)),
+        Err(e) => offline(format!("Request failed: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve minimal HTTP 200 responses on a local port
+    async fn local_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0u8; 1024];
+                let _ = socket.read(&mut buf).await;
+                let _ = socket
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                    .await;
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[test]
+    fn test_classify_error() {
+        assert_eq!(
+            classify_error("Error: getaddrinfo ENOTFOUND api.anthropic.com"),
+            ErrorClass::Network
+        );
+        assert_eq!(
+            classify_error("TypeError: fetch failed"),
+            ErrorClass::Network
+        );
+        assert_eq!(
+            classify_error("Invalid API key · Please run /login"),
+            ErrorClass::Auth
+        );
+        assert_eq!(
+            classify_error("429 rate_limit_error"),
+            ErrorClass::RateLimited
+        );
+        assert_eq!(classify_error("tool failed"), ErrorClass::Other);
+    }
+
+    #[test]
+    fn test_host_and_port() {
+        assert_eq!(
+            host_and_port("https://api.anthropic.com"),
+            Some(("api.anthropic.com".to_string(), 443))
+        );
+        assert_eq!(
+            host_and_port("http://127.0.0.1:8080/v1"),
+            Some(("127.0.0.1".to_string(), 8080))
+        );
+        assert_eq!(
+            host_and_port("http://[::1]:9000"),
+            Some(("::1".to_string(), 9000))
+        );
+        assert_eq!(host_and_port("not a url"), None);
+    }
+
+    #[tokio::test]
+    async fn test_probe_local_server_is_online() {
+        let endpoint = local_server().await;
+        let status = probe(&endpoint).await;
+        assert!(status.online, "{:?}", status.error);
+        assert!(status.latency_ms.is_some());
+        assert_eq!(status.endpoint, endpoint);
+    }
+
+    #[tokio::test]
+    async fn test_probe_unroutable_address_is_offline() {
+        // Reserved TEST-NET-1 address: never routable
+        let started = Instant::now();
+        let status = probe("http://192.0.2.1:81").await;
+        assert!(!status.online);
+        assert!(status.error.is_some());
+        assert!(started.elapsed() < REQUEST_TIMEOUT + Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_probe_invalid_endpoint() {
+        let status = probe("api.anthropic.com").await;
+        assert!(!status.online);
+        assert!(status.error.unwrap().contains("Invalid endpoint"));
+    }
+}
@@ -0,0 +1,270 @@
+//! Context pressure tracking
+//!
+//! Long sessions approach the model's context limit, at which point the CLI
+//! compacts the conversation (or fails with "prompt is too long"). This module
+//! turns the stream's hints — compaction boundaries, per-request token usage
+//! and context-limit errors — into a per-session [`ContextPressure`] so the UI
+//! can suggest `/compact` or a fresh session before answers degrade.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::parser::{Compaction, StreamMessage};
+
+/// Context window assumed for all models
+pub const DEFAULT_CONTEXT_WINDOW_TOKENS: u64 = 200_000;
+
+/// Context usage (in percent of the window) from which a session is at the limit
+pub const AT_LIMIT_PERCENT: u64 = 90;
+
+/// For how many prompts after a compaction the session counts as recently compacted
+pub const COMPACTED_RECENTLY_PROMPTS: u32 = 3;
+
+/// Error texts that mean the conversation no longer fits the context window
+const LIMIT_ERROR_MARKERS: &[&str] = &[
+    "prompt is too long",
+    "context window",
+    "context length",
+    "context limit",
+];
+
+/// How close a session is to its context limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextPressure {
+    #[default]
+    Normal,
+    /// The CLI compacted the conversation within the last few prompts
+    CompactedRecently,
+    /// The context is (nearly) full; the next prompt may fail or compact
+    AtLimit,
+}
+
+/// What a stream message says about the context
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContextSignal {
+    Compacted(Compaction),
+    /// Tokens sent with the latest request (input plus cached input)
+    Usage(u64),
+    /// The CLI or API refused the prompt for being too long
+    LimitReached,
+}
+
+fn mentions_limit(text: &str) -> bool {
+    let text = text.to_lowercase();
+    LIMIT_ERROR_MARKERS
+        .iter()
+        .any(|marker| text.contains(marker))
+}
+
+/// Context size of a request from its `usage` object
+fn context_tokens(usage: &Value) -> Option<u64> {
+    let field = |name: &str| usage.get(name).and_then(Value::as_u64);
+    let input = field("input_tokens")?;
+    Some(
+        input
+            + field("cache_creation_input_tokens").unwrap_or(0)
+            + field("cache_read_input_tokens").unwrap_or(0),
+    )
+}
+
+/// Extract the context signal of a message, if any
+///
+/// Unknown shapes yield no signal rather than an error.
+pub fn context_signal(msg: &StreamMessage) -> Option<ContextSignal> {
+    if let Some(compaction) = msg.compaction() {
+        return Some(ContextSignal::Compacted(compaction));
+    }
+    match msg {
+        StreamMessage::Assistant { extra, .. } => extra
+            .get("usage")
+            .or_else(|| extra.get("message").and_then(|m| m.get("usage")))
+            .and_then(context_tokens)
+            .map(ContextSignal::Usage),
+        StreamMessage::Result {
+            is_error: Some(true),
+            result: Some(text),
+            ..
+        } if mentions_limit(text) => Some(ContextSignal::LimitReached),
+        StreamMessage::Error { error, .. } if mentions_limit(&error.message) => {
+            Some(ContextSignal::LimitReached)
+        }
+        _ => None,
+    }
+}
+
+/// Per-session context state
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ContextTracker {
+    pressure: ContextPressure,
+    /// Tokens of the latest request (or the post-compaction size)
+    context_tokens: Option<u64>,
+    /// `prompt_count` of the prompt during which the last compaction happened
+    compacted_at_prompt: Option<u32>,
+}
+
+impl ContextTracker {
+    /// Resume tracking with a previously reported pressure (e.g. after unarchiving)
+    pub fn resume(pressure: ContextPressure, prompt_count: u32) -> Self {
+        Self {
+            pressure,
+            context_tokens: None,
+            compacted_at_prompt: (pressure == ContextPressure::CompactedRecently)
+                .then_some(prompt_count),
+        }
+    }
+
+    pub fn pressure(&self) -> ContextPressure {
+        self.pressure
+    }
+
+    pub fn context_tokens(&self) -> Option<u64> {
+        self.context_tokens
+    }
+
+    /// Pressure without an at-limit reading
+    fn baseline(&self, prompt_count: u32) -> ContextPressure {
+        match self.compacted_at_prompt {
+            Some(at) if prompt_count < at + COMPACTED_RECENTLY_PROMPTS => {
+                ContextPressure::CompactedRecently
+            }
+            _ => ContextPressure::Normal,
+        }
+    }
+
+    fn set(&mut self, pressure: ContextPressure) -> bool {
+        let changed = self.pressure != pressure;
+        self.pressure = pressure;
+        changed
+    }
+
+    /// Apply a signal seen during prompt number `prompt_count`; returns whether the pressure changed
+    pub fn apply(&mut self, signal: &ContextSignal, prompt_count: u32) -> bool {
+        match signal {
+            ContextSignal::Compacted(compaction) => {
+                self.compacted_at_prompt = Some(prompt_count);
+                self.context_tokens = compaction.post_tokens;
+                self.set(ContextPressure::CompactedRecently)
+            }
+            ContextSignal::Usage(tokens) => {
+                self.context_tokens = Some(*tokens);
+                if *tokens * 100 >= DEFAULT_CONTEXT_WINDOW_TOKENS * AT_LIMIT_PERCENT {
+                    self.set(ContextPressure::AtLimit)
+                } else {
+                    self.set(self.baseline(prompt_count))
+                }
+            }
+            ContextSignal::LimitReached => self.set(ContextPressure::AtLimit),
+        }
+    }
+
+    /// A new prompt started; "compacted recently" wears off after a few prompts
+    pub fn prompt_started(&mut self, prompt_count: u32) -> bool {
+        if self.pressure != ContextPressure::CompactedRecently {
+            return false;
+        }
+        self.set(self.baseline(prompt_count))
+    }
+
+    /// The conversation was cleared (`/clear`)
+    pub fn reset(&mut self) -> bool {
+        let changed = self.pressure != ContextPressure::Normal;
+        *self = Self::default();
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::parser::StreamJsonParser;
+    use serde_json::json;
+
+    fn parse(line: &str) -> StreamMessage {
+        let mut parser = StreamJsonParser::new();
+        parser
+            .parse_chunk(format!("{}\n", line).as_bytes())
+            .pop()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_signals_from_fixtures() {
+        let compact = parse(
+            r#"{"type":"system","subtype":"compact_boundary","session_id":"s","compact_metadata":{"trigger":"auto","pre_tokens":181000}}"#,
+        );
+        assert!(matches!(
+            context_signal(&compact),
+            Some(ContextSignal::Compacted(Compaction {
+                pre_tokens: Some(181000),
+                ..
+            }))
+        ));
+
+        let assistant = parse(
+            r#"{"type":"message","role":"assistant","content":[],"usage":{"input_tokens":12,"cache_creation_input_tokens":3000,"cache_read_input_tokens":150000,"output_tokens":400}}"#,
+        );
+        assert_eq!(
+            context_signal(&assistant),
+            Some(ContextSignal::Usage(153_012))
+        );
+
+        let too_long = parse(
+            r#"{"type":"result","subtype":"error_during_execution","is_error":true,"result":"API Error: 400 prompt is too long: 204817 tokens > 200000 maximum"}"#,
+        );
+        assert_eq!(context_signal(&too_long), Some(ContextSignal::LimitReached));
+
+        // Ordinary messages and shifted formats carry no signal
+        for line in [
+            r#"{"type":"result","subtype":"success","is_error":false,"result":"The context window of this function is small"}"#,
+            r#"{"type":"message","role":"assistant","content":"hi","usage":{"output_tokens":5}}"#,
+            r#"{"type":"system","subtype":"init","session_id":"s"}"#,
+            r#"{"type":"context_pressure","level":"high"}"#,
+        ] {
+            assert_eq!(context_signal(&parse(line)), None, "line: {}", line);
+        }
+    }
+
+    #[test]
+    fn test_tracker_transitions() {
+        let mut tracker = ContextTracker::default();
+        let compacted = ContextSignal::Compacted(Compaction {
+            trigger: Some("auto".to_string()),
+            pre_tokens: Some(190_000),
+            post_tokens: Some(20_000),
+        });
+
+        assert!(!tracker.apply(&ContextSignal::Usage(50_000), 1));
+        assert!(tracker.apply(&ContextSignal::Usage(185_000), 2));
+        assert_eq!(tracker.pressure(), ContextPressure::AtLimit);
+
+        assert!(tracker.apply(&compacted, 2));
+        assert_eq!(tracker.pressure(), ContextPressure::CompactedRecently);
+        assert_eq!(tracker.context_tokens(), Some(20_000));
+
+        // Usage below the limit keeps the recent compaction visible
+        assert!(!tracker.apply(&ContextSignal::Usage(25_000), 3));
+        assert!(!tracker.prompt_started(4));
+        assert!(tracker.prompt_started(5));
+        assert_eq!(tracker.pressure(), ContextPressure::Normal);
+
+        assert!(tracker.apply(&ContextSignal::LimitReached, 6));
+        assert!(tracker.reset());
+        assert_eq!(tracker, ContextTracker::default());
+    }
+
+    #[test]
+    fn test_resume_keeps_recent_compaction_window() {
+        let mut tracker = ContextTracker::resume(ContextPressure::CompactedRecently, 7);
+        assert!(!tracker.prompt_started(8));
+        assert!(tracker.prompt_started(10));
+        assert_eq!(
+            ContextTracker::resume(ContextPressure::AtLimit, 3).pressure(),
+            ContextPressure::AtLimit
+        );
+        assert_eq!(
+            json!(ContextPressure::CompactedRecently),
+            "compacted_recently"
+        );
+    }
+}
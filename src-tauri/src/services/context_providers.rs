@@ -0,0 +1,397 @@
+//! Context blocks prepended to prompts
+//!
+//! A session can list [`ContextProvider`]s in its config, e.g. the current
+//! `git status` or the output of a test command. Before each prompt they are
+//! resolved in order and assembled into a preamble of fenced blocks that is
+//! prepended to the prompt text:
+//!
+//! ````text
+//! <context>
+//! ### git status
+//! ```
+//!  M src/main.rs
+//! ```
+//! </context>
+//!
+//! <the user's prompt>
+//! ````
+//!
+//! Each provider's output is truncated to its own limit and the total to a
+//! byte budget. A provider that fails contributes an inline error note
+//! instead of blocking the prompt.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+
+use super::git_ops::git_output;
+use super::hooks::{run_hook, HookKind, DEFAULT_HOOK_TIMEOUT};
+
+/// Total bytes of provider output prepended to one prompt
+pub const DEFAULT_CONTEXT_BUDGET_BYTES: usize = 64 * 1024;
+
+/// Bytes kept per provider when it sets no limit of its own
+pub const DEFAULT_PROVIDER_MAX_BYTES: usize = 16 * 1024;
+
+const TRUNCATION_NOTE: &str = "[truncated]";
+
+/// A source of context for each prompt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContextProvider {
+    /// `git status --short` of the working directory
+    GitStatus,
+    /// `git diff`, or `git diff --cached` if `staged`
+    GitDiff {
+        #[serde(default)]
+        staged: bool,
+    },
+    /// A file, relative to the working directory unless absolute
+    FileContents {
+        path: PathBuf,
+        #[serde(default)]
+        max_bytes: Option<usize>,
+    },
+    /// A shell command run in the working directory (stdout, then stderr)
+    Command {
+        cmd: String,
+        /// Seconds before the command is killed (defaults to the hook timeout)
+        #[serde(default)]
+        timeout_secs: Option<u64>,
+    },
+}
+
+impl ContextProvider {
+    /// Heading of the provider's block
+    pub fn label(&self) -> String {
+        match self {
+            ContextProvider::GitStatus => "git status".to_string(),
+            ContextProvider::GitDiff { staged: false } => "git diff".to_string(),
+            ContextProvider::GitDiff { staged: true } => "git diff --cached".to_string(),
+            ContextProvider::FileContents { path, .. } => format!("file {}", path.display()),
+            ContextProvider::Command { cmd, .. } => format!("command `{}`", cmd),
+        }
+    }
+
+    fn max_bytes(&self) -> usize {
+        match self {
+            ContextProvider::FileContents {
+                max_bytes: Some(max_bytes),
+                ..
+            } => *max_bytes,
+            _ => DEFAULT_PROVIDER_MAX_BYTES,
+        }
+    }
+
+    /// The provider's output, or why it could not be produced
+    ///
+    /// Output may exceed `max_bytes` by a little; it is truncated when assembled.
+    async fn resolve(&self, working_dir: &Path) -> Result<String, String> {
+        match self {
+            ContextProvider::GitStatus => git_text(working_dir, &["status", "--short"]).await,
+            ContextProvider::GitDiff { staged } => {
+                let args: &[&str] = if *staged {
+                    &["diff", "--cached"]
+                } else {
+                    &["diff"]
+                };
+                git_text(working_dir, args).await
+            }
+            ContextProvider::FileContents { path, .. } => {
+                read_prefix(&working_dir.join(path), self.max_bytes()).await
+            }
+            ContextProvider::Command { cmd, timeout_secs } => {
+                let timeout = timeout_secs
+                    .map(Duration::from_secs)
+                    .unwrap_or(DEFAULT_HOOK_TIMEOUT);
+                let output = run_hook(HookKind::PrePrompt, cmd, working_dir, &[], timeout).await;
+                // A failing test command is exactly the output that is wanted;
+                // only a command that did not run to completion is an error
+                let Some(code) = output.exit_code else {
+                    return Err(if output.timed_out {
+                        format!("command timed out after {} ms", output.duration_ms)
+                    } else {
+                        output.failure_reason()
+                    });
+                };
+                let mut text = output.stdout;
+                if !output.stderr.is_empty() {
+                    if !text.is_empty() && !text.ends_with('\n') {
+                        text.push('\n');
+                    }
+                    text.push_str(&output.stderr);
+                }
+                if code != 0 {
+                    if !text.is_empty() && !text.ends_with('\n') {
+                        text.push('\n');
+                    }
+                    text.push_str(&format!("[exit status {}]", code));
+                }
+                Ok(text)
+            }
+        }
+    }
+}
+
+/// stdout of a git command that must succeed
+async fn git_text(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let output = git_output(dir, args).await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(if stderr.is_empty() {
+            format!("git exited with {}", output.status)
+        } else {
+            stderr
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Up to `max_bytes + 1` bytes of a file, so truncation can be detected
+async fn read_prefix(path: &Path, max_bytes: usize) -> Result<String, String> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut bytes = Vec::new();
+    file.take(max_bytes as u64 + 1)
+        .read_to_end(&mut bytes)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// What one provider added to a prompt, kept in the prompt history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContextContribution {
+    /// The provider's label, e.g. "git status"
+    pub provider: String,
+    /// Bytes of output included (0 for a failed provider)
+    pub bytes: usize,
+    /// Output was cut to the provider's limit or the remaining budget
+    #[serde(default)]
+    pub truncated: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// The assembled context blocks
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ContextPreamble {
+    pub text: String,
+    pub contributions: Vec<ContextContribution>,
+}
+
+impl ContextPreamble {
+    /// `prompt` with the preamble in front; unchanged if there is no context
+    pub fn prepend_to(&self, prompt: &str) -> String {
+        if self.text.is_empty() {
+            prompt.to_string()
+        } else {
+            format!("{}\n{}", self.text, prompt)
+        }
+    }
+}
+
+/// Longest prefix of `text` within `max_bytes`, cut at a char boundary
+fn truncate_to(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// A backtick fence longer than any run of backticks in `body`
+fn fence_for(body: &str) -> String {
+    let longest = body.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+/// Assemble resolved provider outputs, in order, within `budget` bytes of output
+pub fn assemble(
+    outputs: Vec<(String, usize, Result<String, String>)>,
+    budget: usize,
+) -> ContextPreamble {
+    if outputs.is_empty() {
+        return ContextPreamble::default();
+    }
+
+    let mut remaining = budget;
+    let mut text = String::from("<context>\n");
+    let mut contributions = Vec::with_capacity(outputs.len());
+    for (provider, max_bytes, output) in outputs {
+        let (body, contribution) = match output {
+            Ok(output) => {
+                let kept = truncate_to(&output, max_bytes.min(remaining));
+                let truncated = kept.len() < output.len();
+                remaining -= kept.len();
+                let mut body = kept.to_string();
+                if truncated {
+                    if !body.is_empty() && !body.ends_with('\n') {
+                        body.push('\n');
+                    }
+                    body.push_str(TRUNCATION_NOTE);
+                }
+                let contribution = ContextContribution {
+                    provider: provider.clone(),
+                    bytes: kept.len(),
+                    truncated,
+                    error: None,
+                };
+                (body, contribution)
+            }
+            Err(error) => {
+                let body = format!("[error: {}]", error);
+                let contribution = ContextContribution {
+                    provider: provider.clone(),
+                    bytes: 0,
+                    truncated: false,
+                    error: Some(error),
+                };
+                (body, contribution)
+            }
+        };
+
+        let fence = fence_for(&body);
+        text.push_str(&format!("### {}\n{}\n{}", provider, fence, body));
+        if !body.is_empty() && !body.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push_str(&fence);
+        text.push('\n');
+        contributions.push(contribution);
+    }
+    text.push_str("</context>\n");
+
+    ContextPreamble {
+        text,
+        contributions,
+    }
+}
+
+/// Resolve `providers` in order in `working_dir` and assemble their blocks
+pub async fn build_preamble(
+    providers: &[ContextProvider],
+    working_dir: &Path,
+    budget: usize,
+) -> ContextPreamble {
+    let mut outputs = Vec::with_capacity(providers.len());
+    for provider in providers {
+        let output = provider.resolve(working_dir).await;
+        if let Err(ref e) = output {
+            log::warn!("Context provider {} failed: {}", provider.label(), e);
+        }
+        outputs.push((provider.label(), provider.max_bytes(), output));
+    }
+    assemble(outputs, budget)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn command(cmd: &str) -> ContextProvider {
+        ContextProvider::Command {
+            cmd: cmd.to_string(),
+            timeout_secs: Some(5),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_blocks_follow_provider_order() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("notes.md"), "remember the milk\n").unwrap();
+        let providers = vec![
+            ContextProvider::FileContents {
+                path: PathBuf::from("notes.md"),
+                max_bytes: None,
+            },
+            command("echo from-command"),
+        ];
+
+        let preamble = build_preamble(&providers, dir.path(), DEFAULT_CONTEXT_BUDGET_BYTES).await;
+        assert_eq!(
+            preamble.text,
+            "<context>\n\
+             ### file notes.md\n```\nremember the milk\n```\n\
+             ### command `echo from-command`\n```\nfrom-command\n```\n\
+             </context>\n"
+        );
+        let providers: Vec<&str> = preamble
+            .contributions
+            .iter()
+            .map(|c| c.provider.as_str())
+            .collect();
+        assert_eq!(
+            providers,
+            vec!["file notes.md", "command `echo from-command`"]
+        );
+        assert_eq!(preamble.contributions[0].bytes, 18);
+        assert!(preamble
+            .prepend_to("fix it")
+            .ends_with("</context>\n\nfix it"));
+        assert_eq!(ContextPreamble::default().prepend_to("fix it"), "fix it");
+    }
+
+    #[test]
+    fn test_budget_truncates_and_starves_later_providers() {
+        let outputs = vec![
+            ("a".to_string(), 4, Ok("aaaaaaaa".to_string())),
+            ("b".to_string(), 100, Ok("bbbbbbbb".to_string())),
+            ("c".to_string(), 100, Ok("cccc".to_string())),
+        ];
+        let preamble = assemble(outputs, 10);
+
+        let summary: Vec<(usize, bool)> = preamble
+            .contributions
+            .iter()
+            .map(|c| (c.bytes, c.truncated))
+            .collect();
+        // a is cut to its own limit, b to the remaining budget, c gets nothing
+        assert_eq!(summary, vec![(4, true), (6, true), (0, true)]);
+        assert!(preamble.text.contains("aaaa\n[truncated]\n"));
+        assert!(preamble.text.contains("bbbbbb\n[truncated]\n"));
+        assert!(!preamble.text.contains("cccc"));
+
+        // Output containing a fence gets a longer one
+        let preamble = assemble(
+            vec![("d".to_string(), 100, Ok("```rust\n```".to_string()))],
+            100,
+        );
+        assert!(preamble.text.contains("````\n```rust\n```\n````\n"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_failing_command_becomes_error_note() {
+        let dir = TempDir::new().unwrap();
+        let providers = vec![
+            ContextProvider::Command {
+                cmd: "sleep 5".to_string(),
+                timeout_secs: Some(0),
+            },
+            command("echo 'test failed' >&2; exit 3"),
+            ContextProvider::GitStatus,
+        ];
+
+        let preamble = build_preamble(&providers, dir.path(), DEFAULT_CONTEXT_BUDGET_BYTES).await;
+        let timed_out = &preamble.contributions[0];
+        assert_eq!(timed_out.bytes, 0);
+        assert!(timed_out.error.as_deref().unwrap().contains("timed out"));
+        assert!(preamble.text.contains("[error: command timed out"));
+
+        // A non-zero exit is still context, with its status
+        let failing = &preamble.contributions[1];
+        assert_eq!(failing.error, None);
+        assert!(preamble.text.contains("test failed\n[exit status 3]\n"));
+
+        // Not a git repository
+        assert!(preamble.contributions[2].error.is_some());
+    }
+}
@@ -0,0 +1,167 @@
+//! Dry-run prompts: a plan preview without execution
+//!
+//! A dry run spawns the CLI the way a read-only session would, in `plan`
+//! permission mode where it can read but not change anything, and collects the file operations it proposes into a
+//! [`DryRunPlan`] reported with the prompt's completion.
+//!
+//! The real conversation's resume chain must not move. A dry run of a session
+//! with a conversation resumes it with `--fork-session`, so the CLI writes the
+//! dry run to a new conversation instead of appending to the real one, and the
+//! session never adopts the conversation id the CLI reports: its
+//! `claude_session_id` is not changed at any point, so an interrupt or crash
+//! mid-run cannot leave it pointing at the fork. A session without a
+//! conversation yet starts a throwaway one the same way.
+
+use std::ffi::OsString;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::cli_args::{build_claude_args, END_OF_OPTIONS};
+use super::process::SessionConfig;
+
+/// Tool the CLI calls in plan mode to present its finished plan
+const EXIT_PLAN_MODE_TOOL: &str = "ExitPlanMode";
+
+/// Arguments for a dry run of a prompt
+///
+/// Like [`build_claude_args`] for a read-only session, forking instead of
+/// appending to the resumed conversation.
+pub fn build_dry_run_args(
+    config: &SessionConfig,
+    model: &str,
+    prompt: Option<&str>,
+    resume_id: Option<&str>,
+) -> Vec<OsString> {
+    let config = SessionConfig {
+        read_only: true,
+        ..config.clone()
+    };
+    let mut args = build_claude_args(&config, model, None, resume_id);
+    if resume_id.is_some() {
+        args.push("--fork-session".into());
+    }
+    if let Some(prompt) = prompt {
+        args.push(END_OF_OPTIONS.into());
+        args.push(prompt.into());
+    }
+    args
+}
+
+/// What a proposed operation does to its file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlannedOperationKind {
+    /// Edit, MultiEdit or NotebookEdit of an existing file
+    Edit,
+    /// Write of a whole file, new or existing
+    Write,
+}
+
+/// A file operation proposed by a tool call during a dry run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedOperation {
+    pub tool: String,
+    pub kind: PlannedOperationKind,
+    pub path: String,
+}
+
+/// Summary of what a dry run proposed to do
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DryRunPlan {
+    /// Proposed file operations, in the order they were proposed
+    pub operations: Vec<PlannedOperation>,
+    /// The plan text the CLI presented, if it finished planning
+    pub plan: Option<String>,
+}
+
+impl DryRunPlan {
+    /// Add a tool call seen in the dry run's output
+    pub fn observe(&mut self, tool: &str, input: &Value) {
+        let (kind, path_key) = match tool {
+            "Edit" | "MultiEdit" => (PlannedOperationKind::Edit, "file_path"),
+            "NotebookEdit" => (PlannedOperationKind::Edit, "notebook_path"),
+            "Write" => (PlannedOperationKind::Write, "file_path"),
+            EXIT_PLAN_MODE_TOOL => {
+                if let Some(plan) = input.get("plan").and_then(Value::as_str) {
+                    self.plan = Some(plan.to_string());
+                }
+                return;
+            }
+            _ => return,
+        };
+        let Some(path) = input.get(path_key).and_then(Value::as_str) else {
+            return;
+        };
+        self.operations.push(PlannedOperation {
+            tool: tool.to_string(),
+            kind,
+            path: path.to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_dry_run_args() {
+        let config = SessionConfig {
+            allowed_tools: vec!["Edit".to_string()],
+            ..Default::default()
+        };
+        let args = build_dry_run_args(&config, "sonnet", Some("--hi"), Some("abc"));
+        assert_eq!(
+            args,
+            vec![
+                "-p",
+                "--output-format",
+                "stream-json",
+                "--resume",
+                "abc",
+                "--model",
+                "sonnet",
+                "--permission-mode",
+                "plan",
+                "--fork-session",
+                "--",
+                "--hi",
+            ]
+        );
+
+        let args = build_dry_run_args(&config, "sonnet", None, None);
+        assert!(!args.iter().any(|arg| arg == "--fork-session"));
+        assert_eq!(args.last().and_then(|arg| arg.to_str()), Some("plan"));
+    }
+
+    #[test]
+    fn test_plan_collects_file_operations() {
+        let mut plan = DryRunPlan::default();
+        plan.observe("Read", &json!({"file_path": "src/lib.rs"}));
+        plan.observe("Edit", &json!({"file_path": "src/lib.rs"}));
+        plan.observe("Write", &json!({"file_path": "src/new.rs"}));
+        plan.observe("Write", &json!({}));
+        plan.observe("ExitPlanMode", &json!({"plan": "1. Add new.rs"}));
+
+        assert_eq!(
+            plan,
+            DryRunPlan {
+                operations: vec![
+                    PlannedOperation {
+                        tool: "Edit".to_string(),
+                        kind: PlannedOperationKind::Edit,
+                        path: "src/lib.rs".to_string(),
+                    },
+                    PlannedOperation {
+                        tool: "Write".to_string(),
+                        kind: PlannedOperationKind::Write,
+                        path: "src/new.rs".to_string(),
+                    },
+                ],
+                plan: Some("1. Add new.rs".to_string()),
+            }
+        );
+    }
+}
@@ -0,0 +1,688 @@
+//! Delivery of session events to the frontend
+//!
+//! [`SessionEventBridge`] owns everything between the process manager and
+//! the webview: the loop draining a prompt's message channel into the
+//! transcript (which assigns sequence numbers), the `cli-message` payloads,
+//! forwarding of [`SessionEvent`]s, replays, and holding messages back while
+//! the frontend is unreachable (see `frontend_bridge`).
+//!
+//! Events go out through an [`EventSink`]. The app handle is the sink in the
+//! app; tests use a `RecordingSink`, so none of this needs a webview.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::{broadcast, mpsc, RwLock};
+
+use super::frontend_bridge::{FrontendBridge, MissedMessages, RetryBackoff};
+use super::parser::StreamMessage;
+use super::process::{ProcessError, ProcessManager, PromptRecord, SessionEvent, TranscriptEntry};
+
+/// Event carrying one CLI message, see [`CLIMessagePayload`]
+pub const CLI_MESSAGE_EVENT: &str = "cli-message";
+
+/// Where events for the frontend are sent
+pub trait EventSink: Send + Sync {
+    /// Send `event` to every window
+    fn emit(&self, event: &str, payload: Value) -> Result<(), String>;
+
+    /// Send `event` to the window labelled `window` only
+    fn emit_to(&self, window: &str, event: &str, payload: Value) -> Result<(), String>;
+}
+
+/// Payload for cli-message events sent to frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct CLIMessagePayload {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    #[serde(rename = "promptId")]
+    pub prompt_id: String,
+    /// The model that actually ran this prompt (may differ from the session default)
+    pub model: String,
+    /// Per-session sequence number, used to resume with `replay_session_events`
+    pub sequence: u64,
+    /// The Task tool call this message's sub-agent belongs to, for grouping
+    #[serde(rename = "parentToolUseId")]
+    pub parent_tool_use_id: Option<String>,
+    pub message: StreamMessage,
+    /// Whether this is a replay of an earlier message
+    pub replayed: bool,
+    /// Whether the message comes from a dry run (see `send_prompt_dry_run`)
+    pub dry_run: bool,
+}
+
+impl CLIMessagePayload {
+    fn new(
+        session_id: &str,
+        prompt_id: &str,
+        model: &str,
+        sequence: u64,
+        message: StreamMessage,
+    ) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            prompt_id: prompt_id.to_string(),
+            model: model.to_string(),
+            sequence,
+            parent_tool_use_id: message.parent_tool_use_id().map(str::to_string),
+            message,
+            replayed: false,
+            dry_run: false,
+        }
+    }
+
+    fn replayed(session_id: &str, entry: TranscriptEntry) -> Self {
+        Self {
+            replayed: true,
+            dry_run: entry.dry_run,
+            ..Self::new(
+                session_id,
+                &entry.prompt_id,
+                &entry.model,
+                entry.sequence,
+                entry.message,
+            )
+        }
+    }
+}
+
+/// Serialize `payload` and send it through `sink`
+fn emit_serialized(
+    sink: &dyn EventSink,
+    event: &str,
+    payload: &impl Serialize,
+) -> Result<(), String> {
+    let payload = serde_json::to_value(payload).map_err(|e| e.to_string())?;
+    sink.emit(event, payload)
+}
+
+/// Forwards session events and prompt messages to the frontend
+///
+/// Cheap to clone; the commands build one per call around the app's sink.
+#[derive(Clone)]
+pub struct SessionEventBridge {
+    sink: Arc<dyn EventSink>,
+    frontend: FrontendBridge,
+    process_manager: Arc<RwLock<ProcessManager>>,
+}
+
+impl SessionEventBridge {
+    pub fn new(
+        sink: Arc<dyn EventSink>,
+        frontend: FrontendBridge,
+        process_manager: Arc<RwLock<ProcessManager>>,
+    ) -> Self {
+        Self {
+            sink,
+            frontend,
+            process_manager,
+        }
+    }
+
+    /// Forward process manager events (e.g. "prompt-completed") until the
+    /// manager is dropped
+    pub async fn forward_session_events(self, mut events: broadcast::Receiver<SessionEvent>) {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if let Err(e) = emit_serialized(self.sink.as_ref(), event.event_name(), &event)
+                    {
+                        log::error!("Failed to emit {} event: {}", event.event_name(), e);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("Dropped {} session events", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }
+
+    /// Forward a prompt's CLI messages as "cli-message" events, on a new task
+    pub fn spawn_prompt(
+        &self,
+        session_id: String,
+        record: &PromptRecord,
+        rx: mpsc::Receiver<StreamMessage>,
+    ) {
+        tokio::spawn(self.forward_prompt(session_id, record, rx));
+    }
+
+    /// Forward a prompt's CLI messages as "cli-message" events until `rx` closes
+    ///
+    /// Each message is recorded in the session's transcript first, which
+    /// assigns its sequence number. After a failed emit the channel is still
+    /// drained, but the session's messages are held (they are in the
+    /// transcript) while emitting is retried with backoff. Under an
+    /// interrupting policy, the prompt is interrupted once the frontend has
+    /// been unreachable for too long.
+    pub fn forward_prompt(
+        &self,
+        session_id: String,
+        record: &PromptRecord,
+        mut rx: mpsc::Receiver<StreamMessage>,
+    ) -> impl Future<Output = ()> + Send + 'static {
+        let Self {
+            sink,
+            frontend,
+            process_manager,
+        } = self.clone();
+        let prompt_id = record.prompt_id.clone();
+        let model = record.model.clone();
+        let dry_run = record.dry_run;
+        async move {
+            let mut backoff = RetryBackoff::default();
+            let mut retry_at: Option<Instant> = None;
+            let mut interrupted = false;
+            loop {
+                let interrupt_at = frontend
+                    .interrupt_deadline()
+                    .filter(|_| !interrupted && frontend.is_holding(&session_id));
+                let wake_at = retry_at.into_iter().chain(interrupt_at).min();
+                let wake = async move {
+                    match wake_at {
+                        Some(at) => tokio::time::sleep_until(at.into()).await,
+                        None => std::future::pending().await,
+                    }
+                };
+
+                tokio::select! {
+                    msg = rx.recv() => {
+                        let Some(msg) = msg else {
+                            break;
+                        };
+                        let sequence = match process_manager
+                            .read()
+                            .await
+                            .record_message(&session_id, &prompt_id, &model, msg.clone())
+                            .await
+                        {
+                            Ok(sequence) => sequence,
+                            Err(e) => {
+                                // The session was terminated while the prompt was streaming
+                                log::warn!("Dropping cli-message: {}", e);
+                                break;
+                            }
+                        };
+                        if frontend.is_holding(&session_id) {
+                            retry_at.get_or_insert_with(|| Instant::now() + backoff.next_delay());
+                            continue;
+                        }
+
+                        let payload = CLIMessagePayload {
+                            dry_run,
+                            ..CLIMessagePayload::new(&session_id, &prompt_id, &model, sequence, msg)
+                        };
+                        if let Err(e) = emit_serialized(sink.as_ref(), CLI_MESSAGE_EVENT, &payload) {
+                            log::error!(
+                                "Failed to emit cli-message event, holding messages of session {}: {}",
+                                session_id,
+                                e
+                            );
+                            frontend.missed(&session_id, sequence);
+                            retry_at = Some(Instant::now() + backoff.next_delay());
+                        }
+                    }
+                    _ = wake => {
+                        let now = Instant::now();
+                        if retry_at.is_some_and(|at| at <= now) {
+                            retry_at = match frontend.try_recover(sink.as_ref()) {
+                                Some(_) => {
+                                    backoff.reset();
+                                    None
+                                }
+                                None => Some(now + backoff.next_delay()),
+                            };
+                        }
+                        if interrupt_at.is_some_and(|at| at <= now) && frontend.is_holding(&session_id) {
+                            interrupted = true;
+                            log::warn!(
+                                "Frontend unreachable for {:?}, interrupting session {}",
+                                frontend.unreachable_for().unwrap_or_default(),
+                                session_id
+                            );
+                            if let Err(e) = process_manager.read().await.interrupt(&session_id).await {
+                                log::error!("Failed to interrupt session {}: {}", session_id, e);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Messages of a session after `since_sequence`, flagged `replayed`
+    ///
+    /// With `emit`, they are also sent as "cli-message" events, stopping at
+    /// the first failure.
+    pub async fn replay(
+        &self,
+        session_id: &str,
+        since_sequence: u64,
+        emit: bool,
+    ) -> Result<Vec<CLIMessagePayload>, ProcessError> {
+        let entries = self
+            .process_manager
+            .read()
+            .await
+            .replay_messages(session_id, since_sequence)
+            .await?;
+        let payloads: Vec<CLIMessagePayload> = entries
+            .into_iter()
+            .map(|entry| CLIMessagePayload::replayed(session_id, entry))
+            .collect();
+
+        if emit {
+            for payload in &payloads {
+                if let Err(e) = emit_serialized(self.sink.as_ref(), CLI_MESSAGE_EVENT, payload) {
+                    log::error!("Failed to emit replayed cli-message event: {}", e);
+                    break;
+                }
+            }
+        }
+        Ok(payloads)
+    }
+
+    /// The frontend is listening again; report what it missed
+    ///
+    /// None if the recovery event could not be emitted either.
+    pub fn frontend_ready(&self) -> Option<Vec<MissedMessages>> {
+        self.frontend.try_recover(self.sink.as_ref())
+    }
+}
+
+/// An event sent through a [`RecordingSink`]
+#[cfg(test)]
+#[derive(Debug, Clone)]
+pub(crate) struct RecordedEvent {
+    /// The target window, for `emit_to`
+    pub window: Option<String>,
+    pub event: String,
+    pub payload: Value,
+}
+
+/// Sink that records events, and can fail like a crashed webview
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub(crate) struct RecordingSink {
+    /// While set, every emit fails
+    pub down: std::sync::atomic::AtomicBool,
+    /// Fail this many emits, then recover on its own
+    pub failures_left: std::sync::atomic::AtomicUsize,
+    recorded: std::sync::Mutex<Vec<RecordedEvent>>,
+}
+
+#[cfg(test)]
+impl RecordingSink {
+    pub fn recorded(&self) -> Vec<RecordedEvent> {
+        self.recorded.lock().unwrap().clone()
+    }
+
+    /// The names of the recorded events, in order
+    pub fn events(&self) -> Vec<String> {
+        self.recorded().into_iter().map(|e| e.event).collect()
+    }
+
+    /// The payloads of the recorded events named `event`, in order
+    pub fn payloads(&self, event: &str) -> Vec<Value> {
+        self.recorded()
+            .into_iter()
+            .filter(|e| e.event == event)
+            .map(|e| e.payload)
+            .collect()
+    }
+
+    fn record(&self, window: Option<&str>, event: &str, payload: Value) -> Result<(), String> {
+        use std::sync::atomic::Ordering;
+
+        let failing = self
+            .failures_left
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                left.checked_sub(1)
+            })
+            .is_ok();
+        if failing || self.down.load(Ordering::SeqCst) {
+            return Err("webview is gone".to_string());
+        }
+        self.recorded.lock().unwrap().push(RecordedEvent {
+            window: window.map(str::to_string),
+            event: event.to_string(),
+            payload,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl EventSink for RecordingSink {
+    fn emit(&self, event: &str, payload: Value) -> Result<(), String> {
+        self.record(None, event, payload)
+    }
+
+    fn emit_to(&self, window: &str, event: &str, payload: Value) -> Result<(), String> {
+        self.record(Some(window), event, payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::frontend_bridge::BridgePolicy;
+    use crate::services::process::{SessionConfig, SessionStatus};
+    #[cfg(unix)]
+    use crate::services::test_support::write_fake_cli;
+    use crate::services::{ActivityKind, SessionActivity};
+    use std::path::Path;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    fn assistant(text: &str) -> StreamMessage {
+        serde_json::from_value(serde_json::json!({
+            "type": "message",
+            "role": "assistant",
+            "content": text,
+        }))
+        .unwrap()
+    }
+
+    fn record(prompt_id: &str) -> PromptRecord {
+        serde_json::from_value(serde_json::json!({
+            "prompt_id": prompt_id,
+            "model": "sonnet",
+            "started_at": 0,
+        }))
+        .unwrap()
+    }
+
+    /// A bridge around a manager running `cli`, and a session in `dir`
+    async fn bridge_with_session(
+        cli: &std::path::Path,
+        dir: &std::path::Path,
+    ) -> (SessionEventBridge, Arc<RecordingSink>, String) {
+        let manager = ProcessManager::with_cli_path(cli);
+        let config = SessionConfig {
+            working_dir: dir.to_path_buf(),
+            model: "sonnet".to_string(),
+            ..Default::default()
+        };
+        let session_id = manager.create_session(config).await.unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        let bridge = SessionEventBridge::new(
+            sink.clone(),
+            FrontendBridge::new(),
+            Arc::new(RwLock::new(manager)),
+        );
+        (bridge, sink, session_id)
+    }
+
+    /// A bridge whose session runs a fake CLI executing `script`, with a started prompt
+    #[cfg(unix)]
+    async fn start_prompt(
+        script: &str,
+    ) -> (
+        SessionEventBridge,
+        Arc<RecordingSink>,
+        String,
+        PromptRecord,
+        mpsc::Receiver<StreamMessage>,
+        TempDir,
+    ) {
+        let temp_dir = TempDir::new().unwrap();
+        let cli = write_fake_cli(temp_dir.path(), script);
+        let (bridge, sink, session_id) = bridge_with_session(&cli, temp_dir.path()).await;
+        let (tx, rx) = mpsc::channel(64);
+        let record = bridge
+            .process_manager
+            .read()
+            .await
+            .send_prompt(&session_id, "hello", Default::default(), tx)
+            .await
+            .unwrap();
+        (bridge, sink, session_id, record, rx, temp_dir)
+    }
+
+    const THREE_MESSAGES: &str =
+        r#"for i in 1 2 3; do echo '{"type":"message","role":"assistant","content":"hi"}'; done"#;
+
+    async fn finish(forward: impl Future<Output = ()>) {
+        tokio::time::timeout(Duration::from_secs(10), forward)
+            .await
+            .expect("forwarding did not finish");
+    }
+
+    #[tokio::test]
+    async fn test_messages_are_emitted_in_order_with_sequences() {
+        let temp_dir = TempDir::new().unwrap();
+        let (bridge, sink, session_id) =
+            bridge_with_session(Path::new("claude"), temp_dir.path()).await;
+        let (tx, rx) = mpsc::channel(64);
+        let forward = bridge.forward_prompt(session_id.clone(), &record("p1"), rx);
+        for text in ["one", "two", "three"] {
+            tx.send(assistant(text)).await.unwrap();
+        }
+        drop(tx);
+        finish(forward).await;
+
+        let payloads = sink.payloads(CLI_MESSAGE_EVENT);
+        let sequences: Vec<u64> = payloads
+            .iter()
+            .map(|p| p["sequence"].as_u64().unwrap())
+            .collect();
+        let texts: Vec<&str> = payloads
+            .iter()
+            .map(|p| p["message"]["content"].as_str().unwrap())
+            .collect();
+        assert_eq!(sequences, vec![1, 2, 3]);
+        assert_eq!(texts, vec!["one", "two", "three"]);
+        assert!(payloads.iter().all(|p| p["promptId"] == "p1"));
+        assert!(sink.recorded().iter().all(|e| e.window.is_none()));
+
+        // A later prompt continues the session's numbering
+        let (tx, rx) = mpsc::channel(64);
+        let forward = bridge.forward_prompt(session_id, &record("p2"), rx);
+        tx.send(assistant("four")).await.unwrap();
+        drop(tx);
+        finish(forward).await;
+        let last = sink.payloads(CLI_MESSAGE_EVENT).pop().unwrap();
+        assert_eq!(last["sequence"], 4);
+        assert_eq!(last["promptId"], "p2");
+    }
+
+    #[tokio::test]
+    async fn test_forwarding_ends_when_the_channel_closes() {
+        let temp_dir = TempDir::new().unwrap();
+        let (bridge, sink, session_id) =
+            bridge_with_session(Path::new("claude"), temp_dir.path()).await;
+
+        // Closed before any message
+        let (tx, rx) = mpsc::channel(64);
+        drop(tx);
+        finish(bridge.forward_prompt(session_id.clone(), &record("p1"), rx)).await;
+        assert!(sink.recorded().is_empty());
+
+        // The session is gone: forwarding stops although the sender is alive
+        bridge
+            .process_manager
+            .read()
+            .await
+            .terminate(&session_id)
+            .await
+            .unwrap();
+        let (tx, rx) = mpsc::channel(64);
+        let forward = bridge.forward_prompt(session_id, &record("p2"), rx);
+        tx.send(assistant("late")).await.unwrap();
+        finish(forward).await;
+        assert!(sink.recorded().is_empty());
+        assert!(tx.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_session_events_keep_their_names_and_payloads() {
+        let temp_dir = TempDir::new().unwrap();
+        let (bridge, sink, _) = bridge_with_session(Path::new("claude"), temp_dir.path()).await;
+        let (events, rx) = broadcast::channel(1);
+        events
+            .send(SessionEvent::CrashRecovery {
+                prompts: Vec::new(),
+            })
+            .unwrap();
+        // Overflows the channel: the crash-recovery event is dropped, not the loop
+        events
+            .send(SessionEvent::SessionActivity {
+                session_id: "s1".to_string(),
+                prompt_id: "p1".to_string(),
+                activity: SessionActivity {
+                    kind: ActivityKind::ToolRunning,
+                    detail: Some("Bash(ls)".to_string()),
+                },
+            })
+            .unwrap();
+        drop(events);
+        finish(bridge.forward_session_events(rx)).await;
+
+        let recorded = sink.recorded();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].event, "session-activity");
+        assert_eq!(
+            recorded[0].payload,
+            serde_json::json!({
+                "sessionId": "s1",
+                "promptId": "p1",
+                "kind": "tool_running",
+                "detail": "Bash(ls)",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replay_flags_payloads_and_emits_on_request() {
+        let temp_dir = TempDir::new().unwrap();
+        let (bridge, sink, session_id) =
+            bridge_with_session(Path::new("claude"), temp_dir.path()).await;
+        let (tx, rx) = mpsc::channel(64);
+        let forward = bridge.forward_prompt(session_id.clone(), &record("p1"), rx);
+        for text in ["one", "two", "three"] {
+            tx.send(assistant(text)).await.unwrap();
+        }
+        drop(tx);
+        finish(forward).await;
+
+        let replayed = bridge.replay(&session_id, 1, false).await.unwrap();
+        assert_eq!(replayed.len(), 2);
+        assert!(replayed.iter().all(|p| p.replayed));
+        assert_eq!(replayed[0].sequence, 2);
+        assert_eq!(sink.events().len(), 3);
+
+        bridge.replay(&session_id, 2, true).await.unwrap();
+        let payloads = sink.payloads(CLI_MESSAGE_EVENT);
+        assert_eq!(payloads.len(), 4);
+        assert_eq!(payloads[3]["sequence"], 3);
+        assert_eq!(payloads[3]["replayed"], true);
+        assert_eq!(payloads[3]["dry_run"], false);
+
+        assert!(bridge.replay("missing", 0, true).await.is_err());
+    }
+
+    #[test]
+    fn test_payload_exposes_parent_tool_use_id() {
+        let message: StreamMessage = serde_json::from_str(
+            r#"{"type":"tool_use","id":"t2","name":"Read","input":{},"parent_tool_use_id":"task-1"}"#,
+        )
+        .unwrap();
+        let payload = CLIMessagePayload::new("s1", "p1", "sonnet", 7, message);
+        let value = serde_json::to_value(&payload).unwrap();
+
+        assert_eq!(value["sessionId"], "s1");
+        assert_eq!(value["promptId"], "p1");
+        assert_eq!(value["sequence"], 7);
+        assert_eq!(value["parentToolUseId"], "task-1");
+        assert_eq!(value["message"]["parent_tool_use_id"], "task-1");
+        assert_eq!(value["replayed"], false);
+
+        // Main-conversation messages carry an explicit null
+        let payload = CLIMessagePayload::new("s1", "p1", "sonnet", 8, assistant("hi"));
+        let value = serde_json::to_value(&payload).unwrap();
+        assert!(value["parentToolUseId"].is_null());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_emit_failure_keeps_draining_and_recovers_on_ready() {
+        let (bridge, sink, session_id, record, rx, _temp_dir) = start_prompt(THREE_MESSAGES).await;
+        sink.down.store(true, Ordering::SeqCst);
+        finish(bridge.forward_prompt(session_id.clone(), &record, rx)).await;
+
+        // Every message was consumed into the transcript, none delivered
+        let transcript = bridge.replay(&session_id, 0, false).await.unwrap();
+        assert_eq!(transcript.len(), 3);
+        assert!(sink.recorded().is_empty());
+        assert!(bridge.frontend.is_holding(&session_id));
+
+        // The reloaded frontend reports ready and is told what to replay
+        sink.down.store(false, Ordering::SeqCst);
+        let missed = bridge.frontend_ready().unwrap();
+        assert_eq!(
+            missed,
+            vec![MissedMessages {
+                session_id: session_id.clone(),
+                since_sequence: 0,
+            }]
+        );
+        assert_eq!(sink.events(), vec!["frontend-recovered"]);
+        assert!(!bridge.frontend.is_holding(&session_id));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_emit_is_retried_with_backoff() {
+        let (bridge, sink, session_id, record, rx, _temp_dir) = start_prompt(&format!(
+            "echo '{{\"type\":\"message\",\"role\":\"assistant\",\"content\":\"a\"}}'\nsleep 1\n{}",
+            THREE_MESSAGES
+        ))
+        .await;
+        sink.failures_left.store(1, Ordering::SeqCst);
+        finish(bridge.forward_prompt(session_id.clone(), &record, rx)).await;
+
+        // The first message failed; the retry during the pause succeeded and
+        // reported it, so the later ones were emitted directly
+        assert_eq!(
+            sink.events(),
+            vec![
+                "frontend-recovered",
+                "cli-message",
+                "cli-message",
+                "cli-message"
+            ]
+        );
+        let recorded = sink.recorded();
+        assert_eq!(recorded[0].payload["sessions"][0]["sinceSequence"], 0);
+        assert_eq!(recorded[1].payload["sequence"], 2);
+        assert!(!bridge.frontend.is_holding(&session_id));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_policy_interrupts_while_frontend_is_unreachable() {
+        let (bridge, sink, session_id, record, rx, _temp_dir) =
+            start_prompt(&format!("{}\nexec sleep 30", THREE_MESSAGES)).await;
+        sink.down.store(true, Ordering::SeqCst);
+        bridge.frontend.set_policy(BridgePolicy {
+            interrupt_after_secs: Some(0),
+        });
+
+        // Without the interrupt this would wait for the 30 second sleep
+        finish(bridge.forward_prompt(session_id.clone(), &record, rx)).await;
+        let info = bridge
+            .process_manager
+            .read()
+            .await
+            .get_session(&session_id)
+            .await
+            .unwrap();
+        assert_ne!(info.status, SessionStatus::Thinking);
+    }
+}
@@ -0,0 +1,515 @@
+//! A stand-in for the Claude CLI that replays canned transcripts
+//!
+//! For frontend work without a Claude subscription, CI and end-to-end tests.
+//! A fixture is a stream-json transcript (`<name>.jsonl`) of one or more
+//! turns, each ending with its `result` message. A new conversation replays
+//! the fixture's first turn and `--resume` continues with the next one
+//! (starting over after the last). Every `session_id` in the output is
+//! replaced by `fake-<fixture>-<n>` for the backend's n-th conversation, and
+//! costs are the fixture's, so runs are deterministic. A turn whose result is
+//! an error exits with status 1.
+//!
+//! Set `CLAUDE_GUI_FAKE_BACKEND` to `builtin` for the fixtures shipped in
+//! `fake_backend/`, or to a directory of `*.jsonl` files to add or replace
+//! fixtures. `CLAUDE_GUI_FAKE_DELAY_MS` sets the delay before each line. A
+//! prompt starting with `[<fixture>]` picks the fixture of a new
+//! conversation; other prompts get `simple-answer`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+
+use super::cli_args::END_OF_OPTIONS;
+use super::process::{ClaudeBackend, CliHandle, CliProcess};
+
+/// Environment variable enabling the fake backend (`builtin` or a fixture directory)
+pub const FAKE_BACKEND_ENV: &str = "CLAUDE_GUI_FAKE_BACKEND";
+
+/// Environment variable setting the delay before each line, in milliseconds
+pub const FAKE_DELAY_ENV: &str = "CLAUDE_GUI_FAKE_DELAY_MS";
+
+/// Fixture of a conversation whose first prompt names none
+pub const DEFAULT_FIXTURE: &str = "simple-answer";
+
+/// Delay before each line unless configured
+const DEFAULT_LINE_DELAY: Duration = Duration::from_millis(40);
+
+/// Version reported by `check_cli`
+const FAKE_VERSION: &str = "0.0.0 (fake backend)";
+
+/// Capacity of the in-memory pipe standing in for stdout
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+const BUILTIN_FIXTURES: [(&str, &str); 3] = [
+    (
+        "simple-answer",
+        include_str!("fake_backend/simple-answer.jsonl"),
+    ),
+    ("tool-use", include_str!("fake_backend/tool-use.jsonl")),
+    ("error", include_str!("fake_backend/error.jsonl")),
+];
+
+/// A canned transcript, split into turns
+#[derive(Debug, Clone)]
+struct Fixture {
+    turns: Vec<Vec<Value>>,
+}
+
+impl Fixture {
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut turns = Vec::new();
+        let mut turn = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let message: Value =
+                serde_json::from_str(line).map_err(|e| format!("line {}: {}", i + 1, e))?;
+            let is_result = message.get("type").and_then(Value::as_str) == Some("result");
+            turn.push(message);
+            if is_result {
+                turns.push(std::mem::take(&mut turn));
+            }
+        }
+        if !turn.is_empty() {
+            turns.push(turn);
+        }
+        if turns.is_empty() {
+            return Err("no messages".to_string());
+        }
+        Ok(Self { turns })
+    }
+}
+
+/// Where each conversation is in its fixture
+#[derive(Debug, Default)]
+struct Conversations {
+    started: u32,
+    /// Fixture and next turn, per session id
+    next_turn: HashMap<String, (String, usize)>,
+}
+
+/// Replays fixtures in-process instead of spawning the CLI
+#[derive(Debug)]
+pub struct FakeBackend {
+    fixtures: BTreeMap<String, Fixture>,
+    line_delay: Duration,
+    program: PathBuf,
+    conversations: Mutex<Conversations>,
+}
+
+impl FakeBackend {
+    /// The fixtures shipped with the app
+    pub fn builtin() -> Self {
+        let fixtures = BUILTIN_FIXTURES
+            .iter()
+            .map(|(name, text)| {
+                let fixture = Fixture::parse(text)
+                    .unwrap_or_else(|e| panic!("Invalid built-in fixture {}: {}", name, e));
+                (name.to_string(), fixture)
+            })
+            .collect();
+        Self {
+            fixtures,
+            line_delay: DEFAULT_LINE_DELAY,
+            program: PathBuf::from("claude"),
+            conversations: Mutex::new(Conversations::default()),
+        }
+    }
+
+    /// The built-in fixtures plus the `*.jsonl` files of `dir`, which win on a name clash
+    pub fn from_dir(dir: &Path) -> std::io::Result<Self> {
+        let mut backend = Self::builtin();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "jsonl") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let text = std::fs::read_to_string(&path)?;
+            match Fixture::parse(&text) {
+                Ok(fixture) => {
+                    backend.fixtures.insert(name.to_string(), fixture);
+                }
+                Err(e) => log::warn!("Ignoring fixture {}: {}", path.display(), e),
+            }
+        }
+        Ok(backend)
+    }
+
+    /// The backend configured by `CLAUDE_GUI_FAKE_BACKEND`, if set
+    pub fn from_env() -> Option<Self> {
+        let source = std::env::var(FAKE_BACKEND_ENV)
+            .ok()
+            .filter(|source| !source.is_empty())?;
+        let backend = if source == "builtin" {
+            Self::builtin()
+        } else {
+            Self::from_dir(Path::new(&source)).unwrap_or_else(|e| {
+                log::warn!("Failed to load fixtures from {}: {}", source, e);
+                Self::builtin()
+            })
+        };
+        let delay = std::env::var(FAKE_DELAY_ENV)
+            .ok()
+            .and_then(|ms| ms.parse().ok())
+            .map(Duration::from_millis);
+        Some(match delay {
+            Some(delay) => backend.with_line_delay(delay),
+            None => backend,
+        })
+    }
+
+    pub fn with_line_delay(mut self, line_delay: Duration) -> Self {
+        self.line_delay = line_delay;
+        self
+    }
+
+    /// Names of the available fixtures
+    pub fn fixtures(&self) -> Vec<String> {
+        self.fixtures.keys().cloned().collect()
+    }
+
+    /// The fixture named by a `[<fixture>]` prefix of `prompt`, or the default
+    fn fixture_for(&self, prompt: Option<&str>) -> String {
+        prompt
+            .and_then(|prompt| prompt.trim_start().strip_prefix('['))
+            .and_then(|rest| rest.split_once(']'))
+            .map(|(name, _)| name)
+            .filter(|name| self.fixtures.contains_key(*name))
+            .unwrap_or(DEFAULT_FIXTURE)
+            .to_string()
+    }
+
+    /// The session id and messages of the turn a run with `args` replays
+    ///
+    /// A forked resume (a dry run) replays the next turn without using it up.
+    fn next_turn(&self, args: &[OsString]) -> (String, Vec<Value>) {
+        let value_after = |flag: &str| {
+            args.iter()
+                .position(|arg| arg == flag)
+                .and_then(|i| args.get(i + 1))
+                .and_then(|value| value.to_str())
+        };
+        let prompt = value_after(END_OF_OPTIONS);
+        let fork = args.iter().any(|arg| arg == "--fork-session");
+
+        let mut conversations = self.conversations.lock().unwrap_or_else(|e| e.into_inner());
+        let (session_id, fixture, turn) = match value_after("--resume") {
+            Some(resume_id) => {
+                let (fixture, turn) = conversations
+                    .next_turn
+                    .get(resume_id)
+                    .cloned()
+                    .unwrap_or_else(|| (self.fixture_for(prompt), 0));
+                (resume_id.to_string(), fixture, turn)
+            }
+            None => {
+                conversations.started += 1;
+                let fixture = self.fixture_for(prompt);
+                let session_id = format!("fake-{}-{}", fixture, conversations.started);
+                (session_id, fixture, 0)
+            }
+        };
+        if !fork {
+            conversations
+                .next_turn
+                .insert(session_id.clone(), (fixture.clone(), turn + 1));
+        }
+        drop(conversations);
+
+        let turns = &self.fixtures[&fixture].turns;
+        let messages = turns[turn % turns.len()]
+            .iter()
+            .cloned()
+            .map(|mut message| {
+                if let Some(id) = message.get_mut("session_id") {
+                    *id = Value::String(session_id.clone());
+                }
+                message
+            })
+            .collect();
+        (session_id, messages)
+    }
+}
+
+impl ClaudeBackend for FakeBackend {
+    fn spawn(
+        &self,
+        args: &[OsString],
+        _working_dir: &Path,
+        _stdin: Stdio,
+    ) -> std::io::Result<CliProcess> {
+        let (session_id, messages) = self.next_turn(args);
+        log::debug!(
+            "Fake backend replaying {} messages for {}",
+            messages.len(),
+            session_id
+        );
+        let failed = messages.last().is_some_and(|message| {
+            message.get("type").and_then(Value::as_str) == Some("result")
+                && message.get("is_error").and_then(Value::as_bool) == Some(true)
+        });
+
+        let (mut writer, reader) = tokio::io::duplex(PIPE_CAPACITY);
+        let line_delay = self.line_delay;
+        let task = tokio::spawn(async move {
+            for message in messages {
+                tokio::time::sleep(line_delay).await;
+                let line = format!("{}\n", message);
+                if writer.write_all(line.as_bytes()).await.is_err() {
+                    break;
+                }
+            }
+            i32::from(failed)
+        });
+        Ok(CliProcess {
+            handle: CliHandle::Simulated(task),
+            stdout: Box::new(reader),
+            stderr: Box::new(tokio::io::empty()),
+        })
+    }
+
+    fn program(&self) -> &Path {
+        &self.program
+    }
+
+    fn fixed_version(&self) -> Option<String> {
+        Some(FAKE_VERSION.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::money::Usd;
+    use crate::services::{
+        CompletionReason, ProcessManager, PromptOptions, PromptRecord, SessionConfig, SessionEvent,
+        SessionStatus, StreamMessage,
+    };
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    use tokio::sync::{broadcast, mpsc};
+
+    fn fake_manager(line_delay: Duration) -> ProcessManager {
+        ProcessManager::with_backend(Arc::new(FakeBackend::builtin().with_line_delay(line_delay)))
+    }
+
+    async fn create_session(manager: &ProcessManager) -> (String, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let config = SessionConfig {
+            working_dir: temp_dir.path().to_path_buf(),
+            model: "sonnet".to_string(),
+            ..Default::default()
+        };
+        (manager.create_session(config).await.unwrap(), temp_dir)
+    }
+
+    /// Send `prompt` and collect its messages and events up to its completion
+    async fn run_prompt(
+        manager: &ProcessManager,
+        events: &mut broadcast::Receiver<SessionEvent>,
+        session_id: &str,
+        prompt: &str,
+    ) -> (Vec<StreamMessage>, Vec<SessionEvent>, PromptRecord) {
+        let (tx, mut rx) = mpsc::channel(64);
+        manager
+            .send_prompt(session_id, prompt, PromptOptions::default(), tx)
+            .await
+            .unwrap();
+        let mut messages = Vec::new();
+        while let Some(msg) = rx.recv().await {
+            messages.push(msg);
+        }
+        let mut seen = Vec::new();
+        loop {
+            match events.recv().await.unwrap() {
+                SessionEvent::PromptCompleted { prompt, .. } => {
+                    return (messages, seen, *prompt);
+                }
+                SessionEvent::SessionsChanged(_) => {}
+                event => seen.push(event),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_simple_answer_and_resume_update_the_session() {
+        let manager = fake_manager(Duration::from_millis(1));
+        let mut events = manager.subscribe();
+        let (session_id, _dir) = create_session(&manager).await;
+
+        let (messages, _, record) =
+            run_prompt(&manager, &mut events, &session_id, "Explain ownership").await;
+        assert_eq!(messages.len(), 4);
+        assert!(matches!(messages[0], StreamMessage::System { .. }));
+        assert_eq!(record.completion_reason, Some(CompletionReason::Success));
+        assert_eq!(record.cost, Some(Usd::from_dollars(0.0042)));
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert_eq!(
+            info.claude_session_id.as_deref(),
+            Some("fake-simple-answer-1")
+        );
+        assert_eq!(info.status, SessionStatus::Idle);
+
+        // The second prompt resumes the conversation with the fixture's next turn
+        let (messages, _, _) =
+            run_prompt(&manager, &mut events, &session_id, "And borrowing?").await;
+        let Some(StreamMessage::Result { result, .. }) = messages.last() else {
+            panic!("no result: {:?}", messages);
+        };
+        assert!(result.as_deref().unwrap().starts_with("Borrowing"));
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert_eq!(
+            info.claude_session_id.as_deref(),
+            Some("fake-simple-answer-1")
+        );
+        assert_eq!(info.prompt_count, 2);
+        assert_eq!(info.total_cost, Usd::from_dollars(0.0042 + 0.0031));
+    }
+
+    #[tokio::test]
+    async fn test_tool_use_run_records_touched_files() {
+        let manager = fake_manager(Duration::ZERO);
+        let mut events = manager.subscribe();
+        let (session_id, _dir) = create_session(&manager).await;
+
+        let (messages, seen, record) = run_prompt(
+            &manager,
+            &mut events,
+            &session_id,
+            "[tool-use] Greet by name",
+        )
+        .await;
+        let tool_uses = messages
+            .iter()
+            .filter(|msg| matches!(msg, StreamMessage::ToolUse { .. }))
+            .count();
+        assert_eq!(tool_uses, 2);
+        assert_eq!(record.files_touched, vec!["src/main.rs".to_string()]);
+        assert_eq!(record.cost, Some(Usd::from_dollars(0.0186)));
+        // The init message's MCP servers are reported like the CLI's
+        assert!(seen
+            .iter()
+            .any(|event| matches!(event, SessionEvent::McpStatus { servers, .. } if servers[0].name == "github")));
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert_eq!(info.claude_session_id.as_deref(), Some("fake-tool-use-1"));
+    }
+
+    #[tokio::test]
+    async fn test_error_run_reports_a_session_error() {
+        let manager = fake_manager(Duration::ZERO);
+        let mut events = manager.subscribe();
+        let (session_id, _dir) = create_session(&manager).await;
+
+        let (_, seen, record) =
+            run_prompt(&manager, &mut events, &session_id, "[error] Run the tests").await;
+        let error = seen
+            .iter()
+            .find_map(|event| match event {
+                SessionEvent::SessionError {
+                    message, exit_code, ..
+                } => Some((message.clone(), *exit_code)),
+                _ => None,
+            })
+            .expect("no session error");
+        assert!(error.0.contains("Overloaded"), "{}", error.0);
+        assert_eq!(error.1, Some(1));
+        assert_eq!(
+            record.completion_reason,
+            Some(CompletionReason::ExecutionError)
+        );
+        assert_eq!(record.cost, Some(Usd::from_dollars(0.0009)));
+    }
+
+    #[tokio::test]
+    async fn test_interrupt_stops_a_replay() {
+        let manager = fake_manager(Duration::from_secs(30));
+        let mut events = manager.subscribe();
+        let (session_id, _dir) = create_session(&manager).await;
+
+        let (tx, mut rx) = mpsc::channel(64);
+        manager
+            .send_prompt(
+                &session_id,
+                "Explain ownership",
+                PromptOptions::default(),
+                tx,
+            )
+            .await
+            .unwrap();
+        manager.interrupt(&session_id).await.unwrap();
+        assert!(rx.recv().await.is_none());
+        loop {
+            if let SessionEvent::PromptCompleted { prompt, .. } = events.recv().await.unwrap() {
+                assert_eq!(prompt.cost, None);
+                break;
+            }
+        }
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert_eq!(info.status, SessionStatus::Idle);
+    }
+
+    fn args(resume: Option<&str>, prompt: &str) -> Vec<OsString> {
+        let mut args = vec!["-p".into()];
+        if let Some(resume) = resume {
+            args.extend(["--resume".into(), resume.into()]);
+        }
+        args.extend([END_OF_OPTIONS.into(), prompt.into()]);
+        args
+    }
+
+    #[test]
+    fn test_builtin_fixtures_parse() {
+        let backend = FakeBackend::builtin();
+        assert_eq!(
+            backend.fixtures(),
+            vec!["error", "simple-answer", "tool-use"]
+        );
+        assert_eq!(backend.fixtures["simple-answer"].turns.len(), 2);
+        for fixture in backend.fixtures.values() {
+            for turn in &fixture.turns {
+                assert_eq!(turn.last().unwrap()["type"], "result");
+            }
+        }
+    }
+
+    #[test]
+    fn test_resume_continues_the_same_fixture() {
+        let backend = FakeBackend::builtin();
+        let (first, messages) = backend.next_turn(&args(None, "[tool-use] Fix main"));
+        assert_eq!(first, "fake-tool-use-1");
+        assert!(messages
+            .iter()
+            .all(|m| m.get("session_id").is_none_or(|id| id == "fake-tool-use-1")));
+
+        let (other, _) = backend.next_turn(&args(None, "Explain ownership"));
+        assert_eq!(other, "fake-simple-answer-2");
+        let (resumed, messages) = backend.next_turn(&args(Some(&other), "And borrowing?"));
+        assert_eq!(resumed, other);
+        let answer = messages.last().unwrap()["result"].as_str().unwrap();
+        assert!(answer.starts_with("Borrowing"));
+
+        // A dry run forks the conversation, leaving its next turn alone
+        let mut fork = args(Some(&other), "Plan it");
+        fork.push("--fork-session".into());
+        let (_, forked) = backend.next_turn(&fork);
+        let (_, next) = backend.next_turn(&args(Some(&other), "Again"));
+        assert_eq!(forked, next);
+    }
+
+    #[test]
+    fn test_fixture_parse_errors_name_the_line() {
+        let err = Fixture::parse("{\"type\":\"system\"}\nnot json\n").unwrap_err();
+        assert!(err.starts_with("line 2"), "{}", err);
+        assert!(Fixture::parse("\n").is_err());
+    }
+}
@@ -0,0 +1,404 @@
+//! Earlier versions of files changed through the Edit Arbiter
+//!
+//! When an edit is applied on behalf of a session, the content it replaced
+//! and the content it wrote are kept in the session's scratch directory, so
+//! any of them can be restored later even if nothing was committed in
+//! between. Contents are stored under their hash: a content seen before for
+//! the same file (e.g. the result of one edit, replaced by the next) is not
+//! stored or listed again, and its hash is the version id.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::paths::decode_path;
+use super::process::PromptRecord;
+use super::scratch::ScratchSpace;
+use super::storage;
+
+/// Subdirectory of a session's scratch directory holding file versions
+const VERSIONS_DIR_NAME: &str = "file-versions";
+
+/// Index of the versions, in the order they were first seen
+const INDEX_FILE_NAME: &str = "index.json";
+
+/// SHA256 of `content`, hex encoded
+pub fn content_hash(content: &str) -> String {
+    hex::encode(Sha256::digest(content.as_bytes()))
+}
+
+/// An entry of the index: a content of a file, when first seen
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Snapshot {
+    path: String,
+    hash: String,
+    recorded_at: u64,
+    prompt_id: Option<String>,
+}
+
+/// A version of a file, as listed by `get_file_versions`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileVersion {
+    /// Hash of the content; pass to `restore_file_version`
+    pub version_id: String,
+    /// When this content was first seen (unix seconds)
+    pub recorded_at: u64,
+    /// The prompt whose edit wrote this content; None for the content an
+    /// edit first replaced, and for changes made outside the app
+    pub prompt_id: Option<String>,
+    pub size: u64,
+    /// Whether the file has this content now
+    pub current: bool,
+}
+
+/// The file versions kept for one session
+#[derive(Debug, Clone)]
+pub struct FileVersionStore {
+    dir: PathBuf,
+}
+
+impl FileVersionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn for_session(scratch: &ScratchSpace, session_id: &str) -> Self {
+        Self::new(scratch.scratch_dir_for(session_id).join(VERSIONS_DIR_NAME))
+    }
+
+    fn content_path(&self, hash: &str) -> PathBuf {
+        self.dir.join(hash)
+    }
+
+    async fn load_index(&self) -> io::Result<Vec<Snapshot>> {
+        match tokio::fs::read(self.dir.join(INDEX_FILE_NAME)).await {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn save_index(&self, index: &[Snapshot]) -> io::Result<()> {
+        let content = serde_json::to_vec(index)?;
+        storage::write_atomic(&self.dir.join(INDEX_FILE_NAME), &content).await
+    }
+
+    /// Keep the content an edit of `path` replaced (`before`) and wrote (`after`)
+    ///
+    /// None stands for a missing file (a creation or a deletion). Callers
+    /// must not record edits of the same session concurrently.
+    pub async fn record_edit(
+        &self,
+        path: &str,
+        before: Option<&str>,
+        after: Option<&str>,
+        prompt_id: Option<&str>,
+        now: u64,
+    ) -> io::Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let mut index = self.load_index().await?;
+        let mut changed = false;
+        for (content, prompt_id) in [(before, None), (after, prompt_id)] {
+            let Some(content) = content else {
+                continue;
+            };
+            let hash = content_hash(content);
+            if index.iter().any(|s| s.path == path && s.hash == hash) {
+                continue;
+            }
+            let content_path = self.content_path(&hash);
+            if !tokio::fs::try_exists(&content_path).await? {
+                storage::write_atomic(&content_path, content.as_bytes()).await?;
+            }
+            index.push(Snapshot {
+                path: path.to_string(),
+                hash,
+                recorded_at: now,
+                prompt_id: prompt_id.map(str::to_string),
+            });
+            changed = true;
+        }
+        if changed {
+            self.save_index(&index).await?;
+        }
+        Ok(())
+    }
+
+    /// Add the versions kept in `other` (e.g. of a session merged into this one)
+    ///
+    /// Versions already kept here are skipped; the index stays ordered by
+    /// when each version was first seen. `other` is left unchanged.
+    pub async fn merge_from(&self, other: &FileVersionStore) -> io::Result<()> {
+        let theirs = other.load_index().await?;
+        if theirs.is_empty() {
+            return Ok(());
+        }
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let mut index = self.load_index().await?;
+        for snapshot in theirs {
+            if index
+                .iter()
+                .any(|s| s.path == snapshot.path && s.hash == snapshot.hash)
+            {
+                continue;
+            }
+            let content_path = self.content_path(&snapshot.hash);
+            if !tokio::fs::try_exists(&content_path).await? {
+                tokio::fs::copy(other.content_path(&snapshot.hash), &content_path).await?;
+            }
+            index.push(snapshot);
+        }
+        index.sort_by_key(|s| s.recorded_at);
+        self.save_index(&index).await
+    }
+
+    /// The versions of `path`, oldest first, given its `current` content
+    ///
+    /// A current content that was never recorded (changed outside the app)
+    /// is listed last, as of `modified_at`.
+    pub async fn versions(
+        &self,
+        path: &str,
+        current: Option<&str>,
+        modified_at: u64,
+    ) -> io::Result<Vec<FileVersion>> {
+        let current_hash = current.map(content_hash);
+        let mut versions = Vec::new();
+        for snapshot in self.load_index().await? {
+            if snapshot.path != path {
+                continue;
+            }
+            let size = tokio::fs::metadata(self.content_path(&snapshot.hash))
+                .await?
+                .len();
+            versions.push(FileVersion {
+                current: current_hash.as_deref() == Some(snapshot.hash.as_str()),
+                version_id: snapshot.hash,
+                recorded_at: snapshot.recorded_at,
+                prompt_id: snapshot.prompt_id,
+                size,
+            });
+        }
+        if let (Some(content), Some(hash)) = (current, current_hash) {
+            if !versions.iter().any(|v| v.current) {
+                versions.push(FileVersion {
+                    version_id: hash,
+                    recorded_at: modified_at,
+                    prompt_id: None,
+                    size: content.len() as u64,
+                    current: true,
+                });
+            }
+        }
+        Ok(versions)
+    }
+
+    /// The content of version `version_id` of `path`, if it was recorded
+    pub async fn content(&self, path: &str, version_id: &str) -> io::Result<Option<String>> {
+        let index = self.load_index().await?;
+        if !index.iter().any(|s| s.path == path && s.hash == version_id) {
+            return Ok(None);
+        }
+        tokio::fs::read_to_string(self.content_path(version_id))
+            .await
+            .map(Some)
+    }
+}
+
+/// Whether `touched` (as named in a tool call, maybe relative to
+/// `working_dir`) is the file at `path` (as sent by the frontend)
+pub fn same_file(working_dir: &Path, touched: &str, path: &str) -> bool {
+    working_dir.join(touched) == decode_path(path)
+}
+
+/// The prompt of `history` whose tool calls last touched the file at `path`
+///
+/// Falls back to the most recent prompt.
+pub fn prompt_touching(history: &[PromptRecord], working_dir: &Path, path: &str) -> Option<String> {
+    history
+        .iter()
+        .rev()
+        .find(|record| {
+            record
+                .files_touched
+                .iter()
+                .any(|touched| same_file(working_dir, touched, path))
+        })
+        .or(history.last())
+        .map(|record| record.prompt_id.clone())
+}
+
+/// The content of the file at `path` (None if it is missing) and when it
+/// was last modified (unix seconds, `now` if unknown)
+pub async fn read_current(path: &str, now: u64) -> io::Result<(Option<String>, u64)> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(content) => {
+            let modified_at = tokio::fs::metadata(path)
+                .await
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map_or(now, |d| d.as_secs());
+            Ok((Some(content), modified_at))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok((None, now)),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_versions_are_listed_once_per_content() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = FileVersionStore::new(temp_dir.path());
+        let path = "/project/config.rs";
+        store
+            .record_edit(path, Some("v1"), Some("v2"), Some("p1"), 10)
+            .await
+            .unwrap();
+        store
+            .record_edit(path, Some("v2"), Some("v3"), Some("p2"), 20)
+            .await
+            .unwrap();
+        store
+            .record_edit("/project/other.rs", None, Some("v2"), Some("p2"), 20)
+            .await
+            .unwrap();
+
+        let versions = store.versions(path, Some("v3"), 30).await.unwrap();
+        let ids: Vec<&str> = versions.iter().map(|v| v.version_id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec![content_hash("v1"), content_hash("v2"), content_hash("v3")]
+        );
+        let prompts: Vec<Option<&str>> = versions.iter().map(|v| v.prompt_id.as_deref()).collect();
+        assert_eq!(prompts, vec![None, Some("p1"), Some("p2")]);
+        assert_eq!(versions[1].recorded_at, 10);
+        assert_eq!(
+            versions.iter().map(|v| v.current).collect::<Vec<_>>(),
+            vec![false, false, true]
+        );
+
+        // Identical contents are stored once, across files
+        let stored = std::fs::read_dir(temp_dir.path()).unwrap().count();
+        assert_eq!(stored, 3 + 1);
+
+        // Changed outside the app: listed last, not attributed
+        let versions = store.versions(path, Some("v4"), 40).await.unwrap();
+        let last = versions.last().unwrap();
+        assert_eq!(last.version_id, content_hash("v4"));
+        assert_eq!((last.recorded_at, last.prompt_id.as_deref()), (40, None));
+
+        assert_eq!(
+            store.content(path, &content_hash("v2")).await.unwrap(),
+            Some("v2".to_string())
+        );
+        // Only versions of the file itself can be read back
+        assert_eq!(
+            store
+                .content("/project/other.rs", &content_hash("v1"))
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_merge_from_adds_missing_versions_in_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let ours = FileVersionStore::new(temp_dir.path().join("ours"));
+        let theirs = FileVersionStore::new(temp_dir.path().join("theirs"));
+        let path = "/project/config.rs";
+        ours.record_edit(path, Some("v1"), Some("v3"), Some("p1"), 10)
+            .await
+            .unwrap();
+        theirs
+            .record_edit(path, Some("v1"), Some("v2"), Some("p2"), 5)
+            .await
+            .unwrap();
+
+        ours.merge_from(&theirs).await.unwrap();
+        let versions = ours.versions(path, Some("v3"), 0).await.unwrap();
+        let ids: Vec<String> = versions.iter().map(|v| v.version_id.clone()).collect();
+        assert_eq!(
+            ids,
+            vec![content_hash("v2"), content_hash("v1"), content_hash("v3")]
+        );
+        assert_eq!(
+            ours.content(path, &content_hash("v2")).await.unwrap(),
+            Some("v2".to_string())
+        );
+        // Merging a store without versions changes nothing
+        let empty = FileVersionStore::new(temp_dir.path().join("empty"));
+        ours.merge_from(&empty).await.unwrap();
+        assert_eq!(ours.versions(path, Some("v3"), 0).await.unwrap(), versions);
+    }
+
+    #[test]
+    fn test_prompt_touching_falls_back_to_the_last_prompt() {
+        use crate::services::test_support::prompt_record;
+
+        let working_dir = Path::new("/project");
+        let history = vec![
+            PromptRecord {
+                files_touched: vec!["src/lib.rs".to_string()],
+                ..prompt_record("p1", 10)
+            },
+            prompt_record("p2", 20),
+        ];
+        assert_eq!(
+            prompt_touching(&history, working_dir, "/project/src/lib.rs"),
+            Some("p1".to_string())
+        );
+        assert_eq!(
+            prompt_touching(&history, working_dir, "/project/README.md"),
+            Some("p2".to_string())
+        );
+        assert_eq!(
+            prompt_touching(&[], working_dir, "/project/README.md"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_current_of_present_and_missing_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("notes.txt");
+        std::fs::write(&file, "hello").unwrap();
+
+        let (content, modified_at) = read_current(file.to_str().unwrap(), 0).await.unwrap();
+        assert_eq!(content.as_deref(), Some("hello"));
+        assert!(modified_at > 0);
+        let missing = temp_dir.path().join("missing.txt");
+        assert_eq!(
+            read_current(missing.to_str().unwrap(), 42).await.unwrap(),
+            (None, 42)
+        );
+    }
+
+    #[test]
+    fn test_same_file_resolves_relative_paths() {
+        let working_dir = Path::new("/project");
+        assert!(same_file(
+            working_dir,
+            "src/config.rs",
+            "/project/src/config.rs"
+        ));
+        assert!(same_file(
+            working_dir,
+            "/project/src/config.rs",
+            "/project/src/config.rs"
+        ));
+        assert!(!same_file(
+            working_dir,
+            "config.rs",
+            "/project/src/config.rs"
+        ));
+    }
+}
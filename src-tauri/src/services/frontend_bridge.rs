@@ -0,0 +1,240 @@
+//! Event delivery to the webview, and running on while it is unreachable
+//!
+//! After a webview crash, emitting events fails while the CLI keeps running
+//! (and costing money). Forwarding does not stop then: every message is
+//! already in the session's transcript, so the bridge only remembers, per
+//! session, where delivery stopped. Delivery resumes when a retry succeeds or
+//! when the reloaded frontend calls `frontend_ready`; either way a single
+//! `frontend-recovered` event lists the sessions to replay and the sequence to
+//! pass to `replay_session_events`.
+//!
+//! A [`BridgePolicy`] can interrupt prompts instead of letting them run
+//! unobserved for too long.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::event_bridge::EventSink;
+
+/// Event listing the sessions whose messages were not delivered
+pub const RECOVERED_EVENT: &str = "frontend-recovered";
+
+/// First delay before emitting is retried
+const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// Longest delay between retries
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// What happens to running prompts while the frontend is unreachable
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BridgePolicy {
+    /// Interrupt a streaming prompt once the frontend has been unreachable
+    /// this long; None lets it run to the end
+    #[serde(default)]
+    pub interrupt_after_secs: Option<u64>,
+}
+
+/// A session whose messages stopped being delivered, in a `frontend-recovered` event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MissedMessages {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    /// Pass to `replay_session_events` to get every undelivered message
+    #[serde(rename = "sinceSequence")]
+    pub since_sequence: u64,
+}
+
+#[derive(Debug, Default)]
+struct BridgeState {
+    /// When emitting first failed, while the frontend is unreachable
+    unreachable_since: Option<Instant>,
+    /// First undelivered sequence number, per session
+    missed: BTreeMap<String, u64>,
+    policy: BridgePolicy,
+}
+
+/// Delivery state shared by all forwarding tasks
+#[derive(Debug, Clone, Default)]
+pub struct FrontendBridge {
+    state: Arc<Mutex<BridgeState>>,
+}
+
+impl FrontendBridge {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BridgeState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn policy(&self) -> BridgePolicy {
+        self.lock().policy
+    }
+
+    pub fn set_policy(&self, policy: BridgePolicy) {
+        self.lock().policy = policy;
+    }
+
+    /// Whether messages of `session_id` are held back until recovery
+    ///
+    /// Emitting newer messages first would deliver them out of order.
+    pub fn is_holding(&self, session_id: &str) -> bool {
+        self.lock().missed.contains_key(session_id)
+    }
+
+    /// Record that the message `sequence` of `session_id` could not be emitted
+    pub fn missed(&self, session_id: &str, sequence: u64) {
+        let mut state = self.lock();
+        state.unreachable_since.get_or_insert_with(Instant::now);
+        state
+            .missed
+            .entry(session_id.to_string())
+            .or_insert(sequence);
+    }
+
+    /// How long the frontend has been unreachable
+    pub fn unreachable_for(&self) -> Option<Duration> {
+        self.lock().unreachable_since.map(|since| since.elapsed())
+    }
+
+    /// When a prompt streaming while the frontend is unreachable should be
+    /// interrupted under the current policy
+    pub fn interrupt_deadline(&self) -> Option<Instant> {
+        let state = self.lock();
+        let after = Duration::from_secs(state.policy.interrupt_after_secs?);
+        state.unreachable_since.map(|since| since + after)
+    }
+
+    /// Tell the frontend which messages it missed, and resume delivery if it hears
+    ///
+    /// Returns the reported sessions (none if nothing was missed), or None if
+    /// the frontend is still unreachable.
+    pub fn try_recover(&self, sink: &dyn EventSink) -> Option<Vec<MissedMessages>> {
+        // Held across the emit so no message can be marked missed in between
+        let mut state = self.lock();
+        if state.missed.is_empty() {
+            state.unreachable_since = None;
+            return Some(Vec::new());
+        }
+        let missed: Vec<MissedMessages> = state
+            .missed
+            .iter()
+            .map(|(session_id, first)| MissedMessages {
+                session_id: session_id.clone(),
+                since_sequence: first.saturating_sub(1),
+            })
+            .collect();
+        let payload = serde_json::json!({ "sessions": missed });
+        if let Err(e) = sink.emit(RECOVERED_EVENT, payload) {
+            log::debug!("Frontend still unreachable: {}", e);
+            return None;
+        }
+        log::info!(
+            "Frontend reachable again; {} session(s) to replay",
+            missed.len()
+        );
+        state.missed.clear();
+        state.unreachable_since = None;
+        Some(missed)
+    }
+}
+
+/// Exponential delays between emit retries
+#[derive(Debug)]
+pub struct RetryBackoff {
+    next: Duration,
+}
+
+impl Default for RetryBackoff {
+    fn default() -> Self {
+        Self {
+            next: INITIAL_RETRY_DELAY,
+        }
+    }
+}
+
+impl RetryBackoff {
+    /// The delay before the next retry; each call doubles the following one
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(MAX_RETRY_DELAY);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.next = INITIAL_RETRY_DELAY;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::event_bridge::RecordingSink;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn test_recovery_reports_first_missed_sequence() {
+        let bridge = FrontendBridge::new();
+        let sink = RecordingSink::default();
+        assert_eq!(bridge.try_recover(&sink), Some(Vec::new()));
+        assert!(sink.recorded().is_empty());
+
+        bridge.missed("s1", 5);
+        bridge.missed("s1", 9);
+        bridge.missed("s2", 1);
+        assert!(bridge.is_holding("s1"));
+        assert!(bridge.unreachable_for().is_some());
+
+        sink.down.store(true, Ordering::SeqCst);
+        assert_eq!(bridge.try_recover(&sink), None);
+        assert!(bridge.is_holding("s1"));
+
+        sink.down.store(false, Ordering::SeqCst);
+        let expected = vec![
+            MissedMessages {
+                session_id: "s1".to_string(),
+                since_sequence: 4,
+            },
+            MissedMessages {
+                session_id: "s2".to_string(),
+                since_sequence: 0,
+            },
+        ];
+        assert_eq!(bridge.try_recover(&sink), Some(expected));
+        assert!(!bridge.is_holding("s1"));
+        assert_eq!(bridge.unreachable_for(), None);
+
+        let recorded = sink.recorded();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].event, RECOVERED_EVENT);
+        assert_eq!(recorded[0].payload["sessions"][0]["sinceSequence"], 4);
+    }
+
+    #[test]
+    fn test_interrupt_deadline_follows_policy() {
+        let bridge = FrontendBridge::new();
+        bridge.missed("s1", 1);
+        assert_eq!(bridge.interrupt_deadline(), None);
+
+        bridge.set_policy(BridgePolicy {
+            interrupt_after_secs: Some(30),
+        });
+        let deadline = bridge.interrupt_deadline().unwrap();
+        assert!(deadline > Instant::now() + Duration::from_secs(29));
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_cap() {
+        let mut backoff = RetryBackoff::default();
+        let delays: Vec<Duration> = (0..8).map(|_| backoff.next_delay()).collect();
+        assert_eq!(delays[0], INITIAL_RETRY_DELAY);
+        assert_eq!(delays[1], INITIAL_RETRY_DELAY * 2);
+        assert_eq!(delays[7], MAX_RETRY_DELAY);
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), INITIAL_RETRY_DELAY);
+    }
+}
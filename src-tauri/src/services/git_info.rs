@@ -0,0 +1,525 @@
+//! Git status and last commit of many files at once
+//!
+//! The file tree decorates every visible entry, so running git once per file
+//! is far too slow. [`file_info_batch`] runs four git commands whatever the
+//! number of paths:
+//!
+//! 1. `rev-parse --show-toplevel`, to map paths into the repository
+//! 2. `status --porcelain -z`, filtered to the paths in memory
+//! 3. `ls-files -z`, to know which paths have history at all
+//! 4. one `log --name-only` walk from HEAD, stopped as soon as every tracked
+//!    path has been seen
+//!
+//! A directory path gets the combined status of the files under it and the
+//! last commit touching any of them.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Component, Path, PathBuf};
+use std::process::Stdio;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+
+use super::git_ops::git_output;
+
+/// Separates commits in the `log` walk's output
+const RECORD_SEPARATOR: char = '\x1e';
+/// Separates the fields of a commit header in the `log` walk's output
+const FIELD_SEPARATOR: char = '\x1f';
+/// `git log` format producing `RECORD_SEPARATOR hash FIELD_SEPARATOR time FIELD_SEPARATOR subject`
+const LOG_FORMAT: &str = "--format=%x1e%H%x1f%ct%x1f%s";
+
+/// Working tree and index state of a path, from `git status`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GitStatusFlags {
+    /// Changes are staged in the index
+    pub staged: bool,
+    /// The working tree differs from the index
+    pub modified: bool,
+    pub deleted: bool,
+    pub renamed: bool,
+    pub untracked: bool,
+    /// Unmerged after a conflicting merge, rebase or cherry-pick
+    pub conflicted: bool,
+}
+
+impl GitStatusFlags {
+    /// Flags of a `git status --porcelain` XY code
+    fn from_code(x: char, y: char) -> Self {
+        if x == '?' && y == '?' {
+            return Self {
+                untracked: true,
+                ..Default::default()
+            };
+        }
+        let conflicted = x == 'U' || y == 'U' || (x == y && (x == 'A' || x == 'D'));
+        if conflicted {
+            return Self {
+                conflicted: true,
+                ..Default::default()
+            };
+        }
+        Self {
+            staged: "MTADRC".contains(x),
+            modified: y == 'M' || y == 'T',
+            deleted: x == 'D' || y == 'D',
+            renamed: x == 'R' || y == 'R',
+            untracked: false,
+            conflicted: false,
+        }
+    }
+
+    fn merge(&mut self, other: GitStatusFlags) {
+        self.staged |= other.staged;
+        self.modified |= other.modified;
+        self.deleted |= other.deleted;
+        self.renamed |= other.renamed;
+        self.untracked |= other.untracked;
+        self.conflicted |= other.conflicted;
+    }
+}
+
+/// The most recent commit touching a path
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LastCommit {
+    pub hash: String,
+    /// Committer time, in seconds since the Unix epoch
+    pub timestamp: i64,
+    pub subject: String,
+}
+
+/// Git information about one requested path
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileGitInfo {
+    /// The path as it was requested
+    pub path: String,
+    /// The path is not inside the repository; nothing else is set
+    pub outside_repo: bool,
+    pub status: GitStatusFlags,
+    /// None for untracked paths and paths not committed yet
+    pub last_commit: Option<LastCommit>,
+}
+
+/// Entries of `git status --porcelain -z`, as (path, flags)
+///
+/// Untracked directories are reported by git with a trailing `/`, which is
+/// kept. The original path of a rename or copy is skipped.
+fn parse_status(output: &str) -> Vec<(String, GitStatusFlags)> {
+    let mut entries = Vec::new();
+    let mut fields = output.split('\0');
+    while let Some(field) = fields.next() {
+        let mut chars = field.chars();
+        let (Some(x), Some(y), Some(' ')) = (chars.next(), chars.next(), chars.next()) else {
+            continue;
+        };
+        if x == 'R' || x == 'C' {
+            fields.next();
+        }
+        entries.push((chars.as_str().to_string(), GitStatusFlags::from_code(x, y)));
+    }
+    entries
+}
+
+/// One commit of the `log` walk: its header and the files it touched
+fn parse_log_record(record: &str) -> Option<(LastCommit, Vec<&str>)> {
+    let (header, files) = record.split_once('\0')?;
+    let mut fields = header.splitn(3, FIELD_SEPARATOR);
+    let hash = fields.next()?.to_string();
+    let timestamp = fields.next()?.parse().ok()?;
+    let subject = fields.next().unwrap_or_default().to_string();
+    let files = files
+        .split('\0')
+        .map(|file| file.trim_start_matches('\n'))
+        .filter(|file| !file.is_empty())
+        .collect();
+    Some((
+        LastCommit {
+            hash,
+            timestamp,
+            subject,
+        },
+        files,
+    ))
+}
+
+/// `path` and each of its parent directories, as repository paths (`""` is the root)
+fn self_and_ancestors(path: &str) -> impl Iterator<Item = &str> {
+    std::iter::once(path)
+        .chain(
+            path.rmatch_indices('/')
+                .map(move |(index, _)| &path[..index]),
+        )
+        .chain((!path.is_empty()).then_some(""))
+}
+
+/// `path` made absolute against `base`, with `.` and `..` resolved and links
+/// resolved as far as the path exists
+fn resolve(base: &Path, path: &str) -> PathBuf {
+    let mut absolute = PathBuf::new();
+    for component in base.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            other => absolute.push(other),
+        }
+    }
+    // Deleted files no longer exist; resolve the part that does
+    let mut missing = Vec::new();
+    let mut existing = absolute.as_path();
+    loop {
+        if let Ok(canonical) = existing.canonicalize() {
+            return missing
+                .iter()
+                .rev()
+                .fold(canonical, |resolved, name| resolved.join(name));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name.to_os_string());
+                existing = parent;
+            }
+            _ => return absolute,
+        }
+    }
+}
+
+/// `path` relative to `toplevel` with `/` separators, if it is inside it
+fn repo_path(toplevel: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(toplevel).ok()?;
+    let parts: Vec<String> = relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    Some(parts.join("/"))
+}
+
+/// Whether `path` is a file in `index`, or a directory with files in it
+fn has_index_entries(index: &BTreeSet<String>, path: &str) -> bool {
+    if path.is_empty() {
+        return !index.is_empty();
+    }
+    if index.contains(path) {
+        return true;
+    }
+    let prefix = format!("{}/", path);
+    index
+        .range(prefix.clone()..)
+        .next()
+        .is_some_and(|entry| entry.starts_with(&prefix))
+}
+
+/// Walk the history from HEAD until the last commit of every target is known
+///
+/// `targets` maps repository paths to the indices of the requested paths they
+/// came from. Found commits are written into `infos`.
+async fn find_last_commits(
+    toplevel: &Path,
+    mut targets: HashMap<String, Vec<usize>>,
+    infos: &mut [FileGitInfo],
+) -> Result<(), String> {
+    if targets.is_empty() {
+        return Ok(());
+    }
+    let mut child = tokio::process::Command::new("git")
+        .args(["log", "-z", "--name-only", "--no-renames", LOG_FORMAT])
+        .current_dir(toplevel)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+
+    let mut pending = String::new();
+    let mut bytes = Vec::new();
+    let mut buf = [0u8; 64 * 1024];
+    let mut done = false;
+    while !done {
+        let n = stdout
+            .read(&mut buf)
+            .await
+            .map_err(|e| format!("Failed to read git log: {}", e))?;
+        let at_end = n == 0;
+        bytes.extend_from_slice(&buf[..n]);
+        // Keep an incomplete UTF-8 sequence for the next read
+        let valid = match std::str::from_utf8(&bytes) {
+            Ok(_) => bytes.len(),
+            Err(e) if !at_end && e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => bytes.len(),
+        };
+        pending.push_str(&String::from_utf8_lossy(&bytes[..valid]));
+        bytes.drain(..valid);
+
+        // Every record but the last is complete until the output ends
+        let complete = if at_end {
+            pending.len()
+        } else {
+            pending.rfind(RECORD_SEPARATOR).unwrap_or(0)
+        };
+        for record in pending[..complete].split(RECORD_SEPARATOR) {
+            let Some((commit, files)) = parse_log_record(record) else {
+                continue;
+            };
+            for file in files {
+                for path in self_and_ancestors(file) {
+                    for index in targets.remove(path).unwrap_or_default() {
+                        infos[index].last_commit = Some(commit.clone());
+                    }
+                }
+            }
+            if targets.is_empty() {
+                done = true;
+                break;
+            }
+        }
+        pending.drain(..complete);
+        done |= at_end;
+    }
+    // Dropping the child kills a walk that was stopped early
+    Ok(())
+}
+
+/// Status flags and last commit of each of `paths`
+///
+/// Relative paths are taken relative to `dir`, which must be inside the
+/// repository; if it is not in one, every path is reported outside.
+pub async fn file_info_batch(dir: &Path, paths: &[String]) -> Result<Vec<FileGitInfo>, String> {
+    let mut infos: Vec<FileGitInfo> = paths
+        .iter()
+        .map(|path| FileGitInfo {
+            path: path.clone(),
+            outside_repo: true,
+            status: GitStatusFlags::default(),
+            last_commit: None,
+        })
+        .collect();
+
+    let output = git_output(dir, &["rev-parse", "--show-toplevel"]).await?;
+    if !output.status.success() {
+        return Ok(infos);
+    }
+    let toplevel = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let toplevel = toplevel.canonicalize().unwrap_or(toplevel);
+    let base = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+
+    // Repository path of each requested path that is inside the repository
+    let mut inside: Vec<(usize, String)> = Vec::new();
+    for (index, info) in infos.iter_mut().enumerate() {
+        if let Some(path) = repo_path(&toplevel, &resolve(&base, &info.path)) {
+            info.outside_repo = false;
+            inside.push((index, path));
+        }
+    }
+    if inside.is_empty() {
+        return Ok(infos);
+    }
+
+    let output = git_output(&toplevel, &["status", "--porcelain", "-z"]).await?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    let entries = parse_status(&String::from_utf8_lossy(&output.stdout));
+    for (index, path) in &inside {
+        let status = &mut infos[*index].status;
+        let prefix = format!("{}/", path);
+        for (entry, flags) in &entries {
+            // The entry itself, a file under a requested directory, or a file
+            // in an untracked directory
+            let matches = entry.trim_end_matches('/') == path
+                || path.is_empty()
+                || entry.starts_with(&prefix)
+                || (entry.ends_with('/') && path.starts_with(entry.as_str()));
+            if matches {
+                status.merge(*flags);
+            }
+        }
+    }
+
+    let output = git_output(&toplevel, &["ls-files", "-z"]).await?;
+    let index: BTreeSet<String> = String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|file| !file.is_empty())
+        .map(str::to_string)
+        .collect();
+    let mut targets: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index_of_path, path) in inside {
+        if has_index_entries(&index, &path) {
+            targets.entry(path).or_default().push(index_of_path);
+        }
+    }
+    find_last_commits(&toplevel, targets, &mut infos).await?;
+    Ok(infos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use std::time::{Duration, Instant};
+    use tempfile::TempDir;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    fn commit(dir: &Path, files: &[(&str, &str)], message: &str) {
+        for (file, content) in files {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        git(dir, &["add", "-A"]);
+        git(dir, &["commit", "-q", "-m", message]);
+    }
+
+    fn info<'a>(infos: &'a [FileGitInfo], path: &str) -> &'a FileGitInfo {
+        infos.iter().find(|info| info.path == path).unwrap()
+    }
+
+    fn subject(info: &FileGitInfo) -> Option<&str> {
+        info.last_commit
+            .as_ref()
+            .map(|commit| commit.subject.as_str())
+    }
+
+    #[test]
+    fn test_parse_status() {
+        let output = " M src/a.rs\0M  b.rs\0R  new.rs\0old.rs\0?? tmp/\0UU c.rs\0 D gone.rs\0";
+        let entries = parse_status(output);
+        let paths: Vec<&str> = entries.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec!["src/a.rs", "b.rs", "new.rs", "tmp/", "c.rs", "gone.rs"]
+        );
+        assert!(entries[0].1.modified && !entries[0].1.staged);
+        assert!(entries[1].1.staged && !entries[1].1.modified);
+        assert!(entries[2].1.renamed && entries[2].1.staged);
+        assert!(entries[3].1.untracked);
+        assert!(entries[4].1.conflicted && !entries[4].1.staged);
+        assert!(entries[5].1.deleted);
+    }
+
+    #[test]
+    fn test_self_and_ancestors() {
+        let paths: Vec<&str> = self_and_ancestors("a/b/c.rs").collect();
+        assert_eq!(paths, vec!["a/b/c.rs", "a/b", "a", ""]);
+        assert_eq!(self_and_ancestors("").collect::<Vec<_>>(), vec![""]);
+    }
+
+    #[tokio::test]
+    async fn test_mixed_tracked_untracked_and_modified() {
+        let repo = TempDir::new().unwrap();
+        let dir = repo.path();
+        git(dir, &["init", "-q"]);
+        commit(dir, &[("old.txt", "1"), ("src/lib.rs", "1")], "first");
+        commit(dir, &[("src/main.rs", "1"), ("gone.txt", "1")], "second");
+        commit(dir, &[("src/lib.rs", "2")], "third: lib");
+        std::fs::write(dir.join("src/main.rs"), "changed").unwrap();
+        std::fs::write(dir.join("new.txt"), "new").unwrap();
+        std::fs::create_dir(dir.join("scratch")).unwrap();
+        std::fs::write(dir.join("scratch/notes.txt"), "new").unwrap();
+        std::fs::remove_file(dir.join("gone.txt")).unwrap();
+        let outside = TempDir::new().unwrap();
+
+        let paths: Vec<String> = [
+            "old.txt",
+            "src/main.rs",
+            "src/lib.rs",
+            "new.txt",
+            "scratch/notes.txt",
+            "gone.txt",
+            "src",
+            "./src/../old.txt",
+        ]
+        .iter()
+        .map(|path| path.to_string())
+        .chain([outside.path().join("x.txt").to_string_lossy().to_string()])
+        .collect();
+        let infos = file_info_batch(dir, &paths).await.unwrap();
+        assert_eq!(infos.len(), paths.len());
+
+        let old = info(&infos, "old.txt");
+        assert_eq!(old.status, GitStatusFlags::default());
+        assert_eq!(subject(old), Some("first"));
+        assert_eq!(old.last_commit.as_ref().unwrap().hash.len(), 40);
+        assert!(old.last_commit.as_ref().unwrap().timestamp > 0);
+
+        let main = info(&infos, "src/main.rs");
+        assert!(main.status.modified && !main.status.staged);
+        assert_eq!(subject(main), Some("second"));
+
+        assert_eq!(subject(info(&infos, "src/lib.rs")), Some("third: lib"));
+
+        for untracked in ["new.txt", "scratch/notes.txt"] {
+            let untracked = info(&infos, untracked);
+            assert!(untracked.status.untracked, "{:?}", untracked);
+            assert_eq!(untracked.last_commit, None);
+        }
+
+        let gone = info(&infos, "gone.txt");
+        assert!(gone.status.deleted);
+        assert_eq!(subject(gone), Some("second"));
+
+        // A directory combines its files
+        let src = info(&infos, "src");
+        assert!(src.status.modified);
+        assert_eq!(subject(src), Some("third: lib"));
+
+        assert_eq!(subject(info(&infos, "./src/../old.txt")), Some("first"));
+
+        let outside = infos.last().unwrap();
+        assert!(outside.outside_repo);
+        assert_eq!(outside.last_commit, None);
+        assert!(!old.outside_repo);
+    }
+
+    #[tokio::test]
+    async fn test_not_a_repository() {
+        let dir = TempDir::new().unwrap();
+        let infos = file_info_batch(dir.path(), &["a.txt".to_string()])
+            .await
+            .unwrap();
+        assert!(infos[0].outside_repo);
+    }
+
+    /// 500 paths over a repository with some history
+    #[tokio::test]
+    #[ignore]
+    async fn bench_five_hundred_paths() {
+        let repo = TempDir::new().unwrap();
+        let dir = repo.path();
+        git(dir, &["init", "-q"]);
+        for batch in 0..10 {
+            let files: Vec<(String, String)> = (0..50)
+                .map(|i| (format!("dir{}/file{}.txt", batch, i), "x".to_string()))
+                .collect();
+            let files: Vec<(&str, &str)> = files
+                .iter()
+                .map(|(path, content)| (path.as_str(), content.as_str()))
+                .collect();
+            commit(dir, &files, &format!("batch {}", batch));
+        }
+        let paths: Vec<String> = (0..10)
+            .flat_map(|batch| (0..50).map(move |i| format!("dir{}/file{}.txt", batch, i)))
+            .collect();
+
+        // Warm the cache, then time
+        file_info_batch(dir, &paths).await.unwrap();
+        let start = Instant::now();
+        let infos = file_info_batch(dir, &paths).await.unwrap();
+        let elapsed = start.elapsed();
+        eprintln!("500 paths in {:?}", elapsed);
+        assert!(infos.iter().all(|info| info.last_commit.is_some()));
+        assert!(elapsed < Duration::from_secs(1));
+    }
+}
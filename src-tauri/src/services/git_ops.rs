@@ -0,0 +1,657 @@
+//! Long-running git operations with progress reporting
+//!
+//! `git clone` and `git fetch` can take minutes, so they run in the
+//! background with `--progress`. Git writes its progress to stderr as lines
+//! terminated by `\r` (updates of the same phase) or `\n`; each line of the
+//! form `<phase>: <percent>% <detail>` becomes a [`GitEvent::Progress`], and
+//! the run ends with a [`GitEvent::Completed`]. Cancelling kills git and
+//! removes what a partial clone left behind.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use serde::Serialize;
+use thiserror::Error;
+use tokio::io::AsyncReadExt;
+use tokio::sync::mpsc;
+
+use super::operations::CancellationToken;
+
+/// Maximum bytes of git's stderr kept for the failure message (the tail is kept)
+const MAX_STDERR_TAIL: usize = 8 * 1024;
+
+/// Longest line [`ProgressLines`] keeps whole; longer lines are split
+const MAX_LINE_BYTES: usize = 4096;
+
+/// Errors that prevent a git operation from starting
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum GitOpError {
+    #[error("Invalid repository URL: {0}")]
+    InvalidUrl(String),
+    #[error("Local repository URLs are not allowed: {0}")]
+    LocalUrl(String),
+    #[error("Destination already exists and is not empty: {0}")]
+    DestinationExists(String),
+}
+
+/// One progress update of a git operation, emitted as `git-progress`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GitProgress {
+    #[serde(rename = "operationId")]
+    pub operation_id: String,
+    /// e.g. "Receiving objects", "Resolving deltas"
+    pub phase: String,
+    pub percent: u8,
+    /// The rest of the line, e.g. "(148/1234), 1.20 MiB | 2.39 MiB/s"
+    pub detail: String,
+}
+
+/// The end of a git operation, emitted as `git-completed`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GitCompletion {
+    #[serde(rename = "operationId")]
+    pub operation_id: String,
+    pub success: bool,
+    pub cancelled: bool,
+    /// The clone destination or the fetched repository
+    pub path: PathBuf,
+    pub error: Option<String>,
+}
+
+/// Events of a running git operation
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum GitEvent {
+    Progress(GitProgress),
+    Completed(GitCompletion),
+}
+
+impl GitEvent {
+    /// Name of the Tauri event this is emitted as
+    pub fn event_name(&self) -> &'static str {
+        match self {
+            GitEvent::Progress(_) => "git-progress",
+            GitEvent::Completed(_) => "git-completed",
+        }
+    }
+}
+
+/// Run a short git command in `dir` and wait for its output
+///
+/// Only failing to run git is an error; callers decide what a non-zero exit means.
+pub async fn git_output(dir: &Path, args: &[&str]) -> Result<std::process::Output, String> {
+    tokio::process::Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run git: {}", e))
+}
+
+/// Whether `url` refers to the local filesystem rather than a remote host
+fn is_local_url(url: &str) -> bool {
+    let lower = url.to_ascii_lowercase();
+    if lower.starts_with("file:") {
+        return true;
+    }
+    if lower.contains("://") {
+        return false;
+    }
+    // Paths: absolute, relative, home-relative or Windows drive letters
+    let bytes = url.as_bytes();
+    let drive_letter = bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':';
+    url.starts_with('/')
+        || url.starts_with('.')
+        || url.starts_with('~')
+        || url.starts_with('\\')
+        || drive_letter
+        // scp-like `host:path` needs a colon before any slash
+        || !url.split('/').next().is_some_and(|head| head.contains(':'))
+}
+
+/// Check a clone URL; local paths and `file://` URLs need `allow_local`
+///
+/// Transports that run commands (`ext::`) and URLs that could be read as an
+/// option are always rejected.
+pub fn validate_clone_url(url: &str, allow_local: bool) -> Result<(), GitOpError> {
+    let url = url.trim();
+    if url.is_empty() || url.starts_with('-') || url.chars().any(char::is_control) {
+        return Err(GitOpError::InvalidUrl(url.to_string()));
+    }
+    let lower = url.to_ascii_lowercase();
+    if lower.starts_with("ext::") || lower.starts_with("fd::") {
+        return Err(GitOpError::InvalidUrl(url.to_string()));
+    }
+    if let Some((scheme, _)) = lower.split_once("://") {
+        if !["https", "http", "ssh", "git", "git+ssh", "ssh+git", "file"].contains(&scheme) {
+            return Err(GitOpError::InvalidUrl(url.to_string()));
+        }
+    }
+    if is_local_url(url) && !allow_local {
+        return Err(GitOpError::LocalUrl(url.to_string()));
+    }
+    Ok(())
+}
+
+/// Parse one progress line, e.g. `remote: Counting objects:  45% (556/1234)`
+///
+/// Returns (phase, percent, detail); lines without a percentage yield None.
+pub fn parse_progress_line(line: &str) -> Option<(String, u8, String)> {
+    let line = line.trim();
+    let line = line.strip_prefix("remote:").unwrap_or(line).trim_start();
+    let (phase, rest) = line.split_once(':')?;
+    let (percent, detail) = rest.trim_start().split_once('%')?;
+    let percent: u8 = percent.trim().parse().ok()?;
+    if phase.is_empty() || percent > 100 {
+        return None;
+    }
+    Some((phase.trim().to_string(), percent, detail.trim().to_string()))
+}
+
+/// Splits a byte stream into lines at `\r` and `\n`
+///
+/// Empty lines are skipped, and a line is cut after `MAX_LINE_BYTES` so
+/// output without line breaks cannot grow without bound.
+#[derive(Debug, Default)]
+pub struct ProgressLines {
+    pending: Vec<u8>,
+}
+
+impl ProgressLines {
+    /// Feed a chunk; returns the lines it completed
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for &byte in chunk {
+            if byte == b'\r' || byte == b'\n' {
+                if !self.pending.is_empty() {
+                    lines.push(String::from_utf8_lossy(&self.pending).into_owned());
+                    self.pending.clear();
+                }
+            } else {
+                self.pending.push(byte);
+                if self.pending.len() >= MAX_LINE_BYTES {
+                    lines.extend(self.finish());
+                }
+            }
+        }
+        lines
+    }
+
+    /// The unterminated last line, if any
+    pub fn finish(&mut self) -> Option<String> {
+        (!self.pending.is_empty())
+            .then(|| String::from_utf8_lossy(&std::mem::take(&mut self.pending)).into_owned())
+    }
+}
+
+/// A git operation to run in the background
+#[derive(Debug, Clone)]
+pub struct GitOperation {
+    pub operation_id: String,
+    /// Arguments after `git`, including `--progress`
+    pub args: Vec<OsString>,
+    /// Working directory of the git process
+    pub dir: PathBuf,
+    /// Reported as the completion's path
+    pub path: PathBuf,
+    /// What to clean up if the operation fails or is cancelled
+    pub cleanup: Option<Cleanup>,
+}
+
+/// Leftovers of a failed or cancelled clone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cleanup {
+    /// A destination git created: removed with everything in it
+    RemoveDir(PathBuf),
+    /// A destination that existed (empty) before: emptied but kept
+    EmptyDir(PathBuf),
+}
+
+impl Cleanup {
+    async fn run(&self) -> std::io::Result<()> {
+        match self {
+            Cleanup::RemoveDir(dir) => tokio::fs::remove_dir_all(dir).await,
+            Cleanup::EmptyDir(dir) => {
+                let mut entries = tokio::fs::read_dir(dir).await?;
+                while let Some(entry) = entries.next_entry().await? {
+                    if entry.file_type().await?.is_dir() {
+                        tokio::fs::remove_dir_all(entry.path()).await?;
+                    } else {
+                        tokio::fs::remove_file(entry.path()).await?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn dir(&self) -> &Path {
+        match self {
+            Cleanup::RemoveDir(dir) | Cleanup::EmptyDir(dir) => dir,
+        }
+    }
+}
+
+impl GitOperation {
+    /// `git clone --progress [--depth N] -- <url> <dest>`
+    ///
+    /// Fails if `dest` exists and is not an empty directory. A relative
+    /// `dest` is taken relative to the current directory.
+    pub fn clone(
+        operation_id: &str,
+        url: &str,
+        dest: &Path,
+        depth: Option<u32>,
+        allow_local: bool,
+    ) -> Result<Self, GitOpError> {
+        validate_clone_url(url, allow_local)?;
+        let dest_exists = dest.exists();
+        if dest_exists
+            && std::fs::read_dir(dest)
+                .map(|mut entries| entries.next().is_some())
+                .unwrap_or(true)
+        {
+            return Err(GitOpError::DestinationExists(
+                dest.to_string_lossy().to_string(),
+            ));
+        }
+
+        let mut args: Vec<OsString> = vec!["clone".into(), "--progress".into()];
+        if let Some(depth) = depth {
+            args.push("--depth".into());
+            args.push(depth.max(1).to_string().into());
+        }
+        // Git runs in the parent directory, so a relative destination would
+        // be resolved twice
+        let target = std::path::absolute(dest).unwrap_or_else(|_| dest.to_path_buf());
+        args.push("--".into());
+        args.push(url.trim().into());
+        args.push(target.clone().into_os_string());
+
+        Ok(Self {
+            operation_id: operation_id.to_string(),
+            args,
+            dir: target
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| PathBuf::from(".")),
+            path: dest.to_path_buf(),
+            cleanup: Some(if dest_exists {
+                Cleanup::EmptyDir(target)
+            } else {
+                Cleanup::RemoveDir(target)
+            }),
+        })
+    }
+
+    /// `git fetch --progress` in `dir`
+    pub fn fetch(operation_id: &str, dir: &Path) -> Self {
+        Self {
+            operation_id: operation_id.to_string(),
+            args: vec!["fetch".into(), "--progress".into()],
+            dir: dir.to_path_buf(),
+            path: dir.to_path_buf(),
+            cleanup: None,
+        }
+    }
+
+    /// Run git, sending progress events and finally the completion to `events`
+    ///
+    /// The completion is also returned. Cancelling `token` kills git.
+    pub async fn run(
+        self,
+        token: &CancellationToken,
+        events: mpsc::Sender<GitEvent>,
+    ) -> GitCompletion {
+        let (success, cancelled, error) = self.run_git(token, &events).await;
+        if !success {
+            if let Some(ref cleanup) = self.cleanup {
+                if let Err(e) = cleanup.run().await {
+                    if e.kind() != std::io::ErrorKind::NotFound {
+                        log::warn!(
+                            "Failed to remove partial clone {}: {}",
+                            cleanup.dir().display(),
+                            e
+                        );
+                    }
+                }
+            }
+        }
+
+        let completion = GitCompletion {
+            operation_id: self.operation_id,
+            success,
+            cancelled,
+            path: self.path,
+            error,
+        };
+        let _ = events.send(GitEvent::Completed(completion.clone())).await;
+        completion
+    }
+
+    /// Returns (success, cancelled, error message)
+    async fn run_git(
+        &self,
+        token: &CancellationToken,
+        events: &mpsc::Sender<GitEvent>,
+    ) -> (bool, bool, Option<String>) {
+        if token.is_cancelled() {
+            return (false, true, None);
+        }
+        let spawned = tokio::process::Command::new("git")
+            .args(&self.args)
+            .current_dir(&self.dir)
+            // Never block on a credential prompt nobody can answer
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => return (false, false, Some(format!("Failed to run git: {}", e))),
+        };
+        let Some(mut stderr) = child.stderr.take() else {
+            return (
+                false,
+                false,
+                Some("Failed to capture git output".to_string()),
+            );
+        };
+
+        let operation_id = self.operation_id.clone();
+        let events = events.clone();
+        let progress = async move {
+            let mut lines = ProgressLines::default();
+            let mut tail: Vec<String> = Vec::new();
+            let mut tail_len = 0;
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = match stderr.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => n,
+                };
+                for line in lines.push(&buf[..n]) {
+                    match parse_progress_line(&line) {
+                        Some((phase, percent, detail)) => {
+                            let _ = events
+                                .send(GitEvent::Progress(GitProgress {
+                                    operation_id: operation_id.clone(),
+                                    phase,
+                                    percent,
+                                    detail,
+                                }))
+                                .await;
+                        }
+                        None => {
+                            tail_len += line.len();
+                            tail.push(line);
+                            while tail_len > MAX_STDERR_TAIL && tail.len() > 1 {
+                                tail_len -= tail.remove(0).len();
+                            }
+                        }
+                    }
+                }
+            }
+            tail.extend(lines.finish());
+            tail.join("\n")
+        };
+
+        let (status, messages) = tokio::select! {
+            (status, messages) = async { tokio::join!(child.wait(), progress) } => (status, messages),
+            _ = token.cancelled() => {
+                let _ = child.kill().await;
+                return (false, true, None);
+            }
+        };
+        match status {
+            Ok(status) if status.success() => (true, false, None),
+            Ok(status) => {
+                let message = messages.trim();
+                let error = if message.is_empty() {
+                    format!("git exited with {}", status)
+                } else {
+                    message.to_string()
+                };
+                (false, false, Some(error))
+            }
+            Err(e) => (false, false, Some(format!("Failed to wait for git: {}", e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use tempfile::TempDir;
+
+    /// stderr of `git clone --progress`, as captured from a real clone
+    const CLONE_STDERR: &[u8] = b"Cloning into 'repo'...\n\
+remote: Enumerating objects: 1234, done.\n\
+remote: Counting objects:   0% (1/556)\rremote: Counting objects:  45% (251/556)\rremote: Counting objects: 100% (556/556), done.\n\
+remote: Compressing objects: 100% (10/10), done.\n\
+Receiving objects:  12% (148/1234), 1.20 MiB | 2.39 MiB/s\rReceiving objects: 100% (1234/1234), 9.80 MiB | 3.10 MiB/s, done.\n\
+Resolving deltas:  50% (3/6)\rResolving deltas: 100% (6/6), done.\n";
+
+    #[test]
+    fn test_parse_captured_progress() {
+        let mut lines = ProgressLines::default();
+        // Feed in awkward chunks, splitting lines and \r\n pairs
+        let mut parsed = Vec::new();
+        for chunk in CLONE_STDERR.chunks(7) {
+            parsed.extend(lines.push(chunk));
+        }
+        parsed.extend(lines.finish());
+
+        let progress: Vec<(String, u8, String)> = parsed
+            .iter()
+            .filter_map(|l| parse_progress_line(l))
+            .collect();
+        let phases: Vec<(&str, u8)> = progress
+            .iter()
+            .map(|(phase, percent, _)| (phase.as_str(), *percent))
+            .collect();
+        assert_eq!(
+            phases,
+            vec![
+                ("Counting objects", 0),
+                ("Counting objects", 45),
+                ("Counting objects", 100),
+                ("Compressing objects", 100),
+                ("Receiving objects", 12),
+                ("Receiving objects", 100),
+                ("Resolving deltas", 50),
+                ("Resolving deltas", 100),
+            ]
+        );
+        assert_eq!(progress[4].2, "(148/1234), 1.20 MiB | 2.39 MiB/s");
+        assert_eq!(parse_progress_line("Cloning into 'repo'..."), None);
+        assert_eq!(
+            parse_progress_line("remote: Enumerating objects: 1234, done."),
+            None
+        );
+    }
+
+    #[test]
+    fn test_long_lines_are_cut() {
+        let mut lines = ProgressLines::default();
+        let split = lines.push(&vec![b'x'; MAX_LINE_BYTES + 10]);
+        assert_eq!(split.len(), 1);
+        assert_eq!(split[0].len(), MAX_LINE_BYTES);
+        assert_eq!(lines.finish().map(|rest| rest.len()), Some(10));
+    }
+
+    #[test]
+    fn test_url_validation() {
+        for url in [
+            "https://github.com/o/r.git",
+            "git@github.com:o/r.git",
+            "ssh://git@host:22/o/r",
+            "git://host/r",
+        ] {
+            assert_eq!(validate_clone_url(url, false), Ok(()), "url: {}", url);
+        }
+        for url in [
+            "file:///tmp/r",
+            "/tmp/r",
+            "../r",
+            "~/r",
+            "C:\\repos\\r",
+            "repo",
+        ] {
+            assert!(
+                matches!(validate_clone_url(url, false), Err(GitOpError::LocalUrl(_))),
+                "url: {}",
+                url
+            );
+            assert_eq!(validate_clone_url(url, true), Ok(()), "url: {}", url);
+        }
+        for url in [
+            "",
+            "--upload-pack=touch /tmp/x",
+            "ext::sh -c touch% /tmp/x",
+            "ftp://h/r",
+        ] {
+            assert!(
+                matches!(
+                    validate_clone_url(url, true),
+                    Err(GitOpError::InvalidUrl(_))
+                ),
+                "url: {}",
+                url
+            );
+        }
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    /// A fixture repo with two commits
+    fn fixture_repo(dir: &Path) -> String {
+        git(dir, &["init", "-q"]);
+        for (file, message) in [("a.txt", "first"), ("b.txt", "second")] {
+            std::fs::write(dir.join(file), message).unwrap();
+            git(dir, &["add", "."]);
+            git(
+                dir,
+                &[
+                    "-c",
+                    "user.name=test",
+                    "-c",
+                    "user.email=test@example.com",
+                    "commit",
+                    "-qm",
+                    message,
+                ],
+            );
+        }
+        format!("file://{}", dir.display())
+    }
+
+    async fn collect(
+        operation: GitOperation,
+        token: &CancellationToken,
+    ) -> (GitCompletion, Vec<GitEvent>) {
+        let (tx, mut rx) = mpsc::channel(1024);
+        let completion = operation.run(token, tx).await;
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+        (completion, events)
+    }
+
+    #[tokio::test]
+    async fn test_shallow_clone_from_fixture() {
+        let source = TempDir::new().unwrap();
+        let url = fixture_repo(source.path());
+        let target = TempDir::new().unwrap();
+        let dest = target.path().join("clone");
+
+        assert!(matches!(
+            GitOperation::clone("op", &url, &dest, Some(1), false),
+            Err(GitOpError::LocalUrl(_))
+        ));
+        let operation = GitOperation::clone("op", &url, &dest, Some(1), true).unwrap();
+        let (completion, events) = collect(operation, &CancellationToken::new()).await;
+
+        assert!(completion.success, "{:?}", completion.error);
+        assert_eq!(completion.path, dest);
+        assert_eq!(events.last(), Some(&GitEvent::Completed(completion)));
+        assert!(dest.join("b.txt").exists());
+        let log = Command::new("git")
+            .args(["rev-list", "--count", "HEAD"])
+            .current_dir(&dest)
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&log.stdout).trim(), "1");
+
+        // Fetching in the clone works too; cloning onto it again does not
+        let (fetched, _) =
+            collect(GitOperation::fetch("op2", &dest), &CancellationToken::new()).await;
+        assert!(fetched.success, "{:?}", fetched.error);
+        assert!(matches!(
+            GitOperation::clone("op3", &url, &dest, None, true),
+            Err(GitOpError::DestinationExists(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_failed_and_cancelled_clones_clean_up() {
+        let target = TempDir::new().unwrap();
+        let dest = target.path().join("clone");
+        let missing = format!("file://{}", target.path().join("no-such-repo").display());
+
+        let operation = GitOperation::clone("op", &missing, &dest, None, true).unwrap();
+        let (completion, _) = collect(operation, &CancellationToken::new()).await;
+        assert!(!completion.success && !completion.cancelled);
+        assert!(completion.error.is_some());
+        assert!(!dest.exists());
+
+        let token = CancellationToken::new();
+        token.cancel();
+        std::fs::create_dir(&dest).unwrap();
+        let source = TempDir::new().unwrap();
+        let url = fixture_repo(source.path());
+        let operation = GitOperation::clone("op", &url, &dest, None, true).unwrap();
+        // Stands in for what git wrote before it was cancelled
+        std::fs::create_dir(dest.join(".git")).unwrap();
+        std::fs::write(dest.join("a.txt"), "partial").unwrap();
+        let (completion, _) = collect(operation, &token).await;
+        assert!(completion.cancelled);
+        // An existing (empty) destination is emptied but left in place
+        assert!(dest.exists());
+        assert_eq!(std::fs::read_dir(&dest).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_relative_clone_destination_is_resolved_once() {
+        let operation = GitOperation::clone(
+            "op",
+            "https://example.com/r.git",
+            Path::new("a/b"),
+            None,
+            false,
+        )
+        .unwrap();
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(operation.dir, cwd.join("a"));
+        assert_eq!(
+            operation.args.last(),
+            Some(&cwd.join("a/b").into_os_string())
+        );
+        assert_eq!(operation.path, Path::new("a/b"));
+    }
+}
@@ -0,0 +1,338 @@
+//! Shell-out hooks run around each prompt
+//!
+//! A session can configure two shell commands, either in its `SessionConfig`
+//! or in the project's `.claude/gui-companion.json`:
+//! - `pre_prompt` runs in the working directory before the CLI is spawned, with
+//!   the prompt text on stdin. A non-zero exit vetoes the prompt.
+//! - `post_prompt` runs after the CLI process exits, with a JSON
+//!   [`PostPromptSummary`] on stdin.
+//!
+//! Both are killed after a timeout and their output is kept in the prompt history.
+
+use std::path::Path;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Project-level config file (relative to the working directory) that can define hooks
+pub const PROJECT_CONFIG_FILE: &str = ".claude/gui-companion.json";
+
+/// Timeout applied when a hook config does not set one
+pub const DEFAULT_HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maximum bytes of hook stdout/stderr kept in the prompt history
+const MAX_CAPTURED_OUTPUT: usize = 16 * 1024;
+
+/// Hook commands for a session
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HooksConfig {
+    #[serde(default)]
+    pub pre_prompt: Option<String>,
+    #[serde(default)]
+    pub post_prompt: Option<String>,
+    /// Per-hook timeout in seconds (defaults to 30)
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+/// The subset of the project config file that holds hooks
+#[derive(Debug, Default, Deserialize)]
+struct ProjectConfig {
+    #[serde(default)]
+    hooks: HooksConfig,
+}
+
+impl HooksConfig {
+    /// Fill unset fields from `fallback` (session settings win over the project file)
+    pub fn or(self, fallback: HooksConfig) -> HooksConfig {
+        HooksConfig {
+            pre_prompt: self.pre_prompt.or(fallback.pre_prompt),
+            post_prompt: self.post_prompt.or(fallback.post_prompt),
+            timeout_secs: self.timeout_secs.or(fallback.timeout_secs),
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_HOOK_TIMEOUT)
+    }
+}
+
+/// Load hooks from the project config file in `working_dir`, if any
+pub fn load_project_hooks(working_dir: &Path) -> HooksConfig {
+    let path = working_dir.join(PROJECT_CONFIG_FILE);
+    match std::fs::read_to_string(&path) {
+        Ok(content) => match serde_json::from_str::<ProjectConfig>(&content) {
+            Ok(config) => config.hooks,
+            Err(e) => {
+                log::warn!("Ignoring invalid {}: {}", path.display(), e);
+                HooksConfig::default()
+            }
+        },
+        Err(_) => HooksConfig::default(),
+    }
+}
+
+/// Which hook ran
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookKind {
+    PrePrompt,
+    PostPrompt,
+}
+
+/// Captured result of running a hook
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HookOutput {
+    pub kind: HookKind,
+    pub command: String,
+    /// Exit code, or None if the hook was killed or could not be started
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub timed_out: bool,
+}
+
+impl HookOutput {
+    pub fn succeeded(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    /// Short explanation of why a hook failed, preferring its stderr
+    pub fn failure_reason(&self) -> String {
+        if self.timed_out {
+            return format!("hook timed out after {} ms", self.duration_ms);
+        }
+        let stderr = self.stderr.trim();
+        if !stderr.is_empty() {
+            return stderr.to_string();
+        }
+        match self.exit_code {
+            Some(code) => format!("hook exited with status {}", code),
+            None => "hook was terminated".to_string(),
+        }
+    }
+}
+
+/// JSON summary passed on stdin to the post-prompt hook
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostPromptSummary {
+    pub session_id: String,
+    pub prompt_id: String,
+    pub model: String,
+    pub cost_usd: Option<f64>,
+    pub duration_ms: u64,
+    pub files_touched: Vec<String>,
+}
+
+fn shell_command(command: &str) -> Command {
+    #[cfg(target_os = "windows")]
+    {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/C", command]);
+        cmd
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", command]);
+        cmd
+    }
+}
+
+fn captured(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    if text.len() <= MAX_CAPTURED_OUTPUT {
+        return text.into_owned();
+    }
+    let mut end = MAX_CAPTURED_OUTPUT;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}\n[output truncated]", &text[..end])
+}
+
+/// Run a hook command in `working_dir`, writing `input` to its stdin
+///
+/// Failures to start the shell are reported as a failed `HookOutput` rather
+/// than an error so they end up in the prompt history like any other failure.
+pub async fn run_hook(
+    kind: HookKind,
+    command: &str,
+    working_dir: &Path,
+    input: &[u8],
+    timeout: Duration,
+) -> HookOutput {
+    let started = Instant::now();
+    let failed = |stderr: String, timed_out: bool| HookOutput {
+        kind,
+        command: command.to_string(),
+        exit_code: None,
+        stdout: String::new(),
+        stderr,
+        duration_ms: started.elapsed().as_millis() as u64,
+        timed_out,
+    };
+
+    let mut child = match shell_command(command)
+        .current_dir(working_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => return failed(format!("Failed to start hook: {}", e), false),
+    };
+
+    // Written alongside the wait so a hook that never reads a large input
+    // still times out; stdin is closed once written
+    let stdin = child.stdin.take();
+    let write_input = async move {
+        if let Some(mut stdin) = stdin {
+            // The hook may exit without reading stdin; that is not an error
+            let _ = stdin.write_all(input).await;
+        }
+    };
+    let run = async {
+        let ((), output) = tokio::join!(write_input, child.wait_with_output());
+        output
+    };
+
+    match tokio::time::timeout(timeout, run).await {
+        Ok(Ok(output)) => HookOutput {
+            kind,
+            command: command.to_string(),
+            exit_code: output.status.code(),
+            stdout: captured(&output.stdout),
+            stderr: captured(&output.stderr),
+            duration_ms: started.elapsed().as_millis() as u64,
+            timed_out: false,
+        },
+        Ok(Err(e)) => failed(format!("Failed to wait for hook: {}", e), false),
+        // Dropping the future drops stdin and kills the child (kill_on_drop)
+        Err(_) => failed(String::new(), true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_session_hooks_override_project_hooks() {
+        let session = HooksConfig {
+            pre_prompt: Some("session-pre".to_string()),
+            ..Default::default()
+        };
+        let project = HooksConfig {
+            pre_prompt: Some("project-pre".to_string()),
+            post_prompt: Some("project-post".to_string()),
+            timeout_secs: Some(5),
+        };
+
+        let merged = session.or(project);
+        assert_eq!(merged.pre_prompt.as_deref(), Some("session-pre"));
+        assert_eq!(merged.post_prompt.as_deref(), Some("project-post"));
+        assert_eq!(merged.timeout(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_load_project_hooks() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(load_project_hooks(dir.path()), HooksConfig::default());
+
+        std::fs::create_dir_all(dir.path().join(".claude")).unwrap();
+        std::fs::write(
+            dir.path().join(PROJECT_CONFIG_FILE),
+            r#"{"hooks": {"post_prompt": "make fmt"}}"#,
+        )
+        .unwrap();
+        let hooks = load_project_hooks(dir.path());
+        assert_eq!(hooks.post_prompt.as_deref(), Some("make fmt"));
+        assert_eq!(hooks.timeout(), DEFAULT_HOOK_TIMEOUT);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_hook_success_reads_stdin() {
+        let dir = TempDir::new().unwrap();
+        let output = run_hook(
+            HookKind::PrePrompt,
+            "cat",
+            dir.path(),
+            b"the prompt",
+            Duration::from_secs(5),
+        )
+        .await;
+
+        assert!(output.succeeded());
+        assert_eq!(output.stdout, "the prompt");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_hook_failure_reason_uses_stderr() {
+        let dir = TempDir::new().unwrap();
+        let output = run_hook(
+            HookKind::PrePrompt,
+            "echo 'secrets detected' >&2; exit 3",
+            dir.path(),
+            b"",
+            Duration::from_secs(5),
+        )
+        .await;
+
+        assert!(!output.succeeded());
+        assert_eq!(output.exit_code, Some(3));
+        assert_eq!(output.failure_reason(), "secrets detected");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_hook_timeout() {
+        let dir = TempDir::new().unwrap();
+        let started = Instant::now();
+        let output = run_hook(
+            HookKind::PostPrompt,
+            "sleep 10",
+            dir.path(),
+            b"",
+            Duration::from_millis(200),
+        )
+        .await;
+
+        assert!(output.timed_out);
+        assert!(!output.succeeded());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_hook_timeout_covers_unread_input() {
+        let dir = TempDir::new().unwrap();
+        // Larger than any pipe buffer, so writing blocks until the hook exits
+        let input = vec![b'x'; 4 * 1024 * 1024];
+        let started = Instant::now();
+        let output = run_hook(
+            HookKind::PrePrompt,
+            "sleep 10",
+            dir.path(),
+            &input,
+            Duration::from_millis(200),
+        )
+        .await;
+
+        assert!(output.timed_out);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,624 @@
+//! Shared HTTP client factory with proxy support
+//!
+//! Every reqwest client in the crate is built by [`http_client`], so the
+//! proxy configured in `proxy.json` (app data directory) applies to MCP
+//! health checks and the connectivity probe alike. Settings win; when they
+//! name no proxy at all, the usual environment variables are used:
+//!
+//! ```json
+//! { "http_proxy": "http://proxy.corp:3128", "no_proxy": "localhost,.corp,10.0.0.0/8", "username": "jdoe" }
+//! ```
+//!
+//! The proxy password is never written to the file; it is kept in the OS
+//! keyring under the configured username.
+
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{OnceLock, RwLock};
+use std::time::Duration;
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use super::settings_file;
+
+/// File name of the proxy settings in the app data directory
+pub const PROXY_SETTINGS_FILE_NAME: &str = "proxy.json";
+
+/// Keyring service under which proxy passwords are stored
+const KEYRING_SERVICE: &str = "claude-gui-companion-proxy";
+
+/// Proxy settings as stored in `proxy.json`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProxySettings {
+    /// Proxy for plain HTTP requests
+    #[serde(default)]
+    pub http_proxy: Option<String>,
+    /// Proxy for HTTPS requests; falls back to `http_proxy`
+    #[serde(default)]
+    pub https_proxy: Option<String>,
+    /// Hosts that bypass the proxy (comma separated, see [`NoProxy`])
+    #[serde(default)]
+    pub no_proxy: Option<String>,
+    /// Basic auth user; the password is in the keyring
+    #[serde(default)]
+    pub username: Option<String>,
+}
+
+fn non_empty(value: &Option<String>) -> Option<String> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+impl ProxySettings {
+    /// Load the settings from `app_data_dir`; missing or malformed files mean none
+    pub fn load(app_data_dir: &Path) -> Self {
+        settings_file::load(app_data_dir, PROXY_SETTINGS_FILE_NAME)
+    }
+
+    /// Write the settings to `app_data_dir` atomically
+    pub async fn save(&self, app_data_dir: &Path) -> std::io::Result<()> {
+        settings_file::save(app_data_dir, PROXY_SETTINGS_FILE_NAME, self).await
+    }
+
+    fn names_proxy(&self) -> bool {
+        non_empty(&self.http_proxy).is_some() || non_empty(&self.https_proxy).is_some()
+    }
+}
+
+/// Store the proxy password for `username` in the OS keyring
+pub fn store_proxy_password(username: &str, password: &str) -> Result<(), String> {
+    keyring::Entry::new(KEYRING_SERVICE, username)
+        .and_then(|entry| entry.set_password(password))
+        .map_err(|e| format!("Failed to store proxy password: {}", e))
+}
+
+/// The proxy password stored for `username`, if any
+pub fn load_proxy_password(username: &str) -> Option<String> {
+    match keyring::Entry::new(KEYRING_SERVICE, username).and_then(|entry| entry.get_password()) {
+        Ok(password) => Some(password),
+        Err(keyring::Error::NoEntry) => None,
+        Err(e) => {
+            log::warn!("Failed to read proxy password from the keyring: {}", e);
+            None
+        }
+    }
+}
+
+/// One `no_proxy` entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NoProxyRule {
+    /// `*`: nothing is proxied
+    All,
+    /// A host name, matching itself and its subdomains (`.corp` and `corp` alike)
+    Domain(String),
+    /// An IP address or CIDR block
+    Network(IpAddr, u8),
+}
+
+/// Parsed `no_proxy` list, following curl's rules
+///
+/// Entries are separated by commas or whitespace. A domain matches itself and
+/// any subdomain, with or without a leading dot; IP addresses and CIDR blocks
+/// match addresses literally (no DNS lookups); `*` disables the proxy.
+/// Ports in entries are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NoProxy {
+    rules: Vec<NoProxyRule>,
+}
+
+impl NoProxy {
+    pub fn parse(list: &str) -> Self {
+        let rules = list
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| {
+                if entry == "*" {
+                    return Some(NoProxyRule::All);
+                }
+                let (address, prefix) = match entry.split_once('/') {
+                    Some((address, prefix)) => (address, Some(prefix)),
+                    None => (entry, None),
+                };
+                let address = address.trim_start_matches('[').trim_end_matches(']');
+                if let Ok(ip) = address.parse::<IpAddr>() {
+                    let max = if ip.is_ipv4() { 32 } else { 128 };
+                    let prefix = match prefix {
+                        Some(prefix) => prefix.parse::<u8>().ok().filter(|p| *p <= max)?,
+                        None => max,
+                    };
+                    return Some(NoProxyRule::Network(ip, prefix));
+                }
+                // Strip a port, then the leading dot
+                let host = entry.rsplit_once(':').map_or(entry, |(host, _)| host);
+                let host = host.trim_start_matches("*.").trim_start_matches('.');
+                (!host.is_empty()).then(|| NoProxyRule::Domain(host.to_ascii_lowercase()))
+            })
+            .collect();
+        Self { rules }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Whether requests to `host` bypass the proxy
+    pub fn matches(&self, host: &str) -> bool {
+        let host = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .trim_end_matches('.')
+            .to_ascii_lowercase();
+        let ip = host.parse::<IpAddr>().ok();
+        self.rules.iter().any(|rule| match rule {
+            NoProxyRule::All => true,
+            NoProxyRule::Domain(domain) => {
+                host == *domain
+                    || host
+                        .strip_suffix(domain.as_str())
+                        .is_some_and(|prefix| prefix.ends_with('.'))
+            }
+            NoProxyRule::Network(network, prefix) => {
+                ip.is_some_and(|ip| in_network(ip, *network, *prefix))
+            }
+        })
+    }
+}
+
+fn in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+/// The effective proxy configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProxyConfig {
+    pub http: Option<String>,
+    pub https: Option<String>,
+    pub no_proxy: NoProxy,
+    /// Basic auth (username, password)
+    pub credentials: Option<(String, String)>,
+}
+
+impl ProxyConfig {
+    /// Resolve `settings`, falling back to `env` (a variable lookup) when they name no proxy
+    pub fn resolve(
+        settings: &ProxySettings,
+        env: impl Fn(&str) -> Option<String>,
+        password: Option<String>,
+    ) -> Self {
+        let env_var = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| env(name).filter(|value| !value.trim().is_empty()))
+                .map(|value| value.trim().to_string())
+        };
+
+        let (http, https, no_proxy) = if settings.names_proxy() {
+            let http = non_empty(&settings.http_proxy);
+            let https = non_empty(&settings.https_proxy).or_else(|| http.clone());
+            (http, https, non_empty(&settings.no_proxy))
+        } else {
+            let all = env_var(&["ALL_PROXY", "all_proxy"]);
+            (
+                env_var(&["HTTP_PROXY", "http_proxy"]).or_else(|| all.clone()),
+                env_var(&["HTTPS_PROXY", "https_proxy"]).or(all),
+                non_empty(&settings.no_proxy).or_else(|| env_var(&["NO_PROXY", "no_proxy"])),
+            )
+        };
+
+        let credentials = non_empty(&settings.username).map(|username| {
+            let password = password.unwrap_or_default();
+            (username, password)
+        });
+
+        Self {
+            http,
+            https,
+            no_proxy: no_proxy
+                .map(|list| NoProxy::parse(&list))
+                .unwrap_or_default(),
+            credentials,
+        }
+    }
+
+    /// Resolve `settings` against the process environment and the keyring
+    pub fn from_settings(settings: &ProxySettings) -> Self {
+        let password = non_empty(&settings.username).and_then(|user| load_proxy_password(&user));
+        Self::resolve(settings, |name| std::env::var(name).ok(), password)
+    }
+
+    /// Whether any proxy is configured
+    pub fn is_configured(&self) -> bool {
+        self.http.is_some() || self.https.is_some()
+    }
+
+    /// The proxy a request to `url` goes through, if any
+    pub fn proxy_for(&self, url: &Url) -> Option<&str> {
+        if url
+            .host_str()
+            .is_some_and(|host| self.no_proxy.matches(host))
+        {
+            return None;
+        }
+        match url.scheme() {
+            "https" | "wss" => self.https.as_deref(),
+            _ => self.http.as_deref(),
+        }
+    }
+}
+
+fn current() -> &'static RwLock<Option<ProxyConfig>> {
+    static CONFIG: OnceLock<RwLock<Option<ProxyConfig>>> = OnceLock::new();
+    CONFIG.get_or_init(|| RwLock::new(None))
+}
+
+/// Use `config` for all clients built from now on
+pub fn set_proxy_config(config: ProxyConfig) {
+    *current().write().unwrap_or_else(|e| e.into_inner()) = Some(config);
+}
+
+/// The configuration clients are built with (the environment until one is set)
+pub fn proxy_config() -> ProxyConfig {
+    current()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .unwrap_or_else(|| ProxyConfig::from_settings(&ProxySettings::default()))
+}
+
+/// A client for `config` with the given request timeout
+pub fn client_with(config: &ProxyConfig, timeout: Duration) -> reqwest::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder().timeout(timeout).no_proxy();
+    if config.is_configured() {
+        let routes = config.clone();
+        let mut proxy = reqwest::Proxy::custom(move |url| {
+            routes
+                .proxy_for(url)
+                .and_then(|proxy| Url::parse(proxy).ok())
+        });
+        if let Some((ref username, ref password)) = config.credentials {
+            proxy = proxy.basic_auth(username, password);
+        }
+        builder = builder.proxy(proxy);
+    }
+    builder.build()
+}
+
+/// A client using the current proxy configuration
+pub fn http_client(timeout: Duration) -> reqwest::Result<reqwest::Client> {
+    client_with(&proxy_config(), timeout)
+}
+
+/// Why a request through the proxy failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyFailure {
+    /// Nothing listens at the proxy address
+    ConnectRefused,
+    /// The proxy answered 407 Proxy Authentication Required
+    AuthRequired,
+    /// TLS handshake or certificate validation failed
+    Tls,
+    Timeout,
+    Other,
+}
+
+/// Result of `test_proxy_connection`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProxyTestResult {
+    pub ok: bool,
+    pub endpoint: String,
+    /// The proxy the request went through; None if it went direct
+    pub proxy: Option<String>,
+    /// HTTP status of the response, if one arrived
+    pub status: Option<u16>,
+    pub failure: Option<ProxyFailure>,
+    pub message: Option<String>,
+    pub latency_ms: Option<u64>,
+}
+
+/// `error` and its sources, outermost first ("error sending request" alone says little)
+fn error_chain(error: &reqwest::Error) -> String {
+    let mut chain = Vec::new();
+    let mut source: Option<&dyn std::error::Error> = Some(error);
+    while let Some(e) = source {
+        chain.push(e.to_string());
+        source = e.source();
+    }
+    chain.join(": ")
+}
+
+/// Classify a failed request by its error chain
+fn classify_failure(error: &reqwest::Error) -> ProxyFailure {
+    let mut source: Option<&dyn std::error::Error> = Some(error);
+    while let Some(e) = source {
+        if let Some(io) = e.downcast_ref::<std::io::Error>() {
+            if io.kind() == std::io::ErrorKind::ConnectionRefused {
+                return ProxyFailure::ConnectRefused;
+            }
+        }
+        source = e.source();
+    }
+    let text = error_chain(error).to_lowercase();
+    if text.contains("407") || text.contains("proxy auth") {
+        ProxyFailure::AuthRequired
+    } else if text.contains("connection refused") {
+        ProxyFailure::ConnectRefused
+    } else if error.is_timeout() {
+        ProxyFailure::Timeout
+    } else if ["tls", "ssl", "certificate", "handshake"]
+        .iter()
+        .any(|marker| text.contains(marker))
+    {
+        ProxyFailure::Tls
+    } else {
+        ProxyFailure::Other
+    }
+}
+
+/// Send a HEAD request to `endpoint` using `config`, reporting how it failed
+pub async fn test_connection(
+    config: &ProxyConfig,
+    endpoint: &str,
+    timeout: Duration,
+) -> ProxyTestResult {
+    let mut result = ProxyTestResult {
+        ok: false,
+        endpoint: endpoint.to_string(),
+        proxy: None,
+        status: None,
+        failure: None,
+        message: None,
+        latency_ms: None,
+    };
+    let url = match Url::parse(endpoint) {
+        Ok(url) => url,
+        Err(e) => {
+            result.failure = Some(ProxyFailure::Other);
+            result.message = Some(format!("Invalid endpoint URL: {}", e));
+            return result;
+        }
+    };
+    result.proxy = config.proxy_for(&url).map(str::to_string);
+
+    let client = match client_with(config, timeout) {
+        Ok(client) => client,
+        Err(e) => {
+            result.failure = Some(ProxyFailure::Other);
+            result.message = Some(format!("Failed to build HTTP client: {}", e));
+            return result;
+        }
+    };
+
+    let started = std::time::Instant::now();
+    match client.head(url).send().await {
+        Ok(response) => {
+            result.latency_ms = Some(started.elapsed().as_millis() as u64);
+            result.status = Some(response.status().as_u16());
+            if response.status() == reqwest::StatusCode::PROXY_AUTHENTICATION_REQUIRED {
+                result.failure = Some(ProxyFailure::AuthRequired);
+                result.message = Some("Proxy authentication required".to_string());
+            } else {
+                // Any other response (even 401/404) made it through the proxy
+                result.ok = true;
+            }
+        }
+        Err(e) => {
+            result.failure = Some(classify_failure(&e));
+            result.message = Some(error_chain(&e));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_config_resolution_matrix() {
+        let settings = ProxySettings {
+            http_proxy: Some("http://settings:3128".to_string()),
+            ..Default::default()
+        };
+        let corp_env = env(&[
+            ("HTTPS_PROXY", "http://env-https:8080"),
+            ("http_proxy", "http://env-http:8080"),
+            ("NO_PROXY", "internal"),
+        ]);
+
+        // Nothing anywhere: direct
+        let config = ProxyConfig::resolve(&ProxySettings::default(), env(&[]), None);
+        assert!(!config.is_configured());
+        assert_eq!(config.proxy_for(&url("https://api.anthropic.com")), None);
+
+        // Environment only, including lowercase names and NO_PROXY
+        let config = ProxyConfig::resolve(&ProxySettings::default(), &corp_env, None);
+        assert_eq!(config.https.as_deref(), Some("http://env-https:8080"));
+        assert_eq!(config.http.as_deref(), Some("http://env-http:8080"));
+        assert_eq!(config.proxy_for(&url("https://internal/x")), None);
+
+        // ALL_PROXY covers both schemes
+        let config = ProxyConfig::resolve(
+            &ProxySettings::default(),
+            env(&[("ALL_PROXY", "socks5://all:1080")]),
+            None,
+        );
+        assert_eq!(config.http, config.https);
+        assert_eq!(config.https.as_deref(), Some("socks5://all:1080"));
+
+        // Settings win over the environment; https falls back to the http proxy
+        let config = ProxyConfig::resolve(&settings, &corp_env, None);
+        assert_eq!(config.http.as_deref(), Some("http://settings:3128"));
+        assert_eq!(config.https.as_deref(), Some("http://settings:3128"));
+        assert!(config.no_proxy.is_empty());
+        assert_eq!(
+            config.proxy_for(&url("https://api.anthropic.com")),
+            Some("http://settings:3128")
+        );
+
+        // Credentials need a username; a missing keyring entry means an empty password
+        assert_eq!(config.credentials, None);
+        let with_user = ProxySettings {
+            username: Some("jdoe".to_string()),
+            ..settings
+        };
+        let config = ProxyConfig::resolve(&with_user, env(&[]), Some("s3cret".to_string()));
+        assert_eq!(
+            config.credentials,
+            Some(("jdoe".to_string(), "s3cret".to_string()))
+        );
+        assert_eq!(
+            ProxyConfig::resolve(&with_user, env(&[]), None).credentials,
+            Some(("jdoe".to_string(), String::new()))
+        );
+
+        for config in [
+            ProxyConfig::default(),
+            ProxyConfig::resolve(&with_user, env(&[]), Some("pw".to_string())),
+        ] {
+            assert!(client_with(&config, Duration::from_secs(1)).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_no_proxy_matching() {
+        let rules =
+            NoProxy::parse("localhost, .corp.example ,example.org:8443 10.0.0.0/8,::1,[fd00::]/8");
+        for host in [
+            "localhost",
+            "LOCALHOST",
+            "corp.example",
+            "git.corp.example",
+            "example.org",
+            "www.example.org",
+            "10.1.2.3",
+            "::1",
+            "[::1]",
+            "fd12::1",
+        ] {
+            assert!(rules.matches(host), "{} should bypass", host);
+        }
+        for host in [
+            "notlocalhost",
+            "corp.example.com",
+            "badexample.org",
+            "11.0.0.1",
+            "192.168.1.1",
+            "::2",
+        ] {
+            assert!(!rules.matches(host), "{} should be proxied", host);
+        }
+
+        assert!(NoProxy::parse("*").matches("anything.test"));
+        assert!(NoProxy::parse("").is_empty());
+        // Malformed CIDR entries are dropped
+        assert!(!NoProxy::parse("10.0.0.0/99").matches("10.0.0.1"));
+    }
+
+    /// A "proxy" that answers every request with `response`
+    async fn fake_proxy(response: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                let _ = socket.write_all(response).await;
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    fn through(proxy: &str) -> ProxyConfig {
+        ProxyConfig::resolve(
+            &ProxySettings {
+                http_proxy: Some(proxy.to_string()),
+                ..Default::default()
+            },
+            env(&[]),
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_connection_failure_classes() {
+        let timeout = Duration::from_secs(5);
+        let endpoint = "http://example.invalid/";
+
+        let ok =
+            fake_proxy(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n").await;
+        let result = test_connection(&through(&ok), endpoint, timeout).await;
+        assert!(result.ok, "{:?}", result.message);
+        assert_eq!(result.proxy.as_deref(), Some(ok.as_str()));
+
+        let auth = fake_proxy(
+            b"HTTP/1.1 407 Proxy Authentication Required\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+        )
+        .await;
+        let result = test_connection(&through(&auth), endpoint, timeout).await;
+        assert!(!result.ok);
+        assert_eq!(result.status, Some(407));
+        assert_eq!(result.failure, Some(ProxyFailure::AuthRequired));
+        // CONNECT tunnels (https) report the 407 as an error
+        let result = test_connection(&through(&auth), "https://example.invalid/", timeout).await;
+        assert_eq!(
+            result.failure,
+            Some(ProxyFailure::AuthRequired),
+            "{:?}",
+            result.message
+        );
+
+        // A port nothing listens on
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed_addr = format!("http://{}", closed.local_addr().unwrap());
+        drop(closed);
+        let result = test_connection(&through(&closed_addr), endpoint, timeout).await;
+        assert_eq!(
+            result.failure,
+            Some(ProxyFailure::ConnectRefused),
+            "{:?}",
+            result.message
+        );
+
+        // A server that is not speaking TLS
+        let plain = fake_proxy(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n").await;
+        let https = plain.replace("http://", "https://");
+        let result = test_connection(&ProxyConfig::default(), &https, timeout).await;
+        assert_eq!(
+            result.failure,
+            Some(ProxyFailure::Tls),
+            "{:?}",
+            result.message
+        );
+    }
+}
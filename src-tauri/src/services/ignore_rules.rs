@@ -0,0 +1,114 @@
+//! Ignore rules shared by file listing and the file watcher
+//!
+//! A directory's rules are its `.gitignore` (and `.git/info/exclude`) parsed
+//! with the `ignore` crate, plus a fixed set of build and dependency
+//! directories that are skipped even outside git repositories.
+
+use std::path::{Component, Path, PathBuf};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+/// Directories that are always skipped, gitignored or not
+pub const ALWAYS_IGNORED_DIRS: &[&str] = &["node_modules", "target", ".git"];
+
+/// Name of the file whose change reloads the rules
+pub const GITIGNORE_FILE: &str = ".gitignore";
+
+/// Ignore rules rooted at one directory
+#[derive(Debug, Clone)]
+pub struct IgnoreRules {
+    root: PathBuf,
+    gitignore: Gitignore,
+}
+
+impl IgnoreRules {
+    /// Load the rules of `root`; unreadable or invalid rule files are logged and skipped
+    pub fn load(root: &Path) -> Self {
+        let mut builder = GitignoreBuilder::new(root);
+        for file in [
+            root.join(GITIGNORE_FILE),
+            root.join(".git").join("info").join("exclude"),
+        ] {
+            if !file.is_file() {
+                continue;
+            }
+            if let Some(e) = builder.add(&file) {
+                log::warn!("Ignoring invalid rules in {}: {}", file.display(), e);
+            }
+        }
+        let gitignore = builder.build().unwrap_or_else(|e| {
+            log::warn!("Failed to build ignore rules for {}: {}", root.display(), e);
+            Gitignore::empty()
+        });
+
+        Self {
+            root: root.to_path_buf(),
+            gitignore,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Whether `path` (under the root) is ignored; paths outside the root never are
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        let in_skipped_dir = relative.components().any(|component| {
+            matches!(component, Component::Normal(name)
+                if ALWAYS_IGNORED_DIRS.iter().any(|dir| name == *dir))
+        });
+        if in_skipped_dir {
+            return true;
+        }
+        if relative.as_os_str().is_empty() {
+            return false;
+        }
+        self.gitignore
+            .matched_path_or_any_parents(relative, is_dir)
+            .is_ignore()
+    }
+
+    /// Whether a change to `path` should reload these rules
+    pub fn is_rules_file(&self, path: &Path) -> bool {
+        path == self.root.join(GITIGNORE_FILE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_gitignore_and_builtin_dirs() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join(GITIGNORE_FILE), "dist/\n*.log\n!keep.log\n").unwrap();
+
+        let rules = IgnoreRules::load(root);
+        assert!(rules.is_ignored(&root.join("dist/bundle.js"), false));
+        assert!(rules.is_ignored(&root.join("dist"), true));
+        assert!(rules.is_ignored(&root.join("logs/app.log"), false));
+        assert!(!rules.is_ignored(&root.join("keep.log"), false));
+        assert!(rules.is_ignored(&root.join("node_modules/pkg/index.js"), false));
+        assert!(rules.is_ignored(&root.join("crates/a/target/debug/a"), false));
+        assert!(!rules.is_ignored(&root.join("src/main.rs"), false));
+        assert!(!rules.is_ignored(root, true));
+        // Outside the root nothing is ignored
+        assert!(!rules.is_ignored(Path::new("/elsewhere/dist/x.js"), false));
+
+        assert!(rules.is_rules_file(&root.join(GITIGNORE_FILE)));
+        assert!(!rules.is_rules_file(&root.join("src").join(GITIGNORE_FILE)));
+    }
+
+    #[test]
+    fn test_missing_gitignore_only_skips_builtin_dirs() {
+        let dir = TempDir::new().unwrap();
+        let rules = IgnoreRules::load(dir.path());
+        assert!(!rules.is_ignored(&dir.path().join("dist/bundle.js"), false));
+        assert!(rules.is_ignored(&dir.path().join("target/x"), false));
+    }
+}
@@ -0,0 +1,242 @@
+//! Crash-recovery journal for in-flight prompts
+//!
+//! When a prompt's CLI process starts, a small entry is written to
+//! `journal/<prompt_id>.json` in the app data directory; it is removed when
+//! the process exits. Entries still present at startup belong to prompts that
+//! were running when the app died, and are used to restore their sessions so
+//! they can be resumed with `--resume`.
+//!
+//! Entries are written to a temp file and renamed into place, so a crash
+//! mid-write leaves at most a stray temp file, which the scan discards along
+//! with anything that does not parse.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::process::SessionConfig;
+use super::storage;
+
+/// Name of the journal directory inside the app data directory
+pub const JOURNAL_DIR_NAME: &str = "journal";
+
+const ENTRY_EXTENSION: &str = "json";
+
+/// A prompt whose CLI process was running
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub session_id: String,
+    pub prompt_id: String,
+    /// Known from the start when resuming; otherwise filled in once the CLI reports it
+    pub claude_session_id: Option<String>,
+    pub started_at: u64,
+    /// See [`argv_hash`]
+    pub argv_hash: String,
+    pub pid: Option<u32>,
+    /// The session's config, to restore a session that only existed in memory
+    pub config: SessionConfig,
+}
+
+/// Hash identifying a CLI invocation without storing the prompt text
+pub fn argv_hash(args: &[OsString]) -> String {
+    let mut hasher = Sha256::new();
+    for arg in args {
+        hasher.update(arg.as_encoded_bytes());
+        hasher.update([0]);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Whether a process with this pid exists (it may be an unrelated process reusing the pid)
+pub fn process_exists(pid: u32) -> bool {
+    #[cfg(windows)]
+    {
+        super::win_process::is_running(pid)
+    }
+    #[cfg(not(windows))]
+    {
+        use nix::sys::signal::{kill, Signal};
+        use nix::unistd::Pid;
+
+        // Signal 0 only checks that the process exists
+        kill(Pid::from_raw(pid as i32), None::<Signal>).is_ok()
+    }
+}
+
+/// The journal directory; a journal without one records nothing
+#[derive(Debug, Clone, Default)]
+pub struct PromptJournal {
+    dir: Option<PathBuf>,
+}
+
+impl PromptJournal {
+    /// Journal under `app_data_dir`
+    pub fn in_app_data(app_data_dir: &Path) -> Self {
+        Self {
+            dir: Some(app_data_dir.join(JOURNAL_DIR_NAME)),
+        }
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    fn entry_path(dir: &Path, prompt_id: &str) -> PathBuf {
+        let name: String = prompt_id
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        dir.join(name).with_extension(ENTRY_EXTENSION)
+    }
+
+    /// Write (or overwrite) the entry of a running prompt
+    ///
+    /// Failures are logged: the journal must never keep a prompt from running.
+    pub async fn record(&self, entry: &JournalEntry) {
+        let Some(ref dir) = self.dir else {
+            return;
+        };
+        let path = Self::entry_path(dir, &entry.prompt_id);
+        let result = async {
+            let content = serde_json::to_vec(entry)?;
+            storage::write_atomic(&path, &content).await
+        }
+        .await;
+        if let Err(e) = result {
+            log::warn!("Failed to write journal entry {}: {}", path.display(), e);
+        }
+    }
+
+    /// Remove the entry of a prompt whose process has exited
+    pub async fn finish(&self, prompt_id: &str) {
+        let Some(ref dir) = self.dir else {
+            return;
+        };
+        let path = Self::entry_path(dir, prompt_id);
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => log::warn!("Failed to remove journal entry {}: {}", path.display(), e),
+        }
+    }
+
+    /// Remove all entries (after every process was killed on shutdown)
+    pub async fn clear(&self) {
+        if let Some(ref dir) = self.dir {
+            if let Err(e) = tokio::fs::remove_dir_all(dir).await {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("Failed to clear journal {}: {}", dir.display(), e);
+                }
+            }
+        }
+    }
+
+    /// Read and remove all entries left behind, oldest first
+    ///
+    /// Unreadable or partially written entries are logged and discarded.
+    pub async fn take_orphans(&self) -> Vec<JournalEntry> {
+        let Some(ref dir) = self.dir else {
+            return Vec::new();
+        };
+        let mut entries = match tokio::fs::read_dir(dir).await {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut orphans = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == ENTRY_EXTENSION) {
+                match tokio::fs::read(&path)
+                    .await
+                    .map_err(|e| e.to_string())
+                    .and_then(|content| {
+                        serde_json::from_slice::<JournalEntry>(&content).map_err(|e| e.to_string())
+                    }) {
+                    Ok(orphan) => orphans.push(orphan),
+                    Err(e) => log::warn!("Discarding journal entry {}: {}", path.display(), e),
+                }
+            }
+            if let Err(e) = tokio::fs::remove_file(&path).await {
+                log::warn!("Failed to remove journal entry {}: {}", path.display(), e);
+            }
+        }
+        orphans.sort_by_key(|orphan| orphan.started_at);
+        orphans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn entry(prompt_id: &str, started_at: u64) -> JournalEntry {
+        JournalEntry {
+            session_id: "session-1".to_string(),
+            prompt_id: prompt_id.to_string(),
+            claude_session_id: None,
+            started_at,
+            argv_hash: argv_hash(&["-p".into()]),
+            pid: Some(1),
+            config: SessionConfig::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_finished_prompts_leave_no_entries() {
+        let dir = TempDir::new().unwrap();
+        let journal = PromptJournal::in_app_data(dir.path());
+        journal.record(&entry("p1", 10)).await;
+        journal.record(&entry("p2", 20)).await;
+        journal.finish("p1").await;
+        journal.finish("missing").await;
+
+        let orphans = journal.take_orphans().await;
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].prompt_id, "p2");
+        // Taking removes them
+        assert!(journal.take_orphans().await.is_empty());
+
+        journal.record(&entry("p3", 30)).await;
+        journal.clear().await;
+        assert!(journal.take_orphans().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scan_discards_partial_writes() {
+        let dir = TempDir::new().unwrap();
+        let journal = PromptJournal::in_app_data(dir.path());
+        journal.record(&entry("late", 50)).await;
+        journal.record(&entry("early", 5)).await;
+        let journal_dir = journal.dir().unwrap();
+        let complete = serde_json::to_string(&entry("torn", 1)).unwrap();
+        std::fs::write(
+            journal_dir.join("torn.json"),
+            &complete[..complete.len() / 2],
+        )
+        .unwrap();
+        std::fs::write(journal_dir.join("crashed.tmp"), &complete).unwrap();
+
+        let orphans = journal.take_orphans().await;
+        let ids: Vec<&str> = orphans.iter().map(|o| o.prompt_id.as_str()).collect();
+        assert_eq!(ids, vec!["early", "late"]);
+        assert_eq!(std::fs::read_dir(journal_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_argv_hash_separates_arguments() {
+        let hash = |args: &[&str]| argv_hash(&args.iter().map(OsString::from).collect::<Vec<_>>());
+        assert_eq!(hash(&["-p", "x"]), hash(&["-p", "x"]));
+        assert_ne!(hash(&["-p", "x"]), hash(&["-px"]));
+        assert!(process_exists(std::process::id()));
+    }
+}
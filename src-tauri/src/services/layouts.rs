@@ -0,0 +1,189 @@
+//! Saved per-project session layouts
+//!
+//! A layout is a snapshot of the live sessions of one project (working
+//! directory): their configs, names and Claude session IDs, in order. It is
+//! stored as `project_layouts/<hash>.json` in the app data directory, where the
+//! hash is of the working directory, so reopening the project can recreate the
+//! same sessions and resume their conversations.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::process::SessionConfig;
+use super::storage;
+
+/// Name of the layout directory inside the app data directory
+pub const LAYOUTS_DIR_NAME: &str = "project_layouts";
+
+/// One session of a layout
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayoutSession {
+    /// Config the session was created with (its `name` included)
+    pub config: SessionConfig,
+    /// Conversation to resume; None if the session never ran a prompt
+    pub claude_session_id: Option<String>,
+}
+
+/// The saved sessions of one project
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectLayout {
+    #[serde(with = "super::paths::os_path")]
+    pub working_dir: PathBuf,
+    pub saved_at: u64,
+    /// In tab order
+    pub sessions: Vec<LayoutSession>,
+}
+
+/// A layout session that could not be restored
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayoutWarning {
+    /// Position of the session in the layout
+    pub index: usize,
+    pub name: Option<String>,
+    pub message: String,
+}
+
+/// Result of restoring a layout
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RestoredLayout {
+    /// The new sessions, in the saved order
+    pub session_ids: Vec<String>,
+    pub warnings: Vec<LayoutWarning>,
+}
+
+/// Whether `a` and `b` name the same directory
+pub fn same_dir(a: &Path, b: &Path) -> bool {
+    a == b
+        || match (a.canonicalize(), b.canonicalize()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+}
+
+/// Whether the CLI still has the transcript of conversation `claude_session_id`
+///
+/// The CLI keeps conversations in `<claude_dir>/projects/<project>/<id>.jsonl`.
+/// Every project directory is searched, since the project directory name is
+/// derived from the working directory in a way that has changed between CLI
+/// versions.
+pub fn conversation_exists(claude_dir: &Path, claude_session_id: &str) -> bool {
+    let file_name = format!("{}.jsonl", claude_session_id);
+    if claude_session_id.is_empty() || file_name.contains(['/', '\\']) {
+        return false;
+    }
+    match std::fs::read_dir(claude_dir.join("projects")) {
+        Ok(projects) => projects
+            .flatten()
+            .any(|project| project.path().join(&file_name).is_file()),
+        Err(_) => false,
+    }
+}
+
+/// The layout directory; a store without one saves nothing
+#[derive(Debug, Clone, Default)]
+pub struct LayoutStore {
+    dir: Option<PathBuf>,
+}
+
+impl LayoutStore {
+    /// Layouts under `app_data_dir`
+    pub fn in_app_data(app_data_dir: &Path) -> Self {
+        Self {
+            dir: Some(app_data_dir.join(LAYOUTS_DIR_NAME)),
+        }
+    }
+
+    /// The file holding the layout of `working_dir`
+    pub fn path_for(&self, working_dir: &Path) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        let canonical = working_dir
+            .canonicalize()
+            .unwrap_or_else(|_| working_dir.to_path_buf());
+        let hash = hex::encode(Sha256::digest(canonical.to_string_lossy().as_bytes()));
+        Some(dir.join(format!("{}.json", &hash[..32])))
+    }
+
+    /// Write `layout` atomically
+    pub async fn save(&self, layout: &ProjectLayout) -> std::io::Result<PathBuf> {
+        let path = self.path_for(&layout.working_dir).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "layouts are not persisted without an app data directory",
+            )
+        })?;
+        let content = serde_json::to_vec_pretty(layout)?;
+        storage::write_atomic(&path, &content).await?;
+        Ok(path)
+    }
+
+    /// The saved layout of `working_dir`; None if there is none or it does not parse
+    pub async fn load(&self, working_dir: &Path) -> Option<ProjectLayout> {
+        let path = self.path_for(working_dir)?;
+        let content = match tokio::fs::read(&path).await {
+            Ok(content) => content,
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("Failed to read {}: {}", path.display(), e);
+                }
+                return None;
+            }
+        };
+        serde_json::from_slice(&content)
+            .map_err(|e| log::warn!("Ignoring invalid {}: {}", path.display(), e))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_layout_round_trip() {
+        let data_dir = TempDir::new().unwrap();
+        let project = TempDir::new().unwrap();
+        let store = LayoutStore::in_app_data(data_dir.path());
+        assert!(store.load(project.path()).await.is_none());
+
+        let layout = ProjectLayout {
+            working_dir: project.path().to_path_buf(),
+            saved_at: 42,
+            sessions: vec![LayoutSession {
+                config: SessionConfig {
+                    working_dir: project.path().to_path_buf(),
+                    name: Some("backend".to_string()),
+                    ..Default::default()
+                },
+                claude_session_id: Some("abc".to_string()),
+            }],
+        };
+        let path = store.save(&layout).await.unwrap();
+        assert!(path.starts_with(data_dir.path().join(LAYOUTS_DIR_NAME)));
+
+        // The same directory through a different spelling finds the same layout
+        let loaded = store
+            .load(&project.path().join("."))
+            .await
+            .expect("layout saved");
+        assert_eq!(loaded.saved_at, 42);
+        assert_eq!(loaded.sessions[0].config.name.as_deref(), Some("backend"));
+
+        assert!(LayoutStore::default().save(&layout).await.is_err());
+    }
+
+    #[test]
+    fn test_conversation_exists() {
+        let claude_dir = TempDir::new().unwrap();
+        let project = claude_dir.path().join("projects").join("-home-me-app");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(project.join("abc.jsonl"), "{}\n").unwrap();
+
+        assert!(conversation_exists(claude_dir.path(), "abc"));
+        assert!(!conversation_exists(claude_dir.path(), "gone"));
+        assert!(!conversation_exists(claude_dir.path(), ""));
+        assert!(!conversation_exists(claude_dir.path(), "../abc"));
+    }
+}
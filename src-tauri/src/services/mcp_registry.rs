@@ -0,0 +1,111 @@
+//! Registry of MCP servers started by the GUI
+//!
+//! `start_mcp_server` records each server it launches here so that other parts
+//! of the app can tell which servers the GUI can restart. The process manager
+//! uses it to attach a restart action to failed servers reported in a CLI
+//! session's init message.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+/// A stdio MCP server launched by the GUI
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManagedMcpServer {
+    pub name: String,
+    pub command: String,
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: Option<HashMap<String, String>>,
+    pub pid: u32,
+}
+
+/// Action id that restarts a managed server (`restart_mcp_server` with this name)
+pub fn restart_action_id(name: &str) -> String {
+    format!("restart_mcp_server:{}", name)
+}
+
+/// Shared map of managed servers by name
+#[derive(Debug, Clone, Default)]
+pub struct McpRegistry {
+    servers: Arc<RwLock<HashMap<String, ManagedMcpServer>>>,
+}
+
+impl McpRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a server, replacing any previous entry with the same name
+    pub async fn register(&self, server: ManagedMcpServer) {
+        self.servers
+            .write()
+            .await
+            .insert(server.name.clone(), server);
+    }
+
+    pub async fn remove(&self, name: &str) -> Option<ManagedMcpServer> {
+        self.servers.write().await.remove(name)
+    }
+
+    pub async fn get(&self, name: &str) -> Option<ManagedMcpServer> {
+        self.servers.read().await.get(name).cloned()
+    }
+
+    /// Remove the server running as `pid`, if any
+    pub async fn remove_pid(&self, pid: u32) -> Option<ManagedMcpServer> {
+        let mut servers = self.servers.write().await;
+        let name = servers.values().find(|s| s.pid == pid)?.name.clone();
+        servers.remove(&name)
+    }
+
+    /// The restart action for `name`, if the GUI manages that server
+    pub async fn restart_action(&self, name: &str) -> Option<String> {
+        self.servers
+            .read()
+            .await
+            .contains_key(name)
+            .then(|| restart_action_id(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(name: &str, pid: u32) -> ManagedMcpServer {
+        ManagedMcpServer {
+            name: name.to_string(),
+            command: "npx".to_string(),
+            args: vec![format!("@example/{}", name)],
+            env: None,
+            pid,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restart_action_only_for_managed_servers() {
+        let registry = McpRegistry::new();
+        registry.register(server("github", 42)).await;
+
+        assert_eq!(
+            registry.restart_action("github").await.as_deref(),
+            Some("restart_mcp_server:github")
+        );
+        assert_eq!(registry.restart_action("filesystem").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_remove_by_pid() {
+        let registry = McpRegistry::new();
+        registry.register(server("github", 42)).await;
+        registry.register(server("linear", 43)).await;
+
+        assert_eq!(registry.remove_pid(42).await.unwrap().name, "github");
+        assert!(registry.get("github").await.is_none());
+        assert!(registry.get("linear").await.is_some());
+        assert!(registry.remove_pid(42).await.is_none());
+    }
+}
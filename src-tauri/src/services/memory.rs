@@ -0,0 +1,169 @@
+//! CLAUDE.md memory file resolution
+//!
+//! The CLI loads memory from `~/.claude/CLAUDE.md` (user memory) and from
+//! every `CLAUDE.md` between the repository root and the working directory
+//! (project memory, most general first). This module resolves the same chain
+//! so the GUI can show which files apply to a session.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::paths::encode_path;
+
+/// File name of a memory file
+pub const MEMORY_FILE_NAME: &str = "CLAUDE.md";
+
+/// Where a memory file sits in the chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryScope {
+    /// `~/.claude/CLAUDE.md`
+    User,
+    /// `CLAUDE.md` at the repository root (or the working dir outside a repo)
+    Project,
+    /// `CLAUDE.md` in a directory between the repository root and the working dir
+    Nested,
+}
+
+/// A memory file in the resolved chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryFile {
+    pub path: String,
+    pub scope: MemoryScope,
+    pub exists: bool,
+    pub content: Option<String>,
+}
+
+/// Whether `path` names a memory file
+pub fn is_memory_file(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name == MEMORY_FILE_NAME)
+}
+
+/// The repository root containing `dir` (the nearest ancestor with `.git`)
+fn repo_root(dir: &Path) -> Option<&Path> {
+    dir.ancestors().find(|d| d.join(".git").exists())
+}
+
+/// Memory file paths that apply to `working_dir`, in the order the CLI loads them
+///
+/// The user file comes first, then one `CLAUDE.md` per directory from the
+/// repository root down to `working_dir`. Outside a repository only the
+/// working directory itself is considered.
+pub fn resolve_memory_chain(
+    working_dir: &Path,
+    home_dir: Option<&Path>,
+) -> Vec<(PathBuf, MemoryScope)> {
+    let mut chain = Vec::new();
+    if let Some(home) = home_dir {
+        chain.push((
+            home.join(".claude").join(MEMORY_FILE_NAME),
+            MemoryScope::User,
+        ));
+    }
+
+    let root = repo_root(working_dir).unwrap_or(working_dir);
+    let mut dirs: Vec<&Path> = working_dir
+        .ancestors()
+        .take_while(|d| d.starts_with(root))
+        .collect();
+    dirs.reverse();
+
+    for (i, dir) in dirs.into_iter().enumerate() {
+        let scope = if i == 0 {
+            MemoryScope::Project
+        } else {
+            MemoryScope::Nested
+        };
+        chain.push((dir.join(MEMORY_FILE_NAME), scope));
+    }
+    chain
+}
+
+/// Resolve the memory chain for `working_dir` and read each file that exists
+pub async fn load_memory_chain(working_dir: &Path, home_dir: Option<&Path>) -> Vec<MemoryFile> {
+    let mut files = Vec::new();
+    for (path, scope) in resolve_memory_chain(working_dir, home_dir) {
+        let content = tokio::fs::read_to_string(&path).await.ok();
+        files.push(MemoryFile {
+            path: encode_path(&path),
+            scope,
+            exists: content.is_some(),
+            content,
+        });
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn scopes(chain: &[(PathBuf, MemoryScope)]) -> Vec<MemoryScope> {
+        chain.iter().map(|(_, scope)| *scope).collect()
+    }
+
+    #[test]
+    fn test_chain_walks_from_repo_root_to_working_dir() {
+        let home = TempDir::new().unwrap();
+        let repo = TempDir::new().unwrap();
+        std::fs::create_dir(repo.path().join(".git")).unwrap();
+        let nested = repo.path().join("packages").join("app");
+        std::fs::create_dir_all(&nested).unwrap();
+
+        let chain = resolve_memory_chain(&nested, Some(home.path()));
+        let paths: Vec<&PathBuf> = chain.iter().map(|(path, _)| path).collect();
+        assert_eq!(
+            paths,
+            vec![
+                &home.path().join(".claude/CLAUDE.md"),
+                &repo.path().join("CLAUDE.md"),
+                &repo.path().join("packages/CLAUDE.md"),
+                &nested.join("CLAUDE.md"),
+            ]
+        );
+        assert_eq!(
+            scopes(&chain),
+            vec![
+                MemoryScope::User,
+                MemoryScope::Project,
+                MemoryScope::Nested,
+                MemoryScope::Nested,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chain_outside_repo_uses_working_dir_only() {
+        let dir = TempDir::new().unwrap();
+        let chain = resolve_memory_chain(dir.path(), None);
+        assert_eq!(
+            chain,
+            vec![(dir.path().join("CLAUDE.md"), MemoryScope::Project)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_memory_chain_reads_existing_files() {
+        let repo = TempDir::new().unwrap();
+        std::fs::create_dir(repo.path().join(".git")).unwrap();
+        let nested = repo.path().join("src");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(repo.path().join("CLAUDE.md"), "# Root").unwrap();
+
+        let files = load_memory_chain(&nested, None).await;
+        assert_eq!(files.len(), 2);
+        assert!(files[0].exists);
+        assert_eq!(files[0].content.as_deref(), Some("# Root"));
+        assert!(!files[1].exists);
+        assert_eq!(files[1].content, None);
+    }
+
+    #[test]
+    fn test_is_memory_file() {
+        assert!(is_memory_file(Path::new("/repo/CLAUDE.md")));
+        assert!(!is_memory_file(Path::new("/repo/README.md")));
+    }
+}
@@ -3,8 +3,81 @@
 //! This module contains the core services for managing Claude CLI processes
 //! and parsing their output.
 
+pub mod app_log;
+pub mod archive;
+pub mod cli_activity;
+pub mod cli_args;
+pub mod cloud_sync;
+pub mod connectivity;
+pub mod context;
+pub mod context_providers;
+pub mod dry_run;
+pub mod fake_backend;
+pub mod event_bridge;
+pub mod file_versions;
+pub mod frontend_bridge;
+pub mod git_info;
+pub mod git_ops;
+pub mod hooks;
+pub mod http_client;
+pub mod ignore_rules;
+pub mod journal;
+pub mod layouts;
+pub mod mcp_registry;
+pub mod memory;
+pub mod models;
+pub mod money;
+pub mod operations;
 pub mod parser;
+pub mod paths;
 pub mod process;
+pub mod prompt;
+pub mod redact;
+pub mod scratch;
+pub mod session_merge;
+pub mod session_sync;
+pub mod settings_file;
+pub mod snapshot;
+pub mod storage;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod tool_output;
+pub mod tool_presets;
+pub mod tools;
+pub mod usage;
+pub mod watcher;
+#[cfg(windows)]
+pub mod win_process;
+pub mod workspace;
 
-pub use parser::{StreamJsonParser, StreamMessage, ParseError};
-pub use process::{ProcessManager, ProcessError, SessionConfig, SessionInfo, SessionStatus};
+pub use archive::{ArchiveStore, ArchivedSession};
+pub use cli_activity::{ActivityKind, SessionActivity};
+pub use cloud_sync::{SyncProvider, SyncWarning};
+pub use connectivity::{ConnectivityStatus, ErrorClass};
+pub use context::ContextPressure;
+pub use context_providers::{ContextContribution, ContextProvider};
+pub use dry_run::{DryRunPlan, PlannedOperation};
+pub use event_bridge::{CLIMessagePayload, EventSink, SessionEventBridge};
+pub use fake_backend::FakeBackend;
+pub use frontend_bridge::{BridgePolicy, FrontendBridge, MissedMessages};
+pub use hooks::{HookKind, HookOutput, HooksConfig};
+pub use layouts::{ProjectLayout, RestoredLayout};
+pub use mcp_registry::{ManagedMcpServer, McpRegistry};
+pub use memory::{MemoryFile, MemoryScope};
+pub use models::{CostTier, ModelCatalog, ModelInfo};
+pub use money::{CostFormat, Usd};
+pub use operations::{CancellationToken, OperationRegistry};
+pub use parser::{Compaction, McpServerStatus, StreamJsonParser, StreamMessage, ParseError};
+pub use process::{
+    BroadcastDispatch, BroadcastOutcome, ClaudeBackend, CliBackend, CompletionReason, McpServerFailure, MetaCommand, ProcessError, ProcessManager, PromptOptions,
+    PromptRecord, RecoveredPrompt, SessionConfig, SessionEvent, SessionInfo, SessionStatus, TranscriptEntry, TranscriptExport,
+};
+pub use scratch::ScratchSpace;
+pub use session_sync::SessionsDiff;
+pub use snapshot::{CliStatus, EnvironmentSnapshot};
+pub use storage::{StorageAlarm, StorageCritical, StorageHealth, StorageThresholds};
+pub use tool_presets::{ToolPreset, ToolPresets};
+pub use tools::ToolMatcherError;
+pub use usage::{DailyUsage, PromptTiming, UsageSummary};
+pub use watcher::{FileWatcher, WatchEvent, WatchEventKind, WatcherConfig, WatcherStats};
+pub use workspace::{Workspace, WorkspaceError, WorkspaceRoot, WorkspaceStore};
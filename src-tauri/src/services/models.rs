@@ -0,0 +1,224 @@
+//! Model catalog for Claude CLI sessions
+//!
+//! This module provides the list of models a session can run on. The built-in
+//! table covers the CLI aliases (opus, sonnet, haiku) and their full model ids.
+//! Users on custom gateways can extend or override it with a `models.json`
+//! file in the app data directory:
+//!
+//! ```json
+//! {
+//!   "default_model": "my-gateway-model",
+//!   "models": [
+//!     { "id": "my-gateway-model", "display_name": "Gateway Model", "cost_tier": "medium" }
+//!   ]
+//! }
+//! ```
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// File name of the user override file in the app data directory
+pub const MODELS_FILE_NAME: &str = "models.json";
+
+/// Relative cost of running a model
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CostTier {
+    Low,
+    Medium,
+    High,
+}
+
+/// A model that sessions can be configured with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub id: String,
+    pub display_name: String,
+    pub cost_tier: CostTier,
+    /// Whether this is a CLI alias (e.g. "sonnet") rather than a full model id
+    #[serde(default)]
+    pub is_alias: bool,
+}
+
+/// The set of known models and the default used for new sessions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelCatalog {
+    pub models: Vec<ModelInfo>,
+    pub default_model: String,
+}
+
+/// Contents of the `models.json` override file
+#[derive(Debug, Default, Deserialize)]
+struct ModelOverrides {
+    #[serde(default)]
+    models: Vec<ModelInfo>,
+    #[serde(default)]
+    default_model: Option<String>,
+}
+
+fn builtin(id: &str, display_name: &str, cost_tier: CostTier, is_alias: bool) -> ModelInfo {
+    ModelInfo {
+        id: id.to_string(),
+        display_name: display_name.to_string(),
+        cost_tier,
+        is_alias,
+    }
+}
+
+impl ModelCatalog {
+    /// The built-in catalog shipped with the app
+    pub fn builtin() -> Self {
+        Self {
+            models: vec![
+                builtin("opus", "Claude Opus (latest)", CostTier::High, true),
+                builtin("sonnet", "Claude Sonnet (latest)", CostTier::Medium, true),
+                builtin("haiku", "Claude Haiku (latest)", CostTier::Low, true),
+                builtin("claude-opus-4-1-20250805", "Claude Opus 4.1", CostTier::High, false),
+                builtin("claude-opus-4-20250514", "Claude Opus 4", CostTier::High, false),
+                builtin("claude-sonnet-4-5-20250929", "Claude Sonnet 4.5", CostTier::Medium, false),
+                builtin("claude-sonnet-4-20250514", "Claude Sonnet 4", CostTier::Medium, false),
+                builtin("claude-haiku-4-5-20251001", "Claude Haiku 4.5", CostTier::Low, false),
+                builtin("claude-3-5-haiku-20241022", "Claude Haiku 3.5", CostTier::Low, false),
+            ],
+            default_model: "sonnet".to_string(),
+        }
+    }
+
+    /// Load the built-in catalog merged with `models.json` from `app_data_dir`
+    ///
+    /// A missing override file yields the built-in catalog. A malformed one is
+    /// logged and ignored rather than preventing startup.
+    pub fn load(app_data_dir: &Path) -> Self {
+        let mut catalog = Self::builtin();
+        let path = app_data_dir.join(MODELS_FILE_NAME);
+
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return catalog,
+            Err(e) => {
+                log::warn!("Failed to read {}: {}", path.display(), e);
+                return catalog;
+            }
+        };
+
+        match serde_json::from_str::<ModelOverrides>(&content) {
+            Ok(overrides) => catalog.merge(overrides),
+            Err(e) => log::warn!("Ignoring invalid {}: {}", path.display(), e),
+        }
+
+        catalog
+    }
+
+    /// Merge overrides into the catalog: matching ids are replaced, new ids appended
+    fn merge(&mut self, overrides: ModelOverrides) {
+        for model in overrides.models {
+            match self.models.iter_mut().find(|m| m.id == model.id) {
+                Some(existing) => *existing = model,
+                None => self.models.push(model),
+            }
+        }
+
+        if let Some(default_model) = overrides.default_model {
+            if self.contains(&default_model) {
+                self.default_model = default_model;
+            } else {
+                log::warn!(
+                    "Ignoring default_model {:?} which is not in the model catalog",
+                    default_model
+                );
+            }
+        }
+    }
+
+    /// Check whether a model id or alias is known
+    pub fn contains(&self, model: &str) -> bool {
+        self.models.iter().any(|m| m.id == model)
+    }
+
+    /// Look up a model by id or alias
+    pub fn get(&self, model: &str) -> Option<&ModelInfo> {
+        self.models.iter().find(|m| m.id == model)
+    }
+
+    /// Check a model name, accepting unknown names only when `allow_unknown` is set
+    pub fn is_allowed(&self, model: &str, allow_unknown: bool) -> bool {
+        !model.trim().is_empty() && (allow_unknown || self.contains(model))
+    }
+}
+
+impl Default for ModelCatalog {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_builtin_contains_aliases() {
+        let catalog = ModelCatalog::builtin();
+        assert!(catalog.contains("opus"));
+        assert!(catalog.contains("sonnet"));
+        assert!(catalog.contains("haiku"));
+        assert_eq!(catalog.default_model, "sonnet");
+        assert!(catalog.get("sonnet").unwrap().is_alias);
+    }
+
+    #[test]
+    fn test_is_allowed_rejects_typo() {
+        let catalog = ModelCatalog::builtin();
+        assert!(catalog.is_allowed("sonnet", false));
+        assert!(!catalog.is_allowed("sonet", false));
+    }
+
+    #[test]
+    fn test_is_allowed_with_allow_unknown() {
+        let catalog = ModelCatalog::builtin();
+        assert!(catalog.is_allowed("my-gateway-model", true));
+        assert!(!catalog.is_allowed("", true));
+    }
+
+    #[test]
+    fn test_load_without_override_file() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(ModelCatalog::load(dir.path()), ModelCatalog::builtin());
+    }
+
+    #[test]
+    fn test_load_merges_override_file() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join(MODELS_FILE_NAME),
+            r#"{
+                "default_model": "gateway-large",
+                "models": [
+                    {"id": "gateway-large", "display_name": "Gateway Large", "cost_tier": "high"},
+                    {"id": "haiku", "display_name": "Haiku via gateway", "cost_tier": "low", "is_alias": true}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let catalog = ModelCatalog::load(dir.path());
+        assert_eq!(catalog.default_model, "gateway-large");
+        assert_eq!(catalog.get("gateway-large").unwrap().cost_tier, CostTier::High);
+        assert_eq!(catalog.get("haiku").unwrap().display_name, "Haiku via gateway");
+        assert_eq!(catalog.models.len(), ModelCatalog::builtin().models.len() + 1);
+    }
+
+    #[test]
+    fn test_load_ignores_unknown_default_and_bad_json() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(MODELS_FILE_NAME);
+
+        std::fs::write(&path, r#"{"default_model": "nope"}"#).unwrap();
+        assert_eq!(ModelCatalog::load(dir.path()).default_model, "sonnet");
+
+        std::fs::write(&path, "not json").unwrap();
+        assert_eq!(ModelCatalog::load(dir.path()), ModelCatalog::builtin());
+    }
+}
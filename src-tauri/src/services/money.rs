@@ -0,0 +1,237 @@
+//! Costs as integer micro-dollars, and their display
+//!
+//! The CLI reports costs as floating-point dollars. Summing those over
+//! hundreds of prompts drifts (0.1 + 0.2 = 0.30000000000000004), so each
+//! reported cost is rounded to a whole micro-dollar once, on arrival, and
+//! every total is kept in integer space as a [`Usd`].
+//!
+//! Micro-dollars stay internal: [`Usd`] serializes as dollars, so saved
+//! files and the frontend keep seeing the `*_usd` fields they always did.
+//! Read values are rounded to a micro-dollar like the CLI's.
+
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign};
+use std::path::Path;
+use std::sync::{OnceLock, RwLock};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::settings_file;
+
+/// File name of the cost format settings in the app data directory
+pub const COST_FORMAT_FILE_NAME: &str = "cost_format.json";
+
+const MICROS_PER_DOLLAR: u64 = 1_000_000;
+const MICROS_PER_CENT: u64 = 10_000;
+
+/// An amount of US dollars, as a whole number of micro-dollars
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Usd(u64);
+
+impl Usd {
+    pub const ZERO: Usd = Usd(0);
+
+    pub fn from_micros(micros: u64) -> Self {
+        Usd(micros)
+    }
+
+    /// Round a dollar amount to the nearest micro-dollar
+    ///
+    /// Negative and non-finite amounts count as zero.
+    pub fn from_dollars(dollars: f64) -> Self {
+        if !dollars.is_finite() || dollars <= 0.0 {
+            return Usd::ZERO;
+        }
+        Usd((dollars * MICROS_PER_DOLLAR as f64).round() as u64)
+    }
+
+    pub fn micros(self) -> u64 {
+        self.0
+    }
+
+    /// The amount in dollars, for consumers that need a float (e.g. hook input)
+    pub fn as_dollars(self) -> f64 {
+        self.0 as f64 / MICROS_PER_DOLLAR as f64
+    }
+}
+
+impl Add for Usd {
+    type Output = Usd;
+
+    fn add(self, other: Usd) -> Usd {
+        Usd(self.0.saturating_add(other.0))
+    }
+}
+
+impl AddAssign for Usd {
+    fn add_assign(&mut self, other: Usd) {
+        *self = *self + other;
+    }
+}
+
+impl Sum for Usd {
+    fn sum<I: Iterator<Item = Usd>>(iter: I) -> Usd {
+        iter.fold(Usd::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Usd> for Usd {
+    fn sum<I: Iterator<Item = &'a Usd>>(iter: I) -> Usd {
+        iter.copied().sum()
+    }
+}
+
+/// Formats with the current [`CostFormat`]
+impl fmt::Display for Usd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&cost_format().format(*self))
+    }
+}
+
+impl Serialize for Usd {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.as_dollars())
+    }
+}
+
+impl<'de> Deserialize<'de> for Usd {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        f64::deserialize(deserializer).map(Usd::from_dollars)
+    }
+}
+
+/// How costs are shown to people, as stored in `cost_format.json`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CostFormat {
+    /// Separator between dollars and cents, per the user's locale
+    #[serde(default = "default_decimal_separator")]
+    pub decimal_separator: char,
+}
+
+fn default_decimal_separator() -> char {
+    '.'
+}
+
+impl Default for CostFormat {
+    fn default() -> Self {
+        Self {
+            decimal_separator: default_decimal_separator(),
+        }
+    }
+}
+
+impl CostFormat {
+    /// Load the settings from `app_data_dir`; missing or malformed files mean defaults
+    pub fn load(app_data_dir: &Path) -> Self {
+        settings_file::load(app_data_dir, COST_FORMAT_FILE_NAME)
+    }
+
+    /// Write the settings to `app_data_dir` atomically
+    pub async fn save(&self, app_data_dir: &Path) -> std::io::Result<()> {
+        settings_file::save(app_data_dir, COST_FORMAT_FILE_NAME, self).await
+    }
+
+    /// Format `cost` for display: `$0.30`, `$12.05`, or `<$0.01` for a
+    /// nonzero amount under a cent
+    ///
+    /// Amounts of a cent or more are rounded to the nearest cent. The
+    /// frontend's `formatCost` follows the same rules with the saved format.
+    pub fn format(&self, cost: Usd) -> String {
+        if cost.0 > 0 && cost.0 < MICROS_PER_CENT {
+            return format!("<$0{}01", self.decimal_separator);
+        }
+        let cents = (cost.0 + MICROS_PER_CENT / 2) / MICROS_PER_CENT;
+        format!(
+            "${}{}{:02}",
+            cents / 100,
+            self.decimal_separator,
+            cents % 100
+        )
+    }
+}
+
+fn current() -> &'static RwLock<CostFormat> {
+    static FORMAT: OnceLock<RwLock<CostFormat>> = OnceLock::new();
+    FORMAT.get_or_init(|| RwLock::new(CostFormat::default()))
+}
+
+/// Use `format` for costs displayed from now on
+pub fn set_cost_format(format: CostFormat) {
+    *current().write().unwrap_or_else(|e| e.into_inner()) = format;
+}
+
+/// The format costs are displayed with
+pub fn cost_format() -> CostFormat {
+    *current().read().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accumulation_does_not_drift() {
+        let mut total = Usd::ZERO;
+        let mut float_total = 0.0_f64;
+        for _ in 0..10_000 {
+            total += Usd::from_dollars(0.0001);
+            float_total += 0.0001;
+        }
+        assert_eq!(total, Usd::from_micros(1_000_000));
+        assert_eq!(total.as_dollars(), 1.0);
+        // What summing the floats would have given
+        assert_ne!(float_total, 1.0);
+
+        let costs = [Usd::from_dollars(0.1), Usd::from_dollars(0.2)];
+        assert_eq!(costs.iter().sum::<Usd>().as_dollars(), 0.3);
+    }
+
+    #[test]
+    fn test_format_edge_cases() {
+        let format = CostFormat::default();
+        assert_eq!(format.format(Usd::ZERO), "$0.00");
+        assert_eq!(format.format(Usd::from_micros(1)), "<$0.01");
+        assert_eq!(format.format(Usd::from_micros(9_999)), "<$0.01");
+        assert_eq!(format.format(Usd::from_micros(10_000)), "$0.01");
+        assert_eq!(format.format(Usd::from_dollars(0.3)), "$0.30");
+        assert_eq!(format.format(Usd::from_dollars(0.014_999)), "$0.01");
+        assert_eq!(format.format(Usd::from_dollars(0.995)), "$1.00");
+        assert_eq!(format.format(Usd::from_dollars(1234.5)), "$1234.50");
+
+        let comma = CostFormat {
+            decimal_separator: ',',
+        };
+        assert_eq!(comma.format(Usd::from_dollars(12.05)), "$12,05");
+        assert_eq!(comma.format(Usd::from_micros(42)), "<$0,01");
+    }
+
+    #[test]
+    fn test_from_dollars_rounds_and_clamps() {
+        assert_eq!(Usd::from_dollars(0.000_000_4), Usd::ZERO);
+        assert_eq!(Usd::from_dollars(0.000_000_6), Usd::from_micros(1));
+        assert_eq!(Usd::from_dollars(-1.0), Usd::ZERO);
+        assert_eq!(Usd::from_dollars(f64::NAN), Usd::ZERO);
+    }
+
+    #[test]
+    fn test_serializes_as_dollars() {
+        #[derive(Serialize, Deserialize)]
+        struct Record {
+            cost_usd: Option<Usd>,
+        }
+        let drifted: Record = serde_json::from_str(r#"{"cost_usd":0.30000000000000004}"#).unwrap();
+        assert_eq!(drifted.cost_usd, Some(Usd::from_micros(300_000)));
+        let whole: Record = serde_json::from_str(r#"{"cost_usd":2}"#).unwrap();
+        assert_eq!(whole.cost_usd, Some(Usd::from_micros(2_000_000)));
+
+        let sum = Usd::from_dollars(0.1) + Usd::from_dollars(0.2);
+        assert_eq!(
+            serde_json::to_string(&Record {
+                cost_usd: Some(sum)
+            })
+            .unwrap(),
+            r#"{"cost_usd":0.3}"#
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Registry of cancellable long-running operations
+//!
+//! Long file commands (listing, searching, tree walks) register a
+//! [`CancellationToken`] under an operation id supplied by the frontend. The
+//! `cancel_operation` command flips the token, which kills any child process
+//! the operation is waiting on and stops walk loops at their next check.
+//! Operations unregister themselves when their [`OperationGuard`] is dropped.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::Notify;
+
+/// Shared flag that an operation checks to see whether it was cancelled
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<TokenState>,
+}
+
+#[derive(Debug, Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        self.inner.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the token is cancelled
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.inner.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Operations currently in flight, by operation id
+#[derive(Debug, Clone, Default)]
+pub struct OperationRegistry {
+    operations: Arc<Mutex<HashMap<String, CancellationToken>>>,
+}
+
+impl OperationRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, CancellationToken>> {
+        self.operations.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Register an operation, generating an id if none is given
+    ///
+    /// The operation stays registered until the returned guard is dropped.
+    pub fn register(&self, operation_id: Option<String>) -> OperationGuard {
+        let id = operation_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let token = CancellationToken::new();
+        if let Some(previous) = self.lock().insert(id.clone(), token.clone()) {
+            // A reused id supersedes the operation that held it
+            previous.cancel();
+        }
+        OperationGuard {
+            id,
+            token,
+            registry: self.clone(),
+        }
+    }
+
+    /// Cancel an operation; unknown or finished ids are ignored
+    ///
+    /// Returns whether an operation was cancelled.
+    pub fn cancel(&self, operation_id: &str) -> bool {
+        match self.lock().get(operation_id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_running(&self, operation_id: &str) -> bool {
+        self.lock().contains_key(operation_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+}
+
+/// Registration of one operation; unregisters it on drop
+#[derive(Debug)]
+pub struct OperationGuard {
+    id: String,
+    token: CancellationToken,
+    registry: OperationRegistry,
+}
+
+impl OperationGuard {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+}
+
+impl Drop for OperationGuard {
+    fn drop(&mut self) {
+        let mut operations = self.registry.lock();
+        // Only remove our own entry, not one that reused the id after us
+        if operations
+            .get(&self.id)
+            .is_some_and(|token| Arc::ptr_eq(&token.inner, &self.token.inner))
+        {
+            operations.remove(&self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_guard_unregisters_on_drop() {
+        let registry = OperationRegistry::new();
+        let guard = registry.register(Some("op-1".to_string()));
+        assert_eq!(guard.id(), "op-1");
+        assert!(registry.is_running("op-1"));
+
+        drop(guard);
+        assert!(registry.is_empty());
+        // Cancelling a finished operation is a no-op
+        assert!(!registry.cancel("op-1"));
+    }
+
+    #[test]
+    fn test_cancel_flips_token() {
+        let registry = OperationRegistry::new();
+        let guard = registry.register(None);
+        assert!(!guard.token().is_cancelled());
+
+        assert!(registry.cancel(guard.id()));
+        assert!(guard.token().is_cancelled());
+        assert!(!registry.cancel("unknown"));
+    }
+
+    #[test]
+    fn test_reused_id_cancels_previous_operation() {
+        let registry = OperationRegistry::new();
+        let first = registry.register(Some("op".to_string()));
+        let second = registry.register(Some("op".to_string()));
+        assert!(first.token().is_cancelled());
+
+        // Dropping the superseded guard keeps the new registration
+        drop(first);
+        assert!(registry.is_running("op"));
+        drop(second);
+        assert!(registry.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_future_wakes() {
+        let token = CancellationToken::new();
+        let waiter = tokio::spawn({
+            let token = token.clone();
+            async move { token.cancelled().await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        token.cancel();
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -24,6 +24,9 @@ pub enum StreamMessage {
     System {
         #[serde(default)]
         session_id: Option<String>,
+        /// MCP servers and their connection status (in the `init` message)
+        #[serde(default)]
+        mcp_servers: Option<Vec<McpServerStatus>>,
         #[serde(flatten)]
         extra: Value,
     },
@@ -34,6 +37,9 @@ pub enum StreamMessage {
         role: String,
         #[serde(default)]
         content: Value,
+        /// Set when the message comes from a sub-agent started by this Task tool call
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        parent_tool_use_id: Option<String>,
         #[serde(flatten)]
         extra: Value,
     },
@@ -43,6 +49,9 @@ pub enum StreamMessage {
         name: String,
         #[serde(default)]
         input: Value,
+        /// Set when the message comes from a sub-agent started by this Task tool call
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        parent_tool_use_id: Option<String>,
         #[serde(flatten)]
         extra: Value,
     },
@@ -53,6 +62,9 @@ pub enum StreamMessage {
         content: Value,
         #[serde(default)]
         is_error: bool,
+        /// Set when the message comes from a sub-agent started by this Task tool call
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        parent_tool_use_id: Option<String>,
         #[serde(flatten)]
         extra: Value,
     },
@@ -62,6 +74,17 @@ pub enum StreamMessage {
         cost_usd: Option<f64>,
         #[serde(default)]
         duration_ms: Option<u64>,
+        /// Why the run stopped: "success", "error_max_turns", "error_during_execution"
+        #[serde(default)]
+        subtype: Option<String>,
+        /// Claude's final answer text
+        #[serde(default)]
+        result: Option<String>,
+        #[serde(default)]
+        is_error: Option<bool>,
+        /// Tool calls the CLI refused because they were not allowed
+        #[serde(default)]
+        permission_denials: Option<Vec<Value>>,
         #[serde(flatten)]
         extra: Value,
     },
@@ -97,11 +120,100 @@ pub enum StreamMessage {
         #[serde(flatten)]
         extra: Value,
     },
+    /// Raw API stream event (partial message streaming)
+    StreamEvent {
+        #[serde(default)]
+        event: Value,
+        /// Set when the message comes from a sub-agent started by this Task tool call
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        parent_tool_use_id: Option<String>,
+        #[serde(flatten)]
+        extra: Value,
+    },
     /// Unknown message type - fallback for future compatibility
     #[serde(other)]
     Unknown,
 }
 
+/// A context compaction reported by the CLI
+///
+/// Read leniently from a `system` message (subtype `compact_boundary`, with
+/// the numbers in `compact_metadata`), so format changes degrade to missing
+/// fields instead of an unparseable message.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Compaction {
+    /// "auto" when the CLI compacted on its own, "manual" for `/compact`
+    pub trigger: Option<String>,
+    /// Context size before compaction
+    pub pre_tokens: Option<u64>,
+    /// Context size after compaction, when reported
+    pub post_tokens: Option<u64>,
+}
+
+impl StreamMessage {
+    /// The Task tool call whose sub-agent produced this message, if any
+    pub fn parent_tool_use_id(&self) -> Option<&str> {
+        match self {
+            StreamMessage::Assistant {
+                parent_tool_use_id, ..
+            }
+            | StreamMessage::ToolUse {
+                parent_tool_use_id, ..
+            }
+            | StreamMessage::ToolResult {
+                parent_tool_use_id, ..
+            }
+            | StreamMessage::StreamEvent {
+                parent_tool_use_id, ..
+            } => parent_tool_use_id.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The compaction this message reports, if it is a compaction boundary
+    pub fn compaction(&self) -> Option<Compaction> {
+        let StreamMessage::System { extra, .. } = self else {
+            return None;
+        };
+        let subtype = extra.get("subtype").and_then(Value::as_str)?;
+        if !subtype.contains("compact") {
+            return None;
+        }
+
+        let metadata = extra
+            .get("compact_metadata")
+            .or_else(|| extra.get("compactMetadata"))
+            .unwrap_or(extra);
+        let tokens = |keys: &[&str]| {
+            keys.iter()
+                .find_map(|key| metadata.get(*key).and_then(Value::as_u64))
+        };
+        Some(Compaction {
+            trigger: metadata
+                .get("trigger")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            pre_tokens: tokens(&["pre_tokens", "preTokens"]),
+            post_tokens: tokens(&["post_tokens", "postTokens"]),
+        })
+    }
+}
+
+/// Connection status of an MCP server, as reported in the system init message
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct McpServerStatus {
+    pub name: String,
+    /// "connected", "pending", "failed", "needs-auth", ...
+    pub status: String,
+}
+
+impl McpServerStatus {
+    /// Whether the server is unusable (anything but connected or still connecting)
+    pub fn is_failed(&self) -> bool {
+        !matches!(self.status.as_str(), "connected" | "pending")
+    }
+}
+
 /// Error information from Claude CLI
 #[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
 pub struct ErrorInfo {
@@ -346,6 +458,140 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_result_success_fixture() {
+        let mut parser = StreamJsonParser::new();
+        let input = r#"{"type":"result","subtype":"success","is_error":false,"result":"All tests pass.","total_cost_usd":0.02,"permission_denials":[]}"#;
+        let messages = parser.parse_chunk(format!("{}\n", input).as_bytes());
+        match &messages[0] {
+            StreamMessage::Result {
+                subtype,
+                result,
+                is_error,
+                permission_denials,
+                ..
+            } => {
+                assert_eq!(subtype.as_deref(), Some("success"));
+                assert_eq!(result.as_deref(), Some("All tests pass."));
+                assert_eq!(*is_error, Some(false));
+                assert_eq!(permission_denials.as_deref(), Some(&[][..]));
+            }
+            _ => panic!("Expected Result message"),
+        }
+    }
+
+    #[test]
+    fn test_result_max_turns_fixture() {
+        let mut parser = StreamJsonParser::new();
+        let input = r#"{"type":"result","subtype":"error_max_turns","is_error":false,"num_turns":10,"permission_denials":[{"tool_name":"Bash","tool_use_id":"t1","tool_input":{"command":"rm -rf build"}}]}"#;
+        let messages = parser.parse_chunk(format!("{}\n", input).as_bytes());
+        match &messages[0] {
+            StreamMessage::Result {
+                subtype,
+                result,
+                permission_denials,
+                extra,
+                ..
+            } => {
+                assert_eq!(subtype.as_deref(), Some("error_max_turns"));
+                assert_eq!(*result, None);
+                let denials = permission_denials.as_ref().unwrap();
+                assert_eq!(denials.len(), 1);
+                assert_eq!(denials[0]["tool_name"], "Bash");
+                assert_eq!(extra["num_turns"], 10);
+            }
+            _ => panic!("Expected Result message"),
+        }
+    }
+
+    #[test]
+    fn test_result_error_during_execution_fixture() {
+        let mut parser = StreamJsonParser::new();
+        let input = r#"{"type":"result","subtype":"error_during_execution","is_error":true}"#;
+        let messages = parser.parse_chunk(format!("{}\n", input).as_bytes());
+        match &messages[0] {
+            StreamMessage::Result {
+                subtype,
+                is_error,
+                permission_denials,
+                ..
+            } => {
+                assert_eq!(subtype.as_deref(), Some("error_during_execution"));
+                assert_eq!(*is_error, Some(true));
+                assert_eq!(*permission_denials, None);
+            }
+            _ => panic!("Expected Result message"),
+        }
+    }
+
+    #[test]
+    fn test_system_init_mcp_servers_fixture() {
+        let mut parser = StreamJsonParser::new();
+        let input = r#"{"type":"system","subtype":"init","session_id":"abc","tools":["Read"],"mcp_servers":[{"name":"github","status":"failed"},{"name":"filesystem","status":"connected"}]}"#;
+        let messages = parser.parse_chunk(format!("{}\n", input).as_bytes());
+        match &messages[0] {
+            StreamMessage::System {
+                session_id,
+                mcp_servers,
+                extra,
+            } => {
+                assert_eq!(session_id.as_deref(), Some("abc"));
+                let servers = mcp_servers.as_ref().unwrap();
+                assert_eq!(servers.len(), 2);
+                assert_eq!(servers[0].name, "github");
+                assert!(servers[0].is_failed());
+                assert!(!servers[1].is_failed());
+                assert_eq!(extra["subtype"], "init");
+            }
+            _ => panic!("Expected System message"),
+        }
+    }
+
+    #[test]
+    fn test_system_message_without_mcp_servers() {
+        let mut parser = StreamJsonParser::new();
+        let messages = parser.parse_chunk(b"{\"type\":\"system\",\"session_id\":\"abc\"}\n");
+        match &messages[0] {
+            StreamMessage::System { mcp_servers, .. } => assert_eq!(*mcp_servers, None),
+            _ => panic!("Expected System message"),
+        }
+    }
+
+    #[test]
+    fn test_compact_boundary_fixtures() {
+        let mut parser = StreamJsonParser::new();
+        let input = concat!(
+            r#"{"type":"system","subtype":"compact_boundary","session_id":"abc","uuid":"u1","compact_metadata":{"trigger":"auto","pre_tokens":167234}}"#,
+            "\n",
+            r#"{"type":"system","subtype":"compact_boundary","session_id":"abc","compact_metadata":{"trigger":"manual","pre_tokens":52000,"post_tokens":8100}}"#,
+            "\n",
+            // Metadata in an unexpected shape still counts as a compaction
+            r#"{"type":"system","subtype":"compact_boundary","compact_metadata":{"pre_tokens":"lots"}}"#,
+            "\n",
+            r#"{"type":"system","subtype":"init","session_id":"abc"}"#,
+            "\n",
+        );
+        let messages = parser.parse_chunk(input.as_bytes());
+        assert_eq!(messages.len(), 4);
+
+        assert_eq!(
+            messages[0].compaction(),
+            Some(Compaction {
+                trigger: Some("auto".to_string()),
+                pre_tokens: Some(167234),
+                post_tokens: None,
+            })
+        );
+        assert_eq!(messages[1].compaction().unwrap().post_tokens, Some(8100));
+        assert_eq!(messages[2].compaction(), Some(Compaction::default()));
+        assert_eq!(messages[3].compaction(), None);
+        // The session id is still captured from compaction messages
+        assert!(matches!(
+            &messages[0],
+            StreamMessage::System { session_id: Some(id), .. } if id == "abc"
+        ));
+    }
+
     #[test]
     fn test_error_message() {
         let mut parser = StreamJsonParser::new();
@@ -360,6 +606,60 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_sub_agent_message_fixtures() {
+        let mut parser = StreamJsonParser::new();
+        let input = concat!(
+            r#"{"type":"message","role":"assistant","content":[{"type":"text","text":"Searching"}],"parent_tool_use_id":"toolu_task1","session_id":"abc"}"#,
+            "\n",
+            r#"{"type":"tool_use","id":"toolu_sub1","name":"Grep","input":{"pattern":"fn main"},"parent_tool_use_id":"toolu_task1"}"#,
+            "\n",
+            r#"{"type":"tool_result","tool_use_id":"toolu_sub1","content":"src/main.rs","parent_tool_use_id":"toolu_task1"}"#,
+            "\n",
+            r#"{"type":"stream_event","event":{"type":"content_block_delta","index":0},"parent_tool_use_id":"toolu_task1","uuid":"u1"}"#,
+            "\n",
+            r#"{"type":"tool_use","id":"toolu_task1","name":"Task","input":{"prompt":"find main"},"parent_tool_use_id":null}"#,
+            "\n",
+        );
+        let messages = parser.parse_chunk(input.as_bytes());
+        assert_eq!(messages.len(), 5);
+
+        let parents: Vec<Option<&str>> = messages.iter().map(|m| m.parent_tool_use_id()).collect();
+        assert_eq!(
+            parents,
+            vec![
+                Some("toolu_task1"),
+                Some("toolu_task1"),
+                Some("toolu_task1"),
+                Some("toolu_task1"),
+                None,
+            ]
+        );
+        match &messages[3] {
+            StreamMessage::StreamEvent { event, extra, .. } => {
+                assert_eq!(event["type"], "content_block_delta");
+                assert_eq!(extra["uuid"], "u1");
+                // The typed field is not duplicated in extra
+                assert!(extra.get("parent_tool_use_id").is_none());
+            }
+            _ => panic!("Expected StreamEvent message"),
+        }
+    }
+
+    #[test]
+    fn test_parent_tool_use_id_round_trips() {
+        let input = r#"{"type":"tool_result","tool_use_id":"t1","content":"ok","parent_tool_use_id":"task"}"#;
+        let msg: StreamMessage = serde_json::from_str(input).unwrap();
+        let value = serde_json::to_value(&msg).unwrap();
+        assert_eq!(value["parent_tool_use_id"], "task");
+
+        // Main-conversation messages serialize without the field
+        let msg: StreamMessage =
+            serde_json::from_str(r#"{"type":"tool_result","tool_use_id":"t1"}"#).unwrap();
+        let value = serde_json::to_value(&msg).unwrap();
+        assert!(value.get("parent_tool_use_id").is_none());
+    }
+
     #[test]
     fn test_unknown_type_uses_fallback() {
         let mut parser = StreamJsonParser::new();
@@ -0,0 +1,190 @@
+//! Paths as exchanged with the frontend
+//!
+//! Paths cross the IPC boundary as strings, but not every OS path is valid
+//! Unicode: Unix paths are arbitrary bytes and Windows paths may contain
+//! unpaired surrogates. `to_string_lossy` replaces those with U+FFFD, so a
+//! path sent back by the frontend no longer names the same file.
+//!
+//! [`encode_path`] keeps Unicode paths as they are and turns the others into
+//! a tagged form that [`decode_path`] turns back into the exact OS path. The
+//! tag starts with a NUL, which no real path contains, so the two forms can
+//! never be confused. `Path::display` remains the right choice for text that
+//! is only shown.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Prefix of an encoded non-Unicode path, followed by the hex encoded OS
+/// bytes (Unix) or UTF-16 code units (Windows)
+const RAW_PATH_PREFIX: &str = "\0os:";
+
+/// `path` as a string that [`decode_path`] turns back into the same path
+pub fn encode_path(path: &Path) -> String {
+    match path.to_str() {
+        Some(path) => path.to_string(),
+        None => format!("{}{}", RAW_PATH_PREFIX, hex::encode(os_bytes(path))),
+    }
+}
+
+/// The path encoded by [`encode_path`]; plain strings are taken as they are
+pub fn decode_path(path: &str) -> PathBuf {
+    path.strip_prefix(RAW_PATH_PREFIX)
+        .and_then(|raw| hex::decode(raw).ok())
+        .and_then(from_os_bytes)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(path))
+}
+
+/// A path printed by a child process (e.g. ripgrep) as it is named on disk
+///
+/// Processes print raw OS bytes on Unix; elsewhere their output is taken as
+/// UTF-8.
+pub fn path_from_output(bytes: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+#[cfg(unix)]
+fn os_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(unix)]
+fn from_os_bytes(bytes: Vec<u8>) -> Option<OsString> {
+    use std::os::unix::ffi::OsStringExt;
+    Some(OsString::from_vec(bytes))
+}
+
+#[cfg(windows)]
+fn os_bytes(path: &Path) -> Vec<u8> {
+    use std::os::windows::ffi::OsStrExt;
+    path.as_os_str()
+        .encode_wide()
+        .flat_map(u16::to_be_bytes)
+        .collect()
+}
+
+#[cfg(windows)]
+fn from_os_bytes(bytes: Vec<u8>) -> Option<OsString> {
+    use std::os::windows::ffi::OsStringExt;
+    if bytes.len() % 2 != 0 {
+        return None;
+    }
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+        .collect();
+    Some(OsString::from_wide(&units))
+}
+
+/// Serde helpers for a `PathBuf` field, via [`encode_path`]
+///
+/// Serde's own impl fails to serialize non-Unicode paths, which would drop
+/// the whole event or file carrying them.
+pub mod os_path {
+    use super::*;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&encode_path(path))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        Ok(decode_path(&String::deserialize(deserializer)?))
+    }
+}
+
+/// Serde helpers for a `Vec<PathBuf>` field, via [`encode_path`]
+pub mod os_paths {
+    use super::*;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(paths: &[PathBuf], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(paths.iter().map(|path| encode_path(path)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<PathBuf>, D::Error> {
+        Ok(Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|path| decode_path(path))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unicode_paths_are_sent_unchanged() {
+        for path in [
+            "/home/Анна/项目 文件/📁 notes.md",
+            r"C:\Users\田中 太郎\Documents\🎉 party plan.txt",
+            "relative/with space",
+        ] {
+            assert_eq!(encode_path(Path::new(path)), path);
+            assert_eq!(decode_path(path), PathBuf::from(path));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_unicode_unix_path_round_trips() {
+        use std::os::unix::ffi::OsStrExt;
+        let path = Path::new(std::ffi::OsStr::from_bytes(
+            b"/home/\xff\xfe user/\xe6\x96 cut.txt",
+        ));
+        let encoded = encode_path(path);
+        assert!(encoded.starts_with(RAW_PATH_PREFIX));
+        assert_eq!(decode_path(&encoded), path);
+        // The lossy form would name another file
+        assert_ne!(PathBuf::from(path.to_string_lossy().as_ref()), path);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_unpaired_surrogate_windows_path_round_trips() {
+        use std::os::windows::ffi::OsStringExt;
+        let mut units: Vec<u16> = r"C:\Users\".encode_utf16().collect();
+        units.push(0xD800);
+        units.extend("田中 🎉.txt".encode_utf16());
+        let path = PathBuf::from(OsString::from_wide(&units));
+        let encoded = encode_path(&path);
+        assert!(encoded.starts_with(RAW_PATH_PREFIX));
+        assert_eq!(decode_path(&encoded), path);
+    }
+
+    #[test]
+    fn test_malformed_tag_is_taken_literally() {
+        let malformed = format!("{}not hex", RAW_PATH_PREFIX);
+        assert_eq!(decode_path(&malformed), PathBuf::from(&malformed));
+    }
+
+    #[test]
+    fn test_serde_helpers_use_the_encoded_form() {
+        #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+        struct Config {
+            #[serde(with = "os_path")]
+            dir: PathBuf,
+            #[serde(with = "os_paths")]
+            extra: Vec<PathBuf>,
+        }
+        let config = Config {
+            dir: PathBuf::from("/srv/数据 🚀"),
+            extra: vec![PathBuf::from("/opt/ツール")],
+        };
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["dir"], "/srv/数据 🚀");
+        assert_eq!(serde_json::from_value::<Config>(json).unwrap(), config);
+    }
+}
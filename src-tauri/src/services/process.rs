@@ -8,18 +8,54 @@
 //! - The session_id is returned in the first `system` message
 //! - There is NO persistent stdin/stdout communication
 
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use thiserror::Error;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
 use tokio::process::{Child, Command};
-use tokio::sync::{mpsc, Mutex, RwLock};
+use tokio::sync::{broadcast, mpsc, Mutex, RwLock};
 
-use super::parser::{StreamJsonParser, StreamMessage};
+use super::archive::{ArchiveStore, ArchivedSession};
+use super::cli_activity::{self, SessionActivity};
+use super::cli_args::{build_claude_args, CommandLine, ShellFlavor};
+use super::cloud_sync::{self, SyncWarning};
+use super::connectivity::{self, classify_error, ConnectivityStatus, ErrorClass};
+use super::context::{self, ContextPressure, ContextSignal, ContextTracker};
+use super::context_providers::{
+    build_preamble, ContextContribution, ContextProvider, DEFAULT_CONTEXT_BUDGET_BYTES,
+};
+use super::dry_run::{build_dry_run_args, DryRunPlan};
+use super::file_versions::{self, FileVersion, FileVersionStore};
+use super::git_ops::ProgressLines;
+use super::hooks::{load_project_hooks, run_hook, HookKind, HookOutput, HooksConfig, PostPromptSummary};
+use super::journal::{self, JournalEntry, PromptJournal};
+use super::layouts::{
+    self, LayoutSession, LayoutStore, LayoutWarning, ProjectLayout, RestoredLayout,
+};
+use super::mcp_registry::McpRegistry;
+use super::models::ModelCatalog;
+use super::money::Usd;
+use super::parser::{Compaction, McpServerStatus, StreamJsonParser, StreamMessage};
+use super::prompt::{sanitize_prompt, PromptInput, MAX_PROMPT_BYTES, STDIN_THRESHOLD_BYTES};
+use super::redact;
+use super::scratch::ScratchSpace;
+use super::session_merge;
+use super::session_sync::{SessionVersions, SessionsDiff};
+use super::snapshot::{self, CliStatus, CliVersionCache, EnvironmentSnapshot, GitState};
+use super::storage::{self, StorageAlarm, StorageCritical};
+use super::tool_output::{self, DEFAULT_MAX_TOOL_RESULT_BYTES};
+use super::tool_presets::{ToolPresets, UnknownPreset};
+use super::tools::{self, ToolMatcherError};
+use super::usage::{self, PromptClock, PromptTiming, UsageSummary};
+#[cfg(windows)]
+use super::win_process;
 
 /// Errors that can occur during process management
 #[derive(Error, Debug)]
@@ -36,20 +72,497 @@ pub enum ProcessError {
     InvalidWorkingDir(PathBuf),
     #[error("Process terminated unexpectedly")]
     ProcessTerminated,
+    #[error("Unknown model: {0}")]
+    UnknownModel(String),
+    #[error("Session is archived: {0}")]
+    SessionArchived(String),
+    #[error("Failed to persist archived sessions: {0}")]
+    ArchiveFailed(String),
+    #[error("Pre-prompt hook rejected the prompt: {0}")]
+    HookRejected(String),
+    #[error("Prompt is too large ({size} bytes, maximum is {max} bytes)")]
+    PromptTooLarge { size: usize, max: usize },
+    #[error("Unknown meta command: {0}")]
+    UnknownMetaCommand(String),
+    #[error("Unknown tool preset: {0}")]
+    UnknownToolPreset(String),
+    #[error("Cannot merge a session into itself: {0}")]
+    MergeIntoSelf(String),
+    #[error("Invalid allowed tools: {}", join_errors(.0))]
+    InvalidToolMatchers(Vec<ToolMatcherError>),
+    #[error("No saved layout for project: {0}")]
+    LayoutNotFound(PathBuf),
+    #[error("Failed to save project layout: {0}")]
+    LayoutFailed(String),
+    #[error("Tool result not available: {0}")]
+    ToolResultBlobNotFound(String),
+    #[error("Prompt not found: {0}")]
+    PromptNotFound(String),
+    #[error("Cannot leave read-only mode while the session is busy: {0}")]
+    ReadOnlySessionBusy(String),
+    #[error("File version not available: {0}")]
+    FileVersionNotFound(String),
+    #[error("Failed to access file versions: {0}")]
+    FileVersionsFailed(String),
+}
+
+fn join_errors(errors: &[ToolMatcherError]) -> String {
+    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    messages.join("; ")
+}
+
+impl From<UnknownPreset> for ProcessError {
+    fn from(e: UnknownPreset) -> Self {
+        ProcessError::UnknownToolPreset(e.0)
+    }
 }
 
 /// Configuration for spawning a new session
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SessionConfig {
+    #[serde(with = "super::paths::os_path")]
     pub working_dir: PathBuf,
-    #[serde(default = "default_model")]
+    /// Display name, kept so project layouts can restore it
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Model id or alias; empty means the model catalog's default
+    #[serde(default)]
     pub model: String,
+    /// Tool matchers; `preset:<name>` entries are expanded when the config is validated
     #[serde(default)]
     pub allowed_tools: Vec<String>,
+    /// Presets whose tools are (or, before validation, will be) in `allowed_tools`
+    #[serde(default)]
+    pub tool_presets: Vec<String>,
+    /// Accept a model that is not in the model catalog (custom gateways)
+    #[serde(default)]
+    pub allow_unknown_model: bool,
+    /// Shell commands run before and after each prompt
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    /// Mask secrets in Bash tool inputs before they are emitted to the webview
+    #[serde(default)]
+    pub redact_tool_inputs: bool,
+    /// Extra directories the CLI may access besides `working_dir` (`--add-dir`)
+    #[serde(default, with = "super::paths::os_paths")]
+    pub add_dirs: Vec<PathBuf>,
+    /// Context blocks (git status, files, command output) prepended to each prompt
+    #[serde(default)]
+    pub context_providers: Vec<ContextProvider>,
+    /// Existing CLI conversation to attach to; the first prompt resumes it
+    #[serde(default)]
+    pub resume_session_id: Option<String>,
+    /// Tool results larger than this (serialized bytes) are truncated in emitted
+    /// messages; None means [`DEFAULT_MAX_TOOL_RESULT_BYTES`]
+    #[serde(default)]
+    pub max_tool_result_bytes: Option<usize>,
+    /// Run the CLI in plan mode and refuse file writes made for this session
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// How long a client-generated request id is remembered for duplicate suppression
+const REQUEST_DEDUP_WINDOW: Duration = Duration::from_secs(5);
+
+/// Maximum number of recent request ids remembered per session
+const REQUEST_DEDUP_CAPACITY: usize = 16;
+
+/// How long an interrupted CLI gets to exit after CTRL_BREAK before it is killed
+#[cfg(windows)]
+const INTERRUPT_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// Capacity of the manager-wide session event channel
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// CLI slash commands that can be run through `send_meta_command`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetaCommand {
+    /// Summarize the conversation to reduce context
+    Compact,
+    /// Start a fresh conversation
+    Clear,
+    /// Report the conversation's cost
+    Cost,
+    /// Show or edit memory files
+    Memory,
+}
+
+impl MetaCommand {
+    /// Parse a command such as "compact", "/compact" or "/compact <instructions>"
+    ///
+    /// Returns the command and any trailing arguments.
+    pub fn parse(input: &str) -> Result<(Self, &str), ProcessError> {
+        let input = input.trim();
+        let trimmed = input.strip_prefix('/').unwrap_or(input);
+        let (name, args) = match trimmed.split_once(char::is_whitespace) {
+            Some((name, args)) => (name, args.trim()),
+            None => (trimmed, ""),
+        };
+
+        let command = match name {
+            "compact" => MetaCommand::Compact,
+            "clear" => MetaCommand::Clear,
+            "cost" => MetaCommand::Cost,
+            "memory" => MetaCommand::Memory,
+            _ => return Err(ProcessError::UnknownMetaCommand(input.to_string())),
+        };
+        Ok((command, args))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MetaCommand::Compact => "compact",
+            MetaCommand::Clear => "clear",
+            MetaCommand::Cost => "cost",
+            MetaCommand::Memory => "memory",
+        }
+    }
+
+    /// The prompt text that invokes this command in the CLI
+    pub fn to_prompt(&self, args: &str) -> String {
+        if args.is_empty() {
+            format!("/{}", self.name())
+        } else {
+            format!("/{} {}", self.name(), args)
+        }
+    }
+}
+
+/// Why a prompt's run ended, from the CLI's final `result` message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionReason {
+    /// The run finished normally
+    Success,
+    /// The CLI stopped because it hit its turn limit
+    MaxTurns,
+    /// The CLI reported an error while executing
+    ExecutionError,
+    /// The run failed with another error, or exited without a result
+    Error,
+    /// The process was killed by `interrupt`
+    Interrupted,
+}
+
+impl CompletionReason {
+    /// Map a result message's `subtype` and `is_error` to a completion reason
+    pub fn from_result(subtype: Option<&str>, is_error: Option<bool>) -> Self {
+        match subtype {
+            Some("error_max_turns") => CompletionReason::MaxTurns,
+            Some("error_during_execution") => CompletionReason::ExecutionError,
+            _ if is_error == Some(true) => CompletionReason::Error,
+            Some(subtype) if subtype.starts_with("error") => CompletionReason::Error,
+            _ => CompletionReason::Success,
+        }
+    }
+}
+
+/// Per-prompt options for `send_prompt`
+#[derive(Debug, Clone, Default)]
+pub struct PromptOptions {
+    /// Client-generated id used to suppress duplicate submissions
+    pub request_id: Option<String>,
+    /// Model to use for this prompt only; the session's configured model is unchanged
+    pub model_override: Option<String>,
+    /// Set when the prompt is a CLI meta command sent via `send_meta_command`
+    pub meta_command: Option<MetaCommand>,
+    /// Run as a plan preview that changes nothing (see `dry_run`)
+    pub dry_run: bool,
+}
+
+/// Prompt history entry for a session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptRecord {
+    pub prompt_id: String,
+    #[serde(default)]
+    pub request_id: Option<String>,
+    /// The model passed to `--model` for this prompt
+    pub model: String,
+    /// Whether `model` came from a per-prompt override
+    #[serde(default)]
+    pub model_overridden: bool,
+    pub started_at: u64,
+    #[serde(default)]
+    pub completed_at: Option<u64>,
+    #[serde(default, rename = "cost_usd")]
+    pub cost: Option<Usd>,
+    /// Files modified by Edit/Write tool calls during this prompt
+    #[serde(default)]
+    pub files_touched: Vec<String>,
+    /// Output of the pre/post-prompt hooks that ran for this prompt
+    #[serde(default)]
+    pub hooks: Vec<HookOutput>,
+    #[serde(default)]
+    pub meta_command: Option<MetaCommand>,
+    /// `subtype` of the CLI's final result message, as reported
+    #[serde(default)]
+    pub result_subtype: Option<String>,
+    /// Why the run ended; None until the prompt completes
+    #[serde(default)]
+    pub completion_reason: Option<CompletionReason>,
+    /// Where the prompt's time went; None until the prompt completes
+    #[serde(default)]
+    pub timing: Option<PromptTiming>,
+    /// Context providers that ran for this prompt and what they added
+    #[serde(default)]
+    pub context: Vec<ContextContribution>,
+    /// The environment the prompt ran in, for bug reports
+    #[serde(default)]
+    pub snapshot: Option<EnvironmentSnapshot>,
+    /// The prompt ran as a plan preview (see `send_prompt_dry_run`)
+    #[serde(default)]
+    pub dry_run: bool,
+    /// What a dry run proposed to do; None until it completes
+    #[serde(default)]
+    pub plan: Option<DryRunPlan>,
+}
+
+/// A CLI message forwarded to the frontend, numbered for replay
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptEntry {
+    /// Per-session sequence number, starting at 1 and gapless across prompts
+    pub sequence: u64,
+    pub prompt_id: String,
+    pub model: String,
+    /// The message came from a dry run
+    pub dry_run: bool,
+    pub message: StreamMessage,
+}
+
+/// A session's transcript with a header describing its environment
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptExport {
+    pub session: SessionInfo,
+    pub exported_at: u64,
+    /// Snapshot of the most recent prompt, if any prompt ran
+    pub snapshot: Option<EnvironmentSnapshot>,
+    pub entries: Vec<TranscriptEntry>,
+}
+
+/// Events emitted by the process manager alongside the CLI message stream
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum SessionEvent {
+    /// A prompt's process has exited and its history entry is final
+    PromptCompleted {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+        prompt: Box<PromptRecord>,
+    },
+    /// A write failed because the disk is full; sent once until storage recovers
+    StorageCritical(StorageCritical),
+    /// Sessions were added, changed or removed (see `session_sync`)
+    SessionsChanged(SessionsDiff),
+    /// A session's context pressure changed (e.g. after an auto-compaction)
+    ContextPressure {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+        pressure: ContextPressure,
+        #[serde(rename = "contextTokens")]
+        context_tokens: Option<u64>,
+        /// The compaction that caused the change, if any
+        compaction: Option<Compaction>,
+    },
+    /// A prompt's process failed (non-zero exit or an error message)
+    ///
+    /// For network-classified failures the connectivity probe has already run
+    /// and its result is attached, so the UI can tell "offline" from other errors.
+    SessionError {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+        #[serde(rename = "promptId")]
+        prompt_id: String,
+        message: String,
+        #[serde(rename = "errorClass")]
+        error_class: ErrorClass,
+        #[serde(rename = "exitCode")]
+        exit_code: Option<i32>,
+        connectivity: Option<ConnectivityStatus>,
+    },
+    /// Every prompt dispatched by a broadcast has finished
+    BroadcastCompleted {
+        #[serde(rename = "broadcastId")]
+        broadcast_id: String,
+        /// Sum of the reported costs of all targets
+        #[serde(rename = "totalCostUsd")]
+        total_cost: Usd,
+        outcomes: Vec<BroadcastOutcome>,
+    },
+    /// Prompts were running when the app last exited abnormally
+    ///
+    /// Their sessions are in the archive, flagged `recovered_after_crash`, and
+    /// can be resumed by unarchiving them.
+    CrashRecovery { prompts: Vec<RecoveredPrompt> },
+    /// The MCP server status reported at prompt start differs from the previous prompt's
+    McpStatus {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+        servers: Vec<McpServerStatus>,
+        /// Servers that failed to connect
+        failed: Vec<McpServerFailure>,
+    },
+    /// Two live sessions append to the same CLI conversation
+    ///
+    /// `sessionId` just captured or was attached to the conversation that
+    /// `otherSessionId` already owned; `merge_sessions` can combine them.
+    DuplicateClaudeSession {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+        #[serde(rename = "otherSessionId")]
+        other_session_id: String,
+        #[serde(rename = "claudeSessionId")]
+        claude_session_id: String,
+    },
+    /// A session's transcript was renumbered (after `merge_sessions`)
+    ///
+    /// Sequence numbers seen before are stale: replay from the start.
+    TranscriptReset {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+        /// The session whose transcript was merged in
+        #[serde(rename = "mergedSessionId")]
+        merged_session_id: String,
+    },
+    /// A prompt's CLI printed a progress line on stderr (see `cli_activity`)
+    SessionActivity {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+        #[serde(rename = "promptId")]
+        prompt_id: String,
+        #[serde(flatten)]
+        activity: SessionActivity,
+    },
+}
+
+/// A prompt found in the crash-recovery journal, in a `crash-recovery` event
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecoveredPrompt {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    #[serde(rename = "promptId")]
+    pub prompt_id: String,
+    /// Pass to `--resume` (via unarchiving) to continue the conversation
+    #[serde(rename = "claudeSessionId")]
+    pub claude_session_id: Option<String>,
+    #[serde(rename = "startedAt")]
+    pub started_at: u64,
+    pub pid: Option<u32>,
+    /// A process with the recorded pid still exists; it is reported, not
+    /// killed, because the pid may have been reused
+    #[serde(rename = "processRunning")]
+    pub process_running: bool,
+}
+
+/// A failed MCP server in a `session-mcp-status` event
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct McpServerFailure {
+    pub name: String,
+    pub status: String,
+    /// Set when the GUI manages this server and can restart it
+    #[serde(rename = "restartActionId")]
+    pub restart_action_id: Option<String>,
+}
+
+/// How one target of a broadcast prompt finished, in a `broadcast-completed` event
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BroadcastOutcome {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    #[serde(rename = "promptId")]
+    pub prompt_id: String,
+    /// None if the session was terminated before the prompt finished
+    #[serde(rename = "completionReason")]
+    pub completion_reason: Option<CompletionReason>,
+    #[serde(rename = "costUsd")]
+    pub cost: Option<Usd>,
+}
+
+impl BroadcastOutcome {
+    fn from_record(session_id: &str, record: &PromptRecord) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            prompt_id: record.prompt_id.clone(),
+            completion_reason: record.completion_reason,
+            cost: record.cost,
+        }
+    }
+}
+
+/// Result of dispatching a broadcast prompt
+#[derive(Debug)]
+pub struct BroadcastDispatch {
+    pub broadcast_id: String,
+    /// Per target session: the started prompt, or why it could not start
+    pub results: HashMap<String, Result<PromptRecord, ProcessError>>,
+}
+
+/// How often a broadcast re-checks its targets for missed completions
+const BROADCAST_RECONCILE_INTERVAL: Duration = Duration::from_secs(1);
+
+impl SessionEvent {
+    /// Name of the Tauri event this is emitted as
+    pub fn event_name(&self) -> &'static str {
+        match self {
+            SessionEvent::PromptCompleted { .. } => "prompt-completed",
+            SessionEvent::SessionError { .. } => "session-error",
+            SessionEvent::BroadcastCompleted { .. } => "broadcast-completed",
+            SessionEvent::StorageCritical(_) => "storage-critical",
+            SessionEvent::SessionsChanged(_) => "sessions-changed",
+            SessionEvent::ContextPressure { .. } => "session-context-pressure",
+            SessionEvent::McpStatus { .. } => "session-mcp-status",
+            SessionEvent::CrashRecovery { .. } => "crash-recovery",
+            SessionEvent::DuplicateClaudeSession { .. } => "duplicate-claude-session",
+            SessionEvent::TranscriptReset { .. } => "transcript-reset",
+            SessionEvent::SessionActivity { .. } => "session-activity",
+        }
+    }
+}
+
+type SessionMap = RwLock<HashMap<String, Arc<Mutex<Session>>>>;
+
+/// Emit `duplicate-claude-session` for every other live session that owns `claude_id`
+///
+/// Must be called without holding the lock of `session_id`. Returns the other sessions.
+async fn report_duplicate_claude_session(
+    sessions: &SessionMap,
+    events: &broadcast::Sender<SessionEvent>,
+    session_id: &str,
+    claude_id: &str,
+) -> Vec<String> {
+    let others: Vec<(String, Arc<Mutex<Session>>)> = sessions
+        .read()
+        .await
+        .iter()
+        .filter(|(id, _)| id.as_str() != session_id)
+        .map(|(id, session_arc)| (id.clone(), session_arc.clone()))
+        .collect();
+
+    let mut duplicates = Vec::new();
+    for (other_id, session_arc) in others {
+        if session_arc.lock().await.info.claude_session_id.as_deref() == Some(claude_id) {
+            log::warn!(
+                "Sessions {} and {} share Claude session {}",
+                session_id,
+                other_id,
+                claude_id
+            );
+            let _ = events.send(SessionEvent::DuplicateClaudeSession {
+                session_id: session_id.to_string(),
+                other_session_id: other_id.clone(),
+                claude_session_id: claude_id.to_string(),
+            });
+            duplicates.push(other_id);
+        }
+    }
+    duplicates
 }
 
-fn default_model() -> String {
-    "sonnet".to_string()
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
 }
 
 /// Status of a session
@@ -59,26 +572,129 @@ pub enum SessionStatus {
     Thinking,
     Error,
     Terminated,
+    Archived,
 }
 
 /// Information about a session
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SessionInfo {
     pub id: String,
     pub claude_session_id: Option<String>, // The actual Claude CLI session ID for --resume
+    #[serde(with = "super::paths::os_path")]
     pub working_dir: PathBuf,
     pub model: String,
     pub status: SessionStatus,
     pub created_at: u64,
     pub prompt_count: u32,
-    pub total_cost_usd: f64,
+    #[serde(rename = "total_cost_usd")]
+    pub total_cost: Usd,
+    #[serde(default)]
+    pub active_prompt_id: Option<String>,
+    /// Set when a restored session's working directory no longer exists
+    #[serde(default)]
+    pub working_dir_missing: bool,
+    /// MCP server status from the most recent prompt's init message
+    #[serde(default)]
+    pub mcp_servers: Option<Vec<McpServerStatus>>,
+    /// How close the conversation is to the context limit
+    #[serde(default)]
+    pub context_pressure: ContextPressure,
+    /// Tokens sent with the most recent request, when reported
+    #[serde(default)]
+    pub context_tokens: Option<u64>,
+    /// A prompt was running when the app last crashed; cleared by the next prompt
+    #[serde(default)]
+    pub recovered_after_crash: bool,
+    /// Tool presets the session was configured with
+    #[serde(default)]
+    pub tool_presets: Vec<String>,
+    /// The expanded allowed-tools list passed to the CLI
+    #[serde(default)]
+    pub allowed_tools: Vec<String>,
+    /// Set when the working directory is inside a cloud-synced folder
+    #[serde(default)]
+    pub sync_warning: Option<SyncWarning>,
+    /// The CLI runs in plan mode and file writes for the session are refused
+    #[serde(default)]
+    pub read_only: bool,
+    /// Session list version of the last change to this info (see `session_sync`)
+    ///
+    /// Not restored from disk: versions restart with the app.
+    #[serde(default, skip_deserializing)]
+    pub version: u64,
+}
+
+/// A client request id that recently started a prompt
+#[derive(Debug, Clone)]
+struct RecentRequest {
+    request_id: String,
+    prompt_id: String,
+    received_at: Instant,
+}
+
+/// Small per-session LRU of recently received client request ids
+///
+/// Used to make `send_prompt` idempotent: a repeated request id (e.g. from a
+/// double-click in the frontend) maps back to the prompt it already started.
+#[derive(Debug, Default)]
+struct RecentRequests {
+    entries: VecDeque<RecentRequest>,
+}
+
+impl RecentRequests {
+    /// Look up the prompt started by `request_id`, ignoring expired entries
+    fn lookup(&mut self, request_id: &str, now: Instant) -> Option<String> {
+        self.entries
+            .retain(|entry| now.duration_since(entry.received_at) < REQUEST_DEDUP_WINDOW);
+        self.entries
+            .iter()
+            .find(|entry| entry.request_id == request_id)
+            .map(|entry| entry.prompt_id.clone())
+    }
+
+    /// Remember that `request_id` started `prompt_id`, evicting the oldest entry when full
+    fn record(&mut self, request_id: String, prompt_id: String, now: Instant) {
+        if self.entries.len() >= REQUEST_DEDUP_CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(RecentRequest {
+            request_id,
+            prompt_id,
+            received_at: now,
+        });
+    }
 }
 
 /// Internal session state
 struct Session {
     info: SessionInfo,
     config: SessionConfig,
-    active_process: Option<Child>,
+    active_process: Option<CliHandle>,
+    /// Output clock of the running prompt, for stall detection
+    last_output: Option<LastOutput>,
+    recent_requests: RecentRequests,
+    history: Vec<PromptRecord>,
+    /// Every message forwarded for this session, kept until termination
+    transcript: Vec<TranscriptEntry>,
+    context: ContextTracker,
+}
+
+impl Session {
+    /// Copy the tracked context pressure into the session info, as an event
+    fn context_pressure_event(&mut self, compaction: Option<Compaction>) -> SessionEvent {
+        self.info.context_pressure = self.context.pressure();
+        self.info.context_tokens = self.context.context_tokens();
+        SessionEvent::ContextPressure {
+            session_id: self.info.id.clone(),
+            pressure: self.info.context_pressure,
+            context_tokens: self.info.context_tokens,
+            compaction,
+        }
+    }
+
+    fn prompt_record_mut(&mut self, prompt_id: &str) -> Option<&mut PromptRecord> {
+        self.history.iter_mut().find(|r| r.prompt_id == prompt_id)
+    }
 }
 
 /// Manager for Claude CLI processes
@@ -88,15 +704,232 @@ struct Session {
 /// - `send_prompt()` - Spawns a Claude CLI process for this prompt
 /// - Each process uses `--resume` if there's a previous claude_session_id
 pub struct ProcessManager {
-    sessions: Arc<RwLock<HashMap<String, Arc<Mutex<Session>>>>>,
+    sessions: Arc<SessionMap>,
+    backend: Arc<dyn ClaudeBackend>,
+    models: ModelCatalog,
+    events: broadcast::Sender<SessionEvent>,
+    archive: Mutex<ArchiveStore>,
+    /// Endpoint probed after network failures (defaults to the CLI's API endpoint)
+    connectivity_endpoint: Option<String>,
+    mcp_registry: McpRegistry,
+    /// Shared with other writers so a full disk is reported once
+    storage_alarm: StorageAlarm,
+    scratch: ScratchSpace,
+    journal: PromptJournal,
+    tool_presets: ToolPresets,
+    layouts: LayoutStore,
+    /// Save each project's layout before `terminate_all` ends its sessions
+    autosave_layouts: bool,
+    /// Filled by `check_cli`; read when snapshotting each prompt
+    cli_version: CliVersionCache,
+    versions: SessionVersions,
 }
 
 impl ProcessManager {
     /// Create a new process manager
     pub fn new() -> Self {
+        Self::with_cli_path("claude")
+    }
+
+    /// Create a process manager that spawns the given CLI binary instead of `claude`
+    pub fn with_cli_path(cli_path: impl Into<PathBuf>) -> Self {
+        Self::with_backend(Arc::new(CliBackend::new(cli_path)))
+    }
+
+    /// Create a process manager whose prompts are run by `backend`
+    pub fn with_backend(backend: Arc<dyn ClaudeBackend>) -> Self {
+        let events = broadcast::channel(EVENT_CHANNEL_CAPACITY).0;
         Self {
             sessions: Arc::new(RwLock::new(HashMap::new())),
+            backend,
+            models: ModelCatalog::builtin(),
+            versions: SessionVersions::new(events.clone()),
+            events,
+            archive: Mutex::new(ArchiveStore::in_memory()),
+            connectivity_endpoint: None,
+            mcp_registry: McpRegistry::new(),
+            storage_alarm: StorageAlarm::new(),
+            scratch: ScratchSpace::default(),
+            journal: PromptJournal::default(),
+            tool_presets: ToolPresets::builtin(),
+            layouts: LayoutStore::default(),
+            autosave_layouts: false,
+            cli_version: CliVersionCache::default(),
+        }
+    }
+
+    /// Use `app_data_dir` for persisted state, loading the archived session index
+    pub fn set_data_dir(&mut self, app_data_dir: &std::path::Path) {
+        self.archive = Mutex::new(ArchiveStore::load(app_data_dir));
+        self.scratch = ScratchSpace::in_app_data(app_data_dir);
+        self.journal = PromptJournal::in_app_data(app_data_dir);
+        self.layouts = LayoutStore::in_app_data(app_data_dir);
+    }
+
+    /// Per-session scratch directories for temp files
+    pub fn scratch(&self) -> &ScratchSpace {
+        &self.scratch
+    }
+
+    /// Remove scratch directories of sessions that are neither live nor archived
+    ///
+    /// Run at startup, after crash recovery has restored its sessions: this is
+    /// the only place scratch dirs of sessions that were open at the last exit
+    /// are removed (see `scratch`). Returns how many were removed.
+    pub async fn sweep_scratch(&self) -> usize {
+        let mut known: HashSet<String> = self.sessions.read().await.keys().cloned().collect();
+        known.extend(
+            self.archive
+                .lock()
+                .await
+                .infos()
+                .into_iter()
+                .map(|info| info.id),
+        );
+        let removed = self.scratch.sweep_orphans(&known).await;
+        if removed > 0 {
+            log::info!("Removed {} orphaned scratch directories", removed);
+        }
+        removed
+    }
+
+    /// Restore the sessions of prompts that were running when the app last crashed
+    ///
+    /// Reads the entries left in the prompt journal, flags their sessions
+    /// `recovered_after_crash` in the archive (recreating sessions that only
+    /// lived in memory from the journaled config) and emits a `crash-recovery`
+    /// event. Run once at startup, after `set_data_dir`.
+    pub async fn recover_from_crash(&self) -> Vec<RecoveredPrompt> {
+        let orphans = self.journal.take_orphans().await;
+        if orphans.is_empty() {
+            return Vec::new();
+        }
+
+        let mut archive = self.archive.lock().await;
+        let mut recovered = Vec::with_capacity(orphans.len());
+        for entry in orphans {
+            let process_running = entry.pid.is_some_and(journal::process_exists);
+            log::warn!(
+                "Prompt {} of session {} was running when the app exited (process still running: {})",
+                entry.prompt_id,
+                entry.session_id,
+                process_running
+            );
+
+            let mut archived =
+                archive
+                    .remove(&entry.session_id)
+                    .unwrap_or_else(|| ArchivedSession {
+                        info: SessionInfo {
+                            id: entry.session_id.clone(),
+                            claude_session_id: None,
+                            working_dir: entry.config.working_dir.clone(),
+                            model: entry.config.model.clone(),
+                            status: SessionStatus::Archived,
+                            created_at: entry.started_at,
+                            prompt_count: 0,
+                            total_cost: Usd::ZERO,
+                            active_prompt_id: None,
+                            working_dir_missing: false,
+                            mcp_servers: None,
+                            context_pressure: ContextPressure::Normal,
+                            context_tokens: None,
+                            recovered_after_crash: false,
+                            tool_presets: entry.config.tool_presets.clone(),
+                            allowed_tools: entry.config.allowed_tools.clone(),
+                            sync_warning: None,
+                            read_only: entry.config.read_only,
+                            version: 0,
+                        },
+                        config: entry.config.clone(),
+                        history: Vec::new(),
+                        archived_at: unix_now(),
+                    });
+            archived.info.recovered_after_crash = true;
+            if entry.claude_session_id.is_some() {
+                archived.info.claude_session_id = entry.claude_session_id.clone();
+            }
+            self.versions.changed(&mut archived.info);
+            archive.insert(archived);
+
+            recovered.push(RecoveredPrompt {
+                session_id: entry.session_id,
+                prompt_id: entry.prompt_id,
+                claude_session_id: entry.claude_session_id,
+                started_at: entry.started_at,
+                pid: entry.pid,
+                process_running,
+            });
+        }
+        if let Err(e) = self.save_archive(&mut archive).await {
+            log::warn!("Failed to save recovered sessions: {}", e);
+        }
+        drop(archive);
+
+        let _ = self.events.send(SessionEvent::CrashRecovery {
+            prompts: recovered.clone(),
+        });
+        recovered
+    }
+
+    /// Probe `endpoint` instead of the CLI's API endpoint after network failures
+    pub fn set_connectivity_endpoint(&mut self, endpoint: impl Into<String>) {
+        self.connectivity_endpoint = Some(endpoint.into());
+    }
+
+    /// Registry of MCP servers started by the GUI
+    pub fn mcp_registry(&self) -> &McpRegistry {
+        &self.mcp_registry
+    }
+
+    /// Subscribe to session events (prompt completion, etc.)
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionEvent> {
+        self.events.subscribe()
+    }
+
+    /// Replace the model catalog used for validation and defaults
+    pub fn set_model_catalog(&mut self, models: ModelCatalog) {
+        self.models = models;
+    }
+
+    /// Get the model catalog
+    pub fn model_catalog(&self) -> &ModelCatalog {
+        &self.models
+    }
+
+    /// Replace the allowed-tools presets used to expand `preset:` entries
+    pub fn set_tool_presets(&mut self, presets: ToolPresets) {
+        self.tool_presets = presets;
+    }
+
+    pub fn tool_presets(&self) -> &ToolPresets {
+        &self.tool_presets
+    }
+
+    /// Fill in the default model and validate the config against the catalog
+    fn validate_config(&self, config: &mut SessionConfig) -> Result<(), ProcessError> {
+        if !config.working_dir.exists() {
+            return Err(ProcessError::InvalidWorkingDir(config.working_dir.clone()));
         }
+
+        if config.model.is_empty() {
+            config.model = self.models.default_model.clone();
+        }
+        if !self.models.is_allowed(&config.model, config.allow_unknown_model) {
+            return Err(ProcessError::UnknownModel(config.model.clone()));
+        }
+
+        let allowed_tools = tools::validate_tool_matchers(&config.allowed_tools)
+            .map_err(ProcessError::InvalidToolMatchers)?;
+
+        // Expand presets here so the CLI only ever sees concrete matchers
+        let (presets, tools) = self
+            .tool_presets
+            .expand(&config.tool_presets, &allowed_tools)?;
+        config.tool_presets = presets;
+        config.allowed_tools = tools;
+
+        Ok(())
     }
 
     /// Create a new logical session (does NOT spawn Claude CLI yet)
@@ -105,12 +938,10 @@ impl ProcessManager {
     /// when `send_prompt()` is called.
     pub async fn create_session(
         &self,
-        config: SessionConfig,
+        mut config: SessionConfig,
     ) -> Result<String, ProcessError> {
-        // Validate working directory
-        if !config.working_dir.exists() {
-            return Err(ProcessError::InvalidWorkingDir(config.working_dir.clone()));
-        }
+        // Validate working directory and model
+        self.validate_config(&mut config)?;
 
         let session_id = uuid::Uuid::new_v4().to_string();
 
@@ -119,19 +950,39 @@ impl ProcessManager {
             return Err(ProcessError::SessionExists(session_id));
         }
 
+        // Sync clients fight the CLI over files; warn, but create the session anyway
+        let sync_warning = cloud_sync::check_directory(&config.working_dir);
+        if let Some(ref warning) = sync_warning {
+            log::warn!(
+                "Working directory {} is inside a {:?} folder ({})",
+                config.working_dir.display(),
+                warning.provider,
+                warning.sync_root.display()
+            );
+        }
+
         // Create session info
         let info = SessionInfo {
             id: session_id.clone(),
-            claude_session_id: None, // Will be set after first prompt
+            // Set after the first prompt, unless attaching to an existing conversation
+            claude_session_id: config.resume_session_id.clone(),
             working_dir: config.working_dir.clone(),
             model: config.model.clone(),
             status: SessionStatus::Idle,
-            created_at: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
+            created_at: unix_now(),
             prompt_count: 0,
-            total_cost_usd: 0.0,
+            total_cost: Usd::ZERO,
+            active_prompt_id: None,
+            working_dir_missing: false,
+            mcp_servers: None,
+            context_pressure: ContextPressure::Normal,
+            context_tokens: None,
+            recovered_after_crash: false,
+            tool_presets: config.tool_presets.clone(),
+            allowed_tools: config.allowed_tools.clone(),
+            sync_warning,
+            read_only: config.read_only,
+            version: 0,
         };
 
         // Store the session
@@ -139,16 +990,78 @@ impl ProcessManager {
             info,
             config,
             active_process: None,
+            last_output: None,
+            recent_requests: RecentRequests::default(),
+            history: Vec::new(),
+            transcript: Vec::new(),
+            context: ContextTracker::default(),
         };
 
+        let resume_session_id = session.config.resume_session_id.clone();
+        let session_arc = Arc::new(Mutex::new(session));
         self.sessions
             .write()
             .await
-            .insert(session_id.clone(), Arc::new(Mutex::new(session)));
+            .insert(session_id.clone(), session_arc.clone());
+        self.versions.changed(&mut session_arc.lock().await.info);
 
+        if let Some(ref claude_id) = resume_session_id {
+            report_duplicate_claude_session(&self.sessions, &self.events, &session_id, claude_id)
+                .await;
+        }
         Ok(session_id)
     }
 
+    /// Replace a session's configuration
+    ///
+    /// The new config is validated like in `create_session` and takes effect
+    /// on the next prompt; a prompt that is already running is not affected.
+    pub async fn update_session_config(
+        &self,
+        session_id: &str,
+        mut config: SessionConfig,
+    ) -> Result<SessionInfo, ProcessError> {
+        self.validate_config(&mut config)?;
+
+        let sessions = self.sessions.read().await;
+        let session_arc = sessions
+            .get(session_id)
+            .ok_or_else(|| ProcessError::SessionNotFound(session_id.to_string()))?;
+
+        let mut session = session_arc.lock().await;
+        // The running CLI was spawned read-only; it must finish before that is lifted
+        if session.config.read_only
+            && !config.read_only
+            && session.info.status == SessionStatus::Thinking
+        {
+            return Err(ProcessError::ReadOnlySessionBusy(session_id.to_string()));
+        }
+        session.info.working_dir = config.working_dir.clone();
+        session.info.model = config.model.clone();
+        session.info.tool_presets = config.tool_presets.clone();
+        session.info.allowed_tools = config.allowed_tools.clone();
+        session.info.read_only = config.read_only;
+        // Attaching to a different conversation replaces the resume id
+        let attached = config
+            .resume_session_id
+            .clone()
+            .filter(|id| session.info.claude_session_id.as_ref() != Some(id));
+        if attached.is_some() {
+            session.info.claude_session_id = attached.clone();
+        }
+        session.config = config;
+        self.versions.changed(&mut session.info);
+        let info = session.info.clone();
+        drop(session);
+        drop(sessions);
+
+        if let Some(ref claude_id) = attached {
+            report_duplicate_claude_session(&self.sessions, &self.events, session_id, claude_id)
+                .await;
+        }
+        Ok(info)
+    }
+
     /// Send a prompt to a session - spawns a NEW Claude CLI process
     ///
     /// This is the spawn-per-prompt model:
@@ -156,141 +1069,811 @@ impl ProcessManager {
     /// 2. Stream the JSON output via the returned receiver
     /// 3. Process terminates when done
     /// 4. Extract session_id from `system` message for next --resume
+    ///
+    /// The session is reserved (marked Thinking) before the pre-prompt hook
+    /// runs, so concurrent calls see it as busy while the hook is pending.
+    ///
+    /// Returns the prompt's history entry. If `options.request_id` matches a
+    /// request received for this session within the last few seconds, the
+    /// prompt it started is returned instead of spawning again (or failing
+    /// with `SessionBusy`).
     pub async fn send_prompt(
         &self,
         session_id: &str,
         prompt: &str,
+        options: PromptOptions,
         output_tx: mpsc::Sender<StreamMessage>,
-    ) -> Result<(), ProcessError> {
+    ) -> Result<PromptRecord, ProcessError> {
+        let prompt = sanitize_prompt(prompt);
+        if prompt.len() > MAX_PROMPT_BYTES {
+            return Err(ProcessError::PromptTooLarge {
+                size: prompt.len(),
+                max: MAX_PROMPT_BYTES,
+            });
+        }
+
         let sessions = self.sessions.read().await;
-        let session_arc = sessions
-            .get(session_id)
-            .ok_or_else(|| ProcessError::SessionNotFound(session_id.to_string()))?
-            .clone();
+        let session_arc = match sessions.get(session_id) {
+            Some(session_arc) => session_arc.clone(),
+            None => return Err(self.missing_session_error(session_id).await),
+        };
         drop(sessions); // Release read lock
 
         let mut session = session_arc.lock().await;
 
+        // Suppress duplicates of a request that already started a prompt
+        let now = Instant::now();
+        if let Some(ref request_id) = options.request_id {
+            if let Some(prompt_id) = session.recent_requests.lookup(request_id, now) {
+                if let Some(record) = session.prompt_record_mut(&prompt_id) {
+                    log::info!(
+                        "Duplicate request {} for session {}, returning prompt {}",
+                        request_id,
+                        session_id,
+                        prompt_id
+                    );
+                    return Ok(record.clone());
+                }
+            }
+        }
+
         // Check if session is busy
         if session.info.status == SessionStatus::Thinking {
             return Err(ProcessError::SessionBusy);
         }
 
-        // Build the command arguments
-        let mut args: Vec<String> = vec![
-            "-p".to_string(),
-            prompt.to_string(),
-            "--output-format".to_string(),
-            "stream-json".to_string(),
-        ];
+        // Resolve the model for this prompt without touching the session config
+        let model = match options.model_override {
+            Some(ref model) => {
+                if !self.models.is_allowed(model, session.config.allow_unknown_model) {
+                    return Err(ProcessError::UnknownModel(model.clone()));
+                }
+                model.clone()
+            }
+            None => session.config.model.clone(),
+        };
+
+        // Reserve the session for this prompt
+        let prompt_id = uuid::Uuid::new_v4().to_string();
+        let mut record = PromptRecord {
+            prompt_id: prompt_id.clone(),
+            request_id: options.request_id.clone(),
+            model,
+            model_overridden: options.model_override.is_some(),
+            started_at: unix_now(),
+            completed_at: None,
+            cost: None,
+            files_touched: Vec::new(),
+            hooks: Vec::new(),
+            meta_command: options.meta_command,
+            result_subtype: None,
+            completion_reason: None,
+            timing: None,
+            context: Vec::new(),
+            snapshot: None,
+            dry_run: options.dry_run,
+            plan: None,
+        };
+        record.snapshot = Some(EnvironmentSnapshot::new(
+            self.cli_version.get(),
+            &record.model,
+        ));
+        session.info.status = SessionStatus::Thinking;
+        session.info.active_prompt_id = Some(prompt_id.clone());
+        self.versions.changed(&mut session.info);
+        session.history.push(record.clone());
+        if let Some(request_id) = options.request_id {
+            session
+                .recent_requests
+                .record(request_id, prompt_id.clone(), now);
+        }
+
+        let config = session.config.clone();
+        drop(session);
+
+        let hooks = config
+            .hooks
+            .clone()
+            .or(load_project_hooks(&config.working_dir));
 
-        // Add --resume if we have a previous claude session ID
-        if let Some(ref claude_id) = session.info.claude_session_id {
-            args.push("--resume".to_string());
-            args.push(claude_id.clone());
+        // Run the pre-prompt hook without holding the session lock; it can veto the prompt
+        if let Some(ref command) = hooks.pre_prompt {
+            let output = run_hook(
+                HookKind::PrePrompt,
+                command,
+                &config.working_dir,
+                prompt.as_bytes(),
+                hooks.timeout(),
+            )
+            .await;
+            let rejection = (!output.succeeded()).then(|| output.failure_reason());
+            record.hooks.push(output.clone());
+
+            let mut session = session_arc.lock().await;
+            if let Some(entry) = session.prompt_record_mut(&prompt_id) {
+                entry.hooks.push(output);
+            }
+            if let Some(reason) = rejection {
+                log::info!("Pre-prompt hook rejected prompt for session {}: {}", session_id, reason);
+                self.release_reservation(&mut session, &prompt_id);
+                return Err(ProcessError::HookRejected(reason));
+            }
         }
 
-        // Add model
-        args.push("--model".to_string());
-        args.push(session.config.model.clone());
+        // Prepend the configured context blocks; meta commands must stay bare
+        let prompt = if config.context_providers.is_empty() || options.meta_command.is_some() {
+            prompt
+        } else {
+            let preamble = build_preamble(
+                &config.context_providers,
+                &config.working_dir,
+                DEFAULT_CONTEXT_BUDGET_BYTES,
+            )
+            .await;
+            let prompt = sanitize_prompt(&preamble.prepend_to(&prompt));
+            record.context = preamble.contributions;
+
+            let mut session = session_arc.lock().await;
+            if let Some(entry) = session.prompt_record_mut(&prompt_id) {
+                entry.context = record.context.clone();
+            }
+            if prompt.len() > MAX_PROMPT_BYTES {
+                self.release_reservation(&mut session, &prompt_id);
+                return Err(ProcessError::PromptTooLarge {
+                    size: prompt.len(),
+                    max: MAX_PROMPT_BYTES,
+                });
+            }
+            prompt
+        };
+
+        // Large prompts go through a temp file on stdin instead of argv
+        let scratch_dir = self.scratch.scratch_dir_for(session_id);
+        let input = match PromptInput::prepare(&prompt, &scratch_dir).await {
+            Ok(input) => input,
+            Err(e) => {
+                self.release_reservation(&mut *session_arc.lock().await, &prompt_id);
+                return Err(e.into());
+            }
+        };
+
+        let mut session = session_arc.lock().await;
 
-        // Add allowed tools if any
-        if !session.config.allowed_tools.is_empty() {
-            args.push("--allowedTools".to_string());
-            args.push(session.config.allowed_tools.join(","));
+        // The reservation is lost if the session was interrupted or archived meanwhile
+        if session.info.active_prompt_id.as_deref() != Some(prompt_id.as_str()) {
+            input.cleanup().await;
+            return Err(ProcessError::ProcessTerminated);
         }
 
+        let build_args = if options.dry_run {
+            build_dry_run_args
+        } else {
+            build_claude_args
+        };
+        let args = build_args(
+            &config,
+            &record.model,
+            input.arg(),
+            session.info.claude_session_id.as_deref(),
+        );
+
         log::info!(
             "Spawning Claude CLI for session {} with args: {:?}",
             session_id,
-            args
+            redact::args_for_log(&args)
         );
 
+        // Read the git state for the snapshot while the process starts
+        let git_state = tokio::spawn(snapshot::capture_git(
+            config.working_dir.clone(),
+            snapshot::GIT_SNAPSHOT_TIMEOUT,
+        ));
+
         // Spawn the process
-        let mut child = Command::new("claude")
-            .args(&args)
-            .current_dir(&session.config.working_dir)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
+        let spawned = input
+            .stdin()
+            .and_then(|stdin| self.backend.spawn(&args, &config.working_dir, stdin));
+        let process = match spawned {
+            Ok(process) => process,
+            Err(e) => {
+                git_state.abort();
+                self.release_reservation(&mut session, &prompt_id);
+                input.cleanup().await;
+                return Err(e.into());
+            }
+        };
 
-        let stdout = child.stdout.take().expect("Failed to get stdout");
+        #[cfg(windows)]
+        if let Some(pid) = process.handle.id() {
+            win_process::track(pid);
+        }
 
-        // Update session state
-        session.info.status = SessionStatus::Thinking;
-        session.info.prompt_count += 1;
-        session.active_process = Some(child);
+        let clock = PromptClock::new(now, Instant::now());
+        let CliProcess {
+            handle,
+            stdout,
+            stderr,
+        } = process;
+        let journal_entry = JournalEntry {
+            session_id: session_id.to_string(),
+            prompt_id: prompt_id.clone(),
+            claude_session_id: session.info.claude_session_id.clone(),
+            started_at: record.started_at,
+            argv_hash: journal::argv_hash(&args),
+            pid: handle.id(),
+            config: config.clone(),
+        };
 
-        // Clone what we need for the async task
-        let session_id_for_task = session_id.to_string();
-        let sessions_for_task = self.sessions.clone();
+        // Update session state; a dry run is not a turn of the conversation
+        session.info.recovered_after_crash = false;
+        if !options.dry_run {
+            session.info.prompt_count += 1;
+            let prompt_count = session.info.prompt_count;
+            if session.context.prompt_started(prompt_count) {
+                let _ = self.events.send(session.context_pressure_event(None));
+            }
+        }
+        self.versions.changed(&mut session.info);
+        session.active_process = Some(handle);
+        let last_output = LastOutput::new();
+        session.last_output = Some(last_output.clone());
+        drop(session);
+        self.journal.record(&journal_entry).await;
 
-        // Spawn task to handle stdout parsing
-        tokio::spawn(async move {
-            let mut reader = BufReader::new(stdout);
-            let mut parser = StreamJsonParser::new();
-            let mut line = String::new();
+        let task = PromptTask {
+            session_id: session_id.to_string(),
+            prompt_id,
+            model: record.model.clone(),
+            working_dir: config.working_dir.clone(),
+            hooks,
+            input,
+            meta_command: options.meta_command,
+            dry_run: options.dry_run,
+            clock,
+            last_output,
+            git_state: Some(git_state),
+            redact_tool_inputs: config.redact_tool_inputs,
+            max_tool_result_bytes: config
+                .max_tool_result_bytes
+                .unwrap_or(DEFAULT_MAX_TOOL_RESULT_BYTES),
+            scratch: self.scratch.clone(),
+            connectivity_endpoint: self
+                .connectivity_endpoint
+                .clone()
+                .unwrap_or_else(connectivity::api_endpoint),
+            mcp_registry: self.mcp_registry.clone(),
+            journal: self.journal.clone(),
+            journal_entry,
+            sessions: self.sessions.clone(),
+            events: self.events.clone(),
+            versions: self.versions.clone(),
+        };
+        tokio::spawn(task.run(stdout, stderr, output_tx));
 
-            loop {
-                line.clear();
-                match reader.read_line(&mut line).await {
-                    Ok(0) => {
-                        // EOF - flush any remaining content
-                        if let Some(msg) = parser.flush() {
-                            let _ = output_tx.send(msg).await;
-                        }
-                        break;
-                    }
-                    Ok(_) => {
-                        for msg in parser.parse_chunk(line.as_bytes()) {
-                            // Extract claude_session_id from system message
-                            if let StreamMessage::System { session_id: Some(ref claude_id), .. } = msg {
-                                // Update the session with the claude session ID
-                                if let Some(session_arc) = sessions_for_task.read().await.get(&session_id_for_task) {
-                                    let mut session = session_arc.lock().await;
-                                    if session.info.claude_session_id.is_none() {
-                                        session.info.claude_session_id = Some(claude_id.clone());
-                                        log::info!(
-                                            "Captured Claude session ID: {} for app session {}",
-                                            claude_id,
-                                            session_id_for_task
-                                        );
-                                    }
-                                }
-                            }
+        Ok(record)
+    }
 
-                            // Extract cost from result message
-                            if let StreamMessage::Result { cost_usd, .. } = msg {
-                                if let Some(cost) = cost_usd {
-                                    if let Some(session_arc) = sessions_for_task.read().await.get(&session_id_for_task) {
-                                        let mut session = session_arc.lock().await;
-                                        session.info.total_cost_usd += cost;
-                                    }
-                                }
-                            }
+    /// Run a whitelisted CLI meta command through the normal prompt path
+    ///
+    /// The command is sent as a `/command` prompt with `--resume`, so it acts on
+    /// the session's conversation. `/clear` additionally drops the session's
+    /// Claude session ID once it finishes, so the next prompt starts fresh.
+    pub async fn send_meta_command(
+        &self,
+        session_id: &str,
+        command: &str,
+        output_tx: mpsc::Sender<StreamMessage>,
+    ) -> Result<PromptRecord, ProcessError> {
+        let (meta_command, args) = MetaCommand::parse(command)?;
+        let options = PromptOptions {
+            meta_command: Some(meta_command),
+            ..Default::default()
+        };
+        self.send_prompt(session_id, &meta_command.to_prompt(args), options, output_tx)
+            .await
+    }
 
-                            if output_tx.send(msg).await.is_err() {
-                                log::warn!("Output channel closed for session {}", session_id_for_task);
-                                break;
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        log::error!("Error reading stdout: {}", e);
-                        break;
-                    }
-                }
-            }
+    /// Preview what a prompt would do without letting it change anything
+    ///
+    /// The CLI runs in plan mode on a fork of the session's conversation (see
+    /// `dry_run`); the session's prompt count and resume id are unchanged.
+    /// Messages stream like a normal prompt's, and the prompt's completion
+    /// carries the proposed file operations in its `plan`. The run's cost is
+    /// still added to the session's total.
+    pub async fn send_prompt_dry_run(
+        &self,
+        session_id: &str,
+        prompt: &str,
+        output_tx: mpsc::Sender<StreamMessage>,
+    ) -> Result<PromptRecord, ProcessError> {
+        let options = PromptOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        self.send_prompt(session_id, prompt, options, output_tx)
+            .await
+    }
 
-            // Update session status when process completes
-            if let Some(session_arc) = sessions_for_task.read().await.get(&session_id_for_task) {
-                let mut session = session_arc.lock().await;
-                session.info.status = SessionStatus::Idle;
-                session.active_process = None;
+    /// Return a reserved session to Idle after its prompt failed to start
+    fn release_reservation(&self, session: &mut Session, prompt_id: &str) {
+        if session.info.active_prompt_id.as_deref() == Some(prompt_id) {
+            session.info.status = SessionStatus::Idle;
+            session.info.active_prompt_id = None;
+            self.versions.changed(&mut session.info);
+        }
+        if let Some(record) = session.prompt_record_mut(prompt_id) {
+            record.completed_at = Some(unix_now());
+        }
+    }
+
+    /// Error for a session that is not live: archived sessions get a distinct error
+    async fn missing_session_error(&self, session_id: &str) -> ProcessError {
+        if self.archive.lock().await.contains(session_id) {
+            ProcessError::SessionArchived(session_id.to_string())
+        } else {
+            ProcessError::SessionNotFound(session_id.to_string())
+        }
+    }
+
+    /// Archive a session: kill any active process and move it out of the live list
+    ///
+    /// The session's metadata, config, and prompt history are kept in the
+    /// persisted archive index until `unarchive_session` restores it.
+    pub async fn archive_session(&self, session_id: &str) -> Result<SessionInfo, ProcessError> {
+        let session_arc = self
+            .sessions
+            .write()
+            .await
+            .remove(session_id)
+            .ok_or_else(|| ProcessError::SessionNotFound(session_id.to_string()))?;
+
+        let mut session = session_arc.lock().await;
+        if let Some(ref mut child) = session.active_process {
+            log::info!("Killing active process before archiving session {}", session_id);
+            child.kill().await;
+        }
+        session.active_process = None;
+        session.info.active_prompt_id = None;
+        let previous_status = session.info.status;
+        session.info.status = SessionStatus::Archived;
+
+        let mut archive = self.archive.lock().await;
+        self.versions.changed(&mut session.info);
+        let archived = ArchivedSession {
+            info: session.info.clone(),
+            config: session.config.clone(),
+            history: session.history.clone(),
+            archived_at: unix_now(),
+        };
+        archive.insert(archived);
+        if let Err(e) = self.save_archive(&mut archive).await {
+            // Keep the session live rather than losing it
+            archive.remove(session_id);
+            session.info.status = match previous_status {
+                SessionStatus::Thinking => SessionStatus::Idle,
+                status => status,
+            };
+            self.versions.changed(&mut session.info);
+            drop(session);
+            self.sessions
+                .write()
+                .await
+                .insert(session_id.to_string(), session_arc.clone());
+            return Err(ProcessError::ArchiveFailed(e.to_string()));
+        }
+
+        log::info!("Archived session {}", session_id);
+        Ok(session.info.clone())
+    }
+
+    /// Persist the archive index, degrading to in-memory only when the disk is full
+    ///
+    /// Running out of space suspends persistence and raises the storage alarm
+    /// instead of failing, so archiving keeps working for this run.
+    async fn save_archive(&self, archive: &mut ArchiveStore) -> std::io::Result<()> {
+        match archive.save().await {
+            Err(e) if storage::is_out_of_space(&e) => {
+                log::error!("Disk full, keeping the session archive in memory: {}", e);
+                archive.suspend_persistence();
+                let path = archive.path().map(Path::to_path_buf).unwrap_or_default();
+                self.report_storage_critical(StorageCritical::new(path, &e));
+                Ok(())
             }
+            result => result,
+        }
+    }
+
+    /// Emit `storage-critical` unless it was already reported
+    pub fn report_storage_critical(&self, critical: StorageCritical) {
+        if self.storage_alarm.raise() {
+            let _ = self.events.send(SessionEvent::StorageCritical(critical));
+        }
+    }
+
+    /// The alarm shared by all writers that report a full disk
+    pub fn storage_alarm(&self) -> &StorageAlarm {
+        &self.storage_alarm
+    }
+
+    /// Storage is healthy again: re-arm the alarm and persist the archive again
+    pub async fn storage_recovered(&self) {
+        self.storage_alarm.clear();
+        if let Err(e) = self.archive.lock().await.resume_persistence().await {
+            log::warn!("Failed to save the session archive after recovery: {}", e);
+        }
+    }
+
+    /// Restore an archived session as a resumable logical session
+    ///
+    /// The Claude session ID is kept so the next prompt resumes the same
+    /// conversation. If the working directory has since been deleted the
+    /// session is still restored, with `working_dir_missing` set.
+    pub async fn unarchive_session(&self, session_id: &str) -> Result<SessionInfo, ProcessError> {
+        let mut archive = self.archive.lock().await;
+        let archived = archive
+            .remove(session_id)
+            .ok_or_else(|| ProcessError::SessionNotFound(session_id.to_string()))?;
+        if let Err(e) = self.save_archive(&mut archive).await {
+            archive.insert(archived);
+            return Err(ProcessError::ArchiveFailed(e.to_string()));
+        }
+        drop(archive);
+
+        let mut info = archived.info;
+        info.status = SessionStatus::Idle;
+        info.working_dir_missing = !archived.config.working_dir.exists();
+        if info.working_dir_missing {
+            log::warn!(
+                "Working directory {} of unarchived session {} no longer exists",
+                archived.config.working_dir.display(),
+                session_id
+            );
+        }
+
+        let session = Session {
+            info: info.clone(),
+            config: archived.config,
+            active_process: None,
+            last_output: None,
+            recent_requests: RecentRequests::default(),
+            history: archived.history,
+            transcript: Vec::new(),
+            context: ContextTracker::resume(info.context_pressure, info.prompt_count),
+        };
+        let session_arc = Arc::new(Mutex::new(session));
+        self.sessions
+            .write()
+            .await
+            .insert(session_id.to_string(), session_arc.clone());
+        let info = {
+            let mut session = session_arc.lock().await;
+            self.versions.changed(&mut session.info);
+            session.info.clone()
+        };
+
+        if let Some(ref claude_id) = info.claude_session_id {
+            report_duplicate_claude_session(&self.sessions, &self.events, session_id, claude_id)
+                .await;
+        }
+        Ok(info)
+    }
+
+    /// The CLI invocation `send_prompt` would spawn for this session right now
+    ///
+    /// Uses the same argument builder, resume id and model as a real prompt,
+    /// without spawning anything. Without a prompt the command line ends
+    /// before the `--` separator. Secrets are masked (see [`CommandLine::new`]).
+    pub async fn command_line(
+        &self,
+        session_id: &str,
+        prompt: Option<&str>,
+        shell: ShellFlavor,
+    ) -> Result<CommandLine, ProcessError> {
+        let sessions = self.sessions.read().await;
+        let session_arc = sessions
+            .get(session_id)
+            .ok_or_else(|| ProcessError::SessionNotFound(session_id.to_string()))?;
+        let session = session_arc.lock().await;
+
+        let prompt = prompt.map(sanitize_prompt);
+        let prompt_on_stdin = prompt
+            .as_ref()
+            .is_some_and(|prompt| prompt.len() > STDIN_THRESHOLD_BYTES);
+        let args = build_claude_args(
+            &session.config,
+            &session.config.model,
+            prompt.as_deref().filter(|_| !prompt_on_stdin),
+            session.info.claude_session_id.as_deref(),
+        );
+
+        Ok(CommandLine::new(
+            &self.backend.program().display().to_string(),
+            &args,
+            &session.config.working_dir,
+            prompt_on_stdin,
+            shell,
+        ))
+    }
+
+    /// Get the prompt history of a session, oldest first
+    pub async fn get_prompt_history(
+        &self,
+        session_id: &str,
+    ) -> Result<Vec<PromptRecord>, ProcessError> {
+        let sessions = self.sessions.read().await;
+        let session_arc = sessions
+            .get(session_id)
+            .ok_or_else(|| ProcessError::SessionNotFound(session_id.to_string()))?;
+
+        let session = session_arc.lock().await;
+        Ok(session.history.clone())
+    }
+
+    /// The environment snapshot of one prompt
+    pub async fn prompt_snapshot(
+        &self,
+        session_id: &str,
+        prompt_id: &str,
+    ) -> Result<Option<EnvironmentSnapshot>, ProcessError> {
+        let history = self.get_prompt_history(session_id).await?;
+        history
+            .into_iter()
+            .find(|record| record.prompt_id == prompt_id)
+            .map(|record| record.snapshot)
+            .ok_or_else(|| ProcessError::PromptNotFound(prompt_id.to_string()))
+    }
+
+    /// The session's transcript, headed by the snapshot of its latest prompt
+    pub async fn export_transcript(
+        &self,
+        session_id: &str,
+    ) -> Result<TranscriptExport, ProcessError> {
+        let sessions = self.sessions.read().await;
+        let session_arc = sessions
+            .get(session_id)
+            .ok_or_else(|| ProcessError::SessionNotFound(session_id.to_string()))?;
+
+        let session = session_arc.lock().await;
+        Ok(TranscriptExport {
+            session: session.info.clone(),
+            exported_at: unix_now(),
+            snapshot: session
+                .history
+                .iter()
+                .rev()
+                .find_map(|record| record.snapshot.clone()),
+            entries: session.transcript.clone(),
+        })
+    }
+
+    /// Check that the CLI runs, caching its version for prompt snapshots
+    pub async fn check_cli(&self) -> CliStatus {
+        let probed = match self.backend.fixed_version() {
+            Some(version) => Ok(version),
+            None => snapshot::probe_cli_version(self.backend.program()).await,
+        };
+        let status = match probed {
+            Ok(version) => CliStatus {
+                available: true,
+                version: Some(version),
+                error: None,
+            },
+            Err(e) => CliStatus {
+                available: false,
+                version: None,
+                error: Some(e),
+            },
+        };
+        self.cli_version.set(status.version.clone());
+        status
+    }
+
+    /// The full content of a tool result that was truncated in an emitted message
+    pub async fn tool_result_blob(
+        &self,
+        session_id: &str,
+        blob_id: &str,
+    ) -> Result<serde_json::Value, ProcessError> {
+        if !self.sessions.read().await.contains_key(session_id) {
+            return Err(ProcessError::SessionNotFound(session_id.to_string()));
+        }
+        tool_output::load_blob(&self.scratch, session_id, blob_id)
+            .await
+            .map_err(|e| {
+                log::debug!("Failed to read tool result {}: {}", blob_id, e);
+                ProcessError::ToolResultBlobNotFound(blob_id.to_string())
+            })
+    }
+
+    async fn session_arc(&self, session_id: &str) -> Result<Arc<Mutex<Session>>, ProcessError> {
+        self.sessions
+            .read()
+            .await
+            .get(session_id)
+            .cloned()
+            .ok_or_else(|| ProcessError::SessionNotFound(session_id.to_string()))
+    }
+
+    /// The prompt of a session whose tool calls last touched the file at `path`
+    ///
+    /// Falls back to the session's most recent prompt.
+    pub async fn prompt_touching(&self, session_id: &str, path: &str) -> Option<String> {
+        let session_arc = self.session_arc(session_id).await.ok()?;
+        let session = session_arc.lock().await;
+        file_versions::prompt_touching(&session.history, &session.config.working_dir, path)
+    }
+
+    /// Keep what an edit applied for a session replaced and wrote (see `file_versions`)
+    pub async fn record_file_edit(
+        &self,
+        session_id: &str,
+        path: &str,
+        before: Option<&str>,
+        after: Option<&str>,
+        prompt_id: Option<&str>,
+    ) -> Result<(), ProcessError> {
+        let session_arc = self.session_arc(session_id).await?;
+        // Serializes index updates of the session
+        let _session = session_arc.lock().await;
+        FileVersionStore::for_session(&self.scratch, session_id)
+            .record_edit(path, before, after, prompt_id, unix_now())
+            .await
+            .map_err(|e| ProcessError::FileVersionsFailed(e.to_string()))
+    }
+
+    /// The versions of the file at `path` kept for a session, oldest first,
+    /// including its current content
+    pub async fn file_versions(
+        &self,
+        session_id: &str,
+        path: &str,
+    ) -> Result<Vec<FileVersion>, ProcessError> {
+        let session_arc = self.session_arc(session_id).await?;
+        let (current, modified_at) = file_versions::read_current(path, unix_now())
+            .await
+            .map_err(|e| ProcessError::FileVersionsFailed(e.to_string()))?;
+        let _session = session_arc.lock().await;
+        FileVersionStore::for_session(&self.scratch, session_id)
+            .versions(path, current.as_deref(), modified_at)
+            .await
+            .map_err(|e| ProcessError::FileVersionsFailed(e.to_string()))
+    }
+
+    /// The content of a kept version of the file at `path`
+    pub async fn file_version_content(
+        &self,
+        session_id: &str,
+        path: &str,
+        version_id: &str,
+    ) -> Result<String, ProcessError> {
+        let session_arc = self.session_arc(session_id).await?;
+        let _session = session_arc.lock().await;
+        FileVersionStore::for_session(&self.scratch, session_id)
+            .content(path, version_id)
+            .await
+            .map_err(|e| ProcessError::FileVersionsFailed(e.to_string()))?
+            .ok_or_else(|| ProcessError::FileVersionNotFound(version_id.to_string()))
+    }
+
+    /// Usage of all live and archived sessions, by day
+    pub async fn usage_summary(&self) -> UsageSummary {
+        let mut records = Vec::new();
+        for session_arc in self.sessions.read().await.values() {
+            records.extend(session_arc.lock().await.history.clone());
+        }
+        records.extend(self.archive.lock().await.histories().cloned());
+        usage::summarize(&records)
+    }
+
+    /// Send one prompt to several sessions at once
+    ///
+    /// Each target goes through `send_prompt` concurrently, so missing or busy
+    /// sessions fail individually without affecting the others. Once every
+    /// dispatched prompt has finished, a `broadcast-completed` event reports
+    /// the per-session outcomes and total cost. Duplicate targets are ignored.
+    pub async fn broadcast_prompt(
+        &self,
+        prompt: &str,
+        targets: Vec<(String, mpsc::Sender<StreamMessage>)>,
+    ) -> BroadcastDispatch {
+        let broadcast_id = uuid::Uuid::new_v4().to_string();
+        // Subscribe before dispatching so no completion can be missed
+        let events = self.events.subscribe();
+
+        let mut seen = Vec::new();
+        let targets: Vec<_> = targets
+            .into_iter()
+            .filter(|(session_id, _)| {
+                let new = !seen.contains(session_id);
+                seen.push(session_id.clone());
+                new
+            })
+            .collect();
+
+        let dispatches = targets
+            .into_iter()
+            .map(|(session_id, output_tx)| async move {
+                let result = self
+                    .send_prompt(&session_id, prompt, PromptOptions::default(), output_tx)
+                    .await;
+                (session_id, result)
+            });
+        let results: HashMap<String, Result<PromptRecord, ProcessError>> =
+            futures::future::join_all(dispatches)
+                .await
+                .into_iter()
+                .collect();
+
+        let pending: HashMap<String, String> = results
+            .iter()
+            .filter_map(|(session_id, result)| {
+                let record = result.as_ref().ok()?;
+                Some((record.prompt_id.clone(), session_id.clone()))
+            })
+            .collect();
+        tokio::spawn(await_broadcast(
+            broadcast_id.clone(),
+            pending,
+            events,
+            self.sessions.clone(),
+            self.events.clone(),
+        ));
+
+        BroadcastDispatch {
+            broadcast_id,
+            results,
+        }
+    }
+
+    /// Append a forwarded message to the session's transcript
+    ///
+    /// Returns the message's sequence number. Numbers continue across prompts,
+    /// so a frontend can resume from the last one it saw.
+    pub async fn record_message(
+        &self,
+        session_id: &str,
+        prompt_id: &str,
+        model: &str,
+        message: StreamMessage,
+    ) -> Result<u64, ProcessError> {
+        let session_arc = self
+            .sessions
+            .read()
+            .await
+            .get(session_id)
+            .cloned()
+            .ok_or_else(|| ProcessError::SessionNotFound(session_id.to_string()))?;
+
+        let mut session = session_arc.lock().await;
+        let sequence = session.transcript.last().map_or(1, |e| e.sequence + 1);
+        let dry_run = session
+            .prompt_record_mut(prompt_id)
+            .is_some_and(|record| record.dry_run);
+        session.transcript.push(TranscriptEntry {
+            sequence,
+            prompt_id: prompt_id.to_string(),
+            model: model.to_string(),
+            dry_run,
+            message,
         });
+        Ok(sequence)
+    }
 
-        Ok(())
+    /// Transcript entries with a sequence number greater than `since_sequence`, in order
+    pub async fn replay_messages(
+        &self,
+        session_id: &str,
+        since_sequence: u64,
+    ) -> Result<Vec<TranscriptEntry>, ProcessError> {
+        let sessions = self.sessions.read().await;
+        let session_arc = sessions
+            .get(session_id)
+            .ok_or_else(|| ProcessError::SessionNotFound(session_id.to_string()))?;
+
+        let session = session_arc.lock().await;
+        // Sequences are gapless from 1, so entries after `since` start at index `since`
+        let start = (since_sequence as usize).min(session.transcript.len());
+        Ok(session.transcript[start..].to_vec())
     }
 
     /// Interrupt the current Claude process (kills it)
@@ -304,9 +1887,21 @@ impl ProcessManager {
 
         if let Some(ref mut child) = session.active_process {
             log::info!("Interrupting Claude process for session {}", session_id);
-            let _ = child.kill().await;
+            #[cfg(windows)]
+            if let CliHandle::Child(process) = child {
+                if !win_process::interrupt(process, INTERRUPT_GRACE_PERIOD).await {
+                    log::debug!("Claude CLI ignored CTRL_BREAK, killing it");
+                }
+            }
+            child.kill().await;
             session.active_process = None;
+        }
+
+        // Also cancels a prompt that is still waiting on its pre-prompt hook
+        if session.info.status == SessionStatus::Thinking {
             session.info.status = SessionStatus::Idle;
+            session.info.active_prompt_id = None;
+            self.versions.changed(&mut session.info);
         }
 
         Ok(())
@@ -319,14 +1914,100 @@ impl ProcessManager {
         if let Some(session_arc) = sessions.remove(session_id) {
             let mut session = session_arc.lock().await;
             if let Some(ref mut child) = session.active_process {
-                let _ = child.kill().await;
+                child.kill().await;
             }
             session.info.status = SessionStatus::Terminated;
+            self.versions.removed(session_id);
         }
+        drop(sessions);
 
+        self.scratch.remove_session(session_id).await;
         Ok(())
     }
 
+    /// Merge `merge_id` into `keep_id`, for two sessions that share a CLI conversation
+    ///
+    /// Prompt history and transcripts are combined in the order the prompts
+    /// started (transcript sequences are renumbered, see
+    /// `SessionEvent::TranscriptReset`), prompt counts and costs are summed,
+    /// and the config of the more recently created session is kept. Tool
+    /// result blobs and file versions move to `keep_id`'s scratch dir. The
+    /// merged-away session is then terminated. Refused while either session
+    /// is thinking.
+    pub async fn merge_sessions(
+        &self,
+        keep_id: &str,
+        merge_id: &str,
+    ) -> Result<SessionInfo, ProcessError> {
+        if keep_id == merge_id {
+            return Err(ProcessError::MergeIntoSelf(keep_id.to_string()));
+        }
+
+        let mut sessions = self.sessions.write().await;
+        let keep_arc = sessions
+            .get(keep_id)
+            .cloned()
+            .ok_or_else(|| ProcessError::SessionNotFound(keep_id.to_string()))?;
+        let merge_arc = sessions
+            .get(merge_id)
+            .cloned()
+            .ok_or_else(|| ProcessError::SessionNotFound(merge_id.to_string()))?;
+
+        let mut keep = keep_arc.lock().await;
+        let mut merged = merge_arc.lock().await;
+        if keep.info.status == SessionStatus::Thinking
+            || merged.info.status == SessionStatus::Thinking
+        {
+            return Err(ProcessError::SessionBusy);
+        }
+
+        let session = &mut *keep;
+        session_merge::merge_prompts(
+            &mut session.history,
+            &mut session.transcript,
+            std::mem::take(&mut merged.history),
+            std::mem::take(&mut merged.transcript),
+        );
+
+        keep.info.prompt_count += merged.info.prompt_count;
+        keep.info.total_cost += merged.info.total_cost;
+        if keep.info.claude_session_id.is_none() {
+            keep.info.claude_session_id = merged.info.claude_session_id.clone();
+        }
+        if merged.info.created_at > keep.info.created_at {
+            keep.config = merged.config.clone();
+            keep.info.working_dir = keep.config.working_dir.clone();
+            keep.info.model = keep.config.model.clone();
+            keep.info.tool_presets = keep.config.tool_presets.clone();
+            keep.info.allowed_tools = keep.config.allowed_tools.clone();
+        }
+
+        merged.info.status = SessionStatus::Terminated;
+        drop(merged);
+        sessions.remove(merge_id);
+        self.versions.removed(merge_id);
+        self.versions.changed(&mut keep.info);
+        drop(sessions);
+        let info = keep.info.clone();
+        drop(keep);
+
+        if let Err(e) = session_merge::move_scratch(&self.scratch, merge_id, keep_id).await {
+            // What is left of the dir goes with the next startup sweep
+            log::warn!(
+                "Failed to move scratch files of {} into {}: {}",
+                merge_id,
+                keep_id,
+                e
+            );
+        }
+        let _ = self.events.send(SessionEvent::TranscriptReset {
+            session_id: keep_id.to_string(),
+            merged_session_id: merge_id.to_string(),
+        });
+        log::info!("Merged session {} into {}", merge_id, keep_id);
+        Ok(info)
+    }
+
     /// Check if a session is alive
     pub async fn is_alive(&self, session_id: &str) -> bool {
         let sessions = self.sessions.read().await;
@@ -343,26 +2024,77 @@ impl ProcessManager {
         self.sessions.read().await.len()
     }
 
-    /// Get information about all active sessions
-    pub async fn get_sessions(&self) -> Vec<SessionInfo> {
+    /// Get information about all active sessions, optionally followed by archived ones
+    pub async fn get_sessions(&self, include_archived: bool) -> Vec<SessionInfo> {
         let sessions = self.sessions.read().await;
         let mut infos = Vec::new();
         for session_arc in sessions.values() {
             let session = session_arc.lock().await;
             infos.push(session.info.clone());
         }
+        drop(sessions);
+
+        if include_archived {
+            infos.extend(self.archive.lock().await.infos());
+        }
         infos
     }
 
-    /// Get information about a specific session
+    /// Changes to the session list (live and archived sessions) after `since`
+    ///
+    /// Returns every session, flagged `full`, if `since` is too old to diff
+    /// from. Used to resync after missing `sessions-changed` events.
+    pub async fn sessions_since(&self, since: u64) -> SessionsDiff {
+        let version = self.versions.version();
+        let infos = self.get_sessions(true).await;
+        self.versions.diff(since, version, infos)
+    }
+
+    /// Get information about a specific session (live or archived)
     pub async fn get_session(&self, session_id: &str) -> Option<SessionInfo> {
         let sessions = self.sessions.read().await;
         if let Some(session_arc) = sessions.get(session_id) {
             let session = session_arc.lock().await;
             Some(session.info.clone())
         } else {
-            None
+            drop(sessions);
+            self.archive
+                .lock()
+                .await
+                .get(session_id)
+                .map(|archived| archived.info.clone())
+        }
+    }
+
+    /// Seconds since the running prompt's CLI last wrote output or progress
+    ///
+    /// None when the session has no prompt running. The frontend compares this
+    /// against its stall threshold.
+    pub async fn seconds_since_last_output(
+        &self,
+        session_id: &str,
+    ) -> Result<Option<u64>, ProcessError> {
+        let sessions = self.sessions.read().await;
+        let session_arc = sessions
+            .get(session_id)
+            .ok_or_else(|| ProcessError::SessionNotFound(session_id.to_string()))?;
+        let session = session_arc.lock().await;
+        if session.info.status != SessionStatus::Thinking {
+            return Ok(None);
         }
+        Ok(session
+            .last_output
+            .as_ref()
+            .map(|last_output| last_output.elapsed().as_secs()))
+    }
+
+    /// Whether file writes for a session must be refused
+    ///
+    /// Unknown sessions are not read-only.
+    pub async fn is_read_only(&self, session_id: &str) -> bool {
+        self.get_session(session_id)
+            .await
+            .is_some_and(|info| info.read_only)
     }
 
     /// Update session status
@@ -378,39 +2110,979 @@ impl ProcessManager {
 
         let mut session = session_arc.lock().await;
         session.info.status = status;
+        self.versions.changed(&mut session.info);
 
         Ok(())
     }
 
-    /// Terminate all sessions
-    pub async fn terminate_all(&self) {
-        let mut sessions = self.sessions.write().await;
-        for (_, session_arc) in sessions.drain() {
-            let mut session = session_arc.lock().await;
-            if let Some(ref mut child) = session.active_process {
-                let _ = child.kill().await;
+    /// Save project layouts whenever `terminate_all` runs (e.g. on exit)
+    pub fn set_autosave_layouts(&mut self, enabled: bool) {
+        self.autosave_layouts = enabled;
+    }
+
+    /// Snapshot the live sessions in `working_dir` as the project's saved layout
+    ///
+    /// Sessions listed in `order` come first, in that order, the others follow
+    /// in creation order. Replaces any layout saved before.
+    pub async fn save_project_layout(
+        &self,
+        working_dir: &Path,
+        order: &[String],
+    ) -> Result<ProjectLayout, ProcessError> {
+        let mut entries = Vec::new();
+        for session_arc in self.sessions.read().await.values() {
+            let session = session_arc.lock().await;
+            if layouts::same_dir(&session.config.working_dir, working_dir) {
+                let position = order.iter().position(|id| *id == session.info.id);
+                let key = (position.unwrap_or(usize::MAX), session.info.created_at);
+                let entry = LayoutSession {
+                    config: session.config.clone(),
+                    claude_session_id: session.info.claude_session_id.clone(),
+                };
+                entries.push((key, session.info.id.clone(), entry));
             }
         }
-    }
-}
+        entries.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
 
-impl Default for ProcessManager {
-    fn default() -> Self {
-        Self::new()
+        let layout = ProjectLayout {
+            working_dir: working_dir.to_path_buf(),
+            saved_at: unix_now(),
+            sessions: entries.into_iter().map(|(_, _, entry)| entry).collect(),
+        };
+        self.layouts
+            .save(&layout)
+            .await
+            .map_err(|e| ProcessError::LayoutFailed(e.to_string()))?;
+        Ok(layout)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::TempDir;
+    /// Recreate the sessions of `working_dir`'s saved layout, attached to their conversations
+    ///
+    /// Returns the new session ids in the saved order. Sessions whose
+    /// conversation no longer exists under `claude_dir` (the CLI's `~/.claude`;
+    /// None skips the check), or that fail validation, are skipped with a
+    /// warning.
+    pub async fn restore_project_layout(
+        &self,
+        working_dir: &Path,
+        claude_dir: Option<&Path>,
+    ) -> Result<RestoredLayout, ProcessError> {
+        let layout = self
+            .layouts
+            .load(working_dir)
+            .await
+            .ok_or_else(|| ProcessError::LayoutNotFound(working_dir.to_path_buf()))?;
 
-    fn create_test_config() -> (SessionConfig, TempDir) {
+        let mut restored = RestoredLayout::default();
+        for (index, entry) in layout.sessions.into_iter().enumerate() {
+            let warning = |message: String| LayoutWarning {
+                index,
+                name: entry.config.name.clone(),
+                message,
+            };
+            if let (Some(claude_id), Some(claude_dir)) = (&entry.claude_session_id, claude_dir) {
+                if !layouts::conversation_exists(claude_dir, claude_id) {
+                    let message = format!("Conversation {} no longer exists", claude_id);
+                    restored.warnings.push(warning(message));
+                    continue;
+                }
+            }
+
+            let mut config = entry.config.clone();
+            config.resume_session_id = entry.claude_session_id.clone();
+            match self.create_session(config).await {
+                Ok(session_id) => restored.session_ids.push(session_id),
+                Err(e) => restored.warnings.push(warning(e.to_string())),
+            }
+        }
+        Ok(restored)
+    }
+
+    /// Terminate all sessions
+    ///
+    /// With layout autosave on, each project's layout is saved first.
+    pub async fn terminate_all(&self) {
+        if self.autosave_layouts {
+            let mut dirs: Vec<PathBuf> = Vec::new();
+            for session_arc in self.sessions.read().await.values() {
+                let dir = session_arc.lock().await.config.working_dir.clone();
+                if !dirs.iter().any(|known| layouts::same_dir(known, &dir)) {
+                    dirs.push(dir);
+                }
+            }
+            for dir in dirs {
+                if let Err(e) = self.save_project_layout(&dir, &[]).await {
+                    log::warn!("Failed to save layout of {}: {}", dir.display(), e);
+                }
+            }
+        }
+
+        let mut sessions = self.sessions.write().await;
+        for (session_id, session_arc) in sessions.drain() {
+            let mut session = session_arc.lock().await;
+            if let Some(ref mut child) = session.active_process {
+                child.kill().await;
+            }
+            self.versions.removed(&session_id);
+        }
+        drop(sessions);
+
+        // The killed prompts' tasks may not get to remove their entries before exit
+        self.journal.clear().await;
+        // Scratch dirs hold the file versions of archived sessions; the
+        // startup sweep removes the ones no session needs any more
+    }
+}
+
+/// Output stream of a CLI run
+pub type CliOutput = Box<dyn AsyncRead + Send + Unpin>;
+
+/// Starts the CLI for each prompt
+///
+/// [`CliBackend`] spawns the real binary; `fake_backend::FakeBackend`
+/// replays canned transcripts instead. Everything downstream of the output
+/// streams (parsing, status, costs, session ids) is the same for both.
+pub trait ClaudeBackend: Send + Sync {
+    /// Start a run with the CLI arguments `args` in `working_dir`
+    fn spawn(
+        &self,
+        args: &[OsString],
+        working_dir: &Path,
+        stdin: Stdio,
+    ) -> std::io::Result<CliProcess>;
+
+    /// The program shown in copyable command lines and probed by `check_cli`
+    fn program(&self) -> &Path;
+
+    /// Version for `check_cli` to report without running `program`
+    fn fixed_version(&self) -> Option<String> {
+        None
+    }
+}
+
+/// A started CLI run
+pub struct CliProcess {
+    pub handle: CliHandle,
+    pub stdout: CliOutput,
+    pub stderr: CliOutput,
+}
+
+/// Handle to stop or reap a CLI run
+pub enum CliHandle {
+    Child(Child),
+    /// A run replayed in-process, yielding its exit code
+    Simulated(tokio::task::JoinHandle<i32>),
+}
+
+impl CliHandle {
+    pub fn id(&self) -> Option<u32> {
+        match self {
+            CliHandle::Child(child) => child.id(),
+            CliHandle::Simulated(_) => None,
+        }
+    }
+
+    /// Kill the run; on Windows the CLI's whole process tree (see `win_process`)
+    pub async fn kill(&mut self) {
+        match self {
+            CliHandle::Child(child) => {
+                #[cfg(windows)]
+                if let Some(pid) = child.id() {
+                    if let Err(e) = win_process::kill_tree(pid) {
+                        log::debug!("Failed to kill process tree of {}: {}", pid, e);
+                    }
+                }
+                let _ = child.kill().await;
+            }
+            CliHandle::Simulated(task) => task.abort(),
+        }
+    }
+
+    /// Wait for the run to end; its exit code is None if it was killed
+    pub async fn wait(&mut self) -> std::io::Result<Option<i32>> {
+        match self {
+            CliHandle::Child(child) => Ok(child.wait().await?.code()),
+            CliHandle::Simulated(task) => match task.await {
+                Ok(code) => Ok(Some(code)),
+                Err(e) if e.is_cancelled() => Ok(None),
+                Err(e) => Err(std::io::Error::other(e)),
+            },
+        }
+    }
+}
+
+/// Runs the installed `claude` binary (or another binary with its interface)
+#[derive(Debug, Clone)]
+pub struct CliBackend {
+    cli_path: PathBuf,
+}
+
+impl CliBackend {
+    pub fn new(cli_path: impl Into<PathBuf>) -> Self {
+        Self {
+            cli_path: cli_path.into(),
+        }
+    }
+}
+
+impl ClaudeBackend for CliBackend {
+    fn spawn(
+        &self,
+        args: &[OsString],
+        working_dir: &Path,
+        stdin: Stdio,
+    ) -> std::io::Result<CliProcess> {
+        let mut command = Command::new(&self.cli_path);
+        command
+            .args(args)
+            .current_dir(working_dir)
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        #[cfg(windows)]
+        command.creation_flags(win_process::SPAWN_FLAGS);
+        let mut child = command.spawn()?;
+        let stdout = child.stdout.take().expect("Failed to get stdout");
+        let stderr = child.stderr.take().expect("Failed to get stderr");
+        Ok(CliProcess {
+            handle: CliHandle::Child(child),
+            stdout: Box::new(stdout),
+            stderr: Box::new(stderr),
+        })
+    }
+
+    fn program(&self) -> &Path {
+        &self.cli_path
+    }
+}
+
+impl Default for ProcessManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// File path modified by a tool call, if the tool edits files
+fn touched_file(tool_name: &str, input: &Value) -> Option<String> {
+    match tool_name {
+        "Edit" | "MultiEdit" | "Write" => input.get("file_path"),
+        "NotebookEdit" => input.get("notebook_path"),
+        _ => None,
+    }
+    .and_then(Value::as_str)
+    .map(str::to_string)
+}
+
+/// Tool calls in a message (top-level or assistant content blocks), as (name, input)
+fn tool_calls(msg: &StreamMessage) -> Vec<(&str, &Value)> {
+    match msg {
+        StreamMessage::ToolUse { name, input, .. } => vec![(name.as_str(), input)],
+        StreamMessage::Assistant { content, .. } => content
+            .as_array()
+            .map(|blocks| {
+                blocks
+                    .iter()
+                    .filter(|block| block.get("type").and_then(Value::as_str) == Some("tool_use"))
+                    .filter_map(|block| {
+                        let name = block.get("name").and_then(Value::as_str)?;
+                        Some((name, block.get("input").unwrap_or(&Value::Null)))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+/// Files modified by tool calls in a message
+fn files_touched_by(msg: &StreamMessage) -> Vec<String> {
+    tool_calls(msg)
+        .into_iter()
+        .filter_map(|(name, input)| touched_file(name, input))
+        .collect()
+}
+
+/// Wait for every prompt of a broadcast to finish, then emit `broadcast-completed`
+///
+/// `pending` maps prompt ids to their session ids. Completions normally arrive
+/// as `prompt-completed` events; the sessions are also re-checked periodically
+/// in case events were dropped or a session was terminated mid-prompt.
+async fn await_broadcast(
+    broadcast_id: String,
+    mut pending: HashMap<String, String>,
+    mut events: broadcast::Receiver<SessionEvent>,
+    sessions: Arc<SessionMap>,
+    events_tx: broadcast::Sender<SessionEvent>,
+) {
+    let mut outcomes = Vec::new();
+    let mut reconcile = tokio::time::interval(BROADCAST_RECONCILE_INTERVAL);
+
+    while !pending.is_empty() {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(SessionEvent::PromptCompleted { session_id, prompt }) => {
+                    if pending.remove(&prompt.prompt_id).is_some() {
+                        outcomes.push(BroadcastOutcome::from_record(&session_id, &prompt));
+                    }
+                }
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("Broadcast {} missed {} session events", broadcast_id, skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = reconcile.tick() => {
+                let sessions = sessions.read().await;
+                let mut finished = Vec::new();
+                for (prompt_id, session_id) in &pending {
+                    let outcome = match sessions.get(session_id) {
+                        Some(session_arc) => {
+                            let mut session = session_arc.lock().await;
+                            session
+                                .prompt_record_mut(prompt_id)
+                                .filter(|record| record.completed_at.is_some())
+                                .map(|record| BroadcastOutcome::from_record(session_id, record))
+                        }
+                        None => Some(BroadcastOutcome {
+                            session_id: session_id.clone(),
+                            prompt_id: prompt_id.clone(),
+                            completion_reason: None,
+                            cost: None,
+                        }),
+                    };
+                    if let Some(outcome) = outcome {
+                        finished.push(prompt_id.clone());
+                        outcomes.push(outcome);
+                    }
+                }
+                for prompt_id in finished {
+                    pending.remove(&prompt_id);
+                }
+            }
+        }
+    }
+
+    outcomes.sort_by(|a, b| a.session_id.cmp(&b.session_id));
+    let total_cost = outcomes.iter().filter_map(|o| o.cost).sum();
+    let _ = events_tx.send(SessionEvent::BroadcastCompleted {
+        broadcast_id,
+        total_cost,
+        outcomes,
+    });
+}
+
+/// When a running prompt's CLI last showed it was working
+///
+/// Touched by stdout messages and by progress lines on stderr, so a long tool
+/// run that only reports on stderr does not look stalled.
+#[derive(Debug, Clone)]
+struct LastOutput(Arc<std::sync::Mutex<Instant>>);
+
+impl LastOutput {
+    fn new() -> Self {
+        Self(Arc::new(std::sync::Mutex::new(Instant::now())))
+    }
+
+    fn touch(&self) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).elapsed()
+    }
+}
+
+/// Background task that reads one prompt's CLI output and does its bookkeeping
+struct PromptTask {
+    session_id: String,
+    prompt_id: String,
+    model: String,
+    working_dir: PathBuf,
+    hooks: HooksConfig,
+    input: PromptInput,
+    meta_command: Option<MetaCommand>,
+    /// Leave the session's resume id and context tracking alone
+    dry_run: bool,
+    clock: PromptClock,
+    last_output: LastOutput,
+    /// Git state of the working directory, read concurrently with the spawn
+    git_state: Option<tokio::task::JoinHandle<GitState>>,
+    /// Mask secrets in Bash tool inputs of emitted messages
+    redact_tool_inputs: bool,
+    /// Larger tool results are truncated in emitted messages
+    max_tool_result_bytes: usize,
+    /// Where the full content of truncated tool results is kept
+    scratch: ScratchSpace,
+    connectivity_endpoint: String,
+    mcp_registry: McpRegistry,
+    journal: PromptJournal,
+    /// This prompt's journal entry, removed when the process exits
+    journal_entry: JournalEntry,
+    sessions: Arc<SessionMap>,
+    events: broadcast::Sender<SessionEvent>,
+    versions: SessionVersions,
+}
+
+/// Write a prompt's git state into its snapshot once it has been read
+async fn record_git_state(
+    sessions: Arc<SessionMap>,
+    session_id: String,
+    prompt_id: String,
+    git_state: tokio::task::JoinHandle<GitState>,
+) {
+    let Ok(git) = git_state.await else {
+        return;
+    };
+    if git.timed_out {
+        log::debug!(
+            "Git state of session {} not read in time; snapshot is partial",
+            session_id
+        );
+    }
+    let Some(session_arc) = sessions.read().await.get(&session_id).cloned() else {
+        return;
+    };
+    let mut session = session_arc.lock().await;
+    if let Some(snapshot) = session
+        .prompt_record_mut(&prompt_id)
+        .and_then(|record| record.snapshot.as_mut())
+    {
+        snapshot.apply_git(git);
+    }
+}
+
+/// Maximum bytes of CLI stderr kept for error reporting (the tail is kept)
+const MAX_STDERR_TAIL: usize = 16 * 1024;
+
+/// How long to wait for the CLI to exit after it closes stdout
+const EXIT_WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Append a line to the stderr tail, dropping its start beyond `MAX_STDERR_TAIL`
+fn push_stderr_line(tail: &mut String, line: &str) {
+    tail.push_str(line);
+    tail.push('\n');
+    if tail.len() > MAX_STDERR_TAIL {
+        let mut cut = tail.len() - MAX_STDERR_TAIL;
+        while !tail.is_char_boundary(cut) {
+            cut += 1;
+        }
+        tail.drain(..cut);
+    }
+}
+
+/// Drain the CLI's stderr so it never blocks on a full pipe
+///
+/// Progress lines (see `cli_activity`) are passed to `on_activity`; the tail
+/// of the other lines is returned for error reporting.
+async fn read_stderr(
+    mut stderr: CliOutput,
+    mut on_activity: impl FnMut(SessionActivity),
+) -> String {
+    let mut tail = String::new();
+    let mut lines = ProgressLines::default();
+    let mut buf = [0u8; 4096];
+    let mut handle = |line: String, tail: &mut String| match cli_activity::classify(&line) {
+        Some(activity) => on_activity(activity),
+        None => push_stderr_line(tail, &line),
+    };
+    loop {
+        match stderr.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                for line in lines.push(&buf[..n]) {
+                    handle(line, &mut tail);
+                }
+            }
+        }
+    }
+    if let Some(line) = lines.finish() {
+        handle(line, &mut tail);
+    }
+    tail
+}
+
+impl PromptTask {
+    async fn session(&self) -> Option<Arc<Mutex<Session>>> {
+        self.sessions.read().await.get(&self.session_id).cloned()
+    }
+
+    /// The message as it should be emitted: tool input secrets masked if
+    /// enabled, oversized tool results truncated
+    ///
+    /// Bookkeeping in this task uses the original message; only the emitted copy
+    /// is changed, and nothing sent to the CLI is affected. The full content of
+    /// truncated results is stored in the session's scratch directory.
+    async fn for_webview(&self, mut msg: StreamMessage) -> StreamMessage {
+        if self.redact_tool_inputs {
+            redact::redact_tool_inputs(&mut msg);
+        }
+        for blob in tool_output::truncate_tool_results(&mut msg, self.max_tool_result_bytes) {
+            if let Err(e) = tool_output::store_blob(&self.scratch, &self.session_id, &blob).await {
+                log::warn!(
+                    "Failed to store tool result {} of session {}: {}",
+                    blob.blob_id,
+                    self.session_id,
+                    e
+                );
+            }
+        }
+        msg
+    }
+
+    async fn run(
+        mut self,
+        stdout: CliOutput,
+        stderr: CliOutput,
+        output_tx: mpsc::Sender<StreamMessage>,
+    ) {
+        let started = Instant::now();
+        let on_activity = {
+            let events = self.events.clone();
+            let session_id = self.session_id.clone();
+            let prompt_id = self.prompt_id.clone();
+            let last_output = self.last_output.clone();
+            move |activity| {
+                last_output.touch();
+                let _ = events.send(SessionEvent::SessionActivity {
+                    session_id: session_id.clone(),
+                    prompt_id: prompt_id.clone(),
+                    activity,
+                });
+            }
+        };
+        let stderr_task = tokio::spawn(read_stderr(stderr, on_activity));
+        let git_task = self.git_state.take().map(|git_state| {
+            tokio::spawn(record_git_state(
+                self.sessions.clone(),
+                self.session_id.clone(),
+                self.prompt_id.clone(),
+                git_state,
+            ))
+        });
+        let mut reader = BufReader::new(stdout);
+        let mut parser = StreamJsonParser::new();
+        let mut line = String::new();
+        let mut files_touched: Vec<String> = Vec::new();
+        let mut error_messages: Vec<String> = Vec::new();
+        let mut result_subtype: Option<String> = None;
+        let mut completion_reason: Option<CompletionReason> = None;
+        let mut plan = self.dry_run.then(DryRunPlan::default);
+
+        loop {
+            line.clear();
+            match reader.read_line(&mut line).await {
+                Ok(0) => {
+                    // EOF - flush any remaining content
+                    if let Some(msg) = parser.flush() {
+                        let _ = output_tx.send(self.for_webview(msg).await).await;
+                    }
+                    break;
+                }
+                Ok(_) => {
+                    self.clock.mark_first_byte();
+                    self.last_output.touch();
+                    for msg in parser.parse_chunk(line.as_bytes()) {
+                        self.handle_message(&msg).await;
+
+                        match msg {
+                            StreamMessage::Error { ref error, .. } => {
+                                error_messages.push(error.message.clone());
+                            }
+                            StreamMessage::Result {
+                                ref subtype,
+                                ref result,
+                                is_error,
+                                ..
+                            } => {
+                                let reason =
+                                    CompletionReason::from_result(subtype.as_deref(), is_error);
+                                if is_error == Some(true) {
+                                    error_messages.extend(result.clone());
+                                }
+                                result_subtype = subtype.clone();
+                                completion_reason = Some(reason);
+                                self.clock.mark_result();
+                            }
+                            _ => {}
+                        }
+
+                        for path in files_touched_by(&msg) {
+                            if !files_touched.contains(&path) {
+                                files_touched.push(path);
+                            }
+                        }
+                        if let Some(ref mut plan) = plan {
+                            for (name, input) in tool_calls(&msg) {
+                                plan.observe(name, input);
+                            }
+                        }
+
+                        if output_tx.send(self.for_webview(msg).await).await.is_err() {
+                            log::warn!("Output channel closed for session {}", self.session_id);
+                            break;
+                        }
+                    }
+                }
+                Err(e) => {
+                    log::error!("Error reading stdout: {}", e);
+                    break;
+                }
+            }
+        }
+
+        // The process has exited (stdout closed), so its prompt temp file can go
+        self.input.cleanup().await;
+
+        let interrupted = !self.is_active_prompt().await;
+        let exit_code = self.wait_for_exit().await;
+        self.journal.finish(&self.prompt_id).await;
+        let timing = self.clock.timing(Instant::now());
+        let stderr_tail = stderr_task.await.unwrap_or_default();
+        if let Some(git_task) = git_task {
+            let _ = git_task.await;
+        }
+        if exit_code.is_some_and(|code| code != 0) || !error_messages.is_empty() {
+            self.report_failure(exit_code, &error_messages, &stderr_tail)
+                .await;
+        }
+
+        let cost = match self.session().await {
+            Some(session_arc) => {
+                let mut session = session_arc.lock().await;
+                session
+                    .prompt_record_mut(&self.prompt_id)
+                    .and_then(|record| {
+                        record.files_touched = files_touched.clone();
+                        record.cost
+                    })
+            }
+            None => None,
+        };
+
+        // Run the post-prompt hook before the session becomes Idle again
+        let post_hook = match self.hooks.post_prompt {
+            Some(ref command) => {
+                let summary = PostPromptSummary {
+                    session_id: self.session_id.clone(),
+                    prompt_id: self.prompt_id.clone(),
+                    model: self.model.clone(),
+                    cost_usd: cost.map(Usd::as_dollars),
+                    duration_ms: started.elapsed().as_millis() as u64,
+                    files_touched,
+                };
+                let input = serde_json::to_vec(&summary).unwrap_or_default();
+                let output = run_hook(
+                    HookKind::PostPrompt,
+                    command,
+                    &self.working_dir,
+                    &input,
+                    self.hooks.timeout(),
+                )
+                .await;
+                if !output.succeeded() {
+                    log::warn!(
+                        "Post-prompt hook failed for session {}: {}",
+                        self.session_id,
+                        output.failure_reason()
+                    );
+                }
+                Some(output)
+            }
+            None => None,
+        };
+
+        // Update session status when process completes, unless a newer
+        // prompt has already taken over (e.g. after an interrupt)
+        if let Some(session_arc) = self.session().await {
+            let mut session = session_arc.lock().await;
+            let mut info_changed = false;
+            if session.info.active_prompt_id.as_deref() == Some(self.prompt_id.as_str()) {
+                session.info.status = SessionStatus::Idle;
+                session.info.active_prompt_id = None;
+                session.active_process = None;
+                session.last_output = None;
+                info_changed = true;
+            }
+
+            if self.meta_command == Some(MetaCommand::Clear) {
+                log::info!("Cleared conversation for session {}", self.session_id);
+                session.info.claude_session_id = None;
+                if session.context.reset() {
+                    let _ = self.events.send(session.context_pressure_event(None));
+                }
+                info_changed = true;
+            }
+            if info_changed {
+                self.versions.changed(&mut session.info);
+            }
+
+            if let Some(record) = session.prompt_record_mut(&self.prompt_id) {
+                record.hooks.extend(post_hook);
+                record.completed_at = Some(unix_now());
+                record.result_subtype = result_subtype;
+                record.timing = Some(timing);
+                record.plan = plan;
+                record.completion_reason = Some(match completion_reason {
+                    Some(reason) => reason,
+                    None if interrupted => CompletionReason::Interrupted,
+                    None => CompletionReason::Error,
+                });
+                let _ = self.events.send(SessionEvent::PromptCompleted {
+                    session_id: self.session_id.clone(),
+                    prompt: Box::new(record.clone()),
+                });
+            }
+        }
+    }
+
+    /// Whether this task's prompt is still the session's active prompt
+    async fn is_active_prompt(&self) -> bool {
+        match self.session().await {
+            Some(session_arc) => {
+                let session = session_arc.lock().await;
+                session.info.active_prompt_id.as_deref() == Some(self.prompt_id.as_str())
+            }
+            None => false,
+        }
+    }
+
+    /// Reap the CLI process and return its exit code
+    ///
+    /// Returns None when the prompt was interrupted (the process was already
+    /// killed and taken by `interrupt`) or the process could not be waited on.
+    async fn wait_for_exit(&self) -> Option<i32> {
+        let session_arc = self.session().await?;
+        let mut child = {
+            let mut session = session_arc.lock().await;
+            if session.info.active_prompt_id.as_deref() != Some(self.prompt_id.as_str()) {
+                return None;
+            }
+            session.active_process.take()?
+        };
+        #[cfg(windows)]
+        let pid = child.id();
+
+        let exit = tokio::time::timeout(EXIT_WAIT_TIMEOUT, child.wait()).await;
+        #[cfg(windows)]
+        if let (Ok(_), Some(pid)) = (&exit, pid) {
+            win_process::untrack(pid);
+        }
+        match exit {
+            Ok(Ok(code)) => code,
+            Ok(Err(e)) => {
+                log::warn!("Failed to wait for Claude CLI: {}", e);
+                None
+            }
+            Err(_) => {
+                log::warn!(
+                    "Claude CLI for session {} did not exit after closing stdout",
+                    self.session_id
+                );
+                child.kill().await;
+                None
+            }
+        }
+    }
+
+    /// Classify a failed prompt and emit a session-error event
+    ///
+    /// Network failures trigger a connectivity probe whose result is attached.
+    async fn report_failure(
+        &self,
+        exit_code: Option<i32>,
+        error_messages: &[String],
+        stderr_tail: &str,
+    ) {
+        let mut details: Vec<&str> = error_messages.iter().map(String::as_str).collect();
+        if !stderr_tail.trim().is_empty() {
+            details.push(stderr_tail.trim());
+        }
+        let message = match (details.is_empty(), exit_code) {
+            (false, _) => details.join("\n"),
+            (true, Some(code)) => format!("Claude CLI exited with status {}", code),
+            (true, None) => "Claude CLI failed".to_string(),
+        };
+
+        let error_class = classify_error(&message);
+        let connectivity = match error_class {
+            ErrorClass::Network => Some(connectivity::probe(&self.connectivity_endpoint).await),
+            _ => None,
+        };
+        log::warn!(
+            "Prompt {} in session {} failed ({:?}): {}",
+            self.prompt_id,
+            self.session_id,
+            error_class,
+            message
+        );
+
+        let _ = self.events.send(SessionEvent::SessionError {
+            session_id: self.session_id.clone(),
+            prompt_id: self.prompt_id.clone(),
+            message,
+            error_class,
+            exit_code,
+            connectivity,
+        });
+    }
+
+    /// Store the MCP status snapshot, emitting an event when it changed
+    ///
+    /// The first snapshot is only reported if the session uses MCP servers.
+    /// Returns whether the session info changed.
+    async fn update_mcp_status(&self, session: &mut Session, servers: &[McpServerStatus]) -> bool {
+        let previous = session.info.mcp_servers.replace(servers.to_vec());
+        let changed = match previous {
+            Some(ref previous) => previous.as_slice() != servers,
+            None => !servers.is_empty(),
+        };
+        if !changed {
+            return previous.is_none();
+        }
+
+        let mut failed = Vec::new();
+        for server in servers.iter().filter(|s| s.is_failed()) {
+            failed.push(McpServerFailure {
+                name: server.name.clone(),
+                status: server.status.clone(),
+                restart_action_id: self.mcp_registry.restart_action(&server.name).await,
+            });
+        }
+        let _ = self.events.send(SessionEvent::McpStatus {
+            session_id: self.session_id.clone(),
+            servers: servers.to_vec(),
+            failed,
+        });
+        true
+    }
+
+    /// Track context pressure signals, emitting an event when the pressure changes
+    async fn update_context_pressure(&self, signal: ContextSignal) {
+        let Some(session_arc) = self.session().await else {
+            return;
+        };
+        let mut session = session_arc.lock().await;
+        let prompt_count = session.info.prompt_count;
+        if !session.context.apply(&signal, prompt_count) {
+            // Keep the latest token count visible without a pressure event
+            let context_tokens = session.context.context_tokens();
+            if session.info.context_tokens != context_tokens {
+                session.info.context_tokens = context_tokens;
+                self.versions.changed(&mut session.info);
+            }
+            return;
+        }
+        let compaction = match signal {
+            ContextSignal::Compacted(compaction) => {
+                log::info!(
+                    "Claude CLI compacted the conversation of session {} ({:?} tokens before)",
+                    self.session_id,
+                    compaction.pre_tokens
+                );
+                Some(compaction)
+            }
+            ContextSignal::Usage(_) | ContextSignal::LimitReached => None,
+        };
+        let _ = self.events.send(session.context_pressure_event(compaction));
+        self.versions.changed(&mut session.info);
+    }
+
+    /// Update session bookkeeping from a parsed message
+    ///
+    /// A dry run's messages describe a forked conversation, so they neither
+    /// move the session's context pressure nor set its Claude session ID.
+    async fn handle_message(&self, msg: &StreamMessage) {
+        if let Some(signal) = context::context_signal(msg).filter(|_| !self.dry_run) {
+            self.update_context_pressure(signal).await;
+        }
+        match msg {
+            // Extract claude_session_id from system message
+            StreamMessage::System {
+                session_id: claude_id,
+                mcp_servers,
+                ..
+            } => {
+                let mut captured = false;
+                if let Some(session_arc) = self.session().await {
+                    let mut session = session_arc.lock().await;
+                    if let Some(claude_id) = claude_id.as_ref().filter(|_| !self.dry_run) {
+                        if session.info.claude_session_id.is_none() {
+                            session.info.claude_session_id = Some(claude_id.clone());
+                            captured = true;
+                            log::info!(
+                                "Captured Claude session ID: {} for app session {}",
+                                claude_id,
+                                self.session_id
+                            );
+                        }
+                    }
+                    if let Some(record) = session.prompt_record_mut(&self.prompt_id) {
+                        record
+                            .snapshot
+                            .get_or_insert_with(|| EnvironmentSnapshot::new(None, &self.model))
+                            .apply_init(msg);
+                    }
+                    let mcp_changed = match mcp_servers {
+                        Some(servers) => self.update_mcp_status(&mut session, servers).await,
+                        None => false,
+                    };
+                    if captured || mcp_changed {
+                        self.versions.changed(&mut session.info);
+                    }
+                }
+                // Make the session resumable after a crash during its first prompt
+                if captured {
+                    let entry = JournalEntry {
+                        claude_session_id: claude_id.clone(),
+                        ..self.journal_entry.clone()
+                    };
+                    self.journal.record(&entry).await;
+                    if let Some(claude_id) = claude_id {
+                        report_duplicate_claude_session(
+                            &self.sessions,
+                            &self.events,
+                            &self.session_id,
+                            claude_id,
+                        )
+                        .await;
+                    }
+                }
+            }
+            // Extract cost from result message
+            StreamMessage::Result {
+                cost_usd: Some(cost),
+                ..
+            } => {
+                if let Some(session_arc) = self.session().await {
+                    let mut session = session_arc.lock().await;
+                    // Summed as micro-dollars so totals do not drift
+                    let cost = Usd::from_dollars(*cost);
+                    session.info.total_cost += cost;
+                    self.versions.changed(&mut session.info);
+                    if let Some(record) = session.prompt_record_mut(&self.prompt_id) {
+                        *record.cost.get_or_insert_default() += cost;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::test_support;
+    #[cfg(unix)]
+    use crate::services::test_support::write_fake_cli;
+    use tempfile::TempDir;
+
+    fn create_test_config() -> (SessionConfig, TempDir) {
         let temp_dir = TempDir::new().unwrap();
         let config = SessionConfig {
             working_dir: temp_dir.path().to_path_buf(),
             model: "sonnet".to_string(),
-            allowed_tools: vec![],
+            ..Default::default()
         };
         (config, temp_dir)
     }
@@ -440,7 +3112,7 @@ mod tests {
         let config = SessionConfig {
             working_dir: PathBuf::from("/nonexistent/path/that/does/not/exist"),
             model: "sonnet".to_string(),
-            allowed_tools: vec![],
+            ..Default::default()
         };
 
         let result = manager.create_session(config).await;
@@ -473,7 +3145,7 @@ mod tests {
     #[tokio::test]
     async fn test_get_sessions_empty() {
         let manager = ProcessManager::new();
-        let sessions = manager.get_sessions().await;
+        let sessions = manager.get_sessions(true).await;
         assert!(sessions.is_empty());
     }
 
@@ -499,7 +3171,7 @@ mod tests {
         assert_eq!(info.status, SessionStatus::Idle);
         assert!(info.claude_session_id.is_none()); // No Claude session until first prompt
         assert_eq!(info.prompt_count, 0);
-        assert_eq!(info.total_cost_usd, 0.0);
+        assert_eq!(info.total_cost, Usd::ZERO);
     }
 
     #[tokio::test]
@@ -513,4 +3185,2154 @@ mod tests {
         manager.terminate(&session_id).await.unwrap();
         assert_eq!(manager.active_count().await, 0);
     }
+
+    #[tokio::test]
+    async fn test_create_session_rejects_unknown_model() {
+        let manager = ProcessManager::new();
+        let (mut config, _temp_dir) = create_test_config();
+        config.model = "sonet".to_string();
+
+        let result = manager.create_session(config).await;
+        assert!(matches!(result, Err(ProcessError::UnknownModel(m)) if m == "sonet"));
+        assert_eq!(manager.active_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_create_session_allow_unknown_model() {
+        let manager = ProcessManager::new();
+        let (mut config, _temp_dir) = create_test_config();
+        config.model = "my-gateway-model".to_string();
+        config.allow_unknown_model = true;
+
+        let session_id = manager.create_session(config).await.unwrap();
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert_eq!(info.model, "my-gateway-model");
+    }
+
+    #[tokio::test]
+    async fn test_create_session_uses_catalog_default_model() {
+        let mut manager = ProcessManager::new();
+        let mut catalog = ModelCatalog::builtin();
+        catalog.default_model = "haiku".to_string();
+        manager.set_model_catalog(catalog);
+
+        let (mut config, _temp_dir) = create_test_config();
+        config.model = String::new();
+
+        let session_id = manager.create_session(config).await.unwrap();
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert_eq!(info.model, "haiku");
+    }
+
+    #[tokio::test]
+    async fn test_create_session_expands_tool_presets() {
+        let manager = ProcessManager::new();
+        let (mut config, _temp_dir) = create_test_config();
+        config.allowed_tools = vec![
+            "Read".to_string(),
+            "preset:read-only".to_string(),
+            "Bash(make:*)".to_string(),
+        ];
+
+        let session_id = manager.create_session(config.clone()).await.unwrap();
+        let info = manager.get_session(&session_id).await.unwrap();
+        let expected = manager
+            .tool_presets()
+            .expand(&[], &config.allowed_tools)
+            .unwrap()
+            .1;
+        assert_eq!(info.tool_presets, vec!["read-only"]);
+        assert_eq!(info.allowed_tools, expected);
+
+        // The CLI only sees concrete matchers
+        let line = manager
+            .command_line(&session_id, None, ShellFlavor::Posix)
+            .await
+            .unwrap();
+        assert!(line.argv.contains(&expected.join(",")));
+        assert!(!line.command.contains("preset:"));
+
+        config.allowed_tools.push("preset:everything".to_string());
+        let result = manager.update_session_config(&session_id, config).await;
+        assert!(matches!(result, Err(ProcessError::UnknownToolPreset(p)) if p == "everything"));
+    }
+
+    #[tokio::test]
+    async fn test_create_session_validates_tool_matchers() {
+        let manager = ProcessManager::new();
+        let (mut config, _temp_dir) = create_test_config();
+        config.allowed_tools = vec!["Read".to_string(), "Bash(git log*".to_string()];
+        let result = manager.create_session(config.clone()).await;
+        match result {
+            Err(ProcessError::InvalidToolMatchers(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].index, 1);
+                assert_eq!(errors[0].suggestion.as_deref(), Some("Bash(git log*)"));
+            }
+            other => panic!("expected InvalidToolMatchers, got {:?}", other),
+        }
+
+        // Valid entries are stored normalized
+        config.allowed_tools = vec![" Read ".to_string(), "Bash ( git  log:* )".to_string()];
+        let session_id = manager.create_session(config.clone()).await.unwrap();
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert_eq!(info.allowed_tools, vec!["Read", "Bash(git log:*)"]);
+
+        config.allowed_tools = vec!["Bahs".to_string()];
+        let result = manager.update_session_config(&session_id, config).await;
+        assert!(matches!(result, Err(ProcessError::InvalidToolMatchers(_))));
+    }
+
+    #[tokio::test]
+    async fn test_update_session_config_validates_model() {
+        let manager = ProcessManager::new();
+        let (config, _temp_dir) = create_test_config();
+        let session_id = manager.create_session(config.clone()).await.unwrap();
+
+        let mut bad = config.clone();
+        bad.model = "sonet".to_string();
+        let result = manager.update_session_config(&session_id, bad).await;
+        assert!(matches!(result, Err(ProcessError::UnknownModel(_))));
+
+        let mut good = config;
+        good.model = "opus".to_string();
+        let info = manager.update_session_config(&session_id, good).await.unwrap();
+        assert_eq!(info.model, "opus");
+    }
+
+    #[tokio::test]
+    async fn test_leaving_read_only_is_refused_while_thinking() {
+        let manager = ProcessManager::new();
+        let (config, _temp_dir) = create_test_config();
+        let read_only = SessionConfig {
+            read_only: true,
+            ..config.clone()
+        };
+        let session_id = manager.create_session(read_only.clone()).await.unwrap();
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert!(info.read_only);
+        assert!(manager.is_read_only(&session_id).await);
+
+        manager
+            .set_status(&session_id, SessionStatus::Thinking)
+            .await
+            .unwrap();
+        let result = manager
+            .update_session_config(&session_id, config.clone())
+            .await;
+        assert!(matches!(result, Err(ProcessError::ReadOnlySessionBusy(_))));
+        assert!(manager.is_read_only(&session_id).await);
+        // Other changes that keep it read-only are fine
+        let mut renamed = read_only.clone();
+        renamed.name = Some("renamed".to_string());
+        assert!(manager
+            .update_session_config(&session_id, renamed)
+            .await
+            .is_ok());
+
+        manager
+            .set_status(&session_id, SessionStatus::Idle)
+            .await
+            .unwrap();
+        let info = manager
+            .update_session_config(&session_id, config.clone())
+            .await
+            .unwrap();
+        assert!(!info.read_only);
+        assert!(!manager.is_read_only(&session_id).await);
+
+        // Entering read-only is allowed at any time
+        manager
+            .set_status(&session_id, SessionStatus::Thinking)
+            .await
+            .unwrap();
+        let info = manager
+            .update_session_config(&session_id, read_only)
+            .await
+            .unwrap();
+        assert!(info.read_only);
+    }
+
+    #[tokio::test]
+    async fn test_archive_restart_unarchive_flow() {
+        let data_dir = TempDir::new().unwrap();
+        let (config, _temp_dir) = create_test_config();
+
+        let mut manager = ProcessManager::new();
+        manager.set_data_dir(data_dir.path());
+        let session_id = manager.create_session(config).await.unwrap();
+        {
+            let sessions = manager.sessions.read().await;
+            let mut session = sessions.get(&session_id).unwrap().lock().await;
+            session.info.claude_session_id = Some("claude-abc".to_string());
+        }
+
+        let info = manager.archive_session(&session_id).await.unwrap();
+        assert_eq!(info.status, SessionStatus::Archived);
+        assert_eq!(manager.active_count().await, 0);
+        assert!(manager.get_sessions(false).await.is_empty());
+        assert_eq!(manager.get_sessions(true).await.len(), 1);
+
+        // Archived sessions reject prompts
+        let (tx, _rx) = mpsc::channel(8);
+        let result = manager
+            .send_prompt(&session_id, "hello", PromptOptions::default(), tx)
+            .await;
+        assert!(matches!(result, Err(ProcessError::SessionArchived(_))));
+
+        // Simulate a restart: a fresh manager loads the persisted index
+        let mut restarted = ProcessManager::new();
+        restarted.set_data_dir(data_dir.path());
+        let archived = restarted.get_sessions(true).await;
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].status, SessionStatus::Archived);
+
+        let info = restarted.unarchive_session(&session_id).await.unwrap();
+        assert_eq!(info.status, SessionStatus::Idle);
+        assert_eq!(info.claude_session_id, Some("claude-abc".to_string()));
+        assert!(!info.working_dir_missing);
+        assert_eq!(restarted.active_count().await, 1);
+
+        // The index no longer contains the session after unarchiving
+        let mut again = ProcessManager::new();
+        again.set_data_dir(data_dir.path());
+        assert!(again.get_sessions(true).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scratch_lifecycle_across_restart() {
+        let data_dir = TempDir::new().unwrap();
+        let (config, _temp_dir) = create_test_config();
+        let mut manager = ProcessManager::new();
+        manager.set_data_dir(data_dir.path());
+        let archived = manager.create_session(config.clone()).await.unwrap();
+        let open = manager.create_session(config.clone()).await.unwrap();
+        let terminated = manager.create_session(config).await.unwrap();
+        for id in [&archived, &open, &terminated] {
+            manager.scratch().allocate(id, "blob", "txt").await.unwrap();
+        }
+        manager.scratch().allocate_shared("diff", "txt").await.unwrap();
+
+        manager.terminate(&terminated).await.unwrap();
+        assert!(!manager.scratch().scratch_dir_for(&terminated).exists());
+        manager.archive_session(&archived).await.unwrap();
+
+        // Exiting removes nothing
+        manager.terminate_all().await;
+        assert!(manager.scratch().scratch_dir_for(&open).exists());
+        assert!(manager.scratch().shared_dir().exists());
+
+        // The next startup keeps archived sessions' dirs only
+        let mut restarted = ProcessManager::new();
+        restarted.set_data_dir(data_dir.path());
+        assert_eq!(restarted.sweep_scratch().await, 2);
+        assert!(restarted.scratch().scratch_dir_for(&archived).exists());
+        assert!(!restarted.scratch().scratch_dir_for(&open).exists());
+        assert!(!restarted.scratch().shared_dir().exists());
+    }
+
+    #[tokio::test]
+    async fn test_file_versions_survive_exit_and_restart() {
+        let data_dir = TempDir::new().unwrap();
+        let (config, temp_dir) = create_test_config();
+        let file = temp_dir.path().join("notes.txt");
+        std::fs::write(&file, "after").unwrap();
+        let path = file.to_str().unwrap();
+
+        let mut manager = ProcessManager::new();
+        manager.set_data_dir(data_dir.path());
+        let session_id = manager.create_session(config).await.unwrap();
+        manager
+            .record_file_edit(&session_id, path, Some("before"), Some("after"), None)
+            .await
+            .unwrap();
+        manager.archive_session(&session_id).await.unwrap();
+        manager.terminate_all().await;
+
+        let mut restarted = ProcessManager::new();
+        restarted.set_data_dir(data_dir.path());
+        restarted.sweep_scratch().await;
+        restarted.unarchive_session(&session_id).await.unwrap();
+        let versions = restarted.file_versions(&session_id, path).await.unwrap();
+        assert_eq!(versions.len(), 2);
+        let content = restarted
+            .file_version_content(&session_id, path, &versions[0].version_id)
+            .await
+            .unwrap();
+        assert_eq!(content, "before");
+    }
+
+    #[tokio::test]
+    async fn test_archive_with_float_costs_is_migrated() {
+        let data_dir = TempDir::new().unwrap();
+        let (config, _temp_dir) = create_test_config();
+        // An index written when costs were floating-point dollars
+        let legacy = serde_json::json!({
+            "s1": {
+                "info": {
+                    "id": "s1",
+                    "claude_session_id": "claude-abc",
+                    "working_dir": config.working_dir,
+                    "model": "sonnet",
+                    "status": "Archived",
+                    "created_at": 1,
+                    "prompt_count": 2,
+                    "total_cost_usd": 0.30000000000000004,
+                },
+                "config": config,
+                "history": [
+                    {"prompt_id": "p1", "model": "sonnet", "started_at": 1, "cost_usd": 0.1},
+                    {"prompt_id": "p2", "model": "sonnet", "started_at": 2, "cost_usd": 0.2},
+                ],
+                "archived_at": 3,
+            }
+        });
+        let index = data_dir
+            .path()
+            .join(crate::services::archive::ARCHIVE_FILE_NAME);
+        std::fs::write(&index, legacy.to_string()).unwrap();
+
+        let mut manager = ProcessManager::new();
+        manager.set_data_dir(data_dir.path());
+        let info = manager.unarchive_session("s1").await.unwrap();
+        assert_eq!(info.total_cost, Usd::from_micros(300_000));
+        let history = manager.get_prompt_history("s1").await.unwrap();
+        let costs: Vec<Option<Usd>> = history.iter().map(|record| record.cost).collect();
+        assert_eq!(
+            costs,
+            vec![
+                Some(Usd::from_micros(100_000)),
+                Some(Usd::from_micros(200_000))
+            ]
+        );
+        assert_eq!(manager.usage_summary().await.total_cost, info.total_cost);
+
+        // Saved back as dollars, without the float drift
+        manager.archive_session("s1").await.unwrap();
+        let saved = std::fs::read_to_string(&index).unwrap();
+        assert!(saved.contains("\"total_cost_usd\": 0.3"));
+        assert!(!saved.contains("0.30000000000000004"));
+    }
+
+    #[tokio::test]
+    async fn test_unarchive_flags_missing_working_dir() {
+        let data_dir = TempDir::new().unwrap();
+        let (config, temp_dir) = create_test_config();
+
+        let mut manager = ProcessManager::new();
+        manager.set_data_dir(data_dir.path());
+        let session_id = manager.create_session(config).await.unwrap();
+        manager.archive_session(&session_id).await.unwrap();
+
+        drop(temp_dir);
+
+        let mut restarted = ProcessManager::new();
+        restarted.set_data_dir(data_dir.path());
+        let info = restarted.unarchive_session(&session_id).await.unwrap();
+        assert!(info.working_dir_missing);
+        assert!(restarted.is_alive(&session_id).await);
+    }
+
+    #[tokio::test]
+    async fn test_archive_unknown_session() {
+        let manager = ProcessManager::new();
+        let result = manager.archive_session("nonexistent").await;
+        assert!(matches!(result, Err(ProcessError::SessionNotFound(_))));
+        let result = manager.unarchive_session("nonexistent").await;
+        assert!(matches!(result, Err(ProcessError::SessionNotFound(_))));
+    }
+
+    fn with_request_id(request_id: &str) -> PromptOptions {
+        PromptOptions {
+            request_id: Some(request_id.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Send a prompt and wait for its process to exit
+    async fn run_prompt(
+        manager: &ProcessManager,
+        session_id: &str,
+        options: PromptOptions,
+    ) -> Vec<StreamMessage> {
+        let (tx, mut rx) = mpsc::channel(64);
+        manager
+            .send_prompt(session_id, "hello", options, tx)
+            .await
+            .unwrap();
+
+        let mut messages = Vec::new();
+        while let Some(msg) = rx.recv().await {
+            messages.push(msg);
+        }
+        messages
+    }
+
+    /// Value following `flag` in an args line logged by the fake CLI
+    fn arg_value<'a>(args_line: &'a str, flag: &str) -> Option<&'a str> {
+        let mut parts = args_line.split(' ');
+        parts.find(|part| *part == flag)?;
+        parts.next()
+    }
+
+    /// Next queued event that is not a `sessions-changed` event
+    fn try_next_event(events: &mut broadcast::Receiver<SessionEvent>) -> Option<SessionEvent> {
+        std::iter::from_fn(|| events.try_recv().ok())
+            .find(|event| !matches!(event, SessionEvent::SessionsChanged(_)))
+    }
+
+    /// Wait for the next event that is not a `sessions-changed` event
+    async fn next_event(events: &mut broadcast::Receiver<SessionEvent>) -> SessionEvent {
+        loop {
+            match events.recv().await.unwrap() {
+                SessionEvent::SessionsChanged(_) => {}
+                event => return event,
+            }
+        }
+    }
+
+    #[test]
+    fn test_recent_requests_lookup_and_expiry() {
+        let mut recent = RecentRequests::default();
+        let start = Instant::now();
+        recent.record("req-1".to_string(), "prompt-1".to_string(), start);
+
+        assert_eq!(recent.lookup("req-1", start), Some("prompt-1".to_string()));
+        assert_eq!(recent.lookup("req-2", start), None);
+
+        let later = start + REQUEST_DEDUP_WINDOW + Duration::from_millis(1);
+        assert_eq!(recent.lookup("req-1", later), None);
+    }
+
+    #[test]
+    fn test_recent_requests_evicts_oldest() {
+        let mut recent = RecentRequests::default();
+        let now = Instant::now();
+        for i in 0..=REQUEST_DEDUP_CAPACITY {
+            recent.record(format!("req-{}", i), format!("prompt-{}", i), now);
+        }
+
+        assert_eq!(recent.entries.len(), REQUEST_DEDUP_CAPACITY);
+        assert_eq!(recent.lookup("req-0", now), None);
+        assert!(recent.lookup(&format!("req-{}", REQUEST_DEDUP_CAPACITY), now).is_some());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_duplicate_request_returns_existing_prompt() {
+        let (config, temp_dir) = create_test_config();
+        let cli = write_fake_cli(temp_dir.path(), "sleep 2");
+        let manager = ProcessManager::with_cli_path(cli);
+        let session_id = manager.create_session(config).await.unwrap();
+
+        let (tx1, _rx1) = mpsc::channel(8);
+        let first = manager
+            .send_prompt(&session_id, "hello", with_request_id("req-1"), tx1)
+            .await
+            .unwrap();
+
+        let (tx2, _rx2) = mpsc::channel(8);
+        let second = manager
+            .send_prompt(&session_id, "hello", with_request_id("req-1"), tx2)
+            .await
+            .unwrap();
+
+        assert_eq!(first.prompt_id, second.prompt_id);
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert_eq!(info.prompt_count, 1);
+        assert_eq!(info.active_prompt_id, Some(first.prompt_id));
+
+        manager.terminate_all().await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_different_request_while_busy_is_rejected() {
+        let (config, temp_dir) = create_test_config();
+        let cli = write_fake_cli(temp_dir.path(), "sleep 2");
+        let manager = ProcessManager::with_cli_path(cli);
+        let session_id = manager.create_session(config).await.unwrap();
+
+        let (tx1, _rx1) = mpsc::channel(8);
+        manager
+            .send_prompt(&session_id, "hello", with_request_id("req-1"), tx1)
+            .await
+            .unwrap();
+
+        let (tx2, _rx2) = mpsc::channel(8);
+        let result = manager
+            .send_prompt(&session_id, "something else", with_request_id("req-2"), tx2)
+            .await;
+        assert!(matches!(result, Err(ProcessError::SessionBusy)));
+
+        let (tx3, _rx3) = mpsc::channel(8);
+        let result = manager
+            .send_prompt(&session_id, "no id", PromptOptions::default(), tx3)
+            .await;
+        assert!(matches!(result, Err(ProcessError::SessionBusy)));
+
+        manager.terminate_all().await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_model_override_applies_to_one_prompt() {
+        let (config, temp_dir) = create_test_config();
+        let log_path = temp_dir.path().join("args.log");
+        let cli = write_fake_cli(
+            temp_dir.path(),
+            &format!(
+                "echo \"$@\" >> '{}'\necho '{{\"type\":\"result\",\"cost_usd\":0.5}}'",
+                log_path.display()
+            ),
+        );
+        let manager = ProcessManager::with_cli_path(cli);
+        let session_id = manager.create_session(config).await.unwrap();
+        let mut events = manager.subscribe();
+
+        let options = PromptOptions {
+            model_override: Some("opus".to_string()),
+            ..Default::default()
+        };
+        run_prompt(&manager, &session_id, options).await;
+        run_prompt(&manager, &session_id, PromptOptions::default()).await;
+
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(arg_value(lines[0], "--model"), Some("opus"));
+        assert_eq!(arg_value(lines[1], "--model"), Some("sonnet"));
+
+        // The session default is untouched
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert_eq!(info.model, "sonnet");
+
+        let history = manager.get_prompt_history(&session_id).await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].model, "opus");
+        assert!(history[0].model_overridden);
+        assert_eq!(history[1].model, "sonnet");
+        assert!(!history[1].model_overridden);
+        assert_eq!(history[0].cost, Some(Usd::from_dollars(0.5)));
+
+        let SessionEvent::PromptCompleted { prompt, .. } = next_event(&mut events).await else {
+            panic!("Expected a prompt-completed event");
+        };
+        assert_eq!(prompt.model, "opus");
+        assert!(prompt.completed_at.is_some());
+    }
+
+    fn apply_session_diffs(
+        events: &mut broadcast::Receiver<SessionEvent>,
+        sessions: &mut HashMap<String, SessionInfo>,
+    ) {
+        while let Ok(event) = events.try_recv() {
+            if let SessionEvent::SessionsChanged(diff) = event {
+                assert!(!diff.full);
+                diff.apply(sessions);
+            }
+        }
+    }
+
+    fn by_id(infos: Vec<SessionInfo>) -> HashMap<String, SessionInfo> {
+        infos
+            .into_iter()
+            .map(|info| (info.id.clone(), info))
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_session_diffs_reconstruct_the_session_list() {
+        let cli_dir = TempDir::new().unwrap();
+        let cli = write_fake_cli(
+            cli_dir.path(),
+            r#"echo '{"type":"system","subtype":"init","session_id":"claude-1","mcp_servers":[]}'
+echo '{"type":"result","subtype":"success","cost_usd":0.25}'"#,
+        );
+        let manager = ProcessManager::with_cli_path(cli);
+        let mut events = manager.subscribe();
+        let mut streamed = HashMap::new();
+        let (config, _temp_dir) = create_test_config();
+
+        let a = manager.create_session(config.clone()).await.unwrap();
+        let b = manager.create_session(config.clone()).await.unwrap();
+        let c = manager.create_session(config.clone()).await.unwrap();
+        run_prompt(&manager, &a, PromptOptions::default()).await;
+        // Wait for the prompt's final bookkeeping
+        while manager.get_session(&a).await.unwrap().status != SessionStatus::Idle {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        apply_session_diffs(&mut events, &mut streamed);
+        let midpoint = streamed.clone();
+        let midpoint_version = manager.sessions_since(0).await.version;
+
+        let renamed = SessionConfig {
+            model: "opus".to_string(),
+            ..config.clone()
+        };
+        manager.update_session_config(&b, renamed).await.unwrap();
+        manager.set_status(&c, SessionStatus::Error).await.unwrap();
+        manager.archive_session(&c).await.unwrap();
+        manager.terminate(&b).await.unwrap();
+        let d = manager.create_session(config).await.unwrap();
+        apply_session_diffs(&mut events, &mut streamed);
+
+        let full = by_id(manager.get_sessions(true).await);
+        assert_eq!(streamed, full);
+        assert_eq!(full[&a].total_cost, Usd::from_dollars(0.25));
+        assert_eq!(full[&a].claude_session_id.as_deref(), Some("claude-1"));
+        assert_eq!(full[&c].status, SessionStatus::Archived);
+        assert!(full.contains_key(&d) && !full.contains_key(&b));
+
+        // Resyncing from the midpoint gives the same list
+        let diff = manager.sessions_since(midpoint_version).await;
+        assert!(!diff.full);
+        assert_eq!(diff.removed, vec![b.clone()]);
+        let mut resynced = midpoint;
+        diff.apply(&mut resynced);
+        assert_eq!(resynced, full);
+
+        // A version from before a restart gets the whole list
+        let diff = manager.sessions_since(diff.version + 100).await;
+        assert!(diff.full);
+        assert_eq!(by_id(diff.changed), full);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_prompt_records_environment_snapshot() {
+        let cli_dir = TempDir::new().unwrap();
+        let cli = write_fake_cli(
+            cli_dir.path(),
+            r#"if [ "$1" = "--version" ]; then echo "2.0.1 (Claude Code)"; exit 0; fi
+echo '{"type":"system","subtype":"init","session_id":"abc","model":"claude-sonnet-4-5","permissionMode":"default","mcp_servers":[{"name":"github","status":"connected"}]}'
+echo '{"type":"result","subtype":"success","cost_usd":0.01}'"#,
+        );
+        let manager = ProcessManager::with_cli_path(cli);
+        assert_eq!(
+            manager.check_cli().await.version.as_deref(),
+            Some("2.0.1 (Claude Code)")
+        );
+        let (config, _temp_dir) = create_test_config();
+        let session_id = manager.create_session(config).await.unwrap();
+        let mut events = manager.subscribe();
+
+        run_prompt(&manager, &session_id, PromptOptions::default()).await;
+        let prompt = loop {
+            if let SessionEvent::PromptCompleted { prompt, .. } = events.recv().await.unwrap() {
+                break prompt;
+            }
+        };
+
+        let snapshot = manager
+            .prompt_snapshot(&session_id, &prompt.prompt_id)
+            .await
+            .unwrap()
+            .expect("snapshot recorded");
+        assert_eq!(snapshot.cli_version.as_deref(), Some("2.0.1 (Claude Code)"));
+        assert_eq!(snapshot.model_requested, "sonnet");
+        assert_eq!(
+            snapshot.model_reported.as_deref(),
+            Some("claude-sonnet-4-5")
+        );
+        assert_eq!(snapshot.permission_mode.as_deref(), Some("default"));
+        assert_eq!(snapshot.mcp_servers, vec!["github".to_string()]);
+
+        let export = manager.export_transcript(&session_id).await.unwrap();
+        assert_eq!(export.snapshot, Some(snapshot));
+        assert!(matches!(
+            manager.prompt_snapshot(&session_id, "missing").await,
+            Err(ProcessError::PromptNotFound(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_pre_prompt_hook_vetoes_prompt() {
+        let (mut config, temp_dir) = create_test_config();
+        config.hooks.pre_prompt = Some("echo 'prompt mentions a secret' >&2; exit 1".to_string());
+        let cli = write_fake_cli(temp_dir.path(), "echo '{\"type\":\"result\"}'");
+        let manager = ProcessManager::with_cli_path(cli);
+        let session_id = manager.create_session(config).await.unwrap();
+
+        let (tx, _rx) = mpsc::channel(8);
+        let result = manager
+            .send_prompt(&session_id, "hello", PromptOptions::default(), tx)
+            .await;
+        assert!(
+            matches!(result, Err(ProcessError::HookRejected(ref reason)) if reason == "prompt mentions a secret")
+        );
+
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert_eq!(info.status, SessionStatus::Idle);
+        assert_eq!(info.prompt_count, 0);
+        assert!(info.active_prompt_id.is_none());
+
+        let history = manager.get_prompt_history(&session_id).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].hooks.len(), 1);
+        assert_eq!(history[0].hooks[0].exit_code, Some(1));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_pre_and_post_prompt_hooks_run() {
+        let (mut config, temp_dir) = create_test_config();
+        let pre_out = temp_dir.path().join("pre.txt");
+        let post_out = temp_dir.path().join("post.json");
+        config.hooks.pre_prompt = Some(format!("cat > '{}'", pre_out.display()));
+        config.hooks.post_prompt = Some(format!("cat > '{}'", post_out.display()));
+        let cli = write_fake_cli(
+            temp_dir.path(),
+            concat!(
+                "echo '{\"type\":\"tool_use\",\"id\":\"t1\",\"name\":\"Write\",\"input\":{\"file_path\":\"src/main.rs\"}}'\n",
+                "echo '{\"type\":\"result\",\"cost_usd\":0.25}'"
+            ),
+        );
+        let manager = ProcessManager::with_cli_path(cli);
+        let session_id = manager.create_session(config).await.unwrap();
+
+        run_prompt(&manager, &session_id, PromptOptions::default()).await;
+
+        assert_eq!(std::fs::read_to_string(&pre_out).unwrap(), "hello");
+        let summary: PostPromptSummary =
+            serde_json::from_str(&std::fs::read_to_string(&post_out).unwrap()).unwrap();
+        assert_eq!(summary.session_id, session_id);
+        assert_eq!(summary.cost_usd, Some(0.25));
+        assert_eq!(summary.files_touched, vec!["src/main.rs".to_string()]);
+
+        let history = manager.get_prompt_history(&session_id).await.unwrap();
+        let kinds: Vec<HookKind> = history[0].hooks.iter().map(|h| h.kind).collect();
+        assert_eq!(kinds, vec![HookKind::PrePrompt, HookKind::PostPrompt]);
+        assert_eq!(history[0].files_touched, vec!["src/main.rs".to_string()]);
+        let main_rs = temp_dir.path().join("src/main.rs");
+        assert_eq!(
+            manager
+                .prompt_touching(&session_id, main_rs.to_str().unwrap())
+                .await,
+            Some(history[0].prompt_id.clone())
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_pre_prompt_hook_timeout_rejects_prompt() {
+        let (mut config, _temp_dir) = create_test_config();
+        config.hooks.pre_prompt = Some("sleep 10".to_string());
+        config.hooks.timeout_secs = Some(1);
+        let manager = ProcessManager::new();
+        let session_id = manager.create_session(config).await.unwrap();
+
+        let started = Instant::now();
+        let (tx, _rx) = mpsc::channel(8);
+        let result = manager
+            .send_prompt(&session_id, "hello", PromptOptions::default(), tx)
+            .await;
+        assert!(matches!(result, Err(ProcessError::HookRejected(ref reason)) if reason.contains("timed out")));
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let history = manager.get_prompt_history(&session_id).await.unwrap();
+        assert!(history[0].hooks[0].timed_out);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_large_prompt_is_sent_via_stdin_and_cleaned_up() {
+        let (config, temp_dir) = create_test_config();
+        let argc_path = temp_dir.path().join("argc.txt");
+        let stdin_path = temp_dir.path().join("stdin.txt");
+        let cli = write_fake_cli(
+            temp_dir.path(),
+            &format!(
+                "echo $# > '{}'\ncat > '{}'\necho '{{\"type\":\"result\"}}'",
+                argc_path.display(),
+                stdin_path.display()
+            ),
+        );
+        let manager = ProcessManager::with_cli_path(cli);
+        let session_id = manager.create_session(config).await.unwrap();
+
+        // Below the threshold: prompt in argv, nothing on stdin
+        run_prompt(&manager, &session_id, PromptOptions::default()).await;
+        assert_eq!(std::fs::read_to_string(&argc_path).unwrap().trim(), "7");
+        assert_eq!(std::fs::read_to_string(&stdin_path).unwrap(), "");
+
+        // Above the threshold: prompt on stdin, without the prompt and its `--`
+        let prompt = "line\r\n".repeat(crate::services::prompt::STDIN_THRESHOLD_BYTES / 5 + 1);
+        let (tx, mut rx) = mpsc::channel(8);
+        manager
+            .send_prompt(&session_id, &prompt, PromptOptions::default(), tx)
+            .await
+            .unwrap();
+        while rx.recv().await.is_some() {}
+
+        assert_eq!(std::fs::read_to_string(&argc_path).unwrap().trim(), "5");
+        assert_eq!(
+            std::fs::read_to_string(&stdin_path).unwrap(),
+            prompt.replace("\r\n", "\n")
+        );
+
+        // The temp file lives in the session's scratch dir and is removed once the process exits
+        let scratch_dir = manager.scratch().scratch_dir_for(&session_id);
+        assert_eq!(std::fs::read_dir(&scratch_dir).unwrap().count(), 0);
+
+        // Terminating the session removes its scratch dir
+        manager.terminate(&session_id).await.unwrap();
+        assert!(!scratch_dir.exists());
+    }
+
+    #[tokio::test]
+    async fn test_prompt_over_hard_cap_is_rejected() {
+        let manager = ProcessManager::new();
+        let (config, _temp_dir) = create_test_config();
+        let session_id = manager.create_session(config).await.unwrap();
+
+        let prompt = "x".repeat(MAX_PROMPT_BYTES + 1);
+        let (tx, _rx) = mpsc::channel(8);
+        let result = manager
+            .send_prompt(&session_id, &prompt, PromptOptions::default(), tx)
+            .await;
+        assert!(matches!(
+            result,
+            Err(ProcessError::PromptTooLarge { size, max }) if size == MAX_PROMPT_BYTES + 1 && max == MAX_PROMPT_BYTES
+        ));
+
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert_eq!(info.status, SessionStatus::Idle);
+    }
+
+    #[test]
+    fn test_meta_command_parse() {
+        assert_eq!(MetaCommand::parse("compact").unwrap(), (MetaCommand::Compact, ""));
+        assert_eq!(MetaCommand::parse(" /clear ").unwrap(), (MetaCommand::Clear, ""));
+        assert_eq!(
+            MetaCommand::parse("/compact keep the test plan").unwrap(),
+            (MetaCommand::Compact, "keep the test plan")
+        );
+        assert_eq!(MetaCommand::Compact.to_prompt("keep it"), "/compact keep it");
+        assert!(matches!(
+            MetaCommand::parse("/deploy"),
+            Err(ProcessError::UnknownMetaCommand(c)) if c == "/deploy"
+        ));
+        assert!(MetaCommand::parse("").is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_meta_command_clear_resets_resume() {
+        let (config, temp_dir) = create_test_config();
+        let log_path = temp_dir.path().join("args.log");
+        let cli = write_fake_cli(
+            temp_dir.path(),
+            &format!(
+                "echo \"$@\" >> '{}'\necho '{{\"type\":\"system\",\"session_id\":\"claude-new\"}}'\necho '{{\"type\":\"result\",\"cost_usd\":0.1}}'",
+                log_path.display()
+            ),
+        );
+        let manager = ProcessManager::with_cli_path(cli);
+        let session_id = manager.create_session(config).await.unwrap();
+
+        // First prompt captures a Claude session ID
+        run_prompt(&manager, &session_id, PromptOptions::default()).await;
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert_eq!(info.claude_session_id.as_deref(), Some("claude-new"));
+
+        // /compact resumes the conversation and keeps accumulating cost
+        let (tx, mut rx) = mpsc::channel(8);
+        manager.send_meta_command(&session_id, "/compact", tx).await.unwrap();
+        while rx.recv().await.is_some() {}
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert_eq!(info.claude_session_id.as_deref(), Some("claude-new"));
+        assert_eq!(info.total_cost, Usd::from_dollars(0.2));
+
+        // /clear resumes once, then drops the Claude session ID
+        let (tx, mut rx) = mpsc::channel(8);
+        let record = manager.send_meta_command(&session_id, "clear", tx).await.unwrap();
+        assert_eq!(record.meta_command, Some(MetaCommand::Clear));
+        while rx.recv().await.is_some() {}
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert!(info.claude_session_id.is_none());
+
+        // The next prompt starts a fresh conversation
+        run_prompt(&manager, &session_id, PromptOptions::default()).await;
+
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].ends_with(" -- /compact"));
+        assert_eq!(arg_value(lines[1], "--resume"), Some("claude-new"));
+        assert!(lines[2].ends_with(" -- /clear"));
+        assert_eq!(arg_value(lines[2], "--resume"), Some("claude-new"));
+        assert_eq!(arg_value(lines[3], "--resume"), None);
+    }
+
+    /// Wait for the `prompt-completed` event of `prompt_id`
+    async fn next_completion(
+        events: &mut broadcast::Receiver<SessionEvent>,
+        prompt_id: &str,
+    ) -> PromptRecord {
+        loop {
+            if let SessionEvent::PromptCompleted { prompt, .. } = next_event(events).await {
+                if prompt.prompt_id == prompt_id {
+                    return *prompt;
+                }
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_dry_run_keeps_the_resume_chain() {
+        let (config, temp_dir) = create_test_config();
+        let log_path = temp_dir.path().join("args.log");
+        // Every run reports a new conversation id, as a forked resume does
+        let cli = write_fake_cli(
+            temp_dir.path(),
+            &format!(
+                r#"echo "$@" >> '{}'
+case "$*" in *"--permission-mode plan"*) id=claude-fork ;; *) id=claude-real ;; esac
+echo "{{\"type\":\"system\",\"subtype\":\"init\",\"session_id\":\"$id\"}}"
+echo '{{"type":"tool_use","id":"t1","name":"Edit","input":{{"file_path":"src/lib.rs"}}}}'
+echo '{{"type":"tool_use","id":"t2","name":"ExitPlanMode","input":{{"plan":"Edit lib.rs"}}}}'
+echo '{{"type":"result","subtype":"success","cost_usd":0.1}}'"#,
+                log_path.display()
+            ),
+        );
+        let manager = ProcessManager::with_cli_path(cli);
+        let session_id = manager.create_session(config).await.unwrap();
+        let mut events = manager.subscribe();
+
+        // A dry run of a fresh session does not adopt the throwaway conversation
+        let (tx, mut rx) = mpsc::channel(8);
+        manager
+            .send_prompt_dry_run(&session_id, "plan it", tx)
+            .await
+            .unwrap();
+        while rx.recv().await.is_some() {}
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert_eq!(info.claude_session_id, None);
+        assert_eq!(info.prompt_count, 0);
+
+        run_prompt(&manager, &session_id, PromptOptions::default()).await;
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert_eq!(info.claude_session_id.as_deref(), Some("claude-real"));
+        assert_eq!(info.prompt_count, 1);
+
+        // A dry run of an existing conversation forks it and leaves the pointer alone
+        let (tx, mut rx) = mpsc::channel(8);
+        let record = manager
+            .send_prompt_dry_run(&session_id, "plan it", tx)
+            .await
+            .unwrap();
+        assert!(record.dry_run);
+        while rx.recv().await.is_some() {}
+        let completed = next_completion(&mut events, &record.prompt_id).await;
+        let plan = completed.plan.unwrap();
+        assert_eq!(plan.plan.as_deref(), Some("Edit lib.rs"));
+        assert_eq!(plan.operations.len(), 1);
+        assert_eq!(plan.operations[0].path, "src/lib.rs");
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert_eq!(info.claude_session_id.as_deref(), Some("claude-real"));
+        assert_eq!(info.prompt_count, 1);
+        assert_eq!(info.total_cost, Usd::from_dollars(0.3));
+
+        // The next real prompt resumes the real conversation
+        let (tx, mut rx) = mpsc::channel(8);
+        let record = manager
+            .send_prompt(&session_id, "do it", PromptOptions::default(), tx)
+            .await
+            .unwrap();
+        while rx.recv().await.is_some() {}
+        let completed = next_completion(&mut events, &record.prompt_id).await;
+        assert!(!completed.dry_run);
+        assert_eq!(completed.plan, None);
+
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(arg_value(lines[0], "--permission-mode"), Some("plan"));
+        assert_eq!(arg_value(lines[0], "--resume"), None);
+        assert!(!lines[0].contains("--fork-session"));
+        assert_eq!(arg_value(lines[2], "--resume"), Some("claude-real"));
+        assert!(lines[2].contains("--fork-session"));
+        assert_eq!(arg_value(lines[3], "--resume"), Some("claude-real"));
+        assert_eq!(arg_value(lines[3], "--permission-mode"), None);
+    }
+
+    #[tokio::test]
+    async fn test_meta_command_whitelist_enforced() {
+        let manager = ProcessManager::new();
+        let (config, _temp_dir) = create_test_config();
+        let session_id = manager.create_session(config).await.unwrap();
+
+        let (tx, _rx) = mpsc::channel(8);
+        let result = manager.send_meta_command(&session_id, "/init", tx).await;
+        assert!(matches!(result, Err(ProcessError::UnknownMetaCommand(_))));
+
+        let history = manager.get_prompt_history(&session_id).await.unwrap();
+        assert!(history.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_completion_reason_from_result_subtype() {
+        let (config, temp_dir) = create_test_config();
+        // Each run emits the result line stored in the `next-result` file
+        let result_path = temp_dir.path().join("next-result");
+        let cli = write_fake_cli(temp_dir.path(), &format!("cat '{}'", result_path.display()));
+        let manager = ProcessManager::with_cli_path(cli);
+        let session_id = manager.create_session(config).await.unwrap();
+
+        let results = [
+            r#"{"type":"result","subtype":"success","is_error":false,"result":"Done."}"#,
+            r#"{"type":"result","subtype":"error_max_turns","is_error":false}"#,
+            r#"{"type":"result","subtype":"error_during_execution","is_error":true}"#,
+            "",
+        ];
+        for result in results {
+            std::fs::write(&result_path, format!("{}\n", result)).unwrap();
+            run_prompt(&manager, &session_id, PromptOptions::default()).await;
+        }
+
+        let history = manager.get_prompt_history(&session_id).await.unwrap();
+        let reasons: Vec<_> = history.iter().map(|r| r.completion_reason).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                Some(CompletionReason::Success),
+                Some(CompletionReason::MaxTurns),
+                Some(CompletionReason::ExecutionError),
+                // Exited without a result message
+                Some(CompletionReason::Error),
+            ]
+        );
+        assert_eq!(
+            history[1].result_subtype.as_deref(),
+            Some("error_max_turns")
+        );
+        assert_eq!(history[3].result_subtype, None);
+    }
+
+    #[test]
+    fn test_completion_reason_mapping() {
+        use CompletionReason::*;
+        assert_eq!(
+            CompletionReason::from_result(Some("success"), Some(false)),
+            Success
+        );
+        assert_eq!(
+            CompletionReason::from_result(Some("success"), Some(true)),
+            Error
+        );
+        assert_eq!(
+            CompletionReason::from_result(Some("error_max_turns"), None),
+            MaxTurns
+        );
+        assert_eq!(
+            CompletionReason::from_result(Some("error_new_kind"), None),
+            Error
+        );
+        assert_eq!(CompletionReason::from_result(None, None), Success);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_context_pressure_events() {
+        let (config, temp_dir) = create_test_config();
+        let output_path = temp_dir.path().join("next-output");
+        let cli = write_fake_cli(
+            temp_dir.path(),
+            &format!(
+                "cat '{}'; echo '{{\"type\":\"result\"}}'",
+                output_path.display()
+            ),
+        );
+        let manager = ProcessManager::with_cli_path(cli);
+        let mut events = manager.subscribe();
+        let session_id = manager.create_session(config).await.unwrap();
+
+        let near_limit = r#"{"type":"message","role":"assistant","content":[],"usage":{"input_tokens":5,"cache_read_input_tokens":185000}}"#;
+        let compacted = concat!(
+            r#"{"type":"system","subtype":"compact_boundary","compact_metadata":{"trigger":"auto","pre_tokens":185005}}"#,
+            "\n",
+            r#"{"type":"message","role":"assistant","content":[],"usage":{"input_tokens":30000}}"#,
+        );
+        for output in [near_limit, compacted, "", "", ""] {
+            std::fs::write(&output_path, format!("{}\n", output)).unwrap();
+            run_prompt(&manager, &session_id, PromptOptions::default()).await;
+            if output == compacted {
+                let info = manager.get_session(&session_id).await.unwrap();
+                assert_eq!(info.context_pressure, ContextPressure::CompactedRecently);
+                assert_eq!(info.context_tokens, Some(30000));
+            }
+        }
+
+        let mut changes = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let SessionEvent::ContextPressure {
+                pressure,
+                compaction,
+                ..
+            } = event
+            {
+                changes.push((pressure, compaction.and_then(|c| c.pre_tokens)));
+            }
+        }
+        assert_eq!(
+            changes,
+            vec![
+                (ContextPressure::AtLimit, None),
+                (ContextPressure::CompactedRecently, Some(185005)),
+                // Worn off three prompts after the compaction
+                (ContextPressure::Normal, None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_crash_recovery_from_journal() {
+        let data_dir = TempDir::new().unwrap();
+        let (config, _work_dir) = create_test_config();
+        let journal = PromptJournal::in_app_data(data_dir.path());
+        // A prompt of a session that only lived in memory, and one whose
+        // pid is still taken (by this test process)
+        let mut entry = JournalEntry {
+            session_id: "lost".to_string(),
+            prompt_id: "p1".to_string(),
+            claude_session_id: Some("claude-lost".to_string()),
+            started_at: 100,
+            argv_hash: journal::argv_hash(&[]),
+            pid: None,
+            config: config.clone(),
+        };
+        journal.record(&entry).await;
+        entry.session_id = "busy".to_string();
+        entry.prompt_id = "p2".to_string();
+        entry.claude_session_id = None;
+        entry.pid = Some(std::process::id());
+        journal.record(&entry).await;
+
+        let mut manager = ProcessManager::new();
+        manager.set_data_dir(data_dir.path());
+        let mut events = manager.subscribe();
+        let recovered = manager.recover_from_crash().await;
+        assert_eq!(recovered.len(), 2);
+        assert!(!recovered[0].process_running);
+        assert!(recovered[1].process_running);
+
+        match try_next_event(&mut events).unwrap() {
+            SessionEvent::CrashRecovery { prompts } => assert_eq!(prompts, recovered),
+            other => panic!("unexpected event {:?}", other),
+        }
+        // Recovery runs once
+        assert!(manager.recover_from_crash().await.is_empty());
+
+        // The sessions survive a restart in the archive and resume with --resume
+        let mut manager = ProcessManager::new();
+        manager.set_data_dir(data_dir.path());
+        let info = manager.unarchive_session("lost").await.unwrap();
+        assert!(info.recovered_after_crash);
+        assert_eq!(info.claude_session_id.as_deref(), Some("claude-lost"));
+        assert_eq!(info.working_dir, config.working_dir);
+        let line = manager
+            .command_line("lost", None, ShellFlavor::Posix)
+            .await
+            .unwrap();
+        assert!(line.argv.contains(&"claude-lost".to_string()));
+        assert!(
+            manager
+                .get_session("busy")
+                .await
+                .unwrap()
+                .recovered_after_crash
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_finished_prompt_leaves_no_journal_entry() {
+        let data_dir = TempDir::new().unwrap();
+        let (config, temp_dir) = create_test_config();
+        let cli = write_fake_cli(
+            temp_dir.path(),
+            "echo '{\"type\":\"system\",\"subtype\":\"init\",\"session_id\":\"cli-1\"}'; echo '{\"type\":\"result\"}'",
+        );
+        let mut manager = ProcessManager::with_cli_path(cli);
+        manager.set_data_dir(data_dir.path());
+        let session_id = manager.create_session(config).await.unwrap();
+
+        // The output channel closes once the prompt task has finished
+        run_prompt(&manager, &session_id, PromptOptions::default()).await;
+        assert!(manager.recover_from_crash().await.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_command_line_matches_spawned_args() {
+        let (mut config, temp_dir) = create_test_config();
+        config.allowed_tools = vec!["Read".to_string(), "Bash(git:*)".to_string()];
+        let args_path = temp_dir.path().join("args");
+        let cli = write_fake_cli(
+            temp_dir.path(),
+            &format!(
+                "printf '%s\\n' \"$@\" > '{}'; echo '{{\"type\":\"system\",\"subtype\":\"init\",\"session_id\":\"cli-1\"}}'; echo '{{\"type\":\"result\"}}'",
+                args_path.display()
+            ),
+        );
+        let manager = ProcessManager::with_cli_path(&cli);
+        let session_id = manager.create_session(config).await.unwrap();
+
+        // Before and after the first prompt (which adds --resume)
+        for _ in 0..2 {
+            let line = manager
+                .command_line(&session_id, Some("hello"), ShellFlavor::Posix)
+                .await
+                .unwrap();
+            run_prompt(&manager, &session_id, PromptOptions::default()).await;
+            let spawned: Vec<String> = std::fs::read_to_string(&args_path)
+                .unwrap()
+                .lines()
+                .map(String::from)
+                .collect();
+            assert_eq!(line.argv[0], cli.to_string_lossy());
+            assert_eq!(&line.argv[1..], spawned.as_slice());
+            assert!(!line.prompt_on_stdin);
+        }
+
+        let line = manager
+            .command_line(&session_id, None, ShellFlavor::Posix)
+            .await
+            .unwrap();
+        assert!(line.argv.contains(&"cli-1".to_string()));
+        assert_ne!(line.argv.last().map(String::as_str), Some("--"));
+
+        let large = "x".repeat(STDIN_THRESHOLD_BYTES + 1);
+        let line = manager
+            .command_line(&session_id, Some(&large), ShellFlavor::Posix)
+            .await
+            .unwrap();
+        assert!(line.prompt_on_stdin);
+        assert!(!line.command.contains(&large));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_context_providers_prepend_preamble() {
+        let (mut config, temp_dir) = create_test_config();
+        std::fs::write(config.working_dir.join("notes.txt"), "use tabs\n").unwrap();
+        config.context_providers = vec![
+            ContextProvider::FileContents {
+                path: PathBuf::from("notes.txt"),
+                max_bytes: None,
+            },
+            ContextProvider::FileContents {
+                path: PathBuf::from("missing.txt"),
+                max_bytes: None,
+            },
+        ];
+        let prompt_path = temp_dir.path().join("prompt");
+        let cli = write_fake_cli(
+            temp_dir.path(),
+            &format!(
+                "for arg; do last=\"$arg\"; done; printf '%s' \"$last\" > '{}'; echo '{{\"type\":\"result\"}}'",
+                prompt_path.display()
+            ),
+        );
+        let manager = ProcessManager::with_cli_path(&cli);
+        let session_id = manager.create_session(config).await.unwrap();
+
+        run_prompt(&manager, &session_id, PromptOptions::default()).await;
+        let prompt = std::fs::read_to_string(&prompt_path).unwrap();
+        assert!(prompt.starts_with("<context>\n### file notes.txt\n```\nuse tabs\n```\n"));
+        assert!(prompt.contains("### file missing.txt\n```\n[error: Failed to open"));
+        assert!(prompt.ends_with("</context>\n\nhello"));
+
+        let history = manager.get_prompt_history(&session_id).await.unwrap();
+        let context: Vec<(&str, usize, bool)> = history[0]
+            .context
+            .iter()
+            .map(|c| (c.provider.as_str(), c.bytes, c.error.is_some()))
+            .collect();
+        assert_eq!(
+            context,
+            vec![("file notes.txt", 9, false), ("file missing.txt", 0, true)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_claude_session_detected_on_attach() {
+        let (config, _temp_dir) = create_test_config();
+        let manager = ProcessManager::new();
+        let mut events = manager.subscribe();
+
+        let first = manager
+            .create_session(SessionConfig {
+                resume_session_id: Some("claude-shared".to_string()),
+                ..config.clone()
+            })
+            .await
+            .unwrap();
+        let other = manager.create_session(config.clone()).await.unwrap();
+        assert!(try_next_event(&mut events).is_none());
+        assert_eq!(
+            manager.get_session(&first).await.unwrap().claude_session_id,
+            Some("claude-shared".to_string())
+        );
+
+        // Attaching a second session to the same conversation is reported
+        let second = manager
+            .create_session(SessionConfig {
+                resume_session_id: Some("claude-shared".to_string()),
+                ..config.clone()
+            })
+            .await
+            .unwrap();
+        match try_next_event(&mut events).unwrap() {
+            SessionEvent::DuplicateClaudeSession {
+                session_id,
+                other_session_id,
+                claude_session_id,
+            } => {
+                assert_eq!(session_id, second);
+                assert_eq!(other_session_id, first);
+                assert_eq!(claude_session_id, "claude-shared");
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        // So is re-attaching an existing session via its config
+        manager
+            .update_session_config(
+                &other,
+                SessionConfig {
+                    resume_session_id: Some("claude-shared".to_string()),
+                    ..config
+                },
+            )
+            .await
+            .unwrap();
+        let reported: Vec<String> = std::iter::from_fn(|| try_next_event(&mut events))
+            .map(|event| match event {
+                SessionEvent::DuplicateClaudeSession {
+                    session_id,
+                    other_session_id,
+                    ..
+                } => {
+                    assert_eq!(session_id, other);
+                    other_session_id
+                }
+                event => panic!("unexpected event {:?}", event),
+            })
+            .collect();
+        assert_eq!(reported.len(), 2);
+        assert!(reported.contains(&first) && reported.contains(&second));
+    }
+
+    fn merge_record(prompt_id: &str, started_at: u64, cost: f64) -> PromptRecord {
+        PromptRecord {
+            cost: Some(Usd::from_dollars(cost)),
+            completion_reason: Some(CompletionReason::Success),
+            ..test_support::prompt_record(prompt_id, started_at)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_merge_sessions_combines_history_and_costs() {
+        let (config, _temp_dir) = create_test_config();
+        let manager = ProcessManager::new();
+        let keep = manager.create_session(config.clone()).await.unwrap();
+        let newer_config = SessionConfig {
+            model: "opus".to_string(),
+            ..config
+        };
+        let merge = manager.create_session(newer_config).await.unwrap();
+
+        // keep ran prompts at t=10 and t=30, merge at t=20
+        for (session_id, prompts) in [
+            (&keep, vec![("k1", 10, 0.5), ("k2", 30, 0.25)]),
+            (&merge, vec![("m1", 20, 1.0)]),
+        ] {
+            let sessions = manager.sessions.read().await;
+            let mut session = sessions.get(session_id).unwrap().lock().await;
+            for (prompt_id, started_at, cost) in prompts {
+                session
+                    .history
+                    .push(merge_record(prompt_id, started_at, cost));
+                session.info.prompt_count += 1;
+                session.info.total_cost += Usd::from_dollars(cost);
+            }
+            session.info.claude_session_id = Some("claude-shared".to_string());
+            if session_id == &merge {
+                session.info.created_at += 1;
+            }
+        }
+        let result: StreamMessage = serde_json::from_str(r#"{"type":"result"}"#).unwrap();
+        for (session_id, prompt_id) in
+            [(&keep, "k1"), (&keep, "k2"), (&merge, "m1"), (&merge, "m1")]
+        {
+            manager
+                .record_message(session_id, prompt_id, "sonnet", result.clone())
+                .await
+                .unwrap();
+        }
+
+        // Refused while either session is thinking
+        manager
+            .set_status(&merge, SessionStatus::Thinking)
+            .await
+            .unwrap();
+        assert!(matches!(
+            manager.merge_sessions(&keep, &merge).await,
+            Err(ProcessError::SessionBusy)
+        ));
+        manager
+            .set_status(&merge, SessionStatus::Idle)
+            .await
+            .unwrap();
+        assert!(matches!(
+            manager.merge_sessions(&keep, &keep).await,
+            Err(ProcessError::MergeIntoSelf(_))
+        ));
+
+        let info = manager.merge_sessions(&keep, &merge).await.unwrap();
+        assert_eq!(info.id, keep);
+        assert_eq!(info.prompt_count, 3);
+        assert_eq!(info.total_cost, Usd::from_dollars(1.75));
+        // The merged-away session was created later, so its config wins
+        assert_eq!(info.model, "opus");
+        assert!(!manager.is_alive(&merge).await);
+
+        let history = manager.get_prompt_history(&keep).await.unwrap();
+        let ids: Vec<&str> = history.iter().map(|r| r.prompt_id.as_str()).collect();
+        assert_eq!(ids, vec!["k1", "m1", "k2"]);
+        let transcript = manager.replay_messages(&keep, 0).await.unwrap();
+        let entries: Vec<(u64, &str)> = transcript
+            .iter()
+            .map(|e| (e.sequence, e.prompt_id.as_str()))
+            .collect();
+        assert_eq!(entries, vec![(1, "k1"), (2, "m1"), (3, "m1"), (4, "k2")]);
+    }
+
+    #[tokio::test]
+    async fn test_merge_sessions_moves_scratch_files() {
+        let data_dir = TempDir::new().unwrap();
+        let (config, _temp_dir) = create_test_config();
+        let mut manager = ProcessManager::new();
+        manager.set_data_dir(data_dir.path());
+        let keep = manager.create_session(config.clone()).await.unwrap();
+        let merge = manager.create_session(config).await.unwrap();
+
+        let blob = tool_output::ToolResultBlob {
+            blob_id: "abc123".to_string(),
+            content: serde_json::json!("full output"),
+        };
+        tool_output::store_blob(manager.scratch(), &merge, &blob)
+            .await
+            .unwrap();
+
+        let mut events = manager.subscribe();
+        manager.merge_sessions(&keep, &merge).await.unwrap();
+        assert_eq!(
+            manager.tool_result_blob(&keep, "abc123").await.unwrap(),
+            serde_json::json!("full output")
+        );
+        assert!(!manager.scratch().scratch_dir_for(&merge).exists());
+
+        // Clients holding replay cursors are told to drop them
+        let reset = std::iter::from_fn(|| events.try_recv().ok()).find_map(|event| match event {
+            SessionEvent::TranscriptReset {
+                session_id,
+                merged_session_id,
+            } => Some((session_id, merged_session_id)),
+            _ => None,
+        });
+        assert_eq!(reset, Some((keep, merge)));
+    }
+
+    /// Create sessions named `names` in `config`'s dir, with claude ids `claude-<name>`
+    async fn create_named_sessions(
+        manager: &ProcessManager,
+        config: &SessionConfig,
+        names: &[&str],
+    ) -> Vec<String> {
+        let mut ids = Vec::new();
+        for name in names {
+            let config = SessionConfig {
+                name: Some(name.to_string()),
+                ..config.clone()
+            };
+            let session_id = manager.create_session(config).await.unwrap();
+            let sessions = manager.sessions.read().await;
+            let mut session = sessions.get(&session_id).unwrap().lock().await;
+            session.info.claude_session_id = Some(format!("claude-{}", name));
+            drop(session);
+            ids.push(session_id);
+        }
+        ids
+    }
+
+    /// A `~/.claude` with transcripts for the given conversations
+    fn claude_dir_with(conversations: &[&str]) -> TempDir {
+        let dir = TempDir::new().unwrap();
+        let project = dir.path().join("projects").join("-some-project");
+        std::fs::create_dir_all(&project).unwrap();
+        for id in conversations {
+            std::fs::write(project.join(format!("{}.jsonl", id)), "{}\n").unwrap();
+        }
+        dir
+    }
+
+    async fn session_names(manager: &ProcessManager, ids: &[String]) -> Vec<String> {
+        let sessions = manager.sessions.read().await;
+        let mut names = Vec::new();
+        for id in ids {
+            let session = sessions.get(id).unwrap().lock().await;
+            names.push(session.config.name.clone().unwrap_or_default());
+        }
+        names
+    }
+
+    #[tokio::test]
+    async fn test_project_layout_snapshot_and_restore() {
+        let data_dir = TempDir::new().unwrap();
+        let (config, _project) = create_test_config();
+        let (other_config, _other_project) = create_test_config();
+        let mut manager = ProcessManager::new();
+        manager.set_data_dir(data_dir.path());
+        let ids = create_named_sessions(&manager, &config, &["a", "b", "c"]).await;
+        create_named_sessions(&manager, &other_config, &["elsewhere"]).await;
+
+        let order = vec![ids[2].clone(), ids[0].clone()];
+        let layout = manager
+            .save_project_layout(&config.working_dir, &order)
+            .await
+            .unwrap();
+        let saved: Vec<Option<&str>> = layout
+            .sessions
+            .iter()
+            .map(|s| s.claude_session_id.as_deref())
+            .collect();
+        assert_eq!(
+            saved,
+            vec![Some("claude-c"), Some("claude-a"), Some("claude-b")]
+        );
+
+        // Restore into an empty manager, as after a restart
+        let mut restarted = ProcessManager::new();
+        restarted.set_data_dir(data_dir.path());
+        let claude_dir = claude_dir_with(&["claude-a", "claude-b", "claude-c"]);
+        let restored = restarted
+            .restore_project_layout(&config.working_dir, Some(claude_dir.path()))
+            .await
+            .unwrap();
+        assert!(restored.warnings.is_empty());
+        assert_eq!(
+            session_names(&restarted, &restored.session_ids).await,
+            vec!["c", "a", "b"]
+        );
+        let info = restarted
+            .get_session(&restored.session_ids[0])
+            .await
+            .unwrap();
+        assert_eq!(info.claude_session_id.as_deref(), Some("claude-c"));
+
+        assert!(matches!(
+            restarted
+                .restore_project_layout(&other_config.working_dir, None)
+                .await,
+            Err(ProcessError::LayoutNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_restore_project_layout_skips_missing_conversations() {
+        let data_dir = TempDir::new().unwrap();
+        let (config, _project) = create_test_config();
+        let mut manager = ProcessManager::new();
+        manager.set_data_dir(data_dir.path());
+        let ids = create_named_sessions(&manager, &config, &["kept", "gone"]).await;
+        // A session that never ran a prompt has nothing to resume
+        let fresh = SessionConfig {
+            name: Some("fresh".to_string()),
+            ..config.clone()
+        };
+        let fresh_id = manager.create_session(fresh).await.unwrap();
+        let order = vec![ids[0].clone(), ids[1].clone(), fresh_id];
+        manager
+            .save_project_layout(&config.working_dir, &order)
+            .await
+            .unwrap();
+
+        let mut restarted = ProcessManager::new();
+        restarted.set_data_dir(data_dir.path());
+        let claude_dir = claude_dir_with(&["claude-kept"]);
+        let restored = restarted
+            .restore_project_layout(&config.working_dir, Some(claude_dir.path()))
+            .await
+            .unwrap();
+        assert_eq!(
+            session_names(&restarted, &restored.session_ids).await,
+            vec!["kept", "fresh"]
+        );
+        assert_eq!(restored.warnings.len(), 1);
+        assert_eq!(restored.warnings[0].index, 1);
+        assert_eq!(restored.warnings[0].name.as_deref(), Some("gone"));
+        assert!(restored.warnings[0].message.contains("claude-gone"));
+    }
+
+    #[tokio::test]
+    async fn test_terminate_all_autosaves_layouts() {
+        let data_dir = TempDir::new().unwrap();
+        let (config, _project) = create_test_config();
+        let mut manager = ProcessManager::new();
+        manager.set_data_dir(data_dir.path());
+        create_named_sessions(&manager, &config, &["a"]).await;
+        manager.terminate_all().await;
+        assert!(manager.layouts.load(&config.working_dir).await.is_none());
+
+        manager.set_autosave_layouts(true);
+        create_named_sessions(&manager, &config, &["b"]).await;
+        manager.terminate_all().await;
+        let layout = manager.layouts.load(&config.working_dir).await.unwrap();
+        assert_eq!(layout.sessions.len(), 1);
+        assert_eq!(layout.sessions[0].config.name.as_deref(), Some("b"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_mcp_status_event_on_change() {
+        let (config, temp_dir) = create_test_config();
+        let init_path = temp_dir.path().join("next-init");
+        let cli = write_fake_cli(
+            temp_dir.path(),
+            &format!(
+                "cat '{}'; echo '{{\"type\":\"result\"}}'",
+                init_path.display()
+            ),
+        );
+        let manager = ProcessManager::with_cli_path(cli);
+        manager
+            .mcp_registry()
+            .register(crate::services::ManagedMcpServer {
+                name: "github".to_string(),
+                command: "github-mcp".to_string(),
+                args: Vec::new(),
+                env: None,
+                pid: 1234,
+            })
+            .await;
+        let mut events = manager.subscribe();
+        let session_id = manager.create_session(config).await.unwrap();
+
+        let failed = r#"[{"name":"github","status":"failed"},{"name":"fs","status":"connected"}]"#;
+        let recovered =
+            r#"[{"name":"github","status":"connected"},{"name":"fs","status":"connected"}]"#;
+        for servers in [failed, failed, recovered] {
+            let init = format!(
+                r#"{{"type":"system","subtype":"init","mcp_servers":{}}}"#,
+                servers
+            );
+            std::fs::write(&init_path, format!("{}\n", init)).unwrap();
+            run_prompt(&manager, &session_id, PromptOptions::default()).await;
+        }
+
+        let mut statuses = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let SessionEvent::McpStatus { failed, .. } = event {
+                statuses.push(failed);
+            }
+        }
+        // The repeated snapshot is not reported again
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(
+            statuses[0],
+            vec![McpServerFailure {
+                name: "github".to_string(),
+                status: "failed".to_string(),
+                restart_action_id: Some("restart_mcp_server:github".to_string()),
+            }]
+        );
+        assert!(statuses[1].is_empty());
+
+        let info = manager.get_session(&session_id).await.unwrap();
+        let servers = info.mcp_servers.unwrap();
+        assert!(servers.iter().all(|s| !s.is_failed()));
+    }
+
+    /// Next event that is a session-error, skipping prompt-completed events
+    async fn next_session_error(
+        events: &mut broadcast::Receiver<SessionEvent>,
+    ) -> (String, ErrorClass, Option<i32>, Option<ConnectivityStatus>) {
+        loop {
+            if let SessionEvent::SessionError {
+                message,
+                error_class,
+                exit_code,
+                connectivity,
+                ..
+            } = events.recv().await.unwrap()
+            {
+                return (message, error_class, exit_code, connectivity);
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stderr_progress_becomes_session_activity() {
+        let (config, temp_dir) = create_test_config();
+        let cli = write_fake_cli(
+            temp_dir.path(),
+            r#"printf 'Running Bash(cargo test)\r\342\240\271 Running Bash(cargo test) (3s \302\267 esc to interrupt)\n' >&2
+sleep 1
+echo 'fatal: not a git repository' >&2
+exit 1"#,
+        );
+        let manager = ProcessManager::with_cli_path(cli);
+        let mut events = manager.subscribe();
+        let session_id = manager.create_session(config).await.unwrap();
+        assert_eq!(
+            manager
+                .seconds_since_last_output(&session_id)
+                .await
+                .unwrap(),
+            None
+        );
+
+        let (tx, mut rx) = mpsc::channel(64);
+        let record = manager
+            .send_prompt(&session_id, "hello", PromptOptions::default(), tx)
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            let SessionEvent::SessionActivity { activity, .. } = next_event(&mut events).await
+            else {
+                panic!("expected session activity");
+            };
+            assert_eq!(activity.kind, cli_activity::ActivityKind::ToolRunning);
+            assert_eq!(activity.detail.as_deref(), Some("Bash(cargo test)"));
+        }
+        // stdout is silent, but the progress line counts as output
+        assert_eq!(
+            manager
+                .seconds_since_last_output(&session_id)
+                .await
+                .unwrap(),
+            Some(0)
+        );
+
+        while rx.recv().await.is_some() {}
+        let (message, _, exit_code, _) = next_session_error(&mut events).await;
+        assert_eq!(exit_code, Some(1));
+        // Only unrecognized lines reach the raw stderr
+        assert_eq!(message, "fatal: not a git repository");
+        next_completion(&mut events, &record.prompt_id).await;
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert_eq!(info.status, SessionStatus::Idle);
+        assert_eq!(
+            manager
+                .seconds_since_last_output(&session_id)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_network_failure_probes_connectivity() {
+        let (config, temp_dir) = create_test_config();
+        let cli = write_fake_cli(
+            temp_dir.path(),
+            "echo 'Error: getaddrinfo ENOTFOUND api.anthropic.com' >&2; exit 1",
+        );
+        let mut manager = ProcessManager::with_cli_path(cli);
+
+        // A port nothing listens on: the probe fails fast with connection refused
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        manager.set_connectivity_endpoint(endpoint.clone());
+
+        let mut events = manager.subscribe();
+        let session_id = manager.create_session(config).await.unwrap();
+        run_prompt(&manager, &session_id, PromptOptions::default()).await;
+
+        let (message, error_class, exit_code, connectivity) = next_session_error(&mut events).await;
+        assert!(message.contains("ENOTFOUND"));
+        assert_eq!(error_class, ErrorClass::Network);
+        assert_eq!(exit_code, Some(1));
+        let connectivity = connectivity.expect("Network failures attach a probe result");
+        assert!(!connectivity.online);
+        assert_eq!(connectivity.endpoint, endpoint);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_other_failure_skips_probe() {
+        let (config, temp_dir) = create_test_config();
+        let cli = write_fake_cli(temp_dir.path(), "echo 'unexpected crash' >&2; exit 2");
+        let manager = ProcessManager::with_cli_path(cli);
+        let mut events = manager.subscribe();
+        let session_id = manager.create_session(config).await.unwrap();
+        run_prompt(&manager, &session_id, PromptOptions::default()).await;
+
+        let (message, error_class, exit_code, connectivity) = next_session_error(&mut events).await;
+        assert_eq!(message, "unexpected crash");
+        assert_eq!(error_class, ErrorClass::Other);
+        assert_eq!(exit_code, Some(2));
+        assert!(connectivity.is_none());
+
+        let info = manager.get_session(&session_id).await.unwrap();
+        assert_eq!(info.status, SessionStatus::Idle);
+    }
+
+    #[tokio::test]
+    async fn test_model_override_is_validated() {
+        let manager = ProcessManager::new();
+        let (config, _temp_dir) = create_test_config();
+        let session_id = manager.create_session(config).await.unwrap();
+
+        let (tx, _rx) = mpsc::channel(8);
+        let options = PromptOptions {
+            model_override: Some("opsu".to_string()),
+            ..Default::default()
+        };
+        let result = manager.send_prompt(&session_id, "hello", options, tx).await;
+        assert!(matches!(result, Err(ProcessError::UnknownModel(m)) if m == "opsu"));
+
+        let history = manager.get_prompt_history(&session_id).await.unwrap();
+        assert!(history.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_redact_tool_inputs_masks_emitted_copy_only() {
+        let (mut config, temp_dir) = create_test_config();
+        config.redact_tool_inputs = true;
+        let log_path = temp_dir.path().join("args.log");
+        let cli = write_fake_cli(
+            temp_dir.path(),
+            &format!(
+                "echo \"$@\" >> '{}'\necho '{{\"type\":\"tool_use\",\"id\":\"t1\",\"name\":\"Bash\",\"input\":{{\"command\":\"export KEY=sk-ant-REDACTED\"}}}}'",
+                log_path.display()
+            ),
+        );
+        let manager = ProcessManager::with_cli_path(cli);
+        let session_id = manager.create_session(config).await.unwrap();
+
+        let (tx, mut rx) = mpsc::channel(64);
+        manager
+            .send_prompt(
+                &session_id,
+                "use sk-ant-REDACTED",
+                PromptOptions::default(),
+                tx,
+            )
+            .await
+            .unwrap();
+        let mut messages = Vec::new();
+        while let Some(msg) = rx.recv().await {
+            messages.push(msg);
+        }
+
+        let command = messages
+            .iter()
+            .find_map(|msg| match msg {
+                StreamMessage::ToolUse { input, .. } => input["command"].as_str(),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(command, "export KEY=sk-[REDACTED]");
+
+        // The prompt reaches the CLI unredacted
+        let args = std::fs::read_to_string(&log_path).unwrap();
+        assert!(args.contains("use sk-ant-REDACTED"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_huge_tool_result_is_truncated_with_blob() {
+        let (config, temp_dir) = create_test_config();
+        // About 4 MB of output, with multi-byte characters throughout
+        let output = "line of output with ünïcödé\n".repeat(140_000);
+        let line = serde_json::json!({
+            "type": "tool_result",
+            "tool_use_id": "t1",
+            "content": output,
+        });
+        let output_path = temp_dir.path().join("tool-result.json");
+        std::fs::write(&output_path, format!("{}\n", line)).unwrap();
+        let cli = write_fake_cli(temp_dir.path(), &format!("cat '{}'", output_path.display()));
+        let manager = ProcessManager::with_cli_path(cli);
+        let session_id = manager.create_session(config).await.unwrap();
+
+        let messages = run_prompt(&manager, &session_id, PromptOptions::default()).await;
+        let emitted = messages
+            .iter()
+            .find(|msg| matches!(msg, StreamMessage::ToolResult { .. }))
+            .unwrap();
+        let payload = serde_json::to_value(emitted).unwrap();
+        assert!(serde_json::to_vec(&payload).unwrap().len() < DEFAULT_MAX_TOOL_RESULT_BYTES + 1024);
+        assert_eq!(payload["truncated"], true);
+        assert_eq!(
+            payload["total_bytes"],
+            serde_json::to_vec(&line["content"]).unwrap().len()
+        );
+        assert!(output.starts_with(payload["content"].as_str().unwrap()));
+
+        let blob_id = payload["blob_id"].as_str().unwrap();
+        let blob = manager
+            .tool_result_blob(&session_id, blob_id)
+            .await
+            .unwrap();
+        assert_eq!(blob, line["content"]);
+        assert!(matches!(
+            manager.tool_result_blob(&session_id, "missing").await,
+            Err(ProcessError::ToolResultBlobNotFound(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_transcript_sequence_is_gapless_across_prompts() {
+        let (config, temp_dir) = create_test_config();
+        let cli = write_fake_cli(
+            temp_dir.path(),
+            "echo '{\"type\":\"assistant\",\"role\":\"assistant\",\"content\":\"hi\"}'\necho '{\"type\":\"result\",\"cost_usd\":0.1}'",
+        );
+        let manager = ProcessManager::with_cli_path(cli);
+        let session_id = manager.create_session(config).await.unwrap();
+
+        let mut sequences = Vec::new();
+        for _ in 0..2 {
+            let (tx, mut rx) = mpsc::channel(64);
+            let record = manager
+                .send_prompt(&session_id, "hello", PromptOptions::default(), tx)
+                .await
+                .unwrap();
+            while let Some(msg) = rx.recv().await {
+                let sequence = manager
+                    .record_message(&session_id, &record.prompt_id, &record.model, msg)
+                    .await
+                    .unwrap();
+                sequences.push(sequence);
+            }
+        }
+        assert_eq!(sequences, vec![1, 2, 3, 4]);
+
+        // Replay from the middle of the first prompt
+        let replayed = manager.replay_messages(&session_id, 1).await.unwrap();
+        let replayed_sequences: Vec<u64> = replayed.iter().map(|e| e.sequence).collect();
+        assert_eq!(replayed_sequences, vec![2, 3, 4]);
+        assert!(matches!(replayed[0].message, StreamMessage::Result { .. }));
+        assert_ne!(replayed[0].prompt_id, replayed[1].prompt_id);
+
+        assert!(manager
+            .replay_messages(&session_id, 4)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            manager.replay_messages(&session_id, 0).await.unwrap().len(),
+            4
+        );
+
+        // The transcript is dropped with the session
+        manager.terminate(&session_id).await.unwrap();
+        assert!(matches!(
+            manager.replay_messages(&session_id, 0).await,
+            Err(ProcessError::SessionNotFound(_))
+        ));
+    }
+
+    /// Wait for the next `broadcast-completed` event, skipping other events
+    async fn next_broadcast_completed(
+        events: &mut broadcast::Receiver<SessionEvent>,
+    ) -> (String, Usd, Vec<BroadcastOutcome>) {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if let SessionEvent::BroadcastCompleted {
+                    broadcast_id,
+                    total_cost,
+                    outcomes,
+                } = events.recv().await.unwrap()
+                {
+                    return (broadcast_id, total_cost, outcomes);
+                }
+            }
+        })
+        .await
+        .expect("Timed out waiting for broadcast-completed")
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_broadcast_prompt_with_busy_and_missing_targets() {
+        let temp_dir = TempDir::new().unwrap();
+        let cli = write_fake_cli(
+            temp_dir.path(),
+            "sleep 0.5\necho '{\"type\":\"result\",\"subtype\":\"success\",\"cost_usd\":0.25}'",
+        );
+        let manager = ProcessManager::with_cli_path(cli);
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            let config = SessionConfig {
+                working_dir: temp_dir.path().to_path_buf(),
+                ..Default::default()
+            };
+            ids.push(manager.create_session(config).await.unwrap());
+        }
+        let mut events = manager.subscribe();
+
+        // The third session is already running a prompt
+        let (busy_tx, _busy_rx) = mpsc::channel(64);
+        manager
+            .send_prompt(&ids[2], "earlier", PromptOptions::default(), busy_tx)
+            .await
+            .unwrap();
+
+        let mut targets = Vec::new();
+        let mut receivers = Vec::new();
+        for session_id in ids.iter().chain([&"missing".to_string(), &ids[0]]) {
+            let (tx, rx) = mpsc::channel(64);
+            targets.push((session_id.clone(), tx));
+            receivers.push(rx);
+        }
+        let dispatch = manager.broadcast_prompt("update headers", targets).await;
+
+        assert_eq!(dispatch.results.len(), 4);
+        assert!(dispatch.results[&ids[0]].is_ok());
+        assert!(dispatch.results[&ids[1]].is_ok());
+        assert!(matches!(
+            dispatch.results[&ids[2]],
+            Err(ProcessError::SessionBusy)
+        ));
+        assert!(matches!(
+            dispatch.results["missing"],
+            Err(ProcessError::SessionNotFound(_))
+        ));
+
+        let (broadcast_id, total_cost, outcomes) = next_broadcast_completed(&mut events).await;
+        assert_eq!(broadcast_id, dispatch.broadcast_id);
+        assert_eq!(total_cost, Usd::from_dollars(0.5));
+        let mut expected: Vec<&String> = ids[..2].iter().collect();
+        expected.sort();
+        let reported: Vec<&String> = outcomes.iter().map(|o| &o.session_id).collect();
+        assert_eq!(reported, expected);
+        assert!(outcomes
+            .iter()
+            .all(|o| o.completion_reason == Some(CompletionReason::Success)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_broadcast_interrupting_one_target_leaves_others_running() {
+        let temp_dir = TempDir::new().unwrap();
+        let cli = write_fake_cli(
+            temp_dir.path(),
+            "sleep 1\necho '{\"type\":\"result\",\"subtype\":\"success\",\"cost_usd\":0.1}'",
+        );
+        let manager = ProcessManager::with_cli_path(cli);
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let config = SessionConfig {
+                working_dir: temp_dir.path().to_path_buf(),
+                ..Default::default()
+            };
+            ids.push(manager.create_session(config).await.unwrap());
+        }
+        let mut events = manager.subscribe();
+
+        let targets = ids
+            .iter()
+            .map(|id| (id.clone(), mpsc::channel(64).0))
+            .collect();
+        let dispatch = manager.broadcast_prompt("chore", targets).await;
+        assert!(dispatch.results.values().all(|r| r.is_ok()));
+
+        manager.interrupt(&ids[0]).await.unwrap();
+
+        let (_, total_cost, outcomes) = next_broadcast_completed(&mut events).await;
+        let reason = |id: &String| {
+            outcomes
+                .iter()
+                .find(|o| &o.session_id == id)
+                .unwrap()
+                .completion_reason
+        };
+        assert_eq!(reason(&ids[0]), Some(CompletionReason::Interrupted));
+        assert_eq!(reason(&ids[1]), Some(CompletionReason::Success));
+        assert_eq!(total_cost, Usd::from_dollars(0.1));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_prompt_timing_measures_spawn_to_first_byte() {
+        let (config, temp_dir) = create_test_config();
+        let cli = write_fake_cli(
+            temp_dir.path(),
+            "sleep 0.5\necho '{\"type\":\"assistant\",\"role\":\"assistant\",\"content\":\"hi\"}'\nsleep 0.3\necho '{\"type\":\"result\",\"cost_usd\":0.2}'",
+        );
+        let manager = ProcessManager::with_cli_path(cli);
+        let session_id = manager.create_session(config).await.unwrap();
+        let mut events = manager.subscribe();
+
+        run_prompt(&manager, &session_id, PromptOptions::default()).await;
+
+        let SessionEvent::PromptCompleted { prompt, .. } = next_event(&mut events).await else {
+            panic!("Expected a prompt-completed event");
+        };
+        let timing = prompt.timing.unwrap();
+        let first_byte = timing.spawn_to_first_byte_ms.unwrap();
+        assert!((450..2000).contains(&first_byte), "{}", first_byte);
+        let to_result = timing.first_byte_to_result_ms.unwrap();
+        assert!((250..2000).contains(&to_result), "{}", to_result);
+        assert!(timing.total_ms >= first_byte + to_result);
+
+        let summary = manager.usage_summary().await;
+        assert_eq!(summary.prompt_count, 1);
+        assert_eq!(summary.days[0].spawn_latency_p50_ms, Some(first_byte));
+        assert_eq!(
+            manager.get_prompt_history(&session_id).await.unwrap()[0].timing,
+            Some(timing)
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_archive_degrades_when_disk_is_full() {
+        let data_dir = TempDir::new().unwrap();
+        // Writes to the archive's temp file fail with ENOSPC
+        let temp_path = data_dir.path().join("archived_sessions.tmp");
+        std::os::unix::fs::symlink("/dev/full", &temp_path).unwrap();
+
+        let mut manager = ProcessManager::new();
+        manager.set_data_dir(data_dir.path());
+        let mut events = manager.subscribe();
+
+        for _ in 0..2 {
+            let (config, _temp_dir) = create_test_config();
+            let session_id = manager.create_session(config).await.unwrap();
+            let info = manager.archive_session(&session_id).await.unwrap();
+            assert_eq!(info.status, SessionStatus::Archived);
+        }
+        assert_eq!(manager.get_sessions(true).await.len(), 2);
+
+        // Reported once, not per failed write
+        let SessionEvent::StorageCritical(critical) = try_next_event(&mut events).unwrap() else {
+            panic!("Expected a storage-critical event");
+        };
+        assert!(critical.path.ends_with("archived_sessions.json"));
+        assert!(try_next_event(&mut events).is_none());
+
+        // Once space is back, the in-memory archive is written out
+        std::fs::remove_file(&temp_path).unwrap();
+        manager.storage_recovered().await;
+        assert!(!manager.storage_alarm().is_raised());
+        let mut restarted = ProcessManager::new();
+        restarted.set_data_dir(data_dir.path());
+        assert_eq!(restarted.get_sessions(true).await.len(), 2);
+    }
 }
@@ -0,0 +1,118 @@
+//! Prompt input preparation for Claude CLI processes
+//!
+//! Prompts are normally passed as the CLI's positional argument, but a single
+//! argv element is limited by the OS (E2BIG on Linux for very long arguments).
+//! Prompts above [`STDIN_THRESHOLD_BYTES`] are written to a file in the
+//! session's scratch directory and fed to the CLI on stdin instead, which
+//! `claude -p` reads when no prompt argument is given.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use super::scratch::ScratchSpace;
+
+/// Prompts larger than this are passed via stdin instead of argv
+pub const STDIN_THRESHOLD_BYTES: usize = 100 * 1024;
+
+/// Prompts larger than this are rejected outright
+pub const MAX_PROMPT_BYTES: usize = 10 * 1024 * 1024;
+
+/// Strip NUL bytes (which cannot appear in argv) and normalize CRLF/CR to LF
+pub fn sanitize_prompt(prompt: &str) -> String {
+    prompt
+        .replace('\0', "")
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
+}
+
+/// How the prompt text reaches the CLI process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PromptInput {
+    /// Passed as the positional argument (see `build_claude_args`)
+    Arg(String),
+    /// Written to this temp file, which is connected to the CLI's stdin
+    File(PathBuf),
+}
+
+impl PromptInput {
+    /// Choose the input mode for a sanitized prompt, writing the temp file into
+    /// `scratch_dir` (the session's scratch directory) if needed
+    pub async fn prepare(prompt: &str, scratch_dir: &Path) -> std::io::Result<Self> {
+        if prompt.len() <= STDIN_THRESHOLD_BYTES {
+            return Ok(PromptInput::Arg(prompt.to_string()));
+        }
+
+        let path = ScratchSpace::allocate_in(scratch_dir, "prompt", "txt").await?;
+        if let Err(e) = tokio::fs::write(&path, prompt).await {
+            let _ = tokio::fs::remove_file(&path).await;
+            return Err(e);
+        }
+        Ok(PromptInput::File(path))
+    }
+
+    /// The prompt argument, if the prompt is passed via argv
+    pub fn arg(&self) -> Option<&str> {
+        match self {
+            PromptInput::Arg(prompt) => Some(prompt),
+            PromptInput::File(_) => None,
+        }
+    }
+
+    /// Stdio to use as the CLI's stdin
+    pub fn stdin(&self) -> std::io::Result<Stdio> {
+        match self {
+            PromptInput::Arg(_) => Ok(Stdio::null()),
+            PromptInput::File(path) => Ok(Stdio::from(std::fs::File::open(path)?)),
+        }
+    }
+
+    /// Remove the temp file, if any
+    pub async fn cleanup(&self) {
+        if let PromptInput::File(path) = self {
+            if let Err(e) = tokio::fs::remove_file(path).await {
+                log::warn!(
+                    "Failed to remove prompt temp file {}: {}",
+                    path.display(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sanitize_strips_nul_and_normalizes_newlines() {
+        assert_eq!(sanitize_prompt("a\0b\r\nc\rd\ne"), "ab\nc\nd\ne");
+        assert_eq!(sanitize_prompt("plain"), "plain");
+    }
+
+    #[tokio::test]
+    async fn test_small_prompt_uses_arg() {
+        let dir = TempDir::new().unwrap();
+        let input = PromptInput::prepare("hello", dir.path()).await.unwrap();
+        assert_eq!(input.arg(), Some("hello"));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_large_prompt_uses_temp_file() {
+        let dir = TempDir::new().unwrap();
+        let prompt = "x".repeat(STDIN_THRESHOLD_BYTES + 1);
+
+        let input = PromptInput::prepare(&prompt, dir.path()).await.unwrap();
+        let PromptInput::File(ref path) = input else {
+            panic!("Expected a temp file");
+        };
+        assert!(path.starts_with(dir.path()));
+        assert_eq!(std::fs::read_to_string(path).unwrap(), prompt);
+        assert!(input.arg().is_none());
+
+        input.cleanup().await;
+        assert!(!path.exists());
+    }
+}
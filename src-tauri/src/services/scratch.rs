@@ -0,0 +1,268 @@
+//! Per-session scratch directories
+//!
+//! Temporary files (prompt spill files, diff inputs, attachments) live under
+//! one scratch root in the app data directory, with a subdirectory per
+//! session. Files are allocated with unique names so concurrent users never
+//! overwrite each other.
+//!
+//! A session's directory lives as long as the session is live or archived,
+//! since it also holds the session's file versions and tool output blobs. It
+//! is removed when the session is terminated; on startup, directories of
+//! sessions that are neither live nor archived (those still open at the last
+//! exit, or left behind by a crash) are swept along with the shared
+//! directory. Nothing is removed on exit.
+
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the scratch root inside the app data directory
+pub const SCRATCH_DIR_NAME: &str = "scratch";
+
+/// Directory for files that do not belong to a session (e.g. diff inputs)
+pub const SHARED_DIR_NAME: &str = "_shared";
+
+/// Root of all scratch directories
+#[derive(Debug, Clone)]
+pub struct ScratchSpace {
+    root: PathBuf,
+}
+
+/// Directory name for a session id, with anything but `[A-Za-z0-9_-]` replaced
+fn dir_name(session_id: &str) -> String {
+    session_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+impl ScratchSpace {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Scratch space under `app_data_dir`
+    pub fn in_app_data(app_data_dir: &Path) -> Self {
+        Self::new(app_data_dir.join(SCRATCH_DIR_NAME))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The scratch directory of a session (not created)
+    pub fn scratch_dir_for(&self, session_id: &str) -> PathBuf {
+        self.root.join(dir_name(session_id))
+    }
+
+    /// The directory for files that belong to no session (not created)
+    pub fn shared_dir(&self) -> PathBuf {
+        self.root.join(SHARED_DIR_NAME)
+    }
+
+    /// Create a new empty file with a unique name in `dir`
+    ///
+    /// The name is `<stem>-<random>.<extension>`; the file is created
+    /// exclusively, so two callers can never get the same path.
+    pub async fn allocate_in(dir: &Path, stem: &str, extension: &str) -> io::Result<PathBuf> {
+        tokio::fs::create_dir_all(dir).await?;
+        loop {
+            let id = uuid::Uuid::new_v4().simple().to_string();
+            let mut name = format!("{}-{}", stem, &id[..12]);
+            if !extension.is_empty() {
+                name.push('.');
+                name.push_str(extension);
+            }
+            let path = dir.join(name);
+            match tokio::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await
+            {
+                Ok(_) => return Ok(path),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Allocate a unique file in a session's scratch directory
+    pub async fn allocate(
+        &self,
+        session_id: &str,
+        stem: &str,
+        extension: &str,
+    ) -> io::Result<PathBuf> {
+        Self::allocate_in(&self.scratch_dir_for(session_id), stem, extension).await
+    }
+
+    /// Allocate a unique file in the shared scratch directory
+    pub async fn allocate_shared(&self, stem: &str, extension: &str) -> io::Result<PathBuf> {
+        Self::allocate_in(&self.shared_dir(), stem, extension).await
+    }
+
+    /// Remove a session's scratch directory and everything in it
+    pub async fn remove_session(&self, session_id: &str) {
+        remove_dir(&self.scratch_dir_for(session_id)).await;
+    }
+
+    /// Move the files of `from`'s scratch directory into `into`'s, then remove it
+    ///
+    /// Used when sessions are merged. A file whose name is already taken in
+    /// `into` is dropped: names are unique, so it is either the same content
+    /// or one its owner merged beforehand (see `FileVersionStore::merge_from`).
+    pub async fn move_session(&self, from: &str, into: &str) -> io::Result<()> {
+        let source = self.scratch_dir_for(from);
+        let mut pending = vec![(source.clone(), self.scratch_dir_for(into))];
+        while let Some((src, dst)) = pending.pop() {
+            let mut entries = match tokio::fs::read_dir(&src).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            tokio::fs::create_dir_all(&dst).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let target = dst.join(entry.file_name());
+                if entry.file_type().await?.is_dir() {
+                    pending.push((entry.path(), target));
+                } else if !tokio::fs::try_exists(&target).await? {
+                    tokio::fs::rename(entry.path(), target).await?;
+                }
+            }
+        }
+        remove_dir(&source).await;
+        Ok(())
+    }
+
+    /// Remove scratch directories of sessions not in `kept_sessions`
+    ///
+    /// The shared directory is removed too. Returns how many directories were removed.
+    pub async fn sweep_orphans(&self, kept_sessions: &HashSet<String>) -> usize {
+        let live: HashSet<String> = kept_sessions.iter().map(|id| dir_name(id)).collect();
+        let mut entries = match tokio::fs::read_dir(&self.root).await {
+            Ok(entries) => entries,
+            Err(_) => return 0,
+        };
+
+        let mut removed = 0;
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if live.contains(&name) {
+                continue;
+            }
+            remove_dir(&entry.path()).await;
+            removed += 1;
+        }
+        removed
+    }
+}
+
+async fn remove_dir(dir: &Path) {
+    match tokio::fs::remove_dir_all(dir).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => log::warn!("Failed to remove scratch dir {}: {}", dir.display(), e),
+    }
+}
+
+impl Default for ScratchSpace {
+    /// A private scratch space in the system temp dir, for when no app data dir is known
+    fn default() -> Self {
+        Self::new(std::env::temp_dir().join(format!(
+            "claude-gui-scratch-{}",
+            uuid::Uuid::new_v4().simple()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_concurrent_allocation_is_unique() {
+        let dir = TempDir::new().unwrap();
+        let scratch = ScratchSpace::in_app_data(dir.path());
+
+        let tasks: Vec<_> = (0..64)
+            .map(|_| {
+                let scratch = scratch.clone();
+                tokio::spawn(async move { scratch.allocate("session-1", "diff", "txt").await })
+            })
+            .collect();
+        let mut paths = HashSet::new();
+        for task in tasks {
+            let path = task.await.unwrap().unwrap();
+            assert!(path.starts_with(scratch.scratch_dir_for("session-1")));
+            assert!(path.to_string_lossy().ends_with(".txt"));
+            paths.insert(path);
+        }
+        assert_eq!(paths.len(), 64);
+    }
+
+    #[test]
+    fn test_session_dir_cannot_escape_root() {
+        let scratch = ScratchSpace::new("/scratch");
+        assert_eq!(
+            scratch.scratch_dir_for("../../etc"),
+            PathBuf::from("/scratch/______etc")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sweep_removes_orphans_only() {
+        let dir = TempDir::new().unwrap();
+        let scratch = ScratchSpace::in_app_data(dir.path());
+        scratch.allocate("live", "prompt", "txt").await.unwrap();
+        scratch.allocate("gone", "prompt", "txt").await.unwrap();
+        scratch.allocate_shared("original", "rs").await.unwrap();
+
+        let live = HashSet::from(["live".to_string()]);
+        assert_eq!(scratch.sweep_orphans(&live).await, 2);
+        assert!(scratch.scratch_dir_for("live").exists());
+        assert!(!scratch.scratch_dir_for("gone").exists());
+        assert!(!scratch.shared_dir().exists());
+
+        scratch.remove_session("live").await;
+        assert!(!scratch.scratch_dir_for("live").exists());
+        // Sweeping a missing root is a no-op
+        std::fs::remove_dir_all(scratch.root()).unwrap();
+        assert_eq!(scratch.sweep_orphans(&live).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_move_session_keeps_existing_files() {
+        let dir = TempDir::new().unwrap();
+        let scratch = ScratchSpace::in_app_data(dir.path());
+        let moved = scratch.allocate("from", "prompt", "txt").await.unwrap();
+        std::fs::write(&moved, "moved").unwrap();
+        for (session_id, content) in [("from", "theirs"), ("into", "ours")] {
+            let nested = scratch.scratch_dir_for(session_id).join("nested");
+            std::fs::create_dir_all(&nested).unwrap();
+            std::fs::write(nested.join("index.json"), content).unwrap();
+        }
+
+        scratch.move_session("from", "into").await.unwrap();
+        let into = scratch.scratch_dir_for("into");
+        let moved_name = moved.file_name().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(into.join(moved_name)).unwrap(),
+            "moved"
+        );
+        assert_eq!(
+            std::fs::read_to_string(into.join("nested").join("index.json")).unwrap(),
+            "ours"
+        );
+        assert!(!scratch.scratch_dir_for("from").exists());
+        // Moving a session without a directory is a no-op
+        scratch.move_session("from", "into").await.unwrap();
+    }
+}
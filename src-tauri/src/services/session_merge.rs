@@ -0,0 +1,136 @@
+//! Combining two sessions that append to the same CLI conversation
+//!
+//! `ProcessManager::merge_sessions` keeps one session and folds the other
+//! into it. The prompts of both are interleaved in the order they started,
+//! the transcript follows that order (and is renumbered, so replay cursors
+//! of the kept session go stale), and the merged-away session's scratch
+//! files (tool result blobs, file versions) move to the kept session.
+
+use std::collections::HashMap;
+use std::io;
+
+use super::file_versions::FileVersionStore;
+use super::process::{PromptRecord, TranscriptEntry};
+use super::scratch::ScratchSpace;
+
+/// Add another session's prompt history and transcript to a session's
+///
+/// Prompts end up in start order (stable, so ties keep `history`'s first).
+/// Transcript entries are grouped by their prompt's new position, then
+/// renumbered gaplessly from 1.
+pub fn merge_prompts(
+    history: &mut Vec<PromptRecord>,
+    transcript: &mut Vec<TranscriptEntry>,
+    mut other_history: Vec<PromptRecord>,
+    mut other_transcript: Vec<TranscriptEntry>,
+) {
+    history.append(&mut other_history);
+    history.sort_by_key(|record| record.started_at);
+
+    let order: HashMap<&str, usize> = history
+        .iter()
+        .enumerate()
+        .map(|(index, record)| (record.prompt_id.as_str(), index))
+        .collect();
+    transcript.append(&mut other_transcript);
+    transcript.sort_by_key(|entry| {
+        order
+            .get(entry.prompt_id.as_str())
+            .copied()
+            .unwrap_or(usize::MAX)
+    });
+    for (index, entry) in transcript.iter_mut().enumerate() {
+        entry.sequence = index as u64 + 1;
+    }
+}
+
+/// Move the scratch files of session `from` into session `into`'s directory
+///
+/// File versions are merged into `into`'s index first; everything else has
+/// a unique name and is moved as is. `from`'s directory is removed.
+pub async fn move_scratch(scratch: &ScratchSpace, from: &str, into: &str) -> io::Result<()> {
+    FileVersionStore::for_session(scratch, into)
+        .merge_from(&FileVersionStore::for_session(scratch, from))
+        .await?;
+    scratch.move_session(from, into).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::parser::StreamMessage;
+    use crate::services::test_support::prompt_record;
+    use crate::services::tool_output::{self, ToolResultBlob};
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn entry(prompt_id: &str, sequence: u64) -> TranscriptEntry {
+        TranscriptEntry {
+            sequence,
+            prompt_id: prompt_id.to_string(),
+            model: "sonnet".to_string(),
+            dry_run: false,
+            message: serde_json::from_str::<StreamMessage>(r#"{"type":"result"}"#).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_prompts_follow_start_order() {
+        let mut history = vec![prompt_record("k1", 10), prompt_record("k2", 30)];
+        let mut transcript = vec![entry("k1", 1), entry("k2", 2)];
+        let other_history = vec![prompt_record("m1", 20), prompt_record("m2", 30)];
+        let other_transcript = vec![entry("m1", 1), entry("m1", 2), entry("m2", 3)];
+
+        merge_prompts(
+            &mut history,
+            &mut transcript,
+            other_history,
+            other_transcript,
+        );
+        let ids: Vec<&str> = history.iter().map(|r| r.prompt_id.as_str()).collect();
+        // Ties keep the kept session's prompt first
+        assert_eq!(ids, vec!["k1", "m1", "k2", "m2"]);
+        let entries: Vec<(u64, &str)> = transcript
+            .iter()
+            .map(|e| (e.sequence, e.prompt_id.as_str()))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![(1, "k1"), (2, "m1"), (3, "m1"), (4, "k2"), (5, "m2")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scratch_files_move_to_the_kept_session() {
+        let dir = TempDir::new().unwrap();
+        let scratch = ScratchSpace::in_app_data(dir.path());
+        let blob = ToolResultBlob {
+            blob_id: "abc123".to_string(),
+            content: json!("full output"),
+        };
+        tool_output::store_blob(&scratch, "merge", &blob)
+            .await
+            .unwrap();
+        let path = "/project/lib.rs";
+        for (session_id, before, after) in [("keep", "v1", "v2"), ("merge", "v2", "v3")] {
+            FileVersionStore::for_session(&scratch, session_id)
+                .record_edit(path, Some(before), Some(after), None, 10)
+                .await
+                .unwrap();
+        }
+
+        move_scratch(&scratch, "merge", "keep").await.unwrap();
+        assert_eq!(
+            tool_output::load_blob(&scratch, "keep", "abc123")
+                .await
+                .unwrap(),
+            json!("full output")
+        );
+        let versions = FileVersionStore::for_session(&scratch, "keep")
+            .versions(path, Some("v3"), 0)
+            .await
+            .unwrap();
+        assert_eq!(versions.len(), 3);
+        assert!(!scratch.scratch_dir_for("merge").exists());
+    }
+}
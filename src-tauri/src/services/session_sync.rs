@@ -0,0 +1,237 @@
+//! Versioned session list changes
+//!
+//! Instead of polling `get_sessions`, the frontend keeps its session list in
+//! sync from `sessions-changed` events. Every change to a session's
+//! `SessionInfo` stamps it with the next value of one counter and emits a
+//! diff holding just that session; removals are emitted (and remembered for
+//! a while) as ids. After a reconnect, `get_sessions_since(version)` returns
+//! the changes after `version`, or the full list if removals that old are no
+//! longer remembered.
+//!
+//! The tracked list is the live sessions plus the archived ones, i.e. what
+//! `get_sessions(true)` returns; archiving a session is a change, not a removal.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use super::process::{SessionEvent, SessionInfo};
+
+/// Removals remembered for `get_sessions_since`; older versions get a full snapshot
+pub const MAX_TRACKED_REMOVALS: usize = 1024;
+
+/// Payload of the `sessions-changed` event and result of `get_sessions_since`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionsDiff {
+    /// The list version this diff brings a client up to
+    pub version: u64,
+    /// `changed` is the complete list; sessions not in it are gone
+    pub full: bool,
+    /// Sessions added or changed, with their new info
+    pub changed: Vec<SessionInfo>,
+    /// Ids of sessions that were terminated or merged away
+    pub removed: Vec<String>,
+}
+
+impl SessionsDiff {
+    /// Apply the diff to a client-side session list
+    ///
+    /// A changed session replaces the known one only if its version is newer,
+    /// so an event that arrives after an overlapping diff is harmless.
+    pub fn apply(&self, sessions: &mut HashMap<String, SessionInfo>) {
+        if self.full {
+            sessions.clear();
+        }
+        for id in &self.removed {
+            sessions.remove(id);
+        }
+        for info in &self.changed {
+            let newer = sessions
+                .get(&info.id)
+                .is_none_or(|known| known.version < info.version);
+            if newer {
+                sessions.insert(info.id.clone(), info.clone());
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct VersionState {
+    version: u64,
+    /// (version, session id), oldest first
+    removals: VecDeque<(u64, String)>,
+    /// Version of the newest removal that was forgotten
+    horizon: u64,
+}
+
+/// The session list's version counter, shared by the manager and its prompt tasks
+#[derive(Debug, Clone)]
+pub struct SessionVersions {
+    state: Arc<Mutex<VersionState>>,
+    events: broadcast::Sender<SessionEvent>,
+}
+
+impl SessionVersions {
+    pub fn new(events: broadcast::Sender<SessionEvent>) -> Self {
+        Self {
+            state: Arc::default(),
+            events,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VersionState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The version of the latest change
+    pub fn version(&self) -> u64 {
+        self.lock().version
+    }
+
+    /// Stamp `info` with the next version and emit it as changed
+    ///
+    /// Call with the session's lock held, right after mutating `info`, so the
+    /// stamp and the change are seen together.
+    pub fn changed(&self, info: &mut SessionInfo) {
+        let mut state = self.lock();
+        state.version += 1;
+        info.version = state.version;
+        // Sent under the lock so events go out in version order
+        let _ = self
+            .events
+            .send(SessionEvent::SessionsChanged(SessionsDiff {
+                version: state.version,
+                full: false,
+                changed: vec![info.clone()],
+                removed: Vec::new(),
+            }));
+    }
+
+    /// Record that `session_id` left the list and emit its removal
+    pub fn removed(&self, session_id: &str) {
+        let mut state = self.lock();
+        state.version += 1;
+        let version = state.version;
+        state.removals.push_back((version, session_id.to_string()));
+        if state.removals.len() > MAX_TRACKED_REMOVALS {
+            if let Some((forgotten, _)) = state.removals.pop_front() {
+                state.horizon = forgotten;
+            }
+        }
+        let _ = self
+            .events
+            .send(SessionEvent::SessionsChanged(SessionsDiff {
+                version,
+                full: false,
+                changed: Vec::new(),
+                removed: vec![session_id.to_string()],
+            }));
+    }
+
+    /// The changes after `since`, given `infos` (every tracked session) read
+    /// after the counter was at `version`
+    ///
+    /// A full snapshot is returned when removals after `since` may have been
+    /// forgotten, or when `since` is from a previous run of the app.
+    pub fn diff(&self, since: u64, version: u64, infos: Vec<SessionInfo>) -> SessionsDiff {
+        let state = self.lock();
+        if since < state.horizon || since > state.version {
+            return SessionsDiff {
+                version,
+                full: true,
+                changed: infos,
+                removed: Vec::new(),
+            };
+        }
+        SessionsDiff {
+            version,
+            full: false,
+            changed: infos
+                .into_iter()
+                .filter(|info| info.version > since)
+                .collect(),
+            removed: state
+                .removals
+                .iter()
+                .filter(|(removed_at, _)| *removed_at > since)
+                .map(|(_, id)| id.clone())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::process::SessionStatus;
+
+    fn info(id: &str) -> SessionInfo {
+        SessionInfo {
+            id: id.to_string(),
+            claude_session_id: None,
+            working_dir: Default::default(),
+            model: "sonnet".to_string(),
+            status: SessionStatus::Idle,
+            created_at: 0,
+            prompt_count: 0,
+            total_cost: Default::default(),
+            active_prompt_id: None,
+            working_dir_missing: false,
+            mcp_servers: None,
+            context_pressure: Default::default(),
+            context_tokens: None,
+            recovered_after_crash: false,
+            tool_presets: Vec::new(),
+            allowed_tools: Vec::new(),
+            sync_warning: None,
+            read_only: false,
+            version: 0,
+        }
+    }
+
+    #[test]
+    fn test_forgotten_removals_force_a_full_snapshot() {
+        let versions = SessionVersions::new(broadcast::channel(16).0);
+        let mut kept = info("kept");
+        versions.changed(&mut kept);
+        for i in 0..=MAX_TRACKED_REMOVALS {
+            versions.removed(&format!("gone-{}", i));
+        }
+        let version = versions.version();
+        assert_eq!(version, MAX_TRACKED_REMOVALS as u64 + 2);
+
+        let diff = versions.diff(0, version, vec![kept.clone()]);
+        assert!(diff.full);
+        assert_eq!(diff.changed, vec![kept.clone()]);
+
+        let diff = versions.diff(version - 1, version, vec![kept.clone()]);
+        assert!(!diff.full);
+        assert!(diff.changed.is_empty());
+        assert_eq!(diff.removed, vec![format!("gone-{}", MAX_TRACKED_REMOVALS)]);
+
+        // A version from a previous run of the app
+        assert!(versions.diff(version + 10, version, vec![kept]).full);
+    }
+
+    #[test]
+    fn test_apply_ignores_stale_changes() {
+        let mut newer = info("a");
+        newer.version = 5;
+        newer.prompt_count = 2;
+        let mut stale = info("a");
+        stale.version = 3;
+        let mut sessions = HashMap::from([("a".to_string(), newer.clone())]);
+
+        SessionsDiff {
+            version: 3,
+            full: false,
+            changed: vec![stale],
+            removed: Vec::new(),
+        }
+        .apply(&mut sessions);
+        assert_eq!(sessions["a"], newer);
+    }
+}
@@ -0,0 +1,71 @@
+//! Small JSON settings files in the app data directory
+//!
+//! Each setting (proxy, logging, cost format, control server, ...) lives in
+//! its own file. A missing or malformed file means the defaults, so a bad
+//! edit never keeps the app from starting. Saving goes through
+//! [`storage::write_atomic`], and a full disk surfaces as the usual
+//! out-of-space error for the caller to report.
+
+use std::io;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::storage;
+
+/// Load `file_name` from `app_data_dir`; missing or malformed files mean the defaults
+pub fn load<T: DeserializeOwned + Default>(app_data_dir: &Path, file_name: &str) -> T {
+    let path = app_data_dir.join(file_name);
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            log::warn!("Ignoring invalid {}: {}", path.display(), e);
+            T::default()
+        }),
+        Err(e) => {
+            if e.kind() != io::ErrorKind::NotFound {
+                log::warn!("Failed to read {}: {}", path.display(), e);
+            }
+            T::default()
+        }
+    }
+}
+
+/// Write `settings` to `file_name` in `app_data_dir` atomically
+pub async fn save<T: Serialize>(
+    app_data_dir: &Path,
+    file_name: &str,
+    settings: &T,
+) -> io::Result<()> {
+    let content = serde_json::to_string_pretty(settings)?;
+    storage::write_atomic(&app_data_dir.join(file_name), content.as_bytes()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use tempfile::TempDir;
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Settings {
+        level: String,
+    }
+
+    #[tokio::test]
+    async fn test_round_trip_and_defaults() {
+        let dir = TempDir::new().unwrap();
+        let app_data = dir.path().join("app");
+        assert_eq!(load::<Settings>(&app_data, "s.json"), Settings::default());
+
+        let settings = Settings {
+            level: "debug".to_string(),
+        };
+        save(&app_data, "s.json", &settings).await.unwrap();
+        assert_eq!(load::<Settings>(&app_data, "s.json"), settings);
+        assert!(!app_data.join("s.tmp").exists());
+
+        std::fs::write(app_data.join("s.json"), "{not json").unwrap();
+        assert_eq!(load::<Settings>(&app_data, "s.json"), Settings::default());
+    }
+}
@@ -0,0 +1,272 @@
+//! Per-prompt environment snapshots
+//!
+//! Each prompt's history entry records the conditions it ran under (CLI
+//! version, requested and reported model, git state of the working directory,
+//! connected MCP servers, permission mode), so "it behaved differently
+//! yesterday" reports can be compared. Capturing never delays a prompt: the
+//! CLI version comes from a cache filled by the availability check, the git
+//! state is read concurrently with the spawn under a short timeout, and the
+//! rest is taken from the CLI's `init` message as it streams by.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::git_ops::git_output;
+use super::parser::StreamMessage;
+
+/// Longest the git part of a snapshot may take before it is recorded as partial
+pub const GIT_SNAPSHOT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Timeout for `claude --version`
+const VERSION_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The conditions a prompt ran under
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EnvironmentSnapshot {
+    /// `claude --version` output, if the CLI has been checked
+    pub cli_version: Option<String>,
+    /// The model passed to `--model`
+    pub model_requested: String,
+    /// The model the CLI's init message reported
+    pub model_reported: Option<String>,
+    /// HEAD commit of the working directory; None outside a repository
+    pub git_head: Option<String>,
+    /// Files with uncommitted changes (per `git status --porcelain`)
+    pub git_dirty_files: Option<usize>,
+    /// MCP servers connected at the start of the prompt
+    pub mcp_servers: Vec<String>,
+    /// Permission mode the CLI's init message reported
+    pub permission_mode: Option<String>,
+    /// Set when the git state could not be read within [`GIT_SNAPSHOT_TIMEOUT`]
+    #[serde(default)]
+    pub partial: bool,
+}
+
+/// Git state of a working directory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GitState {
+    pub head: Option<String>,
+    pub dirty_files: Option<usize>,
+    pub timed_out: bool,
+}
+
+impl EnvironmentSnapshot {
+    pub fn new(cli_version: Option<String>, model_requested: &str) -> Self {
+        Self {
+            cli_version,
+            model_requested: model_requested.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn apply_git(&mut self, git: GitState) {
+        self.git_head = git.head;
+        self.git_dirty_files = git.dirty_files;
+        self.partial = git.timed_out;
+    }
+
+    /// Take the reported model, permission mode and MCP servers from an init message
+    ///
+    /// Returns whether `msg` was an init message.
+    pub fn apply_init(&mut self, msg: &StreamMessage) -> bool {
+        let StreamMessage::System {
+            mcp_servers, extra, ..
+        } = msg
+        else {
+            return false;
+        };
+        if extra.get("subtype").and_then(Value::as_str) != Some("init") {
+            return false;
+        }
+        let field = |keys: &[&str]| {
+            keys.iter()
+                .find_map(|key| extra.get(*key).and_then(Value::as_str))
+                .map(str::to_string)
+        };
+        self.model_reported = field(&["model"]);
+        self.permission_mode = field(&["permissionMode", "permission_mode"]);
+        self.mcp_servers = mcp_servers
+            .iter()
+            .flatten()
+            .filter(|server| server.status == "connected")
+            .map(|server| server.name.clone())
+            .collect();
+        true
+    }
+}
+
+/// Read HEAD and the dirty file count of `dir`, giving up after `timeout`
+pub async fn capture_git(dir: PathBuf, timeout: Duration) -> GitState {
+    let read = async {
+        let (head, status) = tokio::join!(
+            git_output(&dir, &["rev-parse", "HEAD"]),
+            git_output(&dir, &["status", "--porcelain"]),
+        );
+        let stdout = |output: Result<std::process::Output, String>| {
+            output
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+        };
+        GitState {
+            head: stdout(head).map(|head| head.trim().to_string()),
+            dirty_files: stdout(status).map(|status| status.lines().count()),
+            timed_out: false,
+        }
+    };
+    tokio::time::timeout(timeout, read)
+        .await
+        .unwrap_or_else(|_| GitState {
+            timed_out: true,
+            ..Default::default()
+        })
+}
+
+/// Result of checking that the CLI runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CliStatus {
+    pub available: bool,
+    pub version: Option<String>,
+    pub error: Option<String>,
+}
+
+/// The CLI version found by the last availability check
+#[derive(Debug, Clone, Default)]
+pub struct CliVersionCache(Arc<RwLock<Option<String>>>);
+
+impl CliVersionCache {
+    pub fn get(&self) -> Option<String> {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn set(&self, version: Option<String>) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = version;
+    }
+}
+
+/// Run `<cli_path> --version`, returning the first line of its output
+pub async fn probe_cli_version(cli_path: &Path) -> Result<String, String> {
+    let mut command = tokio::process::Command::new(cli_path);
+    command.arg("--version").kill_on_drop(true);
+    #[cfg(windows)]
+    command.creation_flags(super::win_process::SPAWN_FLAGS);
+    let output = tokio::time::timeout(VERSION_CHECK_TIMEOUT, command.output())
+        .await
+        .map_err(|_| format!("{} --version timed out", cli_path.display()))?
+        .map_err(|e| format!("Failed to run {}: {}", cli_path.display(), e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} --version failed: {}",
+            cli_path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(str::to_string)
+        .ok_or_else(|| format!("{} --version printed nothing", cli_path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use tempfile::TempDir;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[tokio::test]
+    async fn test_capture_git_state() {
+        let repo = TempDir::new().unwrap();
+        git(repo.path(), &["init", "-q"]);
+        git(repo.path(), &["config", "user.email", "test@example.com"]);
+        git(repo.path(), &["config", "user.name", "Test"]);
+        std::fs::write(repo.path().join("a.txt"), "a").unwrap();
+        git(repo.path(), &["add", "a.txt"]);
+        git(repo.path(), &["commit", "-q", "-m", "init"]);
+        std::fs::write(repo.path().join("a.txt"), "changed").unwrap();
+        std::fs::write(repo.path().join("b.txt"), "new").unwrap();
+
+        // Generous timeout: this checks the values, not the speed
+        let state = capture_git(repo.path().to_path_buf(), Duration::from_secs(10)).await;
+        assert_eq!(state.head.as_ref().map(String::len), Some(40));
+        assert_eq!(state.dirty_files, Some(2));
+        assert!(!state.timed_out);
+
+        let outside = TempDir::new().unwrap();
+        let state = capture_git(outside.path().to_path_buf(), Duration::from_secs(10)).await;
+        assert_eq!(state.head, None);
+
+        let state = capture_git(repo.path().to_path_buf(), Duration::ZERO).await;
+        assert!(state.timed_out);
+        assert_eq!(state.head, None);
+    }
+
+    #[test]
+    fn test_snapshot_assembly() {
+        let cache = CliVersionCache::default();
+        assert_eq!(cache.get(), None);
+        cache.set(Some("2.0.1 (Claude Code)".to_string()));
+
+        let mut snapshot = EnvironmentSnapshot::new(cache.get(), "sonnet");
+        snapshot.apply_git(GitState {
+            head: Some("abc".to_string()),
+            dirty_files: Some(3),
+            timed_out: false,
+        });
+
+        let not_init: StreamMessage = serde_json::from_str(
+            r#"{"type":"system","subtype":"compact_boundary","model":"other"}"#,
+        )
+        .unwrap();
+        assert!(!snapshot.apply_init(&not_init));
+        let init: StreamMessage = serde_json::from_str(
+            r#"{"type":"system","subtype":"init","session_id":"s","model":"claude-sonnet-4-5","permissionMode":"acceptEdits","mcp_servers":[{"name":"github","status":"connected"},{"name":"db","status":"failed"}]}"#,
+        )
+        .unwrap();
+        assert!(snapshot.apply_init(&init));
+
+        assert_eq!(
+            snapshot,
+            EnvironmentSnapshot {
+                cli_version: Some("2.0.1 (Claude Code)".to_string()),
+                model_requested: "sonnet".to_string(),
+                model_reported: Some("claude-sonnet-4-5".to_string()),
+                git_head: Some("abc".to_string()),
+                git_dirty_files: Some(3),
+                mcp_servers: vec!["github".to_string()],
+                permission_mode: Some("acceptEdits".to_string()),
+                partial: false,
+            }
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_probe_cli_version() {
+        use crate::services::test_support::write_fake_cli;
+
+        let dir = TempDir::new().unwrap();
+        let cli = write_fake_cli(dir.path(), "echo\necho '2.0.1 (Claude Code)'");
+        assert_eq!(
+            probe_cli_version(&cli).await.unwrap(),
+            "2.0.1 (Claude Code)"
+        );
+        assert!(probe_cli_version(&dir.path().join("missing"))
+            .await
+            .is_err());
+    }
+}
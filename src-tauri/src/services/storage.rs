@@ -0,0 +1,331 @@
+//! Disk space and app data writability checks
+//!
+//! A full disk makes every persisting feature fail with its own generic IO
+//! error. This module reports free space and writability up front
+//! (`check_storage_health`), recognizes out-of-space errors so writers can
+//! degrade instead of failing on every operation, and deduplicates the
+//! resulting `storage-critical` event with a [`StorageAlarm`].
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+/// Free space below which a volume is reported as low
+pub const DEFAULT_WARN_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Free space below which a volume is reported as critical
+pub const DEFAULT_CRITICAL_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Free-space thresholds for warnings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageThresholds {
+    pub warn_bytes: u64,
+    pub critical_bytes: u64,
+}
+
+impl Default for StorageThresholds {
+    fn default() -> Self {
+        Self {
+            warn_bytes: DEFAULT_WARN_BYTES,
+            critical_bytes: DEFAULT_CRITICAL_BYTES,
+        }
+    }
+}
+
+/// How much room a volume has left
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageLevel {
+    Ok,
+    Low,
+    Critical,
+    /// Free space could not be determined
+    Unknown,
+}
+
+/// Free space of the volume holding a path
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VolumeStatus {
+    pub path: String,
+    pub free_bytes: Option<u64>,
+    pub level: StorageLevel,
+}
+
+/// Result of `check_storage_health`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StorageHealth {
+    pub app_data: VolumeStatus,
+    /// Whether a file could be created in the app data dir
+    pub app_data_writable: bool,
+    pub working_dir: Option<VolumeStatus>,
+    /// Human-readable problems, empty when storage is healthy
+    pub warnings: Vec<String>,
+}
+
+impl StorageHealth {
+    /// Whether nothing is critical and the app data dir is writable
+    pub fn is_healthy(&self) -> bool {
+        self.app_data_writable
+            && self.app_data.level != StorageLevel::Critical
+            && self
+                .working_dir
+                .as_ref()
+                .is_none_or(|v| v.level != StorageLevel::Critical)
+    }
+}
+
+/// Whether `e` means the disk (or the user's quota) is full
+pub fn is_out_of_space(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded
+    )
+}
+
+/// Write a file atomically (write to temp, then rename)
+///
+/// Parent directories are created, and no partial temp file is left behind
+/// when the write fails (e.g. because the disk is full).
+pub async fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let temp_path = path.with_extension("tmp");
+    if let Err(e) = tokio::fs::write(&temp_path, content).await {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+        return Err(e);
+    }
+
+    // Atomic on most filesystems
+    tokio::fs::rename(&temp_path, path).await
+}
+
+/// Classify free space against the thresholds
+pub fn classify_free_space(
+    free_bytes: Option<u64>,
+    thresholds: &StorageThresholds,
+) -> StorageLevel {
+    match free_bytes {
+        None => StorageLevel::Unknown,
+        Some(free) if free < thresholds.critical_bytes => StorageLevel::Critical,
+        Some(free) if free < thresholds.warn_bytes => StorageLevel::Low,
+        Some(_) => StorageLevel::Ok,
+    }
+}
+
+/// The nearest existing ancestor of `path` (the path itself if it exists)
+fn existing_ancestor(path: &Path) -> Option<&Path> {
+    path.ancestors().find(|p| p.exists())
+}
+
+/// Bytes available to the current user on the volume holding `path`
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> io::Result<u64> {
+    let path = existing_ancestor(path).ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+    let stat = nix::sys::statvfs::statvfs(path).map_err(io::Error::from)?;
+    Ok(stat.blocks_available() as u64 * stat.fragment_size() as u64)
+}
+
+/// Bytes available to the current user on the volume holding `path`
+#[cfg(not(unix))]
+pub fn free_space(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Whether a file can be created in `dir` (created if missing)
+pub fn probe_writable(dir: &Path) -> bool {
+    let probe = dir.join(format!(".write-probe-{}", uuid::Uuid::new_v4()));
+    let result = std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&probe, b"probe"));
+    let _ = std::fs::remove_file(&probe);
+    result.is_ok()
+}
+
+fn volume_status(path: &Path, thresholds: &StorageThresholds) -> VolumeStatus {
+    let free_bytes = free_space(path).ok();
+    VolumeStatus {
+        path: path.to_string_lossy().to_string(),
+        free_bytes,
+        level: classify_free_space(free_bytes, thresholds),
+    }
+}
+
+fn level_warning(name: &str, volume: &VolumeStatus) -> Option<String> {
+    let free_mb = volume.free_bytes.unwrap_or(0) / (1024 * 1024);
+    match volume.level {
+        StorageLevel::Critical => Some(format!(
+            "{} volume is almost full ({} MB free at {})",
+            name, free_mb, volume.path
+        )),
+        StorageLevel::Low => Some(format!(
+            "{} volume is low on space ({} MB free at {})",
+            name, free_mb, volume.path
+        )),
+        StorageLevel::Ok | StorageLevel::Unknown => None,
+    }
+}
+
+/// Check free space of the app data volume (and a working dir's) and app data writability
+pub fn check_storage_health(
+    app_data_dir: &Path,
+    working_dir: Option<&Path>,
+    thresholds: &StorageThresholds,
+) -> StorageHealth {
+    let app_data = volume_status(app_data_dir, thresholds);
+    let app_data_writable = probe_writable(app_data_dir);
+    let working_dir = working_dir.map(|dir| volume_status(dir, thresholds));
+
+    let mut warnings = Vec::new();
+    if !app_data_writable {
+        warnings.push(format!(
+            "App data directory is not writable: {}",
+            app_data_dir.display()
+        ));
+    }
+    warnings.extend(level_warning("App data", &app_data));
+    if let Some(ref volume) = working_dir {
+        warnings.extend(level_warning("Working directory", volume));
+    }
+
+    StorageHealth {
+        app_data,
+        app_data_writable,
+        working_dir,
+        warnings,
+    }
+}
+
+/// Payload of the `storage-critical` event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StorageCritical {
+    /// The path whose write failed
+    pub path: String,
+    pub message: String,
+}
+
+impl StorageCritical {
+    pub fn new(path: impl Into<PathBuf>, error: &io::Error) -> Self {
+        Self {
+            path: path.into().to_string_lossy().to_string(),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Deduplicates `storage-critical` events until storage is healthy again
+#[derive(Debug, Clone, Default)]
+pub struct StorageAlarm {
+    raised: Arc<AtomicBool>,
+}
+
+impl StorageAlarm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Raise the alarm; returns true only for the first call since the last `clear`
+    pub fn raise(&self) -> bool {
+        !self.raised.swap(true, Ordering::SeqCst)
+    }
+
+    pub fn is_raised(&self) -> bool {
+        self.raised.load(Ordering::SeqCst)
+    }
+
+    /// Storage recovered; the next failure is reported again
+    pub fn clear(&self) {
+        self.raised.store(false, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_classify_free_space() {
+        let thresholds = StorageThresholds {
+            warn_bytes: 1000,
+            critical_bytes: 100,
+        };
+        assert_eq!(
+            classify_free_space(Some(5000), &thresholds),
+            StorageLevel::Ok
+        );
+        assert_eq!(
+            classify_free_space(Some(1000), &thresholds),
+            StorageLevel::Ok
+        );
+        assert_eq!(
+            classify_free_space(Some(999), &thresholds),
+            StorageLevel::Low
+        );
+        assert_eq!(
+            classify_free_space(Some(99), &thresholds),
+            StorageLevel::Critical
+        );
+        assert_eq!(
+            classify_free_space(None, &thresholds),
+            StorageLevel::Unknown
+        );
+    }
+
+    #[test]
+    fn test_out_of_space_classification() {
+        assert!(is_out_of_space(&io::Error::from(
+            io::ErrorKind::StorageFull
+        )));
+        assert!(is_out_of_space(&io::Error::from(
+            io::ErrorKind::QuotaExceeded
+        )));
+        #[cfg(target_os = "linux")]
+        assert!(is_out_of_space(&io::Error::from_raw_os_error(28))); // ENOSPC
+        assert!(!is_out_of_space(&io::Error::from(
+            io::ErrorKind::PermissionDenied
+        )));
+        assert!(!is_out_of_space(&io::Error::other("disk full")));
+    }
+
+    #[test]
+    fn test_alarm_deduplicates_until_cleared() {
+        let alarm = StorageAlarm::new();
+        assert!(alarm.raise());
+        assert!(!alarm.clone().raise());
+        assert!(alarm.is_raised());
+        alarm.clear();
+        assert!(alarm.raise());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_health_of_writable_dir() {
+        let dir = TempDir::new().unwrap();
+        let app_data = dir.path().join("app-data");
+        let thresholds = StorageThresholds {
+            warn_bytes: 0,
+            critical_bytes: 0,
+        };
+
+        let health = check_storage_health(&app_data, Some(dir.path()), &thresholds);
+        assert!(health.app_data_writable);
+        assert!(health.app_data.free_bytes.is_some());
+        assert_eq!(health.app_data.level, StorageLevel::Ok);
+        assert!(health.warnings.is_empty());
+        assert!(health.is_healthy());
+        // The probe file is cleaned up
+        assert_eq!(std::fs::read_dir(&app_data).unwrap().count(), 0);
+
+        // Thresholds above any real free space flag both volumes
+        let thresholds = StorageThresholds {
+            warn_bytes: u64::MAX,
+            critical_bytes: u64::MAX,
+        };
+        let health = check_storage_health(&app_data, Some(dir.path()), &thresholds);
+        assert_eq!(health.warnings.len(), 2);
+        assert!(!health.is_healthy());
+    }
+}
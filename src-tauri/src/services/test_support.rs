@@ -0,0 +1,39 @@
+//! Fixtures shared by the tests of the services and commands
+
+use super::process::PromptRecord;
+
+/// Write an executable shell script standing in for the Claude CLI
+///
+/// The script is `dir/fake-claude`; `script` is its body after the shebang.
+#[cfg(unix)]
+pub fn write_fake_cli(dir: &std::path::Path, script: &str) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = dir.join("fake-claude");
+    std::fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+/// A prompt of the default model that completed a second after `started_at`
+pub fn prompt_record(prompt_id: &str, started_at: u64) -> PromptRecord {
+    PromptRecord {
+        prompt_id: prompt_id.to_string(),
+        request_id: None,
+        model: "sonnet".to_string(),
+        model_overridden: false,
+        started_at,
+        completed_at: Some(started_at + 1),
+        cost: None,
+        files_touched: Vec::new(),
+        hooks: Vec::new(),
+        meta_command: None,
+        result_subtype: None,
+        completion_reason: None,
+        timing: None,
+        context: Vec::new(),
+        snapshot: None,
+        dry_run: false,
+        plan: None,
+    }
+}
@@ -0,0 +1,311 @@
+//! Size policy for tool results emitted to the webview
+//!
+//! A single tool result (a `cat` of a large file, say) can be megabytes of
+//! JSON, which the event bridge, the transcript and the UI all handle badly.
+//! Before a message is emitted, tool results larger than the session's limit
+//! are shrunk in place and marked with `truncated: true`, `total_bytes` and a
+//! `blob_id`. The full content is written to the session's scratch directory
+//! and can be fetched with `get_tool_result_blob`.
+
+use std::io;
+use std::path::PathBuf;
+
+use serde_json::{Map, Value};
+
+use super::parser::StreamMessage;
+use super::scratch::ScratchSpace;
+
+/// Default largest tool result content (serialized bytes) emitted as is
+pub const DEFAULT_MAX_TOOL_RESULT_BYTES: usize = 256 * 1024;
+
+/// Strings up to this length are never cut, so tags like `"type": "text"` survive
+const MIN_CUT_STRING_BYTES: usize = 256;
+
+/// Subdirectory of a session's scratch directory holding full tool results
+const BLOB_DIR_NAME: &str = "tool-results";
+
+/// The full content of a truncated tool result
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolResultBlob {
+    pub blob_id: String,
+    pub content: Value,
+}
+
+/// Bytes `c` takes in a JSON string literal
+fn escaped_len(c: char) -> usize {
+    match c {
+        '"' | '\\' | '\n' | '\r' | '\t' | '\u{8}' | '\u{c}' => 2,
+        c if (c as u32) < 0x20 => 6,
+        c => c.len_utf8(),
+    }
+}
+
+/// Byte length of the longest prefix of `s` whose JSON form fits in `max` bytes
+///
+/// The cut always falls on a char boundary.
+fn prefix_fitting(s: &str, max: usize) -> usize {
+    let mut used = 0;
+    for (i, c) in s.char_indices() {
+        used += escaped_len(c);
+        if used > max {
+            return i;
+        }
+    }
+    s.len()
+}
+
+/// Shrink the string data in `value` to about `budget` serialized bytes, keeping it valid JSON
+///
+/// Long strings are cut, array elements past the budget are dropped, and
+/// object keys, numbers and short strings are kept.
+fn shrink(value: &mut Value, budget: &mut usize) {
+    match value {
+        Value::String(s) => {
+            let len: usize = s.chars().map(escaped_len).sum();
+            if len <= *budget || len <= MIN_CUT_STRING_BYTES {
+                *budget = budget.saturating_sub(len);
+            } else {
+                let kept = prefix_fitting(s, *budget);
+                s.truncate(kept);
+                *budget = 0;
+            }
+        }
+        Value::Array(items) => {
+            let mut kept = items.len();
+            for (i, item) in items.iter_mut().enumerate() {
+                if *budget == 0 {
+                    kept = i;
+                    break;
+                }
+                shrink(item, budget);
+            }
+            items.truncate(kept);
+        }
+        Value::Object(fields) => {
+            for field in fields.values_mut() {
+                shrink(field, budget);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn serialized_len(value: &Value) -> usize {
+    serde_json::to_vec(value).map_or(0, |bytes| bytes.len())
+}
+
+/// Truncate `content` if it is larger than `max_bytes`, marking `fields`
+fn truncate_content(
+    content: &mut Value,
+    fields: &mut Map<String, Value>,
+    max_bytes: usize,
+) -> Option<ToolResultBlob> {
+    let total_bytes = serialized_len(content);
+    if total_bytes <= max_bytes {
+        return None;
+    }
+    let blob = ToolResultBlob {
+        blob_id: uuid::Uuid::new_v4().simple().to_string(),
+        content: content.clone(),
+    };
+    let mut budget = max_bytes;
+    shrink(content, &mut budget);
+    fields.insert("truncated".to_string(), Value::Bool(true));
+    fields.insert("total_bytes".to_string(), Value::from(total_bytes));
+    fields.insert("blob_id".to_string(), Value::String(blob.blob_id.clone()));
+    Some(blob)
+}
+
+/// Truncate the tool results in `msg` (a tool result message, or `tool_result`
+/// content blocks) whose content exceeds `max_bytes`
+///
+/// Returns the full contents of what was truncated, to be stored with [`store_blob`].
+pub fn truncate_tool_results(msg: &mut StreamMessage, max_bytes: usize) -> Vec<ToolResultBlob> {
+    let mut blobs = Vec::new();
+    match msg {
+        StreamMessage::ToolResult { content, extra, .. } => {
+            if !extra.is_object() {
+                *extra = Value::Object(Map::new());
+            }
+            if let Some(fields) = extra.as_object_mut() {
+                blobs.extend(truncate_content(content, fields, max_bytes));
+            }
+        }
+        StreamMessage::Assistant { content, .. } => {
+            let Some(blocks) = content.as_array_mut() else {
+                return blobs;
+            };
+            for block in blocks {
+                let Some(fields) = block.as_object_mut() else {
+                    continue;
+                };
+                if fields.get("type").and_then(Value::as_str) != Some("tool_result") {
+                    continue;
+                }
+                let Some(mut block_content) = fields.remove("content") else {
+                    continue;
+                };
+                blobs.extend(truncate_content(&mut block_content, fields, max_bytes));
+                fields.insert("content".to_string(), block_content);
+            }
+        }
+        _ => {}
+    }
+    blobs
+}
+
+fn blob_path(scratch: &ScratchSpace, session_id: &str, blob_id: &str) -> io::Result<PathBuf> {
+    if blob_id.is_empty() || !blob_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid blob id: {}", blob_id),
+        ));
+    }
+    Ok(scratch
+        .scratch_dir_for(session_id)
+        .join(BLOB_DIR_NAME)
+        .join(format!("{}.json", blob_id)))
+}
+
+/// Write a truncated tool result's full content to the session's scratch directory
+pub async fn store_blob(
+    scratch: &ScratchSpace,
+    session_id: &str,
+    blob: &ToolResultBlob,
+) -> io::Result<()> {
+    let path = blob_path(scratch, session_id, &blob.blob_id)?;
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(&path, serde_json::to_vec(&blob.content)?).await
+}
+
+/// Read the full content of a truncated tool result
+pub async fn load_blob(
+    scratch: &ScratchSpace,
+    session_id: &str,
+    blob_id: &str,
+) -> io::Result<Value> {
+    let path = blob_path(scratch, session_id, blob_id)?;
+    let content = tokio::fs::read(&path).await?;
+    Ok(serde_json::from_slice(&content)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn tool_result(content: Value) -> StreamMessage {
+        serde_json::from_value(json!({
+            "type": "tool_result",
+            "tool_use_id": "t1",
+            "content": content,
+        }))
+        .unwrap()
+    }
+
+    fn marker<'a>(fields: &'a Value, key: &str) -> &'a Value {
+        fields.get(key).unwrap_or(&Value::Null)
+    }
+
+    #[test]
+    fn test_small_results_are_untouched() {
+        let mut msg = tool_result(json!("short output"));
+        let original = msg.clone();
+        assert!(truncate_tool_results(&mut msg, 1024).is_empty());
+        assert_eq!(msg, original);
+    }
+
+    #[test]
+    fn test_string_content_cut_on_char_boundary() {
+        // 3-byte characters, so most byte limits fall inside one
+        let text = "€".repeat(2000);
+        let mut msg = tool_result(json!(text));
+        let blobs = truncate_tool_results(&mut msg, 1000);
+        assert_eq!(blobs.len(), 1);
+        assert_eq!(blobs[0].content, json!(text));
+
+        let StreamMessage::ToolResult { content, extra, .. } = &msg else {
+            panic!("expected a tool result");
+        };
+        let kept = content.as_str().unwrap();
+        assert!(kept.len() <= 1000 && text.starts_with(kept));
+        assert_eq!(kept.len() % 3, 0);
+        assert_eq!(marker(extra, "truncated"), &json!(true));
+        assert_eq!(
+            marker(extra, "total_bytes"),
+            &json!(serialized_len(&json!(text)))
+        );
+        assert_eq!(marker(extra, "blob_id"), &json!(blobs[0].blob_id));
+    }
+
+    #[test]
+    fn test_structured_content_stays_valid() {
+        let big = "x".repeat(10_000);
+        let content = json!([
+            {"type": "text", "text": big},
+            {"type": "text", "text": "dropped"},
+            {"type": "image", "source": {"data": big}},
+        ]);
+        let mut msg = tool_result(content.clone());
+        let blobs = truncate_tool_results(&mut msg, 4096);
+        assert_eq!(blobs[0].content, content);
+
+        let StreamMessage::ToolResult { content, .. } = &msg else {
+            panic!("expected a tool result");
+        };
+        let blocks = content.as_array().unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0]["type"], "text");
+        let text_len = blocks[0]["text"].as_str().unwrap().len();
+        assert!((4000..=4096).contains(&text_len));
+        // The emitted message still round-trips as JSON
+        let serialized = serde_json::to_string(&msg).unwrap();
+        assert!(serialized.len() < 5000);
+        let _: StreamMessage = serde_json::from_str(&serialized).unwrap();
+    }
+
+    #[test]
+    fn test_tool_result_blocks_in_messages() {
+        let big = "y".repeat(50_000);
+        let mut msg: StreamMessage = serde_json::from_value(json!({
+            "type": "message",
+            "content": [
+                {"type": "text", "text": "here"},
+                {"type": "tool_result", "tool_use_id": "t1", "content": big},
+                {"type": "tool_result", "tool_use_id": "t2", "content": "small"},
+            ],
+        }))
+        .unwrap();
+        let blobs = truncate_tool_results(&mut msg, 1024);
+        assert_eq!(blobs.len(), 1);
+
+        let StreamMessage::Assistant { content, .. } = &msg else {
+            panic!("expected a message");
+        };
+        assert_eq!(content[1]["truncated"], true);
+        assert_eq!(content[1]["blob_id"], json!(blobs[0].blob_id));
+        assert_eq!(content[1]["content"].as_str().unwrap().len(), 1024);
+        assert!(content[2].get("truncated").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_blob_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let scratch = ScratchSpace::new(dir.path());
+        let blob = ToolResultBlob {
+            blob_id: "abc123".to_string(),
+            content: json!({"lines": ["a", "b"]}),
+        };
+        store_blob(&scratch, "session-1", &blob).await.unwrap();
+        assert_eq!(
+            load_blob(&scratch, "session-1", "abc123").await.unwrap(),
+            blob.content
+        );
+        assert!(load_blob(&scratch, "session-2", "abc123").await.is_err());
+        let traversal = load_blob(&scratch, "session-1", "../session-1/x").await;
+        assert_eq!(traversal.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
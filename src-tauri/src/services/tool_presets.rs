@@ -0,0 +1,320 @@
+//! Named allowed-tools presets
+//!
+//! Writing `allowed_tools` matchers by hand is error-prone, so sessions can
+//! list `preset:<name>` entries instead, which validation expands into the
+//! preset's vetted tool list before any CLI arguments are built. The built-in
+//! presets can be extended or overridden with a `tool_presets.json` file in the
+//! app data directory:
+//!
+//! ```json
+//! {
+//!   "presets": [
+//!     { "name": "docs", "description": "Edit docs only", "tools": ["Read", "Edit(docs/**)"] }
+//!   ]
+//! }
+//! ```
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// File name of the user preset file in the app data directory
+pub const TOOL_PRESETS_FILE_NAME: &str = "tool_presets.json";
+
+/// Prefix of an `allowed_tools` entry that names a preset
+pub const PRESET_PREFIX: &str = "preset:";
+
+/// An `allowed_tools` entry or preset name that matches no preset
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Unknown tool preset: {0}")]
+pub struct UnknownPreset(pub String);
+
+/// A named list of allowed-tools matchers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolPreset {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub tools: Vec<String>,
+    /// Shipped with the app rather than defined in `tool_presets.json`
+    #[serde(default, skip_deserializing)]
+    pub builtin: bool,
+}
+
+/// Contents of the `tool_presets.json` file
+#[derive(Debug, Default, Deserialize)]
+struct PresetOverrides {
+    #[serde(default)]
+    presets: Vec<ToolPreset>,
+}
+
+const READ_ONLY_TOOLS: &[&str] = &[
+    "Read",
+    "Glob",
+    "Grep",
+    "LS",
+    "Bash(git status:*)",
+    "Bash(git diff:*)",
+    "Bash(git log:*)",
+];
+
+const EDIT_TOOLS: &[&str] = &["Edit", "MultiEdit", "Write", "NotebookEdit"];
+
+const DEV_TOOLS: &[&str] = &["Bash", "WebFetch", "WebSearch", "TodoWrite", "Task"];
+
+const CI_TOOLS: &[&str] = &[
+    "Bash(git:*)",
+    "Bash(npm test:*)",
+    "Bash(npm run:*)",
+    "Bash(cargo build:*)",
+    "Bash(cargo test:*)",
+];
+
+/// Built-in presets: (name, description, tool groups)
+const BUILTIN_PRESETS: &[(&str, &str, &[&[&str]])] = &[
+    (
+        "read-only",
+        "Read and search files, inspect git state",
+        &[READ_ONLY_TOOLS],
+    ),
+    (
+        "safe-edits",
+        "Read-only plus file edits, no shell",
+        &[READ_ONLY_TOOLS, EDIT_TOOLS],
+    ),
+    (
+        "full-dev",
+        "Edits, any shell command and web access",
+        &[READ_ONLY_TOOLS, EDIT_TOOLS, DEV_TOOLS],
+    ),
+    (
+        "ci",
+        "Edits plus git, build and test commands, for unattended runs",
+        &[READ_ONLY_TOOLS, EDIT_TOOLS, CI_TOOLS],
+    ),
+];
+
+/// Push `item` unless it is already present
+fn push_unique(list: &mut Vec<String>, item: &str) {
+    if !list.iter().any(|existing| existing == item) {
+        list.push(item.to_string());
+    }
+}
+
+/// The available presets
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolPresets {
+    presets: Vec<ToolPreset>,
+}
+
+impl ToolPresets {
+    /// The presets shipped with the app
+    pub fn builtin() -> Self {
+        Self {
+            presets: BUILTIN_PRESETS
+                .iter()
+                .map(|&(name, description, groups)| {
+                    let mut tools = Vec::new();
+                    for tool in groups.iter().flat_map(|group| group.iter()) {
+                        push_unique(&mut tools, tool);
+                    }
+                    ToolPreset {
+                        name: name.to_string(),
+                        description: description.to_string(),
+                        tools,
+                        builtin: true,
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Load the built-in presets merged with `tool_presets.json` from `app_data_dir`
+    ///
+    /// A missing file yields the built-in presets. A malformed one is logged
+    /// and ignored rather than preventing startup.
+    pub fn load(app_data_dir: &Path) -> Self {
+        let mut presets = Self::builtin();
+        let path = app_data_dir.join(TOOL_PRESETS_FILE_NAME);
+
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return presets,
+            Err(e) => {
+                log::warn!("Failed to read {}: {}", path.display(), e);
+                return presets;
+            }
+        };
+
+        match serde_json::from_str::<PresetOverrides>(&content) {
+            Ok(overrides) => presets.merge(overrides),
+            Err(e) => log::warn!("Ignoring invalid {}: {}", path.display(), e),
+        }
+        presets
+    }
+
+    /// Merge user presets: matching names are replaced, new names appended
+    ///
+    /// Presets cannot include other presets; such entries are dropped.
+    fn merge(&mut self, overrides: PresetOverrides) {
+        for mut preset in overrides.presets {
+            preset.tools.retain(|tool| {
+                let nested = tool.starts_with(PRESET_PREFIX);
+                if nested {
+                    log::warn!(
+                        "Ignoring {:?} in tool preset {:?}: presets cannot be nested",
+                        tool,
+                        preset.name
+                    );
+                }
+                !nested
+            });
+            match self.presets.iter_mut().find(|p| p.name == preset.name) {
+                Some(existing) => *existing = preset,
+                None => self.presets.push(preset),
+            }
+        }
+    }
+
+    pub fn all(&self) -> &[ToolPreset] {
+        &self.presets
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ToolPreset> {
+        self.presets.iter().find(|p| p.name == name)
+    }
+
+    /// Expand `preset:<name>` entries of `allowed_tools`, plus the presets in `presets`
+    ///
+    /// Returns the preset names used and the concrete tool list, both without
+    /// duplicates and in first-seen order (preset tools where the preset
+    /// appears, `presets` after `allowed_tools`). Expanding an expansion again
+    /// changes nothing.
+    pub fn expand(
+        &self,
+        presets: &[String],
+        allowed_tools: &[String],
+    ) -> Result<(Vec<String>, Vec<String>), UnknownPreset> {
+        let named = presets
+            .iter()
+            .map(|name| format!("{}{}", PRESET_PREFIX, name));
+        let mut names = Vec::new();
+        let mut tools = Vec::new();
+        for entry in allowed_tools.iter().cloned().chain(named) {
+            match entry.strip_prefix(PRESET_PREFIX) {
+                Some(name) => {
+                    let name = name.trim();
+                    let preset = self
+                        .get(name)
+                        .ok_or_else(|| UnknownPreset(name.to_string()))?;
+                    push_unique(&mut names, name);
+                    for tool in &preset.tools {
+                        push_unique(&mut tools, tool);
+                    }
+                }
+                None => push_unique(&mut tools, &entry),
+            }
+        }
+        Ok((names, tools))
+    }
+}
+
+impl Default for ToolPresets {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_builtin_presets() {
+        let presets = ToolPresets::builtin();
+        let names: Vec<&str> = presets.all().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["read-only", "safe-edits", "full-dev", "ci"]);
+        assert!(presets.all().iter().all(|p| p.builtin));
+
+        let read_only = &presets.get("read-only").unwrap().tools;
+        assert!(!read_only
+            .iter()
+            .any(|tool| tool == "Edit" || tool == "Bash"));
+        let ci = &presets.get("ci").unwrap().tools;
+        assert!(ci.contains(&"Bash(cargo test:*)".to_string()));
+        assert!(!ci.contains(&"Bash".to_string()));
+    }
+
+    #[test]
+    fn test_expansion_dedupes_explicit_entries() {
+        let presets = ToolPresets::builtin();
+        let (names, tools) = presets
+            .expand(
+                &[],
+                &strings(&[
+                    "Bash(git log:*)",
+                    "preset:read-only",
+                    "Read",
+                    "preset:read-only",
+                    "Bash(make:*)",
+                ]),
+            )
+            .unwrap();
+        assert_eq!(names, strings(&["read-only"]));
+        assert_eq!(tools[0], "Bash(git log:*)");
+        assert_eq!(tools.last().unwrap(), "Bash(make:*)");
+        assert_eq!(tools.len(), READ_ONLY_TOOLS.len() + 1);
+        assert!(!tools.iter().any(|tool| tool.starts_with(PRESET_PREFIX)));
+
+        // Names passed separately (e.g. from a previous expansion) expand the same way
+        let (again_names, again_tools) = presets.expand(&names, &tools).unwrap();
+        assert_eq!((again_names, again_tools), (names, tools));
+    }
+
+    #[test]
+    fn test_unknown_preset_is_rejected() {
+        let presets = ToolPresets::builtin();
+        assert_eq!(
+            presets.expand(&[], &strings(&["Read", "preset:everything"])),
+            Err(UnknownPreset("everything".to_string()))
+        );
+        assert_eq!(
+            presets.expand(&strings(&["nope"]), &[]),
+            Err(UnknownPreset("nope".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_user_presets_from_file() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join(TOOL_PRESETS_FILE_NAME),
+            r#"{"presets": [
+                {"name": "docs", "description": "Docs only", "tools": ["Read", "Edit(docs/**)", "preset:full-dev"]},
+                {"name": "ci", "tools": ["Read", "Bash(just ci)"]}
+            ]}"#,
+        )
+        .unwrap();
+
+        let presets = ToolPresets::load(dir.path());
+        let docs = presets.get("docs").unwrap();
+        assert!(!docs.builtin);
+        assert_eq!(docs.tools, strings(&["Read", "Edit(docs/**)"]));
+        assert_eq!(
+            presets.get("ci").unwrap().tools,
+            strings(&["Read", "Bash(just ci)"])
+        );
+        let (_, tools) = presets.expand(&[], &strings(&["preset:docs"])).unwrap();
+        assert_eq!(tools, strings(&["Read", "Edit(docs/**)"]));
+
+        // A malformed file falls back to the built-in presets
+        std::fs::write(dir.path().join(TOOL_PRESETS_FILE_NAME), "{").unwrap();
+        assert_eq!(ToolPresets::load(dir.path()), ToolPresets::builtin());
+    }
+}
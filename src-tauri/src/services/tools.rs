@@ -0,0 +1,331 @@
+//! Validation of `allowed_tools` matchers
+//!
+//! The CLI takes matchers of the form `ToolName` or `ToolName(pattern)`, for
+//! example `Read` or `Bash(git log:*)`. It does not report typos such as
+//! `Bash(git log*` or `Bahs`; it either fails to start or silently grants
+//! nothing. Sessions are therefore validated here before any process is
+//! spawned, and each problem comes with a suggested fix.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::tool_presets::PRESET_PREFIX;
+
+/// Tools built into the Claude CLI
+pub const KNOWN_TOOLS: &[&str] = &[
+    "Bash",
+    "BashOutput",
+    "Edit",
+    "ExitPlanMode",
+    "Glob",
+    "Grep",
+    "KillShell",
+    "LS",
+    "MultiEdit",
+    "NotebookEdit",
+    "NotebookRead",
+    "Read",
+    "SlashCommand",
+    "Task",
+    "TodoWrite",
+    "WebFetch",
+    "WebSearch",
+    "Write",
+];
+
+/// Prefix of MCP tool names (`mcp__<server>` or `mcp__<server>__<tool>`)
+const MCP_TOOL_PREFIX: &str = "mcp__";
+
+/// What is wrong with a matcher
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MatcherProblem {
+    #[error("missing tool name")]
+    MissingToolName,
+    #[error("unknown tool `{name}`")]
+    UnknownTool { name: String },
+    #[error("unbalanced parentheses")]
+    UnbalancedParentheses,
+    #[error("empty pattern")]
+    EmptyPattern,
+    #[error("unexpected `{text}` after the closing parenthesis")]
+    TrailingText { text: String },
+}
+
+/// An invalid `allowed_tools` entry
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[error("`{entry}`: {problem}{}", did_you_mean(.suggestion))]
+pub struct ToolMatcherError {
+    /// Position of the entry in the list
+    pub index: usize,
+    pub entry: String,
+    pub problem: MatcherProblem,
+    /// The corrected entry, when there is an obvious fix
+    pub suggestion: Option<String>,
+}
+
+fn did_you_mean(suggestion: &Option<String>) -> String {
+    suggestion
+        .as_ref()
+        .map(|s| format!(" (did you mean `{}`?)", s))
+        .unwrap_or_default()
+}
+
+/// Levenshtein distance between `a` and `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// The known tool closest to `name`, if it is close enough to be a typo
+fn closest_tool(name: &str) -> Option<&'static str> {
+    let lower = name.to_lowercase();
+    let max_distance = 2.max(name.chars().count() / 4);
+    KNOWN_TOOLS
+        .iter()
+        .map(|tool| (edit_distance(&lower, &tool.to_lowercase()), *tool))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, tool)| tool)
+}
+
+fn is_known_tool(name: &str) -> bool {
+    KNOWN_TOOLS.contains(&name)
+        || name
+            .strip_prefix(MCP_TOOL_PREFIX)
+            .is_some_and(|rest| !rest.is_empty())
+}
+
+/// Collapse whitespace runs to single spaces and trim
+fn collapse_whitespace(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn matcher(name: &str, pattern: Option<&str>) -> String {
+    match pattern {
+        Some(pattern) => format!("{}({})", name, pattern),
+        None => name.to_string(),
+    }
+}
+
+/// Validate one trimmed, non-empty entry, returning its normalized form
+fn normalize_entry(entry: &str) -> Result<String, (MatcherProblem, Option<String>)> {
+    let (name, pattern, paren_problem) = match entry.find('(') {
+        Some(open) => {
+            let rest = &entry[open + 1..];
+            let mut depth = 1usize;
+            let close = rest.char_indices().find_map(|(i, c)| {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+                (depth == 0).then_some(i)
+            });
+            let (pattern, problem) = match close {
+                None => (rest, Some(MatcherProblem::UnbalancedParentheses)),
+                Some(close) => {
+                    let after = rest[close + 1..].trim();
+                    let problem = (!after.is_empty()).then(|| MatcherProblem::TrailingText {
+                        text: after.to_string(),
+                    });
+                    (&rest[..close], problem)
+                }
+            };
+            (
+                entry[..open].trim(),
+                Some(collapse_whitespace(pattern)),
+                problem,
+            )
+        }
+        None if entry.contains(')') => {
+            return Err((MatcherProblem::UnbalancedParentheses, None));
+        }
+        None => (entry, None, None),
+    };
+
+    let (problem, fixed_name) = if name.is_empty() {
+        (Some(MatcherProblem::MissingToolName), None)
+    } else if is_known_tool(name) {
+        (None, Some(name))
+    } else {
+        let problem = MatcherProblem::UnknownTool {
+            name: name.to_string(),
+        };
+        (Some(problem), closest_tool(name))
+    };
+
+    let pattern_problem = match pattern.as_deref() {
+        Some("") => Some(MatcherProblem::EmptyPattern),
+        _ => None,
+    };
+    let fixed_pattern = pattern.as_deref().filter(|p| !p.is_empty());
+
+    match paren_problem.or(problem).or(pattern_problem) {
+        None => Ok(matcher(name, fixed_pattern)),
+        Some(problem) => Err((problem, fixed_name.map(|name| matcher(name, fixed_pattern)))),
+    }
+}
+
+/// Validate and normalize `allowed_tools` entries
+///
+/// Whitespace is trimmed around entries, tool names and patterns, and runs of
+/// whitespace inside patterns collapse to one space; blank entries are
+/// dropped. `preset:` entries pass through (presets are checked when they are
+/// expanded). Returns the normalized list, or every invalid entry.
+pub fn validate_tool_matchers(entries: &[String]) -> Result<Vec<String>, Vec<ToolMatcherError>> {
+    let mut normalized = Vec::new();
+    let mut errors = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let trimmed = entry.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if let Some(preset) = trimmed.strip_prefix(PRESET_PREFIX) {
+            normalized.push(format!("{}{}", PRESET_PREFIX, preset.trim()));
+            continue;
+        }
+        match normalize_entry(trimmed) {
+            Ok(matcher) => normalized.push(matcher),
+            Err((problem, suggestion)) => errors.push(ToolMatcherError {
+                index,
+                entry: entry.clone(),
+                problem,
+                suggestion,
+            }),
+        }
+    }
+    if errors.is_empty() {
+        Ok(normalized)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    /// The single error for `entry`
+    fn error(entry: &str) -> ToolMatcherError {
+        let mut errors = validate_tool_matchers(&strings(&[entry])).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        errors.remove(0)
+    }
+
+    #[test]
+    fn test_valid_matchers() {
+        let entries = strings(&[
+            "Read",
+            "Bash(git log:*)",
+            "Edit(docs/**)",
+            "Bash(echo (nested) parens)",
+            "WebFetch(domain:example.com)",
+            "mcp__github",
+            "mcp__github__create_issue",
+            "preset:read-only",
+        ]);
+        assert_eq!(validate_tool_matchers(&entries).unwrap(), entries);
+        assert_eq!(validate_tool_matchers(&[]).unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_whitespace_is_normalized() {
+        let entries = strings(&["  Read ", "Bash (  git   log:* )", "", "   ", "preset: ci "]);
+        assert_eq!(
+            validate_tool_matchers(&entries).unwrap(),
+            strings(&["Read", "Bash(git log:*)", "preset:ci"])
+        );
+    }
+
+    #[test]
+    fn test_malformed_matchers() {
+        let e = error("Bash(git log*");
+        assert_eq!(e.problem, MatcherProblem::UnbalancedParentheses);
+        assert_eq!(e.suggestion.as_deref(), Some("Bash(git log*)"));
+
+        let e = error("Bash git:*)");
+        assert_eq!(e.problem, MatcherProblem::UnbalancedParentheses);
+        assert_eq!(e.suggestion, None);
+
+        let e = error("Bash()");
+        assert_eq!(e.problem, MatcherProblem::EmptyPattern);
+        assert_eq!(e.suggestion.as_deref(), Some("Bash"));
+
+        let e = error("Bash(  )");
+        assert_eq!(e.problem, MatcherProblem::EmptyPattern);
+
+        let e = error("Bash(git:*) extra");
+        assert_eq!(
+            e.problem,
+            MatcherProblem::TrailingText {
+                text: "extra".to_string()
+            }
+        );
+        assert_eq!(e.suggestion.as_deref(), Some("Bash(git:*)"));
+
+        let e = error("(git:*)");
+        assert_eq!(e.problem, MatcherProblem::MissingToolName);
+        assert_eq!(e.suggestion, None);
+
+        let e = error("mcp__");
+        assert!(matches!(e.problem, MatcherProblem::UnknownTool { .. }));
+    }
+
+    #[test]
+    fn test_near_misses_suggest_known_tools() {
+        for (entry, suggestion) in [
+            ("bash", "Bash"),
+            ("Bahs(git:*)", "Bash(git:*)"),
+            ("READ", "Read"),
+            ("Web Fetch", "WebFetch"),
+            ("Grpe", "Grep"),
+            ("TodoWrites", "TodoWrite"),
+            ("Notebookedit", "NotebookEdit"),
+            // Name and parenthesis fixed together
+            ("Bsh(git log*", "Bash(git log*)"),
+        ] {
+            let e = error(entry);
+            assert_eq!(e.suggestion.as_deref(), Some(suggestion), "{}", entry);
+        }
+
+        let e = error("Deploy(prod)");
+        assert_eq!(
+            e.problem,
+            MatcherProblem::UnknownTool {
+                name: "Deploy".to_string()
+            }
+        );
+        assert_eq!(e.suggestion, None);
+    }
+
+    #[test]
+    fn test_all_errors_are_reported() {
+        let entries = strings(&["Read", "Bahs", "Edit", "Bash(", "Write"]);
+        let errors = validate_tool_matchers(&entries).unwrap_err();
+        let indices: Vec<usize> = errors.iter().map(|e| e.index).collect();
+        assert_eq!(indices, vec![1, 3]);
+        assert_eq!(
+            errors[0].to_string(),
+            "`Bahs`: unknown tool `Bahs` (did you mean `Bash`?)"
+        );
+        assert_eq!(
+            errors[1].to_string(),
+            "`Bash(`: unbalanced parentheses (did you mean `Bash`?)"
+        );
+    }
+}
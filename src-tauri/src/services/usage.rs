@@ -0,0 +1,233 @@
+//! Prompt timing and usage aggregation
+//!
+//! Each prompt records how long it waited before its CLI process was spawned,
+//! how long the process took to produce its first output, and how long it then
+//! took to produce its result. `get_usage_summary` aggregates these per day so
+//! the startup overhead of the spawn-per-prompt model can be measured.
+
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use super::money::Usd;
+use super::process::PromptRecord;
+
+/// Where a prompt's wall-clock time went, in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptTiming {
+    /// From accepting the prompt to spawning the CLI (includes the pre-prompt hook)
+    pub queue_ms: u64,
+    /// From spawning the CLI to its first line of output
+    pub spawn_to_first_byte_ms: Option<u64>,
+    /// From the first line of output to the result message
+    pub first_byte_to_result_ms: Option<u64>,
+    /// From spawning the CLI to its exit
+    pub total_ms: u64,
+}
+
+/// Instants captured while a prompt runs, turned into a [`PromptTiming`] at the end
+#[derive(Debug, Clone, Copy)]
+pub struct PromptClock {
+    pub accepted_at: Instant,
+    pub spawned_at: Instant,
+    pub first_byte_at: Option<Instant>,
+    pub result_at: Option<Instant>,
+}
+
+fn millis_between(from: Instant, to: Instant) -> u64 {
+    to.saturating_duration_since(from).as_millis() as u64
+}
+
+impl PromptClock {
+    pub fn new(accepted_at: Instant, spawned_at: Instant) -> Self {
+        Self {
+            accepted_at,
+            spawned_at,
+            first_byte_at: None,
+            result_at: None,
+        }
+    }
+
+    /// Record output; only the first call counts
+    pub fn mark_first_byte(&mut self) {
+        self.first_byte_at.get_or_insert_with(Instant::now);
+    }
+
+    pub fn mark_result(&mut self) {
+        self.result_at = Some(Instant::now());
+    }
+
+    pub fn timing(&self, exited_at: Instant) -> PromptTiming {
+        PromptTiming {
+            queue_ms: millis_between(self.accepted_at, self.spawned_at),
+            spawn_to_first_byte_ms: self
+                .first_byte_at
+                .map(|at| millis_between(self.spawned_at, at)),
+            first_byte_to_result_ms: self
+                .first_byte_at
+                .zip(self.result_at)
+                .map(|(first, result)| millis_between(first, result)),
+            total_ms: millis_between(self.spawned_at, exited_at),
+        }
+    }
+}
+
+/// Usage of one UTC day
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailyUsage {
+    /// `YYYY-MM-DD` (UTC)
+    pub date: String,
+    pub prompt_count: usize,
+    #[serde(rename = "cost_usd")]
+    pub cost: Usd,
+    /// Median spawn-to-first-byte latency of the day's prompts
+    pub spawn_latency_p50_ms: Option<u64>,
+    pub spawn_latency_p95_ms: Option<u64>,
+}
+
+/// Usage across all sessions, by day (oldest first)
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct UsageSummary {
+    pub prompt_count: usize,
+    #[serde(rename = "total_cost_usd")]
+    pub total_cost: Usd,
+    pub days: Vec<DailyUsage>,
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[u64], pct: usize) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    Some(sorted[rank - 1])
+}
+
+/// `YYYY-MM-DD` of a unix timestamp (UTC)
+pub fn utc_date(unix_secs: u64) -> String {
+    // Days-to-civil conversion from Howard Hinnant's date algorithms
+    let days = (unix_secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Aggregate prompt records into per-day usage
+pub fn summarize<'a>(records: impl IntoIterator<Item = &'a PromptRecord>) -> UsageSummary {
+    let mut by_day: BTreeMap<String, (usize, Usd, Vec<u64>)> = BTreeMap::new();
+    for record in records {
+        let day = by_day.entry(utc_date(record.started_at)).or_default();
+        day.0 += 1;
+        day.1 += record.cost.unwrap_or_default();
+        if let Some(latency) = record.timing.and_then(|t| t.spawn_to_first_byte_ms) {
+            day.2.push(latency);
+        }
+    }
+
+    let mut summary = UsageSummary::default();
+    for (date, (prompt_count, cost, mut latencies)) in by_day {
+        latencies.sort_unstable();
+        summary.prompt_count += prompt_count;
+        summary.total_cost += cost;
+        summary.days.push(DailyUsage {
+            date,
+            prompt_count,
+            cost,
+            spawn_latency_p50_ms: percentile(&latencies, 50),
+            spawn_latency_p95_ms: percentile(&latencies, 95),
+        });
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn record(started_at: u64, cost: Usd, latency_ms: Option<u64>) -> PromptRecord {
+        PromptRecord {
+            prompt_id: uuid::Uuid::new_v4().to_string(),
+            request_id: None,
+            model: "sonnet".to_string(),
+            model_overridden: false,
+            started_at,
+            completed_at: Some(started_at + 1),
+            cost: Some(cost),
+            files_touched: Vec::new(),
+            hooks: Vec::new(),
+            meta_command: None,
+            result_subtype: None,
+            completion_reason: None,
+            timing: latency_ms.map(|ms| PromptTiming {
+                queue_ms: 0,
+                spawn_to_first_byte_ms: Some(ms),
+                first_byte_to_result_ms: None,
+                total_ms: ms,
+            }),
+            context: Vec::new(),
+            snapshot: None,
+            dry_run: false,
+            plan: None,
+        }
+    }
+
+    #[test]
+    fn test_utc_date() {
+        assert_eq!(utc_date(0), "1970-01-01");
+        assert_eq!(utc_date(951_782_400), "2000-02-29");
+        assert_eq!(utc_date(1_791_244_799), "2026-10-05");
+    }
+
+    #[test]
+    fn test_percentile_nearest_rank() {
+        let values: Vec<u64> = (1..=20).collect();
+        assert_eq!(percentile(&values, 50), Some(10));
+        assert_eq!(percentile(&values, 95), Some(19));
+        assert_eq!(percentile(&[7], 95), Some(7));
+        assert_eq!(percentile(&[], 50), None);
+    }
+
+    #[test]
+    fn test_summarize_groups_by_day() {
+        let day1 = 1_791_158_400; // 2026-10-05
+        let day2 = day1 + 86_400;
+        let mut records: Vec<PromptRecord> = (1..=10)
+            .map(|i| record(day1 + i, Usd::from_dollars(0.1), Some(i * 100)))
+            .collect();
+        records.push(record(day2, Usd::from_dollars(0.5), None));
+
+        let summary = summarize(&records);
+        assert_eq!(summary.prompt_count, 11);
+        assert_eq!(summary.total_cost, Usd::from_dollars(1.5));
+        assert_eq!(summary.days.len(), 2);
+        assert_eq!(summary.days[0].date, "2026-10-05");
+        assert_eq!(summary.days[0].spawn_latency_p50_ms, Some(500));
+        assert_eq!(summary.days[0].spawn_latency_p95_ms, Some(1000));
+        assert_eq!(summary.days[1].date, "2026-10-06");
+        assert_eq!(summary.days[1].spawn_latency_p50_ms, None);
+    }
+
+    #[test]
+    fn test_clock_timing() {
+        let accepted_at = Instant::now();
+        let spawned_at = accepted_at + Duration::from_millis(30);
+        let mut clock = PromptClock::new(accepted_at, spawned_at);
+        clock.first_byte_at = Some(spawned_at + Duration::from_millis(200));
+        clock.result_at = Some(spawned_at + Duration::from_millis(500));
+
+        let timing = clock.timing(spawned_at + Duration::from_millis(600));
+        assert_eq!(timing.queue_ms, 30);
+        assert_eq!(timing.spawn_to_first_byte_ms, Some(200));
+        assert_eq!(timing.first_byte_to_result_ms, Some(300));
+        assert_eq!(timing.total_ms, 600);
+    }
+}
@@ -0,0 +1,588 @@
+//! File watcher for context and memory files
+//!
+//! Wraps a `notify` watcher and republishes changes as [`WatchEvent`]s on a
+//! broadcast channel, which the app forwards to the frontend as `file-changed`
+//! events (and `memory-changed` for CLAUDE.md files). Individual files are
+//! watched through their parent directory so that atomic saves (write to a
+//! temp file, then rename) and files that do not exist yet are still seen.
+//!
+//! Events under a watched directory are filtered through its ignore rules
+//! (see [`IgnoreRules`]), which are reloaded when its `.gitignore` changes.
+//! Files up to [`WatcherConfig::max_hash_bytes`] are hashed, and an event
+//! identical to one delivered for the same path within the debounce window is
+//! dropped. [`WatcherStats`] counts what was seen, delivered and dropped.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::broadcast;
+
+use super::ignore_rules::IgnoreRules;
+use super::memory::is_memory_file;
+use super::paths::encode_path;
+
+/// Capacity of the watch event channel
+const WATCH_CHANNEL_CAPACITY: usize = 256;
+
+/// Files larger than this are reported without a hash
+pub const DEFAULT_MAX_HASH_BYTES: u64 = 1024 * 1024;
+
+/// Window in which an identical event for the same path is dropped
+pub const DEFAULT_DEBOUNCE_MS: u64 = 100;
+
+/// What happened to a watched path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchEventKind {
+    Created,
+    Modified,
+    Removed,
+}
+
+/// A change to a watched file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchEvent {
+    pub path: String,
+    pub kind: WatchEventKind,
+    /// Whether the file is a CLAUDE.md memory file
+    #[serde(rename = "isMemory")]
+    pub is_memory: bool,
+    /// SHA-256 of the file's content (as `compute_hash`); null for removed,
+    /// unreadable or oversized files and directories
+    pub hash: Option<String>,
+}
+
+impl WatchEvent {
+    /// Names of the Tauri events this is emitted as
+    pub fn event_names(&self) -> &'static [&'static str] {
+        if self.is_memory {
+            &["file-changed", "memory-changed"]
+        } else {
+            &["file-changed"]
+        }
+    }
+}
+
+/// Hashing and debounce limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatcherConfig {
+    pub max_hash_bytes: u64,
+    pub debounce_ms: u64,
+}
+
+impl Default for WatcherConfig {
+    fn default() -> Self {
+        Self {
+            max_hash_bytes: DEFAULT_MAX_HASH_BYTES,
+            debounce_ms: DEFAULT_DEBOUNCE_MS,
+        }
+    }
+}
+
+/// Counters for diagnosing runaway watchers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatcherStats {
+    /// Files and directories passed to `watch`
+    pub watched_roots: usize,
+    /// Path events received from notify
+    pub events_seen: u64,
+    /// Events broadcast after filtering
+    pub events_delivered: u64,
+    /// Events dropped as duplicates within the debounce window
+    pub dropped_debounce: u64,
+}
+
+/// Paths the frontend asked to watch, shared with the notify callback
+#[derive(Debug, Default)]
+struct WatchTargets {
+    /// Files watched individually (via their parent directory)
+    files: HashSet<PathBuf>,
+    /// Directories watched recursively, with their ignore rules
+    dirs: HashMap<PathBuf, IgnoreRules>,
+}
+
+impl WatchTargets {
+    fn matches(&self, path: &Path) -> bool {
+        if self.files.contains(path) {
+            return true;
+        }
+        let is_dir = path.is_dir();
+        self.dirs
+            .iter()
+            .any(|(dir, rules)| path.starts_with(dir) && !rules.is_ignored(path, is_dir))
+    }
+
+    /// Reload the rules of directories whose `.gitignore` is `path`
+    fn reload_rules(&mut self, path: &Path) {
+        for rules in self.dirs.values_mut() {
+            if rules.is_rules_file(path) {
+                log::debug!("Reloading ignore rules for {}", rules.root().display());
+                *rules = IgnoreRules::load(rules.root());
+            }
+        }
+    }
+}
+
+/// The last event delivered for a path
+#[derive(Debug)]
+struct Delivered {
+    kind: WatchEventKind,
+    hash: Option<String>,
+    at: Instant,
+}
+
+/// State shared between the `FileWatcher` and the notify callback
+#[derive(Debug, Default)]
+struct WatchState {
+    targets: WatchTargets,
+    config: WatcherConfig,
+    recent: HashMap<PathBuf, Delivered>,
+    stats: WatcherStats,
+}
+
+impl WatchState {
+    /// Filter, hash and debounce one path event; returns the event to broadcast
+    fn process(&mut self, path: &Path, kind: WatchEventKind, now: Instant) -> Option<WatchEvent> {
+        self.stats.events_seen += 1;
+        self.targets.reload_rules(path);
+        if !self.targets.matches(path) {
+            return None;
+        }
+
+        let hash = match kind {
+            WatchEventKind::Removed => None,
+            WatchEventKind::Created | WatchEventKind::Modified => {
+                hash_file(path, self.config.max_hash_bytes)
+            }
+        };
+
+        let window = Duration::from_millis(self.config.debounce_ms);
+        if let Some(last) = self.recent.get(path) {
+            if last.kind == kind
+                && last.hash == hash
+                && now.saturating_duration_since(last.at) < window
+            {
+                self.stats.dropped_debounce += 1;
+                return None;
+            }
+        }
+        self.recent
+            .retain(|_, last| now.saturating_duration_since(last.at) < window);
+        self.recent.insert(
+            path.to_path_buf(),
+            Delivered {
+                kind,
+                hash: hash.clone(),
+                at: now,
+            },
+        );
+
+        self.stats.events_delivered += 1;
+        Some(WatchEvent {
+            path: encode_path(path),
+            kind,
+            is_memory: is_memory_file(path),
+            hash,
+        })
+    }
+}
+
+/// SHA-256 of a regular file no larger than `max_bytes`
+fn hash_file(path: &Path, max_bytes: u64) -> Option<String> {
+    let metadata = std::fs::metadata(path).ok()?;
+    if !metadata.is_file() || metadata.len() > max_bytes {
+        return None;
+    }
+    let mut content = Vec::new();
+    std::fs::File::open(path)
+        .ok()?
+        // The file may have grown since the metadata was read
+        .take(max_bytes + 1)
+        .read_to_end(&mut content)
+        .ok()?;
+    if content.len() as u64 > max_bytes {
+        return None;
+    }
+    Some(hex::encode(Sha256::digest(&content)))
+}
+
+/// Watches files and directories and broadcasts their changes
+pub struct FileWatcher {
+    /// Created on first use so that constructing the app state cannot fail
+    watcher: Option<RecommendedWatcher>,
+    state: Arc<Mutex<WatchState>>,
+    /// Directories registered with notify and how many targets use each
+    registered: HashMap<PathBuf, usize>,
+    events: broadcast::Sender<WatchEvent>,
+}
+
+fn event_kind(kind: &EventKind) -> Option<WatchEventKind> {
+    match kind {
+        EventKind::Create(_) => Some(WatchEventKind::Created),
+        EventKind::Modify(_) => Some(WatchEventKind::Modified),
+        EventKind::Remove(_) => Some(WatchEventKind::Removed),
+        _ => None,
+    }
+}
+
+impl FileWatcher {
+    pub fn new() -> Self {
+        Self {
+            watcher: None,
+            state: Arc::new(Mutex::new(WatchState::default())),
+            registered: HashMap::new(),
+            events: broadcast::channel(WATCH_CHANNEL_CAPACITY).0,
+        }
+    }
+
+    /// Subscribe to changes of watched paths
+    pub fn subscribe(&self) -> broadcast::Receiver<WatchEvent> {
+        self.events.subscribe()
+    }
+
+    fn watcher(&mut self) -> notify::Result<&mut RecommendedWatcher> {
+        if self.watcher.is_none() {
+            let state = self.state.clone();
+            let events = self.events.clone();
+            let watcher =
+                notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+                    let event = match res {
+                        Ok(event) => event,
+                        Err(e) => {
+                            log::warn!("File watcher error: {}", e);
+                            return;
+                        }
+                    };
+                    let Some(kind) = event_kind(&event.kind) else {
+                        return;
+                    };
+                    let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+                    let now = Instant::now();
+                    for path in &event.paths {
+                        if let Some(event) = state.process(path, kind, now) {
+                            let _ = events.send(event);
+                        }
+                    }
+                })?;
+            self.watcher = Some(watcher);
+        }
+        Ok(self.watcher.as_mut().expect("watcher was just created"))
+    }
+
+    /// Register `dir` with notify, sharing registrations between targets
+    fn register(&mut self, dir: &Path, mode: RecursiveMode) -> notify::Result<()> {
+        if let Some(count) = self.registered.get_mut(dir) {
+            *count += 1;
+            return Ok(());
+        }
+        self.watcher()?.watch(dir, mode)?;
+        self.registered.insert(dir.to_path_buf(), 1);
+        Ok(())
+    }
+
+    fn unregister(&mut self, dir: &Path) {
+        let Some(count) = self.registered.get_mut(dir) else {
+            return;
+        };
+        *count -= 1;
+        if *count == 0 {
+            self.registered.remove(dir);
+            if let Some(ref mut watcher) = self.watcher {
+                let _ = watcher.unwatch(dir);
+            }
+        }
+    }
+
+    /// Watch a file (which need not exist yet) or a directory (recursively)
+    pub fn watch(&mut self, path: &Path) -> notify::Result<()> {
+        let path = path.to_path_buf();
+        {
+            let state = self.lock_state();
+            if state.targets.files.contains(&path) || state.targets.dirs.contains_key(&path) {
+                return Ok(());
+            }
+        }
+
+        if path.is_dir() {
+            self.register(&path, RecursiveMode::Recursive)?;
+            let rules = IgnoreRules::load(&path);
+            self.lock_state().targets.dirs.insert(path, rules);
+        } else {
+            let parent = path
+                .parent()
+                .filter(|p| p.is_dir())
+                .ok_or_else(|| notify::Error::path_not_found().add_path(path.clone()))?
+                .to_path_buf();
+            self.register(&parent, RecursiveMode::NonRecursive)?;
+            self.lock_state().targets.files.insert(path);
+        }
+        Ok(())
+    }
+
+    /// Stop watching a path previously passed to `watch`
+    pub fn unwatch(&mut self, path: &Path) {
+        let (was_file, was_dir) = {
+            let mut state = self.lock_state();
+            (
+                state.targets.files.remove(path),
+                state.targets.dirs.remove(path).is_some(),
+            )
+        };
+        if was_dir {
+            self.unregister(path);
+        }
+        if was_file {
+            if let Some(parent) = path.parent() {
+                self.unregister(parent);
+            }
+        }
+    }
+
+    /// Paths currently being watched
+    pub fn watched_paths(&self) -> Vec<String> {
+        let state = self.lock_state();
+        let mut paths: Vec<String> = state
+            .targets
+            .files
+            .iter()
+            .chain(state.targets.dirs.keys())
+            .map(|p| encode_path(p))
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Replace the hashing and debounce limits
+    pub fn set_config(&mut self, config: WatcherConfig) {
+        self.lock_state().config = config;
+    }
+
+    pub fn config(&self) -> WatcherConfig {
+        self.lock_state().config
+    }
+
+    /// Counters since the watcher was created
+    pub fn stats(&self) -> WatcherStats {
+        let state = self.lock_state();
+        WatcherStats {
+            watched_roots: state.targets.files.len() + state.targets.dirs.len(),
+            ..state.stats
+        }
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, WatchState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for FileWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    /// Next event for `path`, skipping events for other files
+    async fn next_event_for(
+        events: &mut broadcast::Receiver<WatchEvent>,
+        path: &Path,
+    ) -> WatchEvent {
+        next_event_where(events, path, |_| true).await
+    }
+
+    /// Next event for `path` that satisfies `accept`
+    async fn next_event_where(
+        events: &mut broadcast::Receiver<WatchEvent>,
+        path: &Path,
+        accept: impl Fn(&WatchEvent) -> bool,
+    ) -> WatchEvent {
+        let path = encode_path(path);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let event = events.recv().await.unwrap();
+                if event.path == path && accept(&event) {
+                    return event;
+                }
+            }
+        })
+        .await
+        .expect("Timed out waiting for a watch event")
+    }
+
+    #[tokio::test]
+    async fn test_watch_file_reports_memory_changes() {
+        let dir = TempDir::new().unwrap();
+        let dir_path = std::fs::canonicalize(dir.path()).unwrap();
+        let memory = dir_path.join("CLAUDE.md");
+        let other = dir_path.join("notes.txt");
+
+        let mut watcher = FileWatcher::new();
+        let mut events = watcher.subscribe();
+        // The memory file does not exist yet
+        watcher.watch(&memory).unwrap();
+
+        std::fs::write(&other, "ignored").unwrap();
+        std::fs::write(&memory, "# Memory").unwrap();
+
+        let event = next_event_for(&mut events, &memory).await;
+        assert!(event.is_memory);
+        assert_eq!(event.event_names(), &["file-changed", "memory-changed"]);
+
+        watcher.unwatch(&memory);
+        assert!(watcher.watched_paths().is_empty());
+        assert!(watcher.registered.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_watch_directory_is_recursive() {
+        let dir = TempDir::new().unwrap();
+        let dir_path = std::fs::canonicalize(dir.path()).unwrap();
+        std::fs::create_dir_all(dir_path.join("src")).unwrap();
+
+        let mut watcher = FileWatcher::new();
+        let mut events = watcher.subscribe();
+        watcher.watch(&dir_path).unwrap();
+
+        let file = dir_path.join("src").join("main.rs");
+        std::fs::write(&file, "fn main() {}").unwrap();
+
+        let event = next_event_for(&mut events, &file).await;
+        assert!(!event.is_memory);
+        assert_eq!(event.event_names(), &["file-changed"]);
+    }
+
+    /// Events received so far, without waiting
+    fn drain(events: &mut broadcast::Receiver<WatchEvent>) -> Vec<WatchEvent> {
+        std::iter::from_fn(|| events.try_recv().ok()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_build_output_is_filtered() {
+        let dir = TempDir::new().unwrap();
+        let root = std::fs::canonicalize(dir.path()).unwrap();
+        for sub in ["src", "dist", "node_modules/pkg", "target/debug"] {
+            std::fs::create_dir_all(root.join(sub)).unwrap();
+        }
+        std::fs::write(root.join(".gitignore"), "dist/\n").unwrap();
+
+        let mut watcher = FileWatcher::new();
+        let mut events = watcher.subscribe();
+        watcher.watch(&root).unwrap();
+
+        // Build churn, then a source edit
+        for i in 0..20 {
+            std::fs::write(root.join("dist/bundle.js"), format!("build {}", i)).unwrap();
+            std::fs::write(root.join("node_modules/pkg/index.js"), "x").unwrap();
+            std::fs::write(root.join("target/debug/app"), format!("{}", i)).unwrap();
+        }
+        let main = root.join("src/main.rs");
+        std::fs::write(&main, "fn main() {}").unwrap();
+
+        // The create event may see the file still empty; the content's hash follows
+        let expected = hex::encode(Sha256::digest(b"fn main() {}"));
+        next_event_where(&mut events, &main, |e| e.hash.as_ref() == Some(&expected)).await;
+        for event in drain(&mut events) {
+            assert!(event.path.contains("src"), "unexpected event: {:?}", event);
+        }
+
+        let stats = watcher.stats();
+        assert_eq!(stats.watched_roots, 1);
+        assert!(stats.events_seen > stats.events_delivered + 40);
+
+        // Changing .gitignore reloads the rules
+        let gitignore = root.join(".gitignore");
+        std::fs::write(&gitignore, "dist/\n*.log\n").unwrap();
+        next_event_for(&mut events, &gitignore).await;
+        std::fs::write(root.join("src/debug.log"), "noise").unwrap();
+        let lib = root.join("src/lib.rs");
+        std::fs::write(&lib, "pub fn f() {}").unwrap();
+
+        next_event_for(&mut events, &lib).await;
+        for event in drain(&mut events) {
+            assert!(
+                !event.path.ends_with(".log"),
+                "unexpected event: {:?}",
+                event
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_large_files_are_not_hashed() {
+        let dir = TempDir::new().unwrap();
+        let root = std::fs::canonicalize(dir.path()).unwrap();
+
+        let mut watcher = FileWatcher::new();
+        watcher.set_config(WatcherConfig {
+            max_hash_bytes: 16,
+            ..WatcherConfig::default()
+        });
+        std::fs::write(root.join(".gitignore"), "*.tmp\n").unwrap();
+        let mut events = watcher.subscribe();
+        watcher.watch(&root).unwrap();
+
+        // Written under an ignored name and renamed, so every event sees the full file
+        let large = root.join("large.bin");
+        std::fs::write(root.join("large.tmp"), [0u8; 64]).unwrap();
+        std::fs::rename(root.join("large.tmp"), &large).unwrap();
+        assert_eq!(next_event_for(&mut events, &large).await.hash, None);
+
+        let small = root.join("small.txt");
+        std::fs::write(&small, "tiny").unwrap();
+        let expected = hex::encode(Sha256::digest(b"tiny"));
+        next_event_where(&mut events, &small, |e| e.hash.as_ref() == Some(&expected)).await;
+    }
+
+    #[test]
+    fn test_identical_events_are_debounced() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("a.txt");
+        std::fs::write(&file, "one").unwrap();
+
+        let mut state = WatchState::default();
+        state.targets.files.insert(file.clone());
+        let start = Instant::now();
+        let kind = WatchEventKind::Modified;
+
+        assert!(state.process(&file, kind, start).is_some());
+        // Same kind and content within the window
+        assert!(state
+            .process(&file, kind, start + Duration::from_millis(10))
+            .is_none());
+        // New content always comes through
+        std::fs::write(&file, "two").unwrap();
+        assert!(state
+            .process(&file, kind, start + Duration::from_millis(20))
+            .is_some());
+        // As does the same event after the window
+        let later = start + Duration::from_millis(DEFAULT_DEBOUNCE_MS + 50);
+        assert!(state.process(&file, kind, later).is_some());
+        // Paths that are not watched are only counted as seen
+        assert!(state
+            .process(&dir.path().join("other.txt"), kind, later)
+            .is_none());
+
+        assert_eq!(state.stats.events_seen, 5);
+        assert_eq!(state.stats.events_delivered, 3);
+        assert_eq!(state.stats.dropped_debounce, 1);
+    }
+
+    #[test]
+    fn test_watch_rejects_missing_parent() {
+        let mut watcher = FileWatcher::new();
+        assert!(watcher
+            .watch(Path::new("/nonexistent-dir/CLAUDE.md"))
+            .is_err());
+    }
+}
@@ -0,0 +1,251 @@
+//! Windows process handling for CLI and MCP server processes
+//!
+//! Unix sessions rely on signals; on Windows the equivalents are:
+//! - spawning with [`SPAWN_FLAGS`], so no console window flashes up and the
+//!   process leads its own process group;
+//! - [`interrupt`], which sends `CTRL_BREAK_EVENT` to that group and waits
+//!   for a graceful exit before the caller falls back to killing;
+//! - [`track`] / [`kill_tree`], which put a process into a Job Object at
+//!   spawn and terminate the whole job, so grandchildren die with it.
+//!
+//! Processes spawned by a child before it was assigned to its job are not in
+//! the job; `track` is called right after spawning to keep that window small.
+
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::io;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use windows_sys::Win32::Foundation::{CloseHandle, HANDLE, STILL_ACTIVE};
+use windows_sys::Win32::System::Console::{GenerateConsoleCtrlEvent, CTRL_BREAK_EVENT};
+use windows_sys::Win32::System::JobObjects::{
+    AssignProcessToJobObject, CreateJobObjectW, JobObjectBasicAccountingInformation,
+    JobObjectExtendedLimitInformation, QueryInformationJobObject, SetInformationJobObject,
+    TerminateJobObject, JOBOBJECT_BASIC_ACCOUNTING_INFORMATION,
+    JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+};
+use windows_sys::Win32::System::Threading::{
+    GetExitCodeProcess, OpenProcess, CREATE_NEW_PROCESS_GROUP, CREATE_NO_WINDOW,
+    PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_SET_QUOTA, PROCESS_TERMINATE,
+};
+
+/// Creation flags for spawned processes: no console window, a new process group
+pub const SPAWN_FLAGS: u32 = CREATE_NO_WINDOW | CREATE_NEW_PROCESS_GROUP;
+
+/// Exit code of processes killed through their job
+const KILLED_EXIT_CODE: u32 = 1;
+
+/// A Job Object whose processes are killed when it is terminated or dropped
+#[derive(Debug)]
+pub struct JobObject {
+    handle: HANDLE,
+}
+
+// The handle is only used through thread-safe kernel calls
+unsafe impl Send for JobObject {}
+unsafe impl Sync for JobObject {}
+
+impl JobObject {
+    pub fn new() -> io::Result<Self> {
+        let handle = unsafe { CreateJobObjectW(std::ptr::null(), std::ptr::null()) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let job = Self { handle };
+
+        let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { std::mem::zeroed() };
+        info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+        let ok = unsafe {
+            SetInformationJobObject(
+                job.handle,
+                JobObjectExtendedLimitInformation,
+                &info as *const _ as *const c_void,
+                std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(job)
+    }
+
+    /// Add a process, and every process it starts from now on, to the job
+    pub fn assign(&self, pid: u32) -> io::Result<()> {
+        let process = unsafe { OpenProcess(PROCESS_SET_QUOTA | PROCESS_TERMINATE, 0, pid) };
+        if process.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let ok = unsafe { AssignProcessToJobObject(self.handle, process) };
+        let result = if ok == 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        };
+        unsafe { CloseHandle(process) };
+        result
+    }
+
+    /// Kill every process in the job
+    pub fn terminate(&self) -> io::Result<()> {
+        if unsafe { TerminateJobObject(self.handle, KILLED_EXIT_CODE) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Number of processes in the job that are still running
+    pub fn active_processes(&self) -> io::Result<u32> {
+        let mut info: JOBOBJECT_BASIC_ACCOUNTING_INFORMATION = unsafe { std::mem::zeroed() };
+        let ok = unsafe {
+            QueryInformationJobObject(
+                self.handle,
+                JobObjectBasicAccountingInformation,
+                &mut info as *mut _ as *mut c_void,
+                std::mem::size_of::<JOBOBJECT_BASIC_ACCOUNTING_INFORMATION>() as u32,
+                std::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(info.ActiveProcesses)
+    }
+}
+
+impl Drop for JobObject {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.handle) };
+    }
+}
+
+/// Jobs of tracked processes, by the pid of the process that was spawned
+fn jobs() -> &'static Mutex<HashMap<u32, JobObject>> {
+    static JOBS: OnceLock<Mutex<HashMap<u32, JobObject>>> = OnceLock::new();
+    JOBS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Put a just-spawned process into its own job so `kill_tree` reaches its descendants
+pub fn track(pid: u32) {
+    match JobObject::new().and_then(|job| job.assign(pid).map(|()| job)) {
+        Ok(job) => {
+            jobs()
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(pid, job);
+        }
+        Err(e) => log::warn!("Failed to assign process {} to a job object: {}", pid, e),
+    }
+}
+
+/// Forget a process that has exited; descendants it left running are killed
+pub fn untrack(pid: u32) {
+    jobs()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(&pid);
+}
+
+/// Kill a process and all its descendants
+///
+/// Tracked processes are killed through their job; others fall back to
+/// `taskkill /T`, which walks the process tree.
+pub fn kill_tree(pid: u32) -> io::Result<()> {
+    let job = jobs()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(&pid);
+    if let Some(job) = job {
+        return job.terminate();
+    }
+
+    use std::os::windows::process::CommandExt;
+    let status = std::process::Command::new("taskkill")
+        .args(["/PID", &pid.to_string(), "/T", "/F"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "taskkill exited with {} for process {}",
+            status, pid
+        )));
+    }
+    Ok(())
+}
+
+/// Whether a process with this pid is still running
+pub fn is_running(pid: u32) -> bool {
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid) };
+    if process.is_null() {
+        return false;
+    }
+    let mut exit_code = 0u32;
+    let ok = unsafe { GetExitCodeProcess(process, &mut exit_code) };
+    unsafe { CloseHandle(process) };
+    ok != 0 && exit_code == STILL_ACTIVE as u32
+}
+
+/// Send `CTRL_BREAK_EVENT` to the process group led by `pid`
+pub fn send_ctrl_break(pid: u32) -> io::Result<()> {
+    if unsafe { GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, pid) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Ask a process spawned with [`SPAWN_FLAGS`] to stop; returns whether it exited within `grace`
+pub async fn interrupt(child: &mut tokio::process::Child, grace: Duration) -> bool {
+    let Some(pid) = child.id() else {
+        // Already exited
+        return true;
+    };
+    if let Err(e) = send_ctrl_break(pid) {
+        log::debug!("Failed to send CTRL_BREAK to process {}: {}", pid, e);
+        return false;
+    }
+    tokio::time::timeout(grace, child.wait()).await.is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `cmd` that starts a long-running grandchild after a short delay,
+    /// so the grandchild is created after `cmd` joined the job
+    fn spawn_process_tree() -> std::process::Child {
+        use std::os::windows::process::CommandExt;
+        std::process::Command::new("cmd")
+            .args(["/C", "ping -n 2 127.0.0.1 >nul & ping -n 60 127.0.0.1 >nul"])
+            .creation_flags(SPAWN_FLAGS)
+            .spawn()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_kill_tree_terminates_grandchildren() {
+        let mut child = spawn_process_tree();
+        let job = JobObject::new().unwrap();
+        job.assign(child.id()).unwrap();
+
+        // cmd plus the second ping
+        std::thread::sleep(Duration::from_millis(2500));
+        assert!(job.active_processes().unwrap() >= 2);
+
+        job.terminate().unwrap();
+        let _ = child.wait();
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(job.active_processes().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_tracked_process_is_killed_through_its_job() {
+        let mut child = spawn_process_tree();
+        let pid = child.id();
+        track(pid);
+        assert!(jobs().lock().unwrap().contains_key(&pid));
+
+        kill_tree(pid).unwrap();
+        assert!(child.wait().is_ok());
+        assert!(!jobs().lock().unwrap().contains_key(&pid));
+    }
+}
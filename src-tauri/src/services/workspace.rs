@@ -0,0 +1,306 @@
+//! Multi-root workspaces
+//!
+//! A workspace groups several project roots (like a VS Code multi-root
+//! workspace) so a single session can see all of them: the first root becomes
+//! the session's working directory and the others are passed to the CLI with
+//! `--add-dir`. Definitions are persisted in `workspaces.json` in the app data
+//! directory. Roots are not required to exist; missing ones are flagged each
+//! time workspaces are loaded or listed.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::process::SessionConfig;
+use super::storage;
+
+/// File name of the workspace definitions in the app data directory
+pub const WORKSPACES_FILE_NAME: &str = "workspaces.json";
+
+/// Errors of workspace operations
+#[derive(Error, Debug)]
+pub enum WorkspaceError {
+    #[error("Workspace not found: {0}")]
+    NotFound(String),
+    #[error("A workspace needs at least one root")]
+    NoRoots,
+    #[error("Workspace root does not exist: {0}")]
+    MissingRoot(String),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// One project root of a workspace
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkspaceRoot {
+    #[serde(with = "super::paths::os_path")]
+    pub path: PathBuf,
+    /// The directory did not exist when the workspace was last loaded or listed
+    #[serde(default, skip_deserializing)]
+    pub missing: bool,
+}
+
+/// A named set of project roots
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Workspace {
+    pub id: String,
+    pub name: String,
+    /// The first root is the primary one (the session's working directory)
+    pub roots: Vec<WorkspaceRoot>,
+    pub created_at: u64,
+}
+
+impl Workspace {
+    /// A new workspace; duplicate roots are dropped, keeping the first occurrence
+    pub fn new(name: &str, roots: Vec<PathBuf>) -> Result<Self, WorkspaceError> {
+        let mut unique: Vec<PathBuf> = Vec::new();
+        for root in roots {
+            if !unique.contains(&root) {
+                unique.push(root);
+            }
+        }
+        if unique.is_empty() {
+            return Err(WorkspaceError::NoRoots);
+        }
+
+        let mut workspace = Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            roots: unique
+                .into_iter()
+                .map(|path| WorkspaceRoot {
+                    path,
+                    missing: false,
+                })
+                .collect(),
+            created_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        };
+        workspace.refresh_missing();
+        Ok(workspace)
+    }
+
+    /// Re-check which roots exist
+    pub fn refresh_missing(&mut self) {
+        for root in &mut self.roots {
+            root.missing = !root.path.is_dir();
+        }
+    }
+
+    /// Paths of all roots, primary first
+    pub fn root_paths(&self) -> impl Iterator<Item = &Path> {
+        self.roots.iter().map(|root| root.path.as_path())
+    }
+
+    /// `config` with the primary root as working directory and the other roots as add-dirs
+    ///
+    /// Missing secondary roots are skipped with a warning; a missing primary
+    /// root is an error. Add-dirs already in `config` are kept.
+    pub fn session_config(
+        &self,
+        mut config: SessionConfig,
+    ) -> Result<SessionConfig, WorkspaceError> {
+        let (primary, others) = self.roots.split_first().ok_or(WorkspaceError::NoRoots)?;
+        if !primary.path.is_dir() {
+            return Err(WorkspaceError::MissingRoot(
+                primary.path.display().to_string(),
+            ));
+        }
+
+        let mut add_dirs = Vec::new();
+        for root in others {
+            if root.path.is_dir() {
+                add_dirs.push(root.path.clone());
+            } else {
+                log::warn!(
+                    "Skipping missing root {} of workspace {}",
+                    root.path.display(),
+                    self.name
+                );
+            }
+        }
+        for dir in config.add_dirs {
+            if dir != primary.path && !add_dirs.contains(&dir) {
+                add_dirs.push(dir);
+            }
+        }
+
+        config.working_dir = primary.path.clone();
+        config.add_dirs = add_dirs;
+        Ok(config)
+    }
+}
+
+/// Workspace definitions, optionally backed by a file
+#[derive(Debug, Default)]
+pub struct WorkspaceStore {
+    path: Option<PathBuf>,
+    workspaces: HashMap<String, Workspace>,
+}
+
+impl WorkspaceStore {
+    /// Create a store that is not persisted to disk
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Load the workspaces from `app_data_dir`
+    ///
+    /// A missing file yields no workspaces. A malformed file is logged and
+    /// treated as empty so startup is never blocked by it.
+    pub fn load(app_data_dir: &Path) -> Self {
+        let path = app_data_dir.join(WORKSPACES_FILE_NAME);
+        let mut workspaces: HashMap<String, Workspace> = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                log::warn!("Ignoring invalid {}: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("Failed to read {}: {}", path.display(), e);
+                }
+                HashMap::new()
+            }
+        };
+        for workspace in workspaces.values_mut() {
+            workspace.refresh_missing();
+        }
+
+        Self {
+            path: Some(path),
+            workspaces,
+        }
+    }
+
+    /// Write the definitions to disk atomically
+    pub async fn save(&self) -> std::io::Result<()> {
+        let Some(ref path) = self.path else {
+            return Ok(());
+        };
+        let content = serde_json::to_string_pretty(&self.workspaces)?;
+        storage::write_atomic(path, content.as_bytes()).await
+    }
+
+    /// Create and persist a workspace
+    pub async fn create(
+        &mut self,
+        name: &str,
+        roots: Vec<PathBuf>,
+    ) -> Result<Workspace, WorkspaceError> {
+        let workspace = Workspace::new(name, roots)?;
+        self.workspaces
+            .insert(workspace.id.clone(), workspace.clone());
+        self.save().await?;
+        Ok(workspace)
+    }
+
+    /// Delete and persist; returns the removed workspace
+    pub async fn delete(&mut self, id: &str) -> Result<Workspace, WorkspaceError> {
+        let workspace = self
+            .workspaces
+            .remove(id)
+            .ok_or_else(|| WorkspaceError::NotFound(id.to_string()))?;
+        self.save().await?;
+        Ok(workspace)
+    }
+
+    /// A workspace with its missing roots re-checked
+    pub fn get(&mut self, id: &str) -> Result<Workspace, WorkspaceError> {
+        let workspace = self
+            .workspaces
+            .get_mut(id)
+            .ok_or_else(|| WorkspaceError::NotFound(id.to_string()))?;
+        workspace.refresh_missing();
+        Ok(workspace.clone())
+    }
+
+    /// All workspaces, oldest first, with missing roots re-checked
+    pub fn list(&mut self) -> Vec<Workspace> {
+        let mut workspaces: Vec<Workspace> = self
+            .workspaces
+            .values_mut()
+            .map(|workspace| {
+                workspace.refresh_missing();
+                workspace.clone()
+            })
+            .collect();
+        workspaces.sort_by(|a, b| (a.created_at, &a.name).cmp(&(b.created_at, &b.name)));
+        workspaces
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_workspaces_persist_and_flag_missing_roots() {
+        let data_dir = TempDir::new().unwrap();
+        let roots = TempDir::new().unwrap();
+        let app = roots.path().join("app");
+        let lib = roots.path().join("lib");
+        std::fs::create_dir_all(&app).unwrap();
+        std::fs::create_dir_all(&lib).unwrap();
+
+        let mut store = WorkspaceStore::load(data_dir.path());
+        let created = store
+            .create("product", vec![app.clone(), lib.clone(), app.clone()])
+            .await
+            .unwrap();
+        assert_eq!(created.root_paths().collect::<Vec<_>>(), vec![&app, &lib]);
+        assert!(created.roots.iter().all(|root| !root.missing));
+
+        std::fs::remove_dir(&lib).unwrap();
+        let mut reloaded = WorkspaceStore::load(data_dir.path());
+        let listed = reloaded.list();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, created.id);
+        assert!(!listed[0].roots[0].missing);
+        assert!(listed[0].roots[1].missing);
+
+        reloaded.delete(&created.id).await.unwrap();
+        assert!(WorkspaceStore::load(data_dir.path()).list().is_empty());
+        assert!(matches!(
+            reloaded.delete(&created.id).await,
+            Err(WorkspaceError::NotFound(_))
+        ));
+        assert!(matches!(
+            Workspace::new("empty", Vec::new()),
+            Err(WorkspaceError::NoRoots)
+        ));
+    }
+
+    #[test]
+    fn test_session_config_expands_roots_into_add_dirs() {
+        let roots = TempDir::new().unwrap();
+        let [api, web, docs] = ["api", "web", "docs"].map(|name| roots.path().join(name));
+        for dir in [&api, &web] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        let extra = roots.path().to_path_buf();
+        let workspace = Workspace::new("ws", vec![api.clone(), web.clone(), docs]).unwrap();
+
+        let base = SessionConfig {
+            working_dir: PathBuf::from("/ignored"),
+            model: "sonnet".to_string(),
+            add_dirs: vec![extra.clone(), web.clone()],
+            ..Default::default()
+        };
+        let config = workspace.session_config(base).unwrap();
+        assert_eq!(config.working_dir, api);
+        // The missing docs root is skipped, explicit add-dirs are kept once
+        assert_eq!(config.add_dirs, vec![web, extra]);
+        assert_eq!(config.model, "sonnet");
+
+        std::fs::remove_dir(&api).unwrap();
+        assert!(matches!(
+            workspace.session_config(SessionConfig::default()),
+            Err(WorkspaceError::MissingRoot(_))
+        ));
+    }
+}
@@ -6,12 +6,17 @@ import { useEffect, useState } from "react";
 import { AppShell } from "./components/layout/AppShell";
 import { ToastProvider } from "./components/notifications";
 import { initializeSessionPersistence } from "./core/store";
+import { loadCostFormat } from "./modules/usage/calculator";
 
 function App() {
   const [isInitialized, setIsInitialized] = useState(false);
   const [initError, setInitError] = useState<string | null>(null);
 
   useEffect(() => {
+    loadCostFormat().catch((error) => {
+      console.warn("Failed to load cost format:", error);
+    });
+
     // Initialize session persistence on app startup with timeout
     const initPromise = initializeSessionPersistence()
       .then(() => {
@@ -75,10 +75,27 @@ describe('EditArbiter', () => {
         path: edit.filePath,
         original_content: edit.originalContent,
         proposed_content: edit.proposedContent,
+        kind: 'modify',
       });
       expect(result.type).toBe('success');
     });
 
+    it('should create the file of a Write to a missing path', async () => {
+      const edit = createMockEdit({ toolName: 'Write', originalContent: '' });
+      arbiter.queueEdit('s1', edit);
+
+      vi.mocked(invoke)
+        .mockResolvedValueOnce(false) // file_exists
+        .mockResolvedValueOnce({ type: 'success' });
+      await arbiter.acceptEdit(edit.id);
+
+      expect(invoke).toHaveBeenCalledWith('file_exists', { path: edit.filePath });
+      expect(invoke).toHaveBeenCalledWith(
+        'apply_edit',
+        expect.objectContaining({ kind: 'create' })
+      );
+    });
+
     it('should remove edit from queue after accept', async () => {
       const edit = createMockEdit();
       arbiter.queueEdit('s1', edit);
@@ -0,0 +1,37 @@
+/**
+ * Usage calculator unit tests
+ */
+
+import { describe, it, expect, vi, beforeEach } from 'vitest';
+import { invoke } from '@tauri-apps/api/core';
+import { formatCost, loadCostFormat } from '../../modules/usage/calculator';
+
+vi.mock('@tauri-apps/api/core');
+
+describe('formatCost', () => {
+  beforeEach(async () => {
+    vi.mocked(invoke).mockResolvedValueOnce({ decimal_separator: '.' });
+    await loadCostFormat();
+  });
+
+  it('should round to cents', () => {
+    expect(formatCost(0)).toBe('$0.00');
+    expect(formatCost(0.1 + 0.2)).toBe('$0.30');
+    expect(formatCost(12.049)).toBe('$12.05');
+  });
+
+  it('should show nonzero amounts under a cent as <$0.01', () => {
+    expect(formatCost(0.0042)).toBe('<$0.01');
+    expect(formatCost(0.009999)).toBe('<$0.01');
+    expect(formatCost(0.01)).toBe('$0.01');
+  });
+
+  it('should use the saved decimal separator', async () => {
+    vi.mocked(invoke).mockResolvedValueOnce({ decimal_separator: ',' });
+    await loadCostFormat();
+
+    expect(invoke).toHaveBeenCalledWith('get_cost_format');
+    expect(formatCost(1.5)).toBe('$1,50');
+    expect(formatCost(0.001)).toBe('<$0,01');
+  });
+});
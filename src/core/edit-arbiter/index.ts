@@ -7,7 +7,7 @@
  */
 
 import { invoke } from "@tauri-apps/api/core";
-import type { ApplyResult, PendingEdit } from "../types";
+import type { ApplyResult, EditKind, PendingEdit } from "../types";
 
 interface AuditLogEntry {
   editId: string;
@@ -81,6 +81,7 @@ export class EditArbiter {
         path: edit.filePath,
         original_content: edit.originalContent,
         proposed_content: edit.proposedContent,
+        kind: await this.editKind(edit),
       });
 
       if (result.type === "success") {
@@ -237,6 +238,21 @@ export class EditArbiter {
     this.yoloMode = false;
   }
 
+  /**
+   * Internal: How the backend should apply an edit
+   *
+   * A Write to a file that does not exist yet creates it; anything else
+   * modifies an existing file, which the backend treats as a conflict if the
+   * file has gone missing.
+   */
+  private async editKind(edit: PendingEdit): Promise<EditKind> {
+    if (edit.toolName !== "Write") {
+      return "modify";
+    }
+    const exists = await invoke<boolean>("file_exists", { path: edit.filePath });
+    return exists ? "modify" : "create";
+  }
+
   /**
    * Auto-accept an edit in YOLO mode
    */
@@ -246,6 +262,7 @@ export class EditArbiter {
         path: edit.filePath,
         originalContent: edit.originalContent,
         proposedContent: edit.proposedContent,
+        kind: await this.editKind(edit),
       });
 
       if (result.type === "success") {
@@ -190,6 +190,9 @@ export type TranscriptEntry = UserTranscriptEntry | AssistantTranscriptEntry;
 
 export type EditToolName = "Write" | "Edit" | "MultiEdit";
 
+/** What an applied edit does to its file (`EditKind` in the backend) */
+export type EditKind = "modify" | "create" | "delete";
+
 export interface PendingEdit {
   id: string;
   sessionId: string;
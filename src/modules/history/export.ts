@@ -6,6 +6,7 @@
 
 import type { PersistedSession, ExportFormat } from "./types";
 import type { ContentBlock } from "../../core/types";
+import { formatCost } from "../usage/calculator";
 
 /**
  * Export a session to the specified format
@@ -40,7 +41,7 @@ function exportToMarkdown(session: PersistedSession): string {
   lines.push(`**Date:** ${new Date(session.createdAt).toLocaleString()}`);
   lines.push(`**Prompts:** ${session.promptCount}`);
   if (session.totalCostUsd > 0) {
-    lines.push(`**Cost:** $${session.totalCostUsd.toFixed(4)}`);
+    lines.push(`**Cost:** ${formatCost(session.totalCostUsd)}`);
   }
   if (session.tags.length > 0) {
     lines.push(`**Tags:** ${session.tags.join(", ")}`);
@@ -4,9 +4,26 @@
  * Utilities for calculating costs, projections, and analyzing usage patterns
  */
 
+import { invoke } from "@tauri-apps/api/core";
 import type { UsageRecord, ModelPricing, UsageSummary } from "./types";
 import { MODEL_PRICING } from "./types";
 
+/**
+ * How costs are shown, as saved by the backend's `set_cost_format`
+ */
+export interface CostFormat {
+  decimal_separator: string;
+}
+
+let costFormat: CostFormat = { decimal_separator: "." };
+
+/**
+ * Load the saved cost format; costs use "." until it is loaded
+ */
+export async function loadCostFormat(): Promise<void> {
+  costFormat = await invoke<CostFormat>("get_cost_format");
+}
+
 /**
  * Calculate cost from token counts
  */
@@ -239,16 +256,21 @@ export function calculateTrend(
 }
 
 /**
- * Format cost as a string
+ * Format cost as a string: `$0.30`, `$12.05`, or `<$0.01` for a nonzero
+ * amount under a cent, with the saved decimal separator
+ *
+ * Follows the backend's `CostFormat::format`: the amount is rounded to a
+ * micro-dollar, then to the nearest cent.
  */
 export function formatCost(costUsd: number): string {
-  if (costUsd < 0.01) {
-    return `$${costUsd.toFixed(4)}`;
-  } else if (costUsd < 1) {
-    return `$${costUsd.toFixed(3)}`;
-  } else {
-    return `$${costUsd.toFixed(2)}`;
+  const separator = costFormat.decimal_separator;
+  const micros = Math.max(0, Math.round(costUsd * 1_000_000));
+  if (micros > 0 && micros < 10_000) {
+    return `<$0${separator}01`;
   }
+  const cents = Math.floor((micros + 5_000) / 10_000);
+  const fraction = String(cents % 100).padStart(2, "0");
+  return `$${Math.floor(cents / 100)}${separator}${fraction}`;
 }
 
 /**
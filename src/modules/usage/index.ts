@@ -5,6 +5,7 @@
  */
 
 import { usageStorage } from "./storage";
+import { formatCost } from "./calculator";
 import { MODEL_PRICING } from "./types";
 import type {
   UsageRecord,
@@ -159,13 +160,7 @@ export class UsageManager {
    * Format cost as string
    */
   formatCost(costUsd: number): string {
-    if (costUsd < 0.01) {
-      return `$${costUsd.toFixed(4)}`;
-    } else if (costUsd < 1) {
-      return `$${costUsd.toFixed(3)}`;
-    } else {
-      return `$${costUsd.toFixed(2)}`;
-    }
+    return formatCost(costUsd);
   }
 
   /**